@@ -49,19 +49,21 @@ impl Application for AppWindow {
 
         let material = graphics.new_material::<DirLightBumpMap>()?;
 
-        let sphere = graphics.get_mesh_from_file("assets\\Meshes\\sphere_hq.obj")?;
+        let sphere = graphics.get_mesh_from_file("assets/Meshes/sphere_hq.obj")?;
 
         let mut brick_d = material.clone();
-        brick_d.add_texture(graphics.get_texture_from_file("assets\\Textures\\brick_d.jpg")?);
-        brick_d.add_texture(graphics.get_texture_from_file("assets\\Textures\\brick_n.jpg")?);
+        brick_d.add_texture(graphics.get_texture_from_file("assets/Textures/brick_d.jpg")?);
+        brick_d.add_texture(graphics.get_texture_from_file("assets/Textures/brick_n.jpg")?);
 
         world.add_entity(Entity::new(sphere, Some(brick_d), Position::default()));
 
-        let mut sky_material = graphics.new_material::<Skybox>()?.with_frontface_culling();
-        sky_material
-            .add_texture(graphics.get_texture_from_file("assets\\Textures\\stars_map.jpg")?);
+        let mut sky_material = graphics
+            .new_material::<Skybox>()?
+            .with_frontface_culling()
+            .with_background_depth();
+        sky_material.add_texture(graphics.get_cube_map_from_file("assets/Textures/stars_map.jpg")?);
 
-        let sky_mesh = graphics.get_mesh_from_file("assets\\Meshes\\sphere.obj")?;
+        let sky_mesh = graphics.get_mesh_from_file("assets/Meshes/sphere.obj")?;
 
         world.add_sky_entity(Entity::new(
             sky_mesh,
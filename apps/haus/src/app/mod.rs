@@ -1,18 +1,19 @@
 mod world;
 
-use shader::{PointLight, Skybox};
 use world::World;
 
 use engine::components::Entity;
 use engine::error::Result;
 use engine::graphics::color;
-use engine::graphics::render::{SwapChain, WindowState};
+use engine::graphics::render::{RenderStats, SwapChain, WindowState};
+use engine::graphics::resource::LoadProgress;
 use engine::graphics::GRAPHICS;
 use engine::input::INPUT;
 use engine::math::{Matrix4x4, Point};
 use engine::physics::Position;
 use engine::window::{Application, Hwnd, Window};
 
+use log::info;
 use std::sync::Mutex;
 
 pub static WINDOW: Window<AppWindow> = Window::new();
@@ -25,6 +26,8 @@ pub struct AppWindow {
     window_state: WindowState,
     #[listener]
     variables: World,
+    loading: LoadProgress,
+    stats: RenderStats,
 }
 
 impl Application for AppWindow {
@@ -47,48 +50,37 @@ impl Application for AppWindow {
 
         let mut world = World::new();
 
-        let material = graphics.new_material::<PointLight>()?;
-
-        let house = graphics.get_mesh_from_file("assets\\Meshes\\house.obj")?;
-        let plane = graphics.get_mesh_from_file("assets\\Meshes\\plane2.obj")?;
-
-        let mut barrel = material.clone();
-        barrel.add_texture(graphics.get_texture_from_file("assets\\Textures\\barrel.jpg")?);
-        let mut brick = material.clone();
-        brick.add_texture(graphics.get_texture_from_file("assets\\Textures\\house_brick.jpg")?);
-        let mut windows = material.clone();
-        windows.add_texture(graphics.get_texture_from_file("assets\\Textures\\house_windows.jpg")?);
-        let mut wood = material.clone();
-        wood.add_texture(graphics.get_texture_from_file("assets\\Textures\\house_wood.jpg")?);
-        let house_textures = vec![barrel, brick, windows, wood];
-
-        let mut sand = material;
-        sand.add_texture(graphics.get_texture_from_file("assets\\Textures\\sand.jpg")?);
-
-        world.add_entity(Entity::new(
-            house,
-            house_textures,
+        world.add_entity(Entity::load(
+            &mut graphics,
+            "assets/Meshes/house.obj",
+            &[
+                "assets/Materials/barrel.material",
+                "assets/Materials/house_brick.material",
+                "assets/Materials/house_windows.material",
+                "assets/Materials/house_wood.material",
+            ],
             Position::new(Matrix4x4::translation([0.0, 0.0, 0.0])),
-        ));
-        world.add_entity(Entity::new(plane, Some(sand), Position::default()));
-
-        let mut sky_material = graphics.new_material::<Skybox>()?.with_frontface_culling();
-        sky_material
-            .add_texture(graphics.get_texture_from_file("assets\\Textures\\stars_map.jpg")?);
-
-        let sky_mesh = graphics.get_mesh_from_file("assets\\Meshes\\sphere.obj")?;
-
-        world.add_sky_entity(Entity::new(
-            sky_mesh,
-            Some(sky_material),
+        )?);
+        world.add_entity(Entity::load(
+            &mut graphics,
+            "assets/Meshes/plane2.obj",
+            &["assets/Materials/sand.material"],
+            Position::default(),
+        )?);
+        world.add_sky_entity(Entity::load(
+            &mut graphics,
+            "assets/Meshes/sphere.obj",
+            &["assets/Materials/sky.material"],
             Position::default(),
-        ));
+        )?);
 
         let mut app_window = Self {
             hwnd,
             swapchain,
             window_state: WindowState::default(),
             variables: world,
+            loading: LoadProgress::default(),
+            stats: RenderStats::default(),
         };
 
         app_window.variables.screen.set_size(app_window.hwnd.rect());
@@ -101,6 +93,15 @@ impl Application for AppWindow {
 
     fn on_update(&mut self) {
         let mut g = GRAPHICS.lock().unwrap();
+        let progress = g.loading_progress();
+        if progress != self.loading {
+            info!(
+                "Loaded {} of {} resources",
+                progress.requested - progress.pending(),
+                progress.requested
+            );
+            self.loading = progress;
+        }
         let context = g.render.immediate_context();
         context.clear_render_target_color(&mut self.swapchain, color::NICE_BLUE);
         context.set_render_target(&mut self.swapchain);
@@ -109,11 +110,12 @@ impl Application for AppWindow {
 
         self.variables.update();
         let mut environment = self.variables.environment();
-        self.variables
-            .set_environment_data(&g.render, &mut environment);
-
-        for (mesh, materials) in self.variables.meshes_and_materials(&g.render) {
-            g.render.draw_mesh_and_materials(mesh, materials);
+        let queue = self.variables.queue(&g.render, &mut environment);
+        g.render.draw_queue(queue);
+        let stats = g.render.take_stats();
+        if stats != self.stats {
+            info!("Drew {}", stats);
+            self.stats = stats;
         }
 
         self.swapchain.present(0);
@@ -1,13 +1,11 @@
-use std::sync::Arc;
-
-use engine::components::{Camera, Entity, PlayState, Screen};
+use engine::components::{Camera, Entity, Light, PlayState, Screen};
 use engine::graphics::color;
-use engine::graphics::material::Material;
-use engine::graphics::render::Render;
-use engine::graphics::resource::mesh::Mesh;
+use engine::graphics::light_list::LightList;
+use engine::graphics::render::{Render, RenderQueue};
 use engine::input::{self, Listener};
 use engine::math::{Matrix4x4, Point};
 use engine::physics::collision3::{CollisionEngine, GjkEngine, Sphere};
+use engine::physics::Frustum;
 use engine::time::DeltaT;
 
 use shader::Environment;
@@ -24,14 +22,12 @@ pub struct World {
     pub scale_cube: f32,
     world_matrix: Matrix4x4,
     pub camera: Camera,
-    pub light_source: Matrix4x4,
+    pub lights: Vec<Light>,
 
     time: f32,
 
     entities: Vec<Entity>,
     sky_entity: Option<Entity>,
-
-    light_rad: f32,
 }
 
 impl World {
@@ -40,13 +36,16 @@ impl World {
         camera.move_forward(-2.0);
         camera.move_up(1.0);
         //let light_source = Matrix4x4::rotation_x(-std::f32::consts::PI / 6.0);
-        let light_source = Matrix4x4::translation([100.0, 100.0, 100.0]);
+        let lights = vec![
+            Light::point([100.0, 100.0, 100.0], 1000.0),
+            // A lamp by the door
+            Light::point([0.0, 2.0, -3.0], 8.0).with_color([1.0, 0.6, 0.3]),
+        ];
 
         Self {
             scale_cube: 1.0,
             camera,
-            light_source,
-            light_rad: 40000.0,
+            lights,
             ..Default::default()
         }
     }
@@ -67,12 +66,6 @@ impl World {
             };
         }
 
-        // Update Skysphere
-        if let Some(entity) = self.sky_entity.as_mut() {
-            let position = self.camera.get_skysphere();
-            entity.position.set_matrix(position);
-        }
-
         //self.light_source *= Matrix4x4::rotation_y(1.0 * delta_t);
         self.time += delta_t;
     }
@@ -84,19 +77,14 @@ impl World {
         let view = self.camera.get_view();
         let proj = self.camera.get_proj(self.screen.aspect_ratio());
 
-        let light_dir = self.light_source.get_direction_z().to_4d(0.0);
         let camera_pos = self.camera.get_location();
-        let light_pos = self.light_source.get_translation().to_4d(1.0);
 
         Environment {
             view,
             proj,
-            light_dir,
             camera_pos,
-            light_pos,
 
             time: self.time,
-            light_rad: self.light_rad,
         }
     }
 
@@ -104,25 +92,33 @@ impl World {
         self.entities.push(entity);
     }
 
-    pub fn meshes_and_materials<'a>(
+    /// Queues every entity in view to be drawn, sorted from the camera.
+    pub fn queue<'a>(
         &'a mut self,
         render: &Render,
-    ) -> impl Iterator<Item = (&'a mut Arc<Mesh>, &'a mut [Material])> {
-        let vec: Vec<_> = self
-            .entities
-            .iter_mut()
-            .chain(self.sky_entity.as_mut())
-            .map(|entity| entity.get_mesh_and_materials(render))
-            .collect();
-        vec.into_iter()
-    }
-
-    pub fn set_environment_data(&mut self, render: &Render, data: &mut Environment) {
+        environment: &mut Environment,
+    ) -> RenderQueue<'a> {
+        let view_proj = environment.view.clone() * environment.proj.clone();
+        let frustum = Frustum::from_view_proj(&view_proj);
+        let camera_pos = self.camera.get_location().to_3d_unchecked();
+        let lights = LightList::new(&self.lights, camera_pos, Some(&frustum));
+        let mut light_constants = lights.constants();
+        let mut queue = RenderQueue::new(camera_pos)
+            .with_frustum(frustum)
+            .with_lights(lights);
         for entity in self.entities.iter_mut().chain(self.sky_entity.as_mut()) {
+            if entity.cull(&mut queue) {
+                continue;
+            }
             for material in &mut entity.materials {
-                material.set_data(render, 0, data).unwrap();
+                material.set_constants(render, environment).unwrap();
+                material
+                    .set_constants(render, &mut light_constants)
+                    .unwrap();
             }
+            entity.queue(render, &mut queue);
         }
+        queue
     }
 
     pub fn add_sky_entity(&mut self, sky_entity: Entity) {
@@ -155,10 +151,14 @@ impl Listener for World {
                 self.camera.moving_rightward(SPEED);
             }
             b'O' => {
-                self.light_rad -= 5.0 * self.delta_t.get();
+                if let Some(light) = self.lights.first_mut() {
+                    light.range -= 5.0 * self.delta_t.get();
+                }
             }
             b'P' => {
-                self.light_rad += 5.0 * self.delta_t.get();
+                if let Some(light) = self.lights.first_mut() {
+                    light.range += 5.0 * self.delta_t.get();
+                }
             }
             _ => {}
         }
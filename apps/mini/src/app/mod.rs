@@ -48,10 +48,10 @@ impl Application for AppWindow {
         let device = &mut graphics.render.device_mut();
         let swapchain = device.new_swapchain(&hwnd).unwrap();
 
-        let monitor_mesh = graphics.get_mesh_from_file("assets\\Meshes\\monitor.obj")?;
+        let monitor_mesh = graphics.get_mesh_from_file("assets/Meshes/monitor.obj")?;
 
         let mut monitor_mat = graphics.new_material::<DirectionalLight>()?;
-        monitor_mat.add_texture(graphics.get_texture_from_file("assets\\Textures\\brick_d.jpg")?);
+        monitor_mat.add_texture(graphics.get_texture_from_file("assets/Textures/brick_d.jpg")?);
         let mut screen_mat = graphics.new_material::<DirectionalLight>()?;
         screen_mat.add_texture(minigame.render_target.clone());
 
@@ -61,11 +61,13 @@ impl Application for AppWindow {
             Position::new(Matrix4x4::rotation_y(std::f32::consts::PI)),
         ));
 
-        let mut sky_material = graphics.new_material::<Skybox>()?.with_frontface_culling();
-        sky_material
-            .add_texture(graphics.get_texture_from_file("assets\\Textures\\stars_map.jpg")?);
+        let mut sky_material = graphics
+            .new_material::<Skybox>()?
+            .with_frontface_culling()
+            .with_background_depth();
+        sky_material.add_texture(graphics.get_cube_map_from_file("assets/Textures/stars_map.jpg")?);
 
-        let sky_mesh = graphics.get_mesh_from_file("assets\\Meshes\\sphere.obj")?;
+        let sky_mesh = graphics.get_mesh_from_file("assets/Meshes/sphere.obj")?;
 
         world.add_sky_entity(Entity::new(
             sky_mesh,
@@ -1,7 +1,8 @@
 use std::sync::Arc;
 
-use engine::components::{Camera, Entity, PlayState, Screen};
+use engine::components::{Camera, Entity, Light, PlayState, Screen};
 use engine::graphics::color;
+use engine::graphics::light_list::LightList;
 use engine::graphics::material::Material;
 use engine::graphics::render::Render;
 use engine::graphics::resource::mesh::Mesh;
@@ -23,14 +24,12 @@ pub struct World {
     delta_t: DeltaT,
     pub scale_cube: f32,
     pub camera: Camera,
-    pub light_source: Matrix4x4,
+    pub lights: Vec<Light>,
 
     time: f32,
 
     entities: Vec<Entity>,
     sky_entity: Option<Entity>,
-
-    light_rad: f32,
 }
 
 impl World {
@@ -39,13 +38,13 @@ impl World {
         camera.move_forward(-4.0);
         //camera.move_up(1.0);
         let light_source = Matrix4x4::rotation_y(std::f32::consts::PI);
+        let lights = vec![Light::directional(-light_source.get_direction_z())];
         //let light_source = Matrix4x4::translation([100.0, 100.0, 100.0]);
 
         Self {
             scale_cube: 1.0,
             camera,
-            light_source,
-            light_rad: 40000.0,
+            lights,
             ..Default::default()
         }
     }
@@ -66,12 +65,6 @@ impl World {
             };
         }
 
-        // Update Skysphere
-        if let Some(entity) = self.sky_entity.as_mut() {
-            let position = self.camera.get_skysphere();
-            entity.position.set_matrix(position);
-        }
-
         //self.light_source *= Matrix4x4::rotation_y(1.0 * delta_t);
         self.time += delta_t;
     }
@@ -80,19 +73,14 @@ impl World {
         let view = self.camera.get_view();
         let proj = self.camera.get_proj(self.screen.aspect_ratio());
 
-        let light_dir = self.light_source.get_direction_z().to_4d(0.0);
         let camera_pos = self.camera.get_location();
-        let light_pos = self.light_source.get_translation().to_4d(1.0);
 
         Environment {
             view,
             proj,
-            light_dir,
             camera_pos,
-            light_pos,
 
             time: self.time,
-            light_rad: self.light_rad,
         }
     }
 
@@ -113,10 +101,18 @@ impl World {
         vec.into_iter()
     }
 
+    /// Sets the environment and the lights on every entity, and picks the lights each
+    /// entity is lit by.
     pub fn set_environment_data(&mut self, render: &Render, data: &mut Environment) {
+        let lights = LightList::new(&self.lights, data.camera_pos.to_3d_unchecked(), None);
+        let mut light_constants = lights.constants();
         for entity in self.entities.iter_mut().chain(self.sky_entity.as_mut()) {
+            entity.lights = lights.select(&entity.world_bounds().sphere);
             for material in &mut entity.materials {
-                material.set_data(render, 0, data).unwrap();
+                material.set_constants(render, data).unwrap();
+                material
+                    .set_constants(render, &mut light_constants)
+                    .unwrap();
             }
         }
     }
@@ -40,10 +40,9 @@ impl MiniGame {
 
         let material = graphics.new_material::<DirectionalLight>()?;
 
-        let spaceship = graphics.get_mesh_from_file("assets\\Meshes\\spaceship.obj")?;
+        let spaceship = graphics.get_mesh_from_file("assets/Meshes/spaceship.obj")?;
         let mut spaceship_mat = material.clone();
-        spaceship_mat
-            .add_texture(graphics.get_texture_from_file("assets\\Textures\\spaceship.jpg")?);
+        spaceship_mat.add_texture(graphics.get_texture_from_file("assets/Textures/spaceship.jpg")?);
 
         world.add_entity(
             "ship".into(),
@@ -54,11 +53,13 @@ impl MiniGame {
             ),
         );
 
-        let mut sky_material = graphics.new_material::<Skybox>()?.with_frontface_culling();
-        sky_material
-            .add_texture(graphics.get_texture_from_file("assets\\Textures\\stars_map.jpg")?);
+        let mut sky_material = graphics
+            .new_material::<Skybox>()?
+            .with_frontface_culling()
+            .with_background_depth();
+        sky_material.add_texture(graphics.get_cube_map_from_file("assets/Textures/stars_map.jpg")?);
 
-        let sky_mesh = graphics.get_mesh_from_file("assets\\Meshes\\sphere.obj")?;
+        let sky_mesh = graphics.get_mesh_from_file("assets/Meshes/sphere.obj")?;
 
         world.add_sky_entity(Entity::new(
             sky_mesh,
@@ -68,9 +69,9 @@ impl MiniGame {
 
         let mut asteroids_pos = Vec::new();
 
-        let asteroid = graphics.get_mesh_from_file("assets\\Meshes\\asteroid.obj")?;
+        let asteroid = graphics.get_mesh_from_file("assets/Meshes/asteroid.obj")?;
         let mut asteroid_mat = material;
-        asteroid_mat.add_texture(graphics.get_texture_from_file("assets\\Textures\\asteroid.jpg")?);
+        asteroid_mat.add_texture(graphics.get_texture_from_file("assets/Textures/asteroid.jpg")?);
 
         let mut rng = rand::thread_rng();
         let loc_range = Uniform::new(-2000.0, 2000.0);
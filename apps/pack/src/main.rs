@@ -0,0 +1,66 @@
+#![allow(clippy::uninlined_format_args)]
+
+//! Builds pack archives for the virtual file system.
+//!
+//! `pack <output.pack> <dir>...` packs each directory under its own name,
+//! so `pack data.pack assets shaders` holds `assets/..` and `shaders/..`.
+//! `pack --list <file.pack>` prints the files in a pack.
+
+use engine::vfs::{PackMount, PackWriter};
+
+use std::env;
+use std::io;
+use std::path::Path;
+use std::process;
+
+use log::info;
+
+const USAGE: &str = "Usage: pack <output.pack> <dir>...\n       pack --list <file.pack>";
+
+fn main() {
+    env_logger::init();
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.as_slice() {
+        [flag, pack] if flag == "--list" => list(pack),
+        [output, dirs @ ..] if !dirs.is_empty() && !output.starts_with('-') => build(output, dirs),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(e) = result {
+        eprintln!("pack: {}", e);
+        process::exit(1);
+    }
+}
+
+fn build(output: &str, dirs: &[String]) -> io::Result<()> {
+    let mut writer = PackWriter::new();
+    for dir in dirs {
+        let dir = Path::new(dir);
+        let prefix = dir
+            .file_name()
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} has no name to pack it under", dir.display()),
+                )
+            })?
+            .to_string_lossy();
+        let added = writer.add_dir(dir, &prefix)?;
+        info!("Added {} files from {}", added, dir.display());
+    }
+
+    writer.save(output)?;
+    println!("Packed {} files into {}", writer.len(), output);
+    Ok(())
+}
+
+fn list(pack: &str) -> io::Result<()> {
+    for file in PackMount::open(pack)?.files() {
+        println!("{}", file);
+    }
+    Ok(())
+}
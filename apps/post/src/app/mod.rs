@@ -1,20 +1,23 @@
 mod world;
 
 use rand::{distributions::uniform::Uniform, prelude::*};
-use shader::{DirectionalLight, Skybox};
+use shader::{DirectionalLight, Skybox, ToneMap, ToneMapSettings};
 use world::World;
 
 use engine::components::Entity;
 use engine::error::Result;
 use engine::graphics::color;
-use engine::graphics::render::{SwapChain, WindowState};
+use engine::graphics::material::Material;
+use engine::graphics::render::rendered_texture::{ColorFormat, Flavor};
+use engine::graphics::render::{Device, RenderedTexture, SamplerDesc, SwapChain, WindowState};
 use engine::graphics::GRAPHICS;
 use engine::input::INPUT;
-use engine::math::{Matrix4x4, Point, Vector3d};
+use engine::math::{Matrix4x4, Point, Rect, Vector3d};
 use engine::physics::Position;
 use engine::window::{Application, Hwnd, Window};
+use log::info;
 
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 
 pub static WINDOW: Window<AppWindow> = Window::new();
 
@@ -24,6 +27,11 @@ pub struct AppWindow {
     hwnd: Hwnd,
     swapchain: SwapChain,
     window_state: WindowState,
+    /// The scene is lit into these, then tone mapped onto the swapchain.
+    hdr_target: Arc<RenderedTexture>,
+    hdr_depth: RenderedTexture,
+    tone_map: Material,
+    tone_map_settings: ToneMapSettings,
     #[listener]
     variables: World,
 
@@ -52,10 +60,9 @@ impl Application for AppWindow {
 
         let material = graphics.new_material::<DirectionalLight>()?;
 
-        let spaceship = graphics.get_mesh_from_file("assets\\Meshes\\spaceship.obj")?;
+        let spaceship = graphics.get_mesh_from_file("assets/Meshes/spaceship.obj")?;
         let mut spaceship_mat = material.clone();
-        spaceship_mat
-            .add_texture(graphics.get_texture_from_file("assets\\Textures\\spaceship.jpg")?);
+        spaceship_mat.add_texture(graphics.get_texture_from_file("assets/Textures/spaceship.jpg")?);
 
         world.add_entity(
             "ship".into(),
@@ -66,11 +73,13 @@ impl Application for AppWindow {
             ),
         );
 
-        let mut sky_material = graphics.new_material::<Skybox>()?.with_frontface_culling();
-        sky_material
-            .add_texture(graphics.get_texture_from_file("assets\\Textures\\stars_map.jpg")?);
+        let mut sky_material = graphics
+            .new_material::<Skybox>()?
+            .with_frontface_culling()
+            .with_background_depth();
+        sky_material.add_texture(graphics.get_cube_map_from_file("assets/Textures/stars_map.jpg")?);
 
-        let sky_mesh = graphics.get_mesh_from_file("assets\\Meshes\\sphere.obj")?;
+        let sky_mesh = graphics.get_mesh_from_file("assets/Meshes/sphere.obj")?;
 
         world.add_sky_entity(Entity::new(
             sky_mesh,
@@ -80,9 +89,9 @@ impl Application for AppWindow {
 
         let mut asteroids_pos = Vec::new();
 
-        let asteroid = graphics.get_mesh_from_file("assets\\Meshes\\asteroid.obj")?;
+        let asteroid = graphics.get_mesh_from_file("assets/Meshes/asteroid.obj")?;
         let mut asteroid_mat = material;
-        asteroid_mat.add_texture(graphics.get_texture_from_file("assets\\Textures\\asteroid.jpg")?);
+        asteroid_mat.add_texture(graphics.get_texture_from_file("assets/Textures/asteroid.jpg")?);
 
         let mut rng = rand::thread_rng();
         let loc_range = Uniform::new(-2000.0, 2000.0);
@@ -113,10 +122,21 @@ impl Application for AppWindow {
             asteroids_pos.push((loc, rot, scale));
         }
 
+        let (hdr_target, hdr_depth) = new_hdr_targets(graphics.render.device(), &hwnd.rect())?;
+        let mut tone_map = graphics.new_material::<ToneMap>()?;
+        tone_map.add_texture_with_sampler(
+            hdr_target.clone(),
+            graphics.get_sampler(&SamplerDesc::clamp())?,
+        );
+
         let mut app_window = Self {
             hwnd,
             swapchain,
             window_state: WindowState::default(),
+            hdr_target,
+            hdr_depth,
+            tone_map,
+            tone_map_settings: ToneMapSettings::default(),
             variables: world,
             _asteroids_pos: asteroids_pos,
         };
@@ -132,8 +152,9 @@ impl Application for AppWindow {
     fn on_update(&mut self) {
         let mut g = GRAPHICS.lock().unwrap();
         let context = g.render.immediate_context();
-        context.clear_render_target_color(&mut self.swapchain, color::NICE_BLUE);
-        context.set_render_target(&mut self.swapchain);
+        let mut hdr = (self.hdr_target.as_ref(), &self.hdr_depth);
+        context.clear_render_target_color(&mut hdr, color::NICE_BLUE);
+        context.set_render_target(&mut hdr);
         let (width, height) = self.hwnd.rect().dims();
         context.set_viewport_size(width as f32, height as f32);
 
@@ -146,6 +167,14 @@ impl Application for AppWindow {
             g.render.draw_mesh_and_materials(mesh, materials);
         }
 
+        let context = g.render.immediate_context();
+        context.clear_render_target_color(&mut self.swapchain, color::NICE_BLUE);
+        context.set_render_target(&mut self.swapchain);
+        self.tone_map
+            .set_constants(&g.render, &mut self.tone_map_settings)
+            .unwrap();
+        g.render.draw_fullscreen(&mut self.tone_map);
+
         self.swapchain.present(0);
     }
 
@@ -168,6 +197,12 @@ impl Application for AppWindow {
         }
         let graphics = GRAPHICS.lock().unwrap();
         self.swapchain.resize(graphics.render.device()).unwrap();
+
+        let (hdr_target, hdr_depth) =
+            new_hdr_targets(graphics.render.device(), &self.hwnd.rect()).unwrap();
+        self.tone_map.textures[0] = Some(hdr_target.clone());
+        self.hdr_target = hdr_target;
+        self.hdr_depth = hdr_depth;
     }
 
     fn on_move(&mut self) {
@@ -182,6 +217,13 @@ impl AppWindow {
     fn on_key_up(&mut self, key: usize) {
         let key = key as u8;
         match key {
+            b'T' => {
+                let settings = &mut self.tone_map_settings;
+                settings.operator = settings.operator.next();
+                info!("Tone mapping with {:?}", settings.operator);
+            }
+            b'E' => self.tone_map_settings.exposure *= 1.25,
+            b'Q' => self.tone_map_settings.exposure /= 1.25,
             b'F' => {
                 self.window_state.toggle();
                 let state = self.window_state;
@@ -194,3 +236,17 @@ impl AppWindow {
         }
     }
 }
+
+fn new_hdr_targets(
+    device: &Device,
+    rect: &Rect<i32>,
+) -> Result<(Arc<RenderedTexture>, RenderedTexture)> {
+    let target = RenderedTexture::with_format(
+        rect.into(),
+        Flavor::RenderTarget,
+        ColorFormat::Rgba16Float,
+        device,
+    )?;
+    let depth = RenderedTexture::new(rect.into(), Flavor::DepthStencil, device)?;
+    Ok((Arc::new(target), depth))
+}
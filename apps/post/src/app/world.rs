@@ -2,7 +2,8 @@ use std::borrow::Cow;
 use std::collections::HashMap;
 use std::sync::Arc;
 
-use engine::components::{Camera0, Entity, PlayState, Screen, SpaceShip};
+use engine::components::{Camera0, Entity, Light, PlayState, Screen, SpaceShip};
+use engine::graphics::light_list::LightList;
 use engine::graphics::material::Material;
 use engine::graphics::render::Render;
 use engine::graphics::resource::mesh::Mesh;
@@ -23,7 +24,7 @@ pub struct World {
     //pub camera: ThirdPersonCamera,
     pub camera: Camera0,
     pub spaceship: SpaceShip,
-    pub light_source: Matrix4x4,
+    pub lights: Vec<Light>,
 
     delta_mouse_x: f32,
     delta_mouse_y: f32,
@@ -31,7 +32,6 @@ pub struct World {
     time: f32,
 
     entities: HashMap<Cow<'static, str>, Entity>,
-    light_rad: f32,
 }
 
 impl World {
@@ -42,12 +42,12 @@ impl World {
         let mut light_source = Matrix4x4::identity();
         light_source *= Matrix4x4::rotation_x(-0.707);
         light_source *= Matrix4x4::rotation_y(0.707);
+        let lights = vec![Light::directional(-light_source.get_direction_z())];
 
         Self {
             camera,
             spaceship,
-            light_source,
-            light_rad: 40000.0,
+            lights,
             ..Default::default()
         }
     }
@@ -84,12 +84,6 @@ impl World {
 
         self.camera.update(delta_t);
 
-        // Update Skysphere
-        if let Some(entity) = self.entities.get_mut("skybox") {
-            let position = self.camera.get_skysphere();
-            entity.position.set_matrix(position);
-        }
-
         //self.light_source *= Matrix4x4::rotation_y(1.0 * delta_t);
         self.time += delta_t;
     }
@@ -98,19 +92,14 @@ impl World {
         let view = self.camera.view_cam();
         let proj = self.camera.proj_cam(Rect::<f32>::from(&self.screen.rect));
 
-        let light_dir = self.light_source.get_direction_z().to_4d(0.0);
         let camera_pos = self.camera.get_cam_pos().to_4d(1.0);
-        let light_pos = self.light_source.get_translation().to_4d(1.0);
 
         Environment {
             view,
             proj,
-            light_dir,
             camera_pos,
-            light_pos,
 
             time: self.time,
-            light_rad: self.light_rad,
         }
     }
 
@@ -134,10 +123,18 @@ impl World {
         vec.into_iter()
     }
 
+    /// Sets the environment and the lights on every entity, and picks the lights each
+    /// entity is lit by.
     pub fn set_environment_data(&mut self, render: &Render, data: &mut Environment) {
+        let lights = LightList::new(&self.lights, data.camera_pos.to_3d_unchecked(), None);
+        let mut light_constants = lights.constants();
         for entity in self.entities.values_mut() {
+            entity.lights = lights.select(&entity.world_bounds().sphere);
             for material in &mut entity.materials {
-                material.set_data(render, 0, data).unwrap();
+                material.set_constants(render, data).unwrap();
+                material
+                    .set_constants(render, &mut light_constants)
+                    .unwrap();
             }
         }
     }
@@ -178,10 +175,14 @@ impl Listener for World {
                 // self.camera.rightward = SPEED;
             }
             b'O' => {
-                self.light_rad -= 5.0 * self.delta_t.get();
+                if let Some(light) = self.lights.first_mut() {
+                    light.range -= 5.0 * self.delta_t.get();
+                }
             }
             b'P' => {
-                self.light_rad += 5.0 * self.delta_t.get();
+                if let Some(light) = self.lights.first_mut() {
+                    light.range += 5.0 * self.delta_t.get();
+                }
             }
             input::key::SHIFT => {
                 self.spaceship.speed = SpaceShip::DEFAULT_SPEED * 5.0;
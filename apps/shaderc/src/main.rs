@@ -0,0 +1,143 @@
+#![allow(clippy::uninlined_format_args)]
+
+//! Compiles the shaders of every template ahead of time.
+//!
+//! `shaderc` compiles each variant of every template in the shader library into
+//! the shader cache, so shipped builds load bytecode instead of compiling.
+//! `shaderc --cache <dir>` writes the cache somewhere else.
+//! `shaderc --spirv <dir>` also translates every variant to SPIR-V with `dxc`,
+//! which must be on the path.
+
+use engine::error::{self, Custom};
+use engine::graphics::material::{self, keyword_combinations, Template};
+use engine::graphics::resource::shader::{preprocess_file, Pixel, ShaderType, Vertex};
+use engine::graphics::SHADER_CACHE_DIR;
+use engine::vfs::VFS;
+
+use shader::{DirLightBumpMap, DirectionalLight, PointLight, Skybox, ToneMap};
+
+use std::any::type_name;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+use log::info;
+
+const USAGE: &str = "Usage: shaderc [--cache <dir>] [--spirv <dir>]";
+
+struct Options {
+    cache_dir: PathBuf,
+    spirv_dir: Option<PathBuf>,
+}
+
+type Compile = fn(&Options) -> error::Result<usize>;
+
+const TEMPLATES: [Compile; 5] = [
+    compile::<DirectionalLight>,
+    compile::<DirLightBumpMap>,
+    compile::<PointLight>,
+    compile::<Skybox>,
+    compile::<ToneMap>,
+];
+
+fn main() {
+    env_logger::init();
+
+    let options = match parse_args(env::args().skip(1)) {
+        Some(options) => options,
+        None => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    let mut compiled = 0;
+    for template in TEMPLATES {
+        match template(&options) {
+            Ok(count) => compiled += count,
+            Err(e) => {
+                eprintln!("shaderc: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+    println!(
+        "Compiled {} shaders into {}",
+        compiled,
+        options.cache_dir.display()
+    );
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Option<Options> {
+    let mut options = Options {
+        cache_dir: VFS.read().unwrap().root().join(SHADER_CACHE_DIR),
+        spirv_dir: None,
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--cache" => options.cache_dir = args.next()?.into(),
+            "--spirv" => options.spirv_dir = Some(args.next()?.into()),
+            _ => return None,
+        }
+    }
+    Some(options)
+}
+
+fn compile<T: Template>(options: &Options) -> error::Result<usize> {
+    let compiled = material::precompile::<T>(&options.cache_dir)?;
+    info!("Compiled {} shaders of {}", compiled, type_name::<T>());
+
+    if let Some(spirv_dir) = &options.spirv_dir {
+        fs::create_dir_all(spirv_dir)?;
+        for keywords in keyword_combinations(T::KEYWORDS) {
+            translate::<Vertex>(T::VERTEX_SHADER_PATH, &keywords, spirv_dir)?;
+            translate::<Pixel>(T::PIXEL_SHADER_PATH, &keywords, spirv_dir)?;
+        }
+    }
+    Ok(compiled)
+}
+
+/// Translates a variant to SPIR-V, named after its path and keywords.
+fn translate<S: ShaderType>(path: &str, keywords: &[&str], dir: &Path) -> error::Result<()> {
+    let preprocessed = preprocess_file(path, keywords)?;
+    let mut name = path.trim_end_matches(".hlsl").replace('/', "_");
+    for keyword in keywords {
+        name.push('.');
+        name.push_str(keyword);
+    }
+
+    let source = env::temp_dir().join(format!("{}.hlsl", name));
+    fs::write(&source, &preprocessed.source)?;
+    let output = dir.join(format!("{}.spv", name));
+
+    // DXC only targets shader model 6 and up.
+    let profile = S::TARGET.replace("_5_0", "_6_0");
+    let mut command = Command::new("dxc");
+    command
+        .args([
+            "-spirv",
+            "-fvk-use-dx-layout",
+            "-T",
+            &profile,
+            "-E",
+            S::ENTRY_POINT,
+        ])
+        .arg("-Fo")
+        .arg(&output)
+        .arg(&source);
+    for keyword in keywords {
+        command.arg("-D").arg(format!("{}=1", keyword));
+    }
+    let status = command.status();
+    let _ = fs::remove_file(&source);
+
+    match status {
+        Ok(status) if status.success() => {
+            info!("Translated {} to {}", path, output.display());
+            Ok(())
+        }
+        Ok(_) => Err(Custom(format!("dxc could not translate {}", path))),
+        Err(e) => Err(Custom(format!("Could not run dxc: {}", e))),
+    }
+}
@@ -4,16 +4,19 @@ use rand::{distributions::uniform::Uniform, prelude::*};
 use shader::{DirectionalLight, Skybox};
 use world::World;
 
-use engine::components::Entity;
+use engine::components::{Entity, InstancedEntity};
 use engine::error::Result;
 use engine::graphics::color;
-use engine::graphics::render::{SwapChain, WindowState};
+use engine::graphics::render::{RenderStats, SwapChain, WindowState};
+use engine::graphics::resource::mesh::{LodTarget, Mesh};
+use engine::graphics::resource::{LoadHandle, LoadProgress};
 use engine::graphics::GRAPHICS;
 use engine::input::INPUT;
 use engine::math::{Matrix4x4, Point, Vector3d};
 use engine::physics::Position;
 use engine::window::{Application, Hwnd, Window};
 
+use log::info;
 use std::sync::Mutex;
 
 pub static WINDOW: Window<AppWindow> = Window::new();
@@ -28,6 +31,10 @@ pub struct AppWindow {
     variables: World,
 
     _asteroids_pos: Vec<(Vector3d, Vector3d, Vector3d)>,
+    /// LODs are generated once the asteroid has loaded.
+    asteroid: Option<LoadHandle<Mesh>>,
+    loading: LoadProgress,
+    stats: RenderStats,
 }
 
 impl Application for AppWindow {
@@ -52,10 +59,15 @@ impl Application for AppWindow {
 
         let material = graphics.new_material::<DirectionalLight>()?;
 
-        let spaceship = graphics.get_mesh_from_file("assets\\Meshes\\spaceship.obj")?;
+        let spaceship = graphics
+            .load_mesh_async("assets/Meshes/spaceship.obj")?
+            .resource();
         let mut spaceship_mat = material.clone();
-        spaceship_mat
-            .add_texture(graphics.get_texture_from_file("assets\\Textures\\spaceship.jpg")?);
+        spaceship_mat.add_texture(
+            graphics
+                .load_texture_async("assets/Textures/spaceship.jpg")?
+                .resource(),
+        );
 
         world.add_entity(
             "ship".into(),
@@ -66,11 +78,19 @@ impl Application for AppWindow {
             ),
         );
 
-        let mut sky_material = graphics.new_material::<Skybox>()?.with_frontface_culling();
-        sky_material
-            .add_texture(graphics.get_texture_from_file("assets\\Textures\\stars_map.jpg")?);
+        let mut sky_material = graphics
+            .new_material::<Skybox>()?
+            .with_frontface_culling()
+            .with_background_depth();
+        sky_material.add_texture(
+            graphics
+                .load_cube_map_async("assets/Textures/stars_map.jpg")?
+                .resource(),
+        );
 
-        let sky_mesh = graphics.get_mesh_from_file("assets\\Meshes\\sphere.obj")?;
+        let sky_mesh = graphics
+            .load_mesh_async("assets/Meshes/sphere.obj")?
+            .resource();
 
         world.add_sky_entity(Entity::new(
             sky_mesh,
@@ -80,15 +100,20 @@ impl Application for AppWindow {
 
         let mut asteroids_pos = Vec::new();
 
-        let asteroid = graphics.get_mesh_from_file("assets\\Meshes\\asteroid.obj")?;
+        let asteroid_handle = graphics.load_mesh_async("assets/Meshes/asteroid.obj")?;
         let mut asteroid_mat = material;
-        asteroid_mat.add_texture(graphics.get_texture_from_file("assets\\Textures\\asteroid.jpg")?);
+        asteroid_mat.add_texture(
+            graphics
+                .load_texture_async("assets/Textures/asteroid.jpg")?
+                .resource(),
+        );
+        let mut asteroids = InstancedEntity::new(asteroid_handle.resource(), vec![asteroid_mat])?;
 
         let mut rng = rand::thread_rng();
         let loc_range = Uniform::new(-2000.0, 2000.0);
         let rot_range = Uniform::new(0.0, std::f32::consts::TAU);
         let scale_range = Uniform::new(6.0, 30.0);
-        for i in 0..200 {
+        for _ in 0..200 {
             let loc = Vector3d::new(
                 rng.sample(loc_range),
                 rng.sample(loc_range),
@@ -105,13 +130,11 @@ impl Application for AppWindow {
             let mut pos = Position::default();
             pos.set_postition(scale, rot, loc);
 
-            world.add_entity(
-                format!("asteroid_{}", i).into(),
-                Entity::new(asteroid.clone(), vec![asteroid_mat.clone()], pos),
-            );
+            asteroids.add_instance(pos.get_matrix());
 
             asteroids_pos.push((loc, rot, scale));
         }
+        world.set_asteroids(asteroids);
 
         let mut app_window = Self {
             hwnd,
@@ -119,6 +142,9 @@ impl Application for AppWindow {
             window_state: WindowState::default(),
             variables: world,
             _asteroids_pos: asteroids_pos,
+            asteroid: Some(asteroid_handle),
+            loading: LoadProgress::default(),
+            stats: RenderStats::default(),
         };
 
         app_window.variables.set_screen_size(app_window.hwnd.rect());
@@ -131,6 +157,24 @@ impl Application for AppWindow {
 
     fn on_update(&mut self) {
         let mut g = GRAPHICS.lock().unwrap();
+        let progress = g.loading_progress();
+        if progress != self.loading {
+            info!(
+                "Loaded {} of {} resources",
+                progress.requested - progress.pending(),
+                progress.requested
+            );
+            self.loading = progress;
+        }
+        if let Some(asteroid) = self.asteroid.as_ref().and_then(LoadHandle::get) {
+            let triangles = asteroid.inner().triangle_count(0);
+            if let Err(e) =
+                asteroid.generate_lods(g.render.device(), &LodTarget::halving(triangles, 3))
+            {
+                info!("Could not generate asteroid LODs: {}", e);
+            }
+            self.asteroid = None;
+        }
         let context = g.render.immediate_context();
         context.clear_render_target_color(&mut self.swapchain, color::NICE_BLUE);
         context.set_render_target(&mut self.swapchain);
@@ -139,11 +183,12 @@ impl Application for AppWindow {
 
         self.variables.update();
         let mut environment = self.variables.environment();
-        self.variables
-            .set_environment_data(&g.render, &mut environment);
-
-        for (mesh, materials) in self.variables.meshes_and_materials(&g.render) {
-            g.render.draw_mesh_and_materials(mesh, materials);
+        let queue = self.variables.queue(&g.render, &mut environment);
+        g.render.draw_queue(queue);
+        let stats = g.render.take_stats();
+        if stats != self.stats {
+            info!("Drew {}", stats);
+            self.stats = stats;
         }
 
         self.swapchain.present(0);
@@ -1,14 +1,13 @@
 use std::borrow::Cow;
 use std::collections::HashMap;
-use std::sync::Arc;
 
-use engine::components::{Camera0, Entity, PlayState, Screen, SpaceShip};
-use engine::graphics::material::Material;
-use engine::graphics::render::Render;
-use engine::graphics::resource::mesh::Mesh;
+use engine::components::{Camera0, Entity, InstancedEntity, Light, PlayState, Screen, SpaceShip};
+use engine::graphics::light_list::LightList;
+use engine::graphics::render::{Render, RenderQueue};
 use engine::input::{self, Listener};
 use engine::math::{Matrix4x4, Point, Rect};
 //use engine::physics::collision3::{CollisionEngine, GjkEngine, Sphere};
+use engine::physics::Frustum;
 use engine::time::DeltaT;
 
 use shader::Environment;
@@ -23,7 +22,7 @@ pub struct World {
     //pub camera: ThirdPersonCamera,
     pub camera: Camera0,
     pub spaceship: SpaceShip,
-    pub light_source: Matrix4x4,
+    pub lights: Vec<Light>,
 
     delta_mouse_x: f32,
     delta_mouse_y: f32,
@@ -31,7 +30,8 @@ pub struct World {
     time: f32,
 
     entities: HashMap<Cow<'static, str>, Entity>,
-    light_rad: f32,
+    /// Drawn with instanced draw calls, rather than an entity each.
+    asteroids: Option<InstancedEntity>,
 }
 
 impl World {
@@ -42,12 +42,12 @@ impl World {
         let mut light_source = Matrix4x4::identity();
         light_source *= Matrix4x4::rotation_x(-0.707);
         light_source *= Matrix4x4::rotation_y(0.707);
+        let lights = vec![Light::directional(-light_source.get_direction_z())];
 
         Self {
             camera,
             spaceship,
-            light_source,
-            light_rad: 40000.0,
+            lights,
             ..Default::default()
         }
     }
@@ -84,10 +84,13 @@ impl World {
 
         self.camera.update(delta_t);
 
-        // Update Skysphere
-        if let Some(entity) = self.entities.get_mut("skybox") {
-            let position = self.camera.get_skysphere();
-            entity.position.set_matrix(position);
+        let camera_pos = self.camera.get_cam_pos();
+        let proj = self.camera.proj_cam(Rect::<f32>::from(&self.screen.rect));
+        for entity in self.entities.values_mut() {
+            entity.select_lod(camera_pos, &proj);
+        }
+        if let Some(asteroids) = &mut self.asteroids {
+            asteroids.select_lod(camera_pos, &proj);
         }
 
         //self.light_source *= Matrix4x4::rotation_y(1.0 * delta_t);
@@ -98,19 +101,14 @@ impl World {
         let view = self.camera.view_cam();
         let proj = self.camera.proj_cam(Rect::<f32>::from(&self.screen.rect));
 
-        let light_dir = self.light_source.get_direction_z().to_4d(0.0);
         let camera_pos = self.camera.get_cam_pos().to_4d(1.0);
-        let light_pos = self.light_source.get_translation().to_4d(1.0);
 
         Environment {
             view,
             proj,
-            light_dir,
             camera_pos,
-            light_pos,
 
             time: self.time,
-            light_rad: self.light_rad,
         }
     }
 
@@ -122,30 +120,52 @@ impl World {
         self.entities.insert(name, entity);
     }
 
-    pub fn meshes_and_materials<'a>(
+    /// Queues every entity in view to be drawn, sorted from the camera.
+    pub fn queue<'a>(
         &'a mut self,
         render: &Render,
-    ) -> impl Iterator<Item = (&'a mut Arc<Mesh>, &'a mut [Material])> {
-        let vec: Vec<_> = self
-            .entities
-            .values_mut()
-            .map(|entity| entity.get_mesh_and_materials(render))
-            .collect();
-        vec.into_iter()
-    }
-
-    pub fn set_environment_data(&mut self, render: &Render, data: &mut Environment) {
+        environment: &mut Environment,
+    ) -> RenderQueue<'a> {
+        let view_proj = environment.view.clone() * environment.proj.clone();
+        let frustum = Frustum::from_view_proj(&view_proj);
+        let camera_pos = self.camera.get_cam_pos();
+        let lights = LightList::new(&self.lights, camera_pos, Some(&frustum));
+        let mut light_constants = lights.constants();
+        let mut queue = RenderQueue::new(camera_pos)
+            .with_frustum(frustum)
+            .with_lights(lights);
         for entity in self.entities.values_mut() {
+            if entity.cull(&mut queue) {
+                continue;
+            }
             for material in &mut entity.materials {
-                material.set_data(render, 0, data).unwrap();
+                material.set_constants(render, environment).unwrap();
+                material
+                    .set_constants(render, &mut light_constants)
+                    .unwrap();
             }
+            entity.queue(render, &mut queue);
         }
+        if let Some(asteroids) = &mut self.asteroids {
+            for material in &mut asteroids.materials {
+                material.set_constants(render, environment).unwrap();
+                material
+                    .set_constants(render, &mut light_constants)
+                    .unwrap();
+            }
+            asteroids.queue(&mut queue);
+        }
+        queue
     }
 
     pub fn add_sky_entity(&mut self, sky_entity: Entity) {
         self.entities.insert("skybox".into(), sky_entity);
     }
 
+    pub fn set_asteroids(&mut self, asteroids: InstancedEntity) {
+        self.asteroids = Some(asteroids);
+    }
+
     pub fn is_playing(&self) -> bool {
         self.play_state.is_playing()
     }
@@ -178,10 +198,14 @@ impl Listener for World {
                 // self.camera.rightward = SPEED;
             }
             b'O' => {
-                self.light_rad -= 5.0 * self.delta_t.get();
+                if let Some(light) = self.lights.first_mut() {
+                    light.range -= 5.0 * self.delta_t.get();
+                }
             }
             b'P' => {
-                self.light_rad += 5.0 * self.delta_t.get();
+                if let Some(light) = self.lights.first_mut() {
+                    light.range += 5.0 * self.delta_t.get();
+                }
             }
             input::key::SHIFT => {
                 self.spaceship.speed = SpaceShip::DEFAULT_SPEED * 5.0;
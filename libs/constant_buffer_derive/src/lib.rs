@@ -0,0 +1,176 @@
+extern crate proc_macro;
+
+use proc_macro2::TokenStream;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+use syn::{
+    parse_macro_input, Attribute, Data, DeriveInput, Error, Fields, Lit, LitStr, Meta, NestedMeta,
+};
+
+/// Implements `Constants` for a `#[repr(C)]` struct, and checks at compile time that
+/// HLSL packs every field at the same offset Rust does.
+///
+/// The `cbuffer` name is the struct name in snake case, unless it is set with
+/// `#[constant_buffer(name = "...")]`. Fields starting with `_` are padding: they
+/// aren't declared in HLSL, and only move the next field to where HLSL puts it.
+///
+/// `[f32; N]` fields are HLSL vectors, so `N` is at most 4. HLSL arrays pad every
+/// element to 16 bytes, so arrays of scalars must be declared as `[Vector<f32, 4>; N]`.
+#[proc_macro_derive(ConstantBuffer, attributes(constant_buffer))]
+pub fn derive_constant_buffer(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    expand(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "ConstantBuffer types can't be generic",
+        ));
+    }
+    if !has_repr_c(&input.attrs)? {
+        return Err(Error::new_spanned(
+            name,
+            "ConstantBuffer types need #[repr(C)] for Rust to keep their fields in order",
+        ));
+    }
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new_spanned(
+                    name,
+                    "ConstantBuffer fields need names to match the cbuffer members",
+                ))
+            }
+        },
+        Data::Enum(_) | Data::Union(_) => {
+            return Err(Error::new_spanned(
+                name,
+                "ConstantBuffer can only be derived for structs",
+            ))
+        }
+    };
+    let cbuffer_name = cbuffer_name(input)?;
+
+    let mut layouts = Vec::new();
+    let mut checks = Vec::new();
+    for field in fields {
+        let ident = field.ident.as_ref().unwrap();
+        let field_name = ident.to_string();
+        if field_name.starts_with('_') {
+            continue;
+        }
+        let ty = &field.ty;
+        let size_message = format!("{}::{} is a different size in HLSL", name, field_name);
+        let offset_message = format!(
+            "HLSL packs {}::{} at a different offset; add a padding field before it",
+            name, field_name
+        );
+
+        checks.push(quote_spanned! {field.span()=>
+            let offset = engine::graphics::resource::shader::hlsl_offset(
+                end,
+                <#ty as engine::graphics::resource::shader::HlslType>::SIZE,
+                <#ty as engine::graphics::resource::shader::HlslType>::REGISTER_ALIGNED,
+            );
+            assert!(
+                std::mem::size_of::<#ty>()
+                    == <#ty as engine::graphics::resource::shader::HlslType>::SIZE,
+                #size_message
+            );
+            assert!(std::mem::offset_of!(#name, #ident) == offset, #offset_message);
+            let end = offset + <#ty as engine::graphics::resource::shader::HlslType>::SIZE;
+        });
+        layouts.push(quote! {
+            engine::graphics::resource::shader::FieldLayout {
+                name: #field_name,
+                offset: std::mem::offset_of!(#name, #ident),
+                size: std::mem::size_of::<#ty>(),
+            }
+        });
+    }
+    let size_message = format!(
+        "{} is a different size from the cbuffer, which is padded to 16 bytes; \
+         add #[repr(C, align(16))]",
+        name
+    );
+
+    Ok(quote! {
+        impl engine::graphics::resource::shader::Constants for #name {
+            const NAME: &'static str = #cbuffer_name;
+
+            fn fields() -> Vec<engine::graphics::resource::shader::FieldLayout> {
+                vec![#(#layouts),*]
+            }
+        }
+
+        const _: () = {
+            let end = 0usize;
+            #(#checks)*
+            assert!(std::mem::size_of::<#name>() == end.next_multiple_of(16), #size_message);
+        };
+    })
+}
+
+fn has_repr_c(attributes: &[Attribute]) -> syn::Result<bool> {
+    for attribute in attributes {
+        if !attribute.path.is_ident("repr") {
+            continue;
+        }
+        if let Meta::List(list) = attribute.parse_meta()? {
+            let repr_c = list.nested.iter().any(
+                |nested| matches!(nested, NestedMeta::Meta(Meta::Path(path)) if path.is_ident("C")),
+            );
+            if repr_c {
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
+
+fn cbuffer_name(input: &DeriveInput) -> syn::Result<LitStr> {
+    for attribute in &input.attrs {
+        if !attribute.path.is_ident("constant_buffer") {
+            continue;
+        }
+        let meta = attribute.parse_meta()?;
+        if let Meta::List(list) = &meta {
+            for nested in &list.nested {
+                if let NestedMeta::Meta(Meta::NameValue(pair)) = nested {
+                    if let (true, Lit::Str(name)) = (pair.path.is_ident("name"), &pair.lit) {
+                        return Ok(name.clone());
+                    }
+                }
+            }
+        }
+        return Err(Error::new_spanned(
+            meta,
+            "Expected #[constant_buffer(name = \"...\")]",
+        ));
+    }
+
+    let name = &input.ident;
+    Ok(LitStr::new(&snake_case(&name.to_string()), name.span()))
+}
+
+fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (idx, c) in name.char_indices() {
+        if c.is_uppercase() {
+            if idx > 0 {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
@@ -25,12 +25,6 @@ impl Camera {
         self.position.set_location(loc);
     }
 
-    pub fn get_skysphere(&self) -> Matrix4x4 {
-        let mut matrix = Matrix4x4::scaling(self.back_plate);
-        matrix.set_translation(self.position.get_location());
-        matrix
-    }
-
     pub fn get_view(&self) -> Matrix4x4 {
         self.position.get_matrix().inverse().unwrap()
     }
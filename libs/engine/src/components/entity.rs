@@ -1,15 +1,29 @@
+use std::path::Path;
 use std::sync::Arc;
 
+use crate::error;
 use crate::graphics::color;
+use crate::graphics::light_list::{LightSelection, FIRST_LIGHTS};
 use crate::graphics::material::Material;
-use crate::graphics::render::Render;
+use crate::graphics::render::{render_layer, Instance, Render, RenderLayer, RenderQueue};
 use crate::graphics::resource::Mesh;
-use crate::math::Vector3d;
-use crate::physics::Position;
+use crate::graphics::Graphics;
+use crate::math::{Matrix4x4, Vector3d};
+use crate::physics::{Bounds, Position};
+use crate::{self as engine};
 
-#[derive(Default, Debug)]
+/// Where an entity is, bound to the `transform` cbuffer.
+#[derive(Default, Debug, ConstantBuffer)]
+#[repr(C, align(16))]
+pub struct Transform {
+    pub world: Matrix4x4,
+}
+
+#[derive(Default, Debug, ConstantBuffer)]
 #[repr(C, align(16))]
 pub struct MeshInfo {
+    /// Indices into the `lights` cbuffer, or `NO_LIGHT`.
+    pub lights: LightSelection,
     pub color: Vector3d,
 }
 
@@ -20,6 +34,10 @@ pub struct Entity {
 
     pub position: Position,
     pub color: Vector3d,
+    /// Lights the entity is lit by. Picked by `queue`.
+    pub lights: LightSelection,
+    /// Level of detail of `mesh` to draw.
+    pub lod: usize,
 }
 
 impl Entity {
@@ -34,28 +52,104 @@ impl Entity {
             materials,
             position,
             color: color::WHITE.into(),
+            lights: FIRST_LIGHTS,
+            lod: 0,
         }
     }
 
+    /// Loads the mesh in the background, with one material file per mesh material.
+    pub fn load(
+        graphics: &mut Graphics,
+        mesh_path: impl AsRef<Path>,
+        material_paths: &[impl AsRef<Path>],
+        position: Position,
+    ) -> error::Result<Self> {
+        let mesh = graphics.load_mesh_async(mesh_path)?.resource();
+        let materials = material_paths
+            .iter()
+            .map(|path| graphics.get_material_from_file(path))
+            .collect::<error::Result<Vec<_>>>()?;
+        Ok(Self::new(mesh, materials, position))
+    }
+
     pub fn update(&mut self, delta_t: f32) {
         self.position.update(delta_t);
     }
 
+    /// Bounds of the mesh in world space.
+    pub fn world_bounds(&self) -> Bounds {
+        self.mesh
+            .inner()
+            .bounds
+            .transform(&self.position.get_matrix())
+    }
+
+    /// Picks a level of detail from how much of the screen height the mesh covers.
+    pub fn select_lod(&mut self, camera_pos: Vector3d, proj: &Matrix4x4) {
+        let sphere = self.world_bounds().sphere;
+        let distance = (sphere.center - camera_pos).magnitude();
+        self.lod = if distance <= sphere.radius {
+            0
+        } else {
+            let screen_size = sphere.radius * proj.0[1][1] / distance;
+            self.mesh.inner().select_lod(screen_size)
+        };
+    }
+
     pub fn get_mesh_and_materials<'a>(
         &'a mut self,
         render: &Render,
     ) -> (&'a mut Arc<Mesh>, &'a mut [Material]) {
+        self.set_constants(render, false);
+        (&mut self.mesh, &mut self.materials)
+    }
+
+    /// Whether the entity is out of view of `queue` and shouldn't be queued. Backgrounds
+    /// are drawn around the camera wherever they are, so are never culled.
+    pub fn cull(&self, queue: &mut RenderQueue) -> bool {
+        let background = !self.materials.is_empty()
+            && self
+                .materials
+                .iter()
+                .all(|material| render_layer(material) == RenderLayer::Background);
+        !background && queue.cull(&self.world_bounds())
+    }
+
+    /// Queues the selected level of detail of the mesh to be drawn, lit by the lights
+    /// the queue selects for it. Check `cull` first to skip entities out of view.
+    /// Materials with an `instanced_vs` are drawn from the instance, so only the
+    /// constants of the others are set.
+    pub fn queue<'a>(&'a mut self, render: &Render, queue: &mut RenderQueue<'a>) {
+        self.lights = queue.select_lights(&self.world_bounds().sphere);
+        self.set_constants(render, true);
+        let instance = Instance {
+            world: self.position.get_matrix().into(),
+            color: self.color.into(),
+            lights: self.lights.into(),
+        };
+        queue.push(&self.mesh, self.lod, &mut self.materials, instance);
+    }
+
+    /// Sets the `transform` and `mesh_info` constants of the materials, skipping those
+    /// with an `instanced_vs` if `instanced`.
+    fn set_constants(&mut self, render: &Render, instanced: bool) {
         for material in &mut self.materials {
-            //Datum 1 is position. How to label?
-            material
-                .set_data(render, 1, &mut self.position.get_matrix())
-                .unwrap();
-            //Datum 2 is color. Mostly unused
+            if instanced && material.instanced_vs.is_some() {
+                continue;
+            }
+            let mut transform = Transform {
+                world: self.position.get_matrix(),
+            };
+            material.set_constants(render, &mut transform).unwrap();
             material
-                .set_data(render, 2, &mut MeshInfo { color: self.color })
+                .set_constants(
+                    render,
+                    &mut MeshInfo {
+                        lights: self.lights,
+                        color: self.color,
+                    },
+                )
                 .unwrap();
         }
-
-        (&mut self.mesh, &mut self.materials)
     }
 }
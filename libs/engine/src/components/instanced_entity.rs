@@ -0,0 +1,90 @@
+use std::sync::Arc;
+
+use crate::error;
+use crate::graphics::color;
+use crate::graphics::material::Material;
+use crate::graphics::render::{Instance, RenderQueue};
+use crate::graphics::resource::Mesh;
+use crate::math::{Matrix4x4, Vector3d};
+
+/// Many copies of a mesh drawn with the same materials, with instanced draw calls.
+/// Cheaper than an `Entity` each, but every copy is only a world matrix and a color.
+#[derive(Clone)]
+pub struct InstancedEntity {
+    pub mesh: Arc<Mesh>,
+    /// Each needs an `instanced_vs`.
+    pub materials: Vec<Material>,
+
+    pub instances: Vec<Instance>,
+    /// Level of detail of `mesh` to draw for every instance.
+    pub lod: usize,
+}
+
+impl InstancedEntity {
+    /// Errors if a material can't be instanced.
+    pub fn new(
+        mesh: Arc<Mesh>,
+        materials: impl IntoIterator<Item = Material>,
+    ) -> error::Result<Self> {
+        let materials: Vec<_> = materials.into_iter().collect();
+        if let Some(idx) = materials
+            .iter()
+            .position(|material| material.instanced_vs.is_none())
+        {
+            return Err(error::Custom(format!(
+                "Material {} can't be instanced; its template needs the {} keyword",
+                idx,
+                crate::graphics::material::INSTANCED
+            )));
+        }
+        Ok(Self {
+            mesh,
+            materials,
+            instances: Vec::new(),
+            lod: 0,
+        })
+    }
+
+    /// Adds a white instance at `world`.
+    pub fn add_instance(&mut self, world: impl Into<Matrix4x4>) {
+        self.add_colored_instance(world, color::WHITE);
+    }
+
+    pub fn add_colored_instance(
+        &mut self,
+        world: impl Into<Matrix4x4>,
+        color: impl Into<Vector3d>,
+    ) {
+        self.instances.push(Instance {
+            world: world.into().into(),
+            color: color.into().into(),
+            lights: Default::default(),
+        });
+    }
+
+    /// Picks a level of detail for every instance from how much of the screen height
+    /// the nearest one covers, so none is drawn with too little detail.
+    pub fn select_lod(&mut self, camera_pos: Vector3d, proj: &Matrix4x4) {
+        let inner = self.mesh.inner();
+        let screen_size = self
+            .instances
+            .iter()
+            .map(|instance| {
+                let sphere = inner.bounds.transform(&instance.world).sphere;
+                let distance = (sphere.center - camera_pos).magnitude();
+                if distance <= sphere.radius {
+                    f32::INFINITY
+                } else {
+                    sphere.radius * proj.0[1][1] / distance
+                }
+            })
+            .fold(0.0, f32::max);
+        self.lod = inner.select_lod(screen_size);
+    }
+
+    /// Queues every instance in view to be drawn, each lit by the lights the queue
+    /// selects for it.
+    pub fn queue<'a>(&'a mut self, queue: &mut RenderQueue<'a>) {
+        queue.push_instances(&self.mesh, self.lod, &mut self.materials, &self.instances);
+    }
+}
@@ -0,0 +1,136 @@
+use crate::graphics::color;
+use crate::math::Vector3d;
+use crate::physics::BoundingSphere;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightKind {
+    /// Infinitely far away, like the sun. Lights everything from `direction`.
+    Directional,
+    /// Shines every way from `position`, fading out at `range`.
+    Point,
+    /// Shines along `direction` from `position`, fading out at `range` and between the
+    /// inner and outer angles from `direction`, in radians.
+    Spot { inner_angle: f32, outer_angle: f32 },
+}
+
+/// A light in the scene. Collected into a `LightList` each frame.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Light {
+    pub kind: LightKind,
+    /// Unused by directional lights.
+    pub position: Vector3d,
+    /// Which way the light shines. Unused by point lights.
+    pub direction: Vector3d,
+    pub color: Vector3d,
+    pub intensity: f32,
+    /// Distance at which point and spot lights have faded out.
+    pub range: f32,
+}
+
+impl Light {
+    pub fn directional(direction: impl Into<Vector3d>) -> Self {
+        Self {
+            kind: LightKind::Directional,
+            position: Vector3d::ORIGIN,
+            direction: direction.into().normalize(),
+            color: color::WHITE.into(),
+            intensity: 1.0,
+            range: f32::INFINITY,
+        }
+    }
+
+    pub fn point(position: impl Into<Vector3d>, range: f32) -> Self {
+        Self {
+            kind: LightKind::Point,
+            position: position.into(),
+            direction: Vector3d::FORWARD,
+            color: color::WHITE.into(),
+            intensity: 1.0,
+            range,
+        }
+    }
+
+    pub fn spot(
+        position: impl Into<Vector3d>,
+        direction: impl Into<Vector3d>,
+        range: f32,
+        inner_angle: f32,
+        outer_angle: f32,
+    ) -> Self {
+        Self {
+            kind: LightKind::Spot {
+                inner_angle,
+                outer_angle,
+            },
+            position: position.into(),
+            direction: direction.into().normalize(),
+            color: color::WHITE.into(),
+            intensity: 1.0,
+            range,
+        }
+    }
+
+    pub fn with_color(mut self, color: impl Into<Vector3d>) -> Self {
+        self.color = color.into();
+        self
+    }
+
+    pub fn with_intensity(mut self, intensity: f32) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    /// Sphere lit by the light, or `None` for directional lights, which light everything.
+    pub fn bounds(&self) -> Option<BoundingSphere> {
+        match self.kind {
+            LightKind::Directional => None,
+            LightKind::Point | LightKind::Spot { .. } => {
+                Some(BoundingSphere::new(self.position, self.range))
+            }
+        }
+    }
+
+    /// Roughly how brightly the light reaches anything in `sphere`, matching the falloff
+    /// in `lights.hlsl` at the nearest point. Zero if it doesn't reach at all.
+    pub fn influence(&self, sphere: &BoundingSphere) -> f32 {
+        let brightness = self.intensity * self.color.0.into_iter().fold(0.0, f32::max);
+        let (inner_angle, outer_angle) = match self.kind {
+            LightKind::Directional => return brightness,
+            LightKind::Point => return brightness * self.falloff(sphere),
+            LightKind::Spot {
+                inner_angle,
+                outer_angle,
+            } => (inner_angle, outer_angle),
+        };
+
+        let to_center = sphere.center - self.position;
+        let distance = to_center.magnitude();
+        if distance <= sphere.radius {
+            return brightness * self.falloff(sphere);
+        }
+        // Widened by the angle the sphere takes up, so it counts if any of it is in the cone
+        let angle = self
+            .direction
+            .dot(to_center / distance)
+            .clamp(-1.0, 1.0)
+            .acos();
+        let angle = angle - (sphere.radius / distance).asin();
+        let cone = smoothstep(outer_angle.cos(), inner_angle.cos(), angle.max(0.0).cos());
+        brightness * self.falloff(sphere) * cone
+    }
+
+    fn falloff(&self, sphere: &BoundingSphere) -> f32 {
+        let distance = ((sphere.center - self.position).magnitude() - sphere.radius).max(0.0);
+        let fade = (1.0 - (distance / self.range).powi(2)).max(0.0);
+        fade * fade
+    }
+}
+
+/// 0 at `from`, 1 at `to`, easing in between, like HLSL's `smoothstep`.
+fn smoothstep(from: f32, to: f32, x: f32) -> f32 {
+    if from >= to {
+        return if x >= to { 1.0 } else { 0.0 };
+    }
+    let t = ((x - from) / (to - from)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
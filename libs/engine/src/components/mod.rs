@@ -1,5 +1,7 @@
 mod camera;
 mod entity;
+mod instanced_entity;
+mod light;
 mod play_state;
 mod screen;
 mod spaceship;
@@ -8,6 +10,8 @@ mod third_person_camera0;
 
 pub use camera::Camera;
 pub use entity::Entity;
+pub use instanced_entity::InstancedEntity;
+pub use light::{Light, LightKind};
 pub use play_state::PlayState;
 pub use screen::Screen;
 pub use spaceship::SpaceShip;
@@ -23,14 +23,6 @@ impl ThirdPersonCamera {
         self.position.set_location(loc);
     }
 
-    pub fn get_skysphere(&self) -> Matrix4x4 {
-        let mut matrix = Matrix4x4::scaling(self.back_plate);
-        let mut position = self.position.clone();
-        position.move_forward(-self.offset);
-        matrix.set_translation(position.get_location());
-        matrix
-    }
-
     pub fn get_view(&self) -> Matrix4x4 {
         let mut position = self.position.clone();
         position.move_forward(-self.offset);
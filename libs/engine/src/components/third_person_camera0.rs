@@ -62,13 +62,6 @@ impl Camera {
         Matrix4x4::perspective(FOV, rect.aspect(), FRONT_PLATE, BACK_PLATE)
     }
 
-    pub fn get_skysphere(&self) -> Matrix4x4 {
-        // Scale down by one because sphere isn't perfect
-        let mut matrix = Matrix4x4::scaling(BACK_PLATE - 100f32);
-        matrix.set_translation(self.world_cam().get_translation());
-        matrix
-    }
-
     pub fn reset_velocity(&mut self) {
         self.forward = 0.0;
         self.rightward = 0.0;
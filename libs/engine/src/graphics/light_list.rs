@@ -0,0 +1,210 @@
+use crate::components::{Light, LightKind};
+use crate::math::{Vector, Vector3d, Vector4d};
+use crate::physics::{BoundingSphere, Frustum};
+use crate::{self as engine};
+
+use std::cmp::Ordering;
+
+/// Lights drawn each frame. Matches `MAX_LIGHTS` in `lights.hlsl`.
+pub const MAX_LIGHTS: usize = 16;
+/// Lights each object is lit by, picked from the frame's lights.
+pub const MAX_OBJECT_LIGHTS: usize = 4;
+/// Fills the places of an object's lights that are left over.
+pub const NO_LIGHT: i32 = -1;
+
+/// Indices into `Lights` of the lights an object is lit by, brightest first.
+pub type LightSelection = [i32; MAX_OBJECT_LIGHTS];
+/// Lights objects without a selection of their own are lit by.
+pub const FIRST_LIGHTS: LightSelection = [0, 1, 2, 3];
+
+/// Every light of a frame, bound to the `lights` cbuffer.
+#[derive(Debug, Default, ConstantBuffer)]
+#[repr(C, align(16))]
+pub struct Lights {
+    /// xyz position, w 0 for directional lights and 1 otherwise.
+    pub positions: [Vector4d; MAX_LIGHTS],
+    /// xyz direction the light shines in, w range.
+    pub directions: [Vector4d; MAX_LIGHTS],
+    /// rgb color times intensity.
+    pub colors: [Vector4d; MAX_LIGHTS],
+    /// x cosine of the outer angle of spot lights, y of the inner angle.
+    pub cones: [Vector4d; MAX_LIGHTS],
+    pub count: u32,
+}
+
+/// The lights drawn in a frame, and which of them light each object.
+#[derive(Clone, Debug, Default)]
+pub struct LightList {
+    lights: Vec<Light>,
+}
+
+impl LightList {
+    /// Keeps the lights reaching into `frustum`, if there is one, up to `MAX_LIGHTS`.
+    /// Directional lights are kept first, then the lights nearest `camera_pos` for
+    /// their range.
+    pub fn new<'a>(
+        lights: impl IntoIterator<Item = &'a Light>,
+        camera_pos: Vector3d,
+        frustum: Option<&Frustum>,
+    ) -> Self {
+        let mut lights: Vec<_> = lights
+            .into_iter()
+            .filter(|light| match (light.bounds(), frustum) {
+                (Some(bounds), Some(frustum)) => frustum.intersects_sphere(&bounds),
+                _ => true,
+            })
+            .cloned()
+            .collect();
+        let nearness = |light: &Light| match light.kind {
+            LightKind::Directional => 0.0,
+            LightKind::Point | LightKind::Spot { .. } => {
+                (light.position - camera_pos).magnitude() / light.range
+            }
+        };
+        lights.sort_by(|a, b| {
+            nearness(a)
+                .partial_cmp(&nearness(b))
+                .unwrap_or(Ordering::Equal)
+        });
+        lights.truncate(MAX_LIGHTS);
+        Self { lights }
+    }
+
+    pub fn lights(&self) -> &[Light] {
+        &self.lights
+    }
+
+    pub fn len(&self) -> usize {
+        self.lights.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    /// The lights that reach into `sphere` the most, up to `MAX_OBJECT_LIGHTS`.
+    pub fn select(&self, sphere: &BoundingSphere) -> LightSelection {
+        let mut influences: Vec<_> = self
+            .lights
+            .iter()
+            .map(|light| light.influence(sphere))
+            .enumerate()
+            .filter(|&(_, influence)| influence > 0.0)
+            .collect();
+        influences.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+
+        let mut selection = [NO_LIGHT; MAX_OBJECT_LIGHTS];
+        for (place, (idx, _)) in selection.iter_mut().zip(influences) {
+            *place = idx as i32;
+        }
+        selection
+    }
+
+    pub fn constants(&self) -> Lights {
+        let mut constants = Lights {
+            count: self.lights.len() as u32,
+            ..Default::default()
+        };
+        for (idx, light) in self.lights.iter().enumerate() {
+            let (positioned, cone) = match light.kind {
+                // Lit all the way round, with the edges apart for `smoothstep`
+                LightKind::Directional => (0.0, [-2.0, -1.0]),
+                LightKind::Point => (1.0, [-2.0, -1.0]),
+                LightKind::Spot {
+                    inner_angle,
+                    outer_angle,
+                } => (1.0, [outer_angle.cos(), inner_angle.cos()]),
+            };
+            constants.positions[idx] = light.position.to_4d(positioned);
+            constants.directions[idx] = light.direction.to_4d(light.range);
+            constants.colors[idx] = (light.color * light.intensity).to_4d(1.0);
+            constants.cones[idx] = Vector([cone[0], cone[1], 0.0, 0.0]);
+        }
+        constants
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::math::Matrix4x4;
+
+    /// Looking down +z from the origin, seeing 1 to 100 units away.
+    fn frustum() -> Frustum {
+        let proj = Matrix4x4::perspective(std::f32::consts::FRAC_PI_2, 1.0, 1.0, 100.0);
+        Frustum::from_view_proj(&proj)
+    }
+
+    #[test]
+    fn keeps_lights_in_view() {
+        let lights = [
+            Light::point([0.0, 0.0, 50.0], 10.0),
+            Light::point([0.0, 0.0, -50.0], 10.0),
+            Light::directional([0.0, -1.0, 0.0]),
+            Light::point([0.0, 0.0, 5.0], 10.0),
+        ];
+        let list = LightList::new(&lights, Vector3d::ORIGIN, Some(&frustum()));
+        assert_eq!(
+            list.lights(),
+            [lights[2].clone(), lights[3].clone(), lights[0].clone()]
+        );
+
+        let many = vec![Light::point([0.0, 0.0, 10.0], 10.0); MAX_LIGHTS + 4];
+        assert_eq!(
+            LightList::new(&many, Vector3d::ORIGIN, None).len(),
+            MAX_LIGHTS
+        );
+    }
+
+    #[test]
+    fn selects_brightest() {
+        let lights = [
+            Light::point([20.0, 0.0, 0.0], 30.0),
+            Light::point([100.0, 0.0, 0.0], 10.0),
+            Light::point([2.0, 0.0, 0.0], 30.0),
+            Light::spot([0.0, 5.0, 0.0], [0.0, 1.0, 0.0], 30.0, 0.3, 0.5),
+        ];
+        let list = LightList::new(&lights, Vector3d::ORIGIN, None);
+        let sphere = BoundingSphere::new([0.0, 0.0, 0.0], 1.0);
+        let selection: Vec<_> = list
+            .select(&sphere)
+            .iter()
+            .map(|&idx| list.lights().get(idx as usize).map(|light| light.position))
+            .collect();
+        // Out of range and behind the spot light
+        assert_eq!(
+            selection,
+            [
+                Some(lights[2].position),
+                Some(lights[0].position),
+                None,
+                None
+            ]
+        );
+        assert_eq!(list.select(&sphere)[2], NO_LIGHT);
+
+        // In front of the spot light
+        let lit = list.select(&BoundingSphere::new([0.0, 15.0, 0.0], 1.0));
+        assert!(lit
+            .iter()
+            .filter_map(|&idx| list.lights().get(idx as usize))
+            .any(|light| light.position == lights[3].position));
+    }
+
+    #[test]
+    fn constants() {
+        let lights = [
+            Light::directional([0.0, -2.0, 0.0]).with_intensity(2.0),
+            Light::spot([1.0, 2.0, 3.0], [0.0, 0.0, 1.0], 20.0, 0.0, 0.5),
+        ];
+        let constants = LightList::new(&lights, Vector3d::ORIGIN, None).constants();
+        assert_eq!(constants.count, 2);
+        assert_eq!(constants.positions[0].0[3], 0.0);
+        assert_eq!(constants.directions[0].0[..3], [0.0, -1.0, 0.0]);
+        assert_eq!(constants.colors[0].0, [2.0, 2.0, 2.0, 1.0]);
+        assert_eq!(constants.positions[1].0, [1.0, 2.0, 3.0, 1.0]);
+        assert_eq!(constants.directions[1].0[3], 20.0);
+        assert_eq!(constants.cones[1].0[..2], [0.5f32.cos(), 1.0]);
+        assert_eq!(constants.positions[2], Vector4d::default());
+    }
+}
@@ -0,0 +1,621 @@
+//! Materials described in text files, so they can change without recompiling.
+//!
+//! One setting per line, `#` starts a comment. Paths are virtual paths.
+//!
+//! ```text
+//! vertex_shader shaders/point_light/vertex_shader.hlsl
+//! pixel_shader shaders/point_light/pixel_shader.hlsl
+//! define SPECULAR
+//! instancing on
+//! texture Texture assets/Textures/barrel.jpg
+//! cube_map Sky assets/Textures/stars_map.jpg
+//! sampler Texture address=clamp filter=point anisotropy=8
+//! constant mesh_info.color 1.0 0.5 0.5
+//! constant tone_map_settings.tone_operator uint 1
+//! cull front
+//! fill wireframe
+//! depth background
+//! depth_write off
+//! depth_func less_equal
+//! depth_clip off
+//! depth_bias 100 1.5 0.01
+//! stencil ref=1 func=equal pass=keep back_func=always
+//! scissor 0 0 640 480
+//! blend alpha
+//! ```
+//!
+//! `instancing on` also compiles the vertex shader with `INSTANCED`, so entities
+//! sharing the mesh and material are drawn together.
+//! Textures and samplers are bound to the pixel shader texture of the same name.
+//! Constants are `float` unless `int` or `uint` comes before the values.
+//! Settings apply in order: `blend alpha` turns off depth writes, as
+//! `Material::with_blend_mode` does, unless a `depth_write on` comes after it.
+
+use super::{BlendMode, CullMode};
+
+use crate::error;
+use crate::graphics::render::{
+    AddressMode, ComparisonFunc, DepthStencilDesc, Device, FillMode, FilterMode, RasterDesc,
+    SamplerDesc, StencilOp,
+};
+use crate::graphics::resource::{Resource, ResourceManager};
+use crate::math::Rect;
+use crate::vfs;
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// The extension of material files.
+pub const MATERIAL_EXTENSION: &str = "material";
+
+pub type MaterialFileManager = ResourceManager<MaterialFile>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureKind {
+    Texture,
+    CubeMap,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextureDesc {
+    /// The texture in the pixel shader.
+    pub name: String,
+    pub path: PathBuf,
+    pub kind: TextureKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConstantValue {
+    Float(Vec<f32>),
+    Int(Vec<i32>),
+    Uint(Vec<u32>),
+}
+
+impl ConstantValue {
+    /// The values as HLSL lays them out.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Self::Float(values) => values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+            Self::Int(values) => values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+            Self::Uint(values) => values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConstantDesc {
+    /// The `cbuffer` the variable is in.
+    pub buffer: String,
+    pub variable: String,
+    pub value: ConstantValue,
+}
+
+/// Everything a material file sets.
+#[derive(Clone, Debug)]
+pub struct MaterialDesc {
+    pub vertex_shader: PathBuf,
+    pub pixel_shader: PathBuf,
+    /// Keywords the shaders are compiled with.
+    pub defines: Vec<String>,
+    /// Whether an `INSTANCED` variant of the vertex shader is compiled too.
+    pub instancing: bool,
+    pub textures: Vec<TextureDesc>,
+    /// Samplers for textures, by texture name.
+    pub samplers: Vec<(String, SamplerDesc)>,
+    pub constants: Vec<ConstantDesc>,
+    pub blend_mode: BlendMode,
+    pub depth_stencil: DepthStencilDesc,
+    pub stencil_ref: u32,
+    pub raster: RasterDesc,
+    pub scissor: Option<Rect<i32>>,
+}
+
+impl MaterialDesc {
+    /// Reads a material file through the virtual file system.
+    pub fn load(path: impl AsRef<Path>) -> error::Result<Self> {
+        let path = path.as_ref();
+        Self::parse(&vfs::read_to_string(path)?)
+            .map_err(|e| error::Custom(format!("{}: {}", path.display(), e)))
+    }
+
+    pub fn parse(text: &str) -> error::Result<Self> {
+        let mut vertex_shader = None;
+        let mut pixel_shader = None;
+        let mut desc = Self {
+            vertex_shader: PathBuf::new(),
+            pixel_shader: PathBuf::new(),
+            defines: Vec::new(),
+            instancing: false,
+            textures: Vec::new(),
+            samplers: Vec::new(),
+            constants: Vec::new(),
+            blend_mode: BlendMode::Opaque,
+            depth_stencil: DepthStencilDesc::default(),
+            stencil_ref: 0,
+            raster: RasterDesc::default(),
+            scissor: None,
+        };
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let mut fields = rest.split_whitespace();
+            desc.parse_line(key, &mut fields, &mut vertex_shader, &mut pixel_shader)
+                .map_err(|e| error::Custom(format!("Material line {}: {}", number + 1, e)))?;
+        }
+
+        desc.vertex_shader = vertex_shader.ok_or("Material has no vertex_shader")?;
+        desc.pixel_shader = pixel_shader.ok_or("Material has no pixel_shader")?;
+        Ok(desc)
+    }
+
+    fn parse_line<'a>(
+        &mut self,
+        key: &str,
+        fields: &mut impl Iterator<Item = &'a str>,
+        vertex_shader: &mut Option<PathBuf>,
+        pixel_shader: &mut Option<PathBuf>,
+    ) -> Result<(), String> {
+        let mut field = |what: &str| fields.next().ok_or(format!("{} needs a {}", key, what));
+        match key {
+            "vertex_shader" => *vertex_shader = Some(path(field("path")?)?),
+            "pixel_shader" => *pixel_shader = Some(path(field("path")?)?),
+            "define" => self.defines.push(field("keyword")?.to_owned()),
+            "instancing" => self.instancing = switch(field("on or off")?)?,
+            "texture" | "cube_map" => {
+                let name = field("name")?.to_owned();
+                let path = path(field("path")?)?;
+                let kind = match key {
+                    "texture" => TextureKind::Texture,
+                    _ => TextureKind::CubeMap,
+                };
+                self.textures.push(TextureDesc { name, path, kind });
+            }
+            "sampler" => {
+                let name = field("texture name")?.to_owned();
+                let sampler = parse_sampler(fields)?;
+                self.samplers.push((name, sampler));
+            }
+            "constant" => {
+                let (buffer, variable) = field("cbuffer.variable")?
+                    .split_once('.')
+                    .ok_or("constant needs a cbuffer.variable")?;
+                let (buffer, variable) = (buffer.to_owned(), variable.to_owned());
+                let value = parse_constant(fields)?;
+                self.constants.push(ConstantDesc {
+                    buffer,
+                    variable,
+                    value,
+                });
+            }
+            "cull" => {
+                self.raster.cull = match field("mode")? {
+                    "none" => CullMode::None,
+                    "front" => CullMode::Front,
+                    "back" => CullMode::Back,
+                    other => return Err(format!("Unknown cull mode {}", other)),
+                }
+            }
+            "fill" => {
+                self.raster.fill = match field("mode")? {
+                    "solid" => FillMode::Solid,
+                    "wireframe" => FillMode::Wireframe,
+                    other => return Err(format!("Unknown fill mode {}", other)),
+                }
+            }
+            "depth" => {
+                let stencil = self.depth_stencil.stencil;
+                self.depth_stencil = match field("mode")? {
+                    "test" => DepthStencilDesc::default(),
+                    "background" => DepthStencilDesc::background(),
+                    "transparent" => DepthStencilDesc::transparent(),
+                    "off" => DepthStencilDesc::off(),
+                    other => return Err(format!("Unknown depth mode {}", other)),
+                };
+                self.depth_stencil.stencil = stencil;
+            }
+            "depth_write" => self.depth_stencil.depth_write = switch(field("on or off")?)?,
+            "depth_func" => self.depth_stencil.depth_func = comparison_func(field("comparison")?)?,
+            "depth_clip" => self.raster.depth_clip = switch(field("on or off")?)?,
+            "depth_bias" => {
+                self.raster.depth_bias = number(field("bias")?)?;
+                let mut rest = fields.map(number::<f32>);
+                self.raster.slope_scaled_depth_bias = rest.next().transpose()?.unwrap_or(0.0);
+                self.raster.depth_bias_clamp = rest.next().transpose()?.unwrap_or(0.0);
+            }
+            "stencil" => {
+                let stencil = self
+                    .depth_stencil
+                    .stencil
+                    .get_or_insert_with(Default::default);
+                for field in fields {
+                    let (key, value) = field
+                        .split_once('=')
+                        .ok_or(format!("Expected key=value, not {}", field))?;
+                    match key {
+                        "ref" => self.stencil_ref = number(value)?,
+                        "read_mask" => stencil.read_mask = number(value)?,
+                        "write_mask" => stencil.write_mask = number(value)?,
+                        _ => {
+                            let (faces, setting) = match key.split_once('_') {
+                                Some(("front", setting)) => (vec![&mut stencil.front], setting),
+                                Some(("back", setting)) => (vec![&mut stencil.back], setting),
+                                _ => (vec![&mut stencil.front, &mut stencil.back], key),
+                            };
+                            for face in faces {
+                                match setting {
+                                    "func" => face.func = comparison_func(value)?,
+                                    "fail" => face.fail = stencil_op(value)?,
+                                    "depth_fail" => face.depth_fail = stencil_op(value)?,
+                                    "pass" => face.pass = stencil_op(value)?,
+                                    _ => return Err(format!("Unknown stencil setting {}", key)),
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            "scissor" => {
+                let mut edge =
+                    || -> Result<i32, String> { number(field("left top right bottom")?) };
+                let (left, top, right, bottom) = (edge()?, edge()?, edge()?, edge()?);
+                self.scissor = Some(Rect([left..right, top..bottom]));
+            }
+            "blend" => {
+                self.blend_mode = match field("mode")? {
+                    "opaque" => BlendMode::Opaque,
+                    "alpha" => BlendMode::Alpha,
+                    "additive" => BlendMode::Additive,
+                    "premultiplied" => BlendMode::Premultiplied,
+                    other => return Err(format!("Unknown blend mode {}", other)),
+                };
+                if self.blend_mode != BlendMode::Opaque {
+                    self.depth_stencil.depth_write = false;
+                }
+            }
+            _ => return Err(format!("Unknown setting {}", key)),
+        }
+        Ok(())
+    }
+}
+
+fn path(path: &str) -> Result<PathBuf, String> {
+    vfs::normalize(path)
+        .map(PathBuf::from)
+        .map_err(|e| e.to_string())
+}
+
+fn number<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{} is not a valid value", value))
+}
+
+fn switch(value: &str) -> Result<bool, String> {
+    match value {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(format!("Expected on or off, not {}", value)),
+    }
+}
+
+fn parse_sampler<'a>(fields: impl Iterator<Item = &'a str>) -> Result<SamplerDesc, String> {
+    let mut sampler = SamplerDesc::default();
+    for field in fields {
+        let (key, value) = field
+            .split_once('=')
+            .ok_or(format!("Expected key=value, not {}", field))?;
+        let number = || -> Result<f32, String> {
+            value
+                .parse()
+                .map_err(|_| format!("{} is not a number", value))
+        };
+        match key {
+            "address" => sampler = sampler.with_address(address_mode(value)?),
+            "address_u" => sampler.address_u = address_mode(value)?,
+            "address_v" => sampler.address_v = address_mode(value)?,
+            "address_w" => sampler.address_w = address_mode(value)?,
+            "filter" => sampler = sampler.with_filter(filter_mode(value)?),
+            "min_filter" => sampler.min_filter = filter_mode(value)?,
+            "mag_filter" => sampler.mag_filter = filter_mode(value)?,
+            "mip_filter" => sampler.mip_filter = filter_mode(value)?,
+            "anisotropy" => {
+                sampler.max_anisotropy = value
+                    .parse()
+                    .ok()
+                    .filter(|samples| (1..=16).contains(samples))
+                    .ok_or(format!("Anisotropy {} is not from 1 to 16", value))?
+            }
+            "lod_bias" => sampler.mip_lod_bias = number()?,
+            "min_lod" => sampler.min_lod = number()?,
+            "max_lod" => sampler.max_lod = number()?,
+            "border" => {
+                let channels = value
+                    .split(',')
+                    .map(|channel| channel.parse().ok())
+                    .collect::<Option<Vec<f32>>>();
+                sampler.border_color = channels
+                    .and_then(|channels| channels.try_into().ok())
+                    .ok_or(format!("Border {} is not r,g,b,a", value))?;
+            }
+            "compare" => sampler.comparison = Some(comparison_func(value)?),
+            _ => return Err(format!("Unknown sampler setting {}", key)),
+        }
+    }
+    Ok(sampler)
+}
+
+fn address_mode(mode: &str) -> Result<AddressMode, String> {
+    Ok(match mode {
+        "wrap" => AddressMode::Wrap,
+        "mirror" => AddressMode::Mirror,
+        "clamp" => AddressMode::Clamp,
+        "border" => AddressMode::Border,
+        "mirror_once" => AddressMode::MirrorOnce,
+        _ => return Err(format!("Unknown address mode {}", mode)),
+    })
+}
+
+fn filter_mode(mode: &str) -> Result<FilterMode, String> {
+    Ok(match mode {
+        "point" => FilterMode::Point,
+        "linear" => FilterMode::Linear,
+        _ => return Err(format!("Unknown filter {}", mode)),
+    })
+}
+
+fn comparison_func(func: &str) -> Result<ComparisonFunc, String> {
+    Ok(match func {
+        "never" => ComparisonFunc::Never,
+        "less" => ComparisonFunc::Less,
+        "equal" => ComparisonFunc::Equal,
+        "less_equal" => ComparisonFunc::LessEqual,
+        "greater" => ComparisonFunc::Greater,
+        "not_equal" => ComparisonFunc::NotEqual,
+        "greater_equal" => ComparisonFunc::GreaterEqual,
+        "always" => ComparisonFunc::Always,
+        _ => return Err(format!("Unknown comparison {}", func)),
+    })
+}
+
+fn stencil_op(op: &str) -> Result<StencilOp, String> {
+    Ok(match op {
+        "keep" => StencilOp::Keep,
+        "zero" => StencilOp::Zero,
+        "replace" => StencilOp::Replace,
+        "increment_clamp" => StencilOp::IncrementClamp,
+        "decrement_clamp" => StencilOp::DecrementClamp,
+        "invert" => StencilOp::Invert,
+        "increment" => StencilOp::Increment,
+        "decrement" => StencilOp::Decrement,
+        _ => return Err(format!("Unknown stencil op {}", op)),
+    })
+}
+
+fn parse_constant<'a>(fields: impl Iterator<Item = &'a str>) -> Result<ConstantValue, String> {
+    let mut fields = fields.peekable();
+    let kind = match fields.peek() {
+        Some(&kind @ ("float" | "int" | "uint")) => {
+            fields.next();
+            kind
+        }
+        _ => "float",
+    };
+    let values: Vec<&str> = fields.collect();
+    if values.is_empty() {
+        return Err("constant needs a value".to_owned());
+    }
+
+    fn parse_all<T: std::str::FromStr>(values: &[&str]) -> Result<Vec<T>, String> {
+        values.iter().map(|value| number(value)).collect()
+    }
+    Ok(match kind {
+        "int" => ConstantValue::Int(parse_all(&values)?),
+        "uint" => ConstantValue::Uint(parse_all(&values)?),
+        _ => ConstantValue::Float(parse_all(&values)?),
+    })
+}
+
+/// A parsed material file. Materials are made from it, and reloading the file only
+/// changes materials made afterwards.
+pub struct MaterialFile(Mutex<Arc<MaterialDesc>>);
+
+impl MaterialFile {
+    pub fn desc(&self) -> Arc<MaterialDesc> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+impl Resource for MaterialFile {
+    fn load_resource_from_file(
+        _device: &Device,
+        path: impl AsRef<Path>,
+    ) -> error::Result<Arc<Self>> {
+        let desc = MaterialDesc::load(path)?;
+        Ok(Arc::new(Self(Mutex::new(Arc::new(desc)))))
+    }
+
+    fn replace(&self, _device: &Device, fresh: Self) -> error::Result<()> {
+        *self.0.lock().unwrap() = fresh.0.into_inner().unwrap();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let desc = MaterialDesc::parse(
+            "# A window
+            vertex_shader shaders/point_light/vertex_shader.hlsl
+            pixel_shader shaders\\point_light\\pixel_shader.hlsl
+
+            define SPECULAR
+            instancing on
+            texture Texture assets/Textures/house_windows.jpg # glass
+            cube_map Sky assets/Textures/stars_map.jpg
+            sampler Texture address=clamp filter=point border=0,0,0,1
+            sampler Sky anisotropy=8 compare=less_equal
+            constant mesh_info.color 1.0 0.5 0.25
+            constant tone_map_settings.tone_operator uint 1
+            cull front
+            depth background
+            depth_clip off
+            blend alpha",
+        )
+        .unwrap();
+
+        assert_eq!(
+            desc.vertex_shader,
+            Path::new("shaders/point_light/vertex_shader.hlsl")
+        );
+        assert_eq!(
+            desc.pixel_shader,
+            Path::new("shaders/point_light/pixel_shader.hlsl")
+        );
+        assert_eq!(desc.defines, ["SPECULAR"]);
+        assert!(desc.instancing);
+        assert_eq!(
+            desc.textures,
+            [
+                TextureDesc {
+                    name: "Texture".to_owned(),
+                    path: "assets/Textures/house_windows.jpg".into(),
+                    kind: TextureKind::Texture,
+                },
+                TextureDesc {
+                    name: "Sky".to_owned(),
+                    path: "assets/Textures/stars_map.jpg".into(),
+                    kind: TextureKind::CubeMap,
+                },
+            ]
+        );
+
+        let (name, sampler) = &desc.samplers[0];
+        assert_eq!(name, "Texture");
+        assert_eq!(sampler.address_v, AddressMode::Clamp);
+        assert_eq!(sampler.mip_filter, FilterMode::Point);
+        assert_eq!(sampler.border_color, [0.0, 0.0, 0.0, 1.0]);
+        let (name, sampler) = &desc.samplers[1];
+        assert_eq!(name, "Sky");
+        assert_eq!(sampler.max_anisotropy, 8);
+        assert_eq!(sampler.comparison, Some(ComparisonFunc::LessEqual));
+
+        assert_eq!(
+            desc.constants,
+            [
+                ConstantDesc {
+                    buffer: "mesh_info".to_owned(),
+                    variable: "color".to_owned(),
+                    value: ConstantValue::Float(vec![1.0, 0.5, 0.25]),
+                },
+                ConstantDesc {
+                    buffer: "tone_map_settings".to_owned(),
+                    variable: "tone_operator".to_owned(),
+                    value: ConstantValue::Uint(vec![1]),
+                },
+            ]
+        );
+        assert_eq!(desc.constants[1].value.to_bytes(), [1, 0, 0, 0]);
+        assert_eq!(desc.raster.cull, CullMode::Front);
+        assert!(!desc.raster.depth_clip);
+        assert_eq!(desc.depth_stencil, DepthStencilDesc::background());
+        assert_eq!(desc.blend_mode, BlendMode::Alpha);
+    }
+
+    #[test]
+    fn pipeline_state() {
+        let desc = MaterialDesc::parse(
+            "vertex_shader a.hlsl
+            pixel_shader b.hlsl
+            blend additive
+            depth_write on
+            depth_func greater_equal
+            depth_bias 100 1.5
+            fill wireframe
+            cull none
+            stencil ref=3 write_mask=15 func=equal pass=replace back_func=always
+            scissor 10 20 110 220",
+        )
+        .unwrap();
+
+        assert_eq!(desc.blend_mode, BlendMode::Additive);
+        assert!(desc.depth_stencil.depth_write);
+        assert_eq!(desc.depth_stencil.depth_func, ComparisonFunc::GreaterEqual);
+        assert_eq!(desc.raster.depth_bias, 100);
+        assert_eq!(desc.raster.slope_scaled_depth_bias, 1.5);
+        assert_eq!(desc.raster.depth_bias_clamp, 0.0);
+        assert_eq!(desc.raster.fill, FillMode::Wireframe);
+        assert_eq!(desc.raster.cull, CullMode::None);
+
+        let stencil = desc.depth_stencil.stencil.unwrap();
+        assert_eq!(desc.stencil_ref, 3);
+        assert_eq!(stencil.read_mask, 0xff);
+        assert_eq!(stencil.write_mask, 15);
+        assert_eq!(stencil.front.func, ComparisonFunc::Equal);
+        assert_eq!(stencil.front.pass, StencilOp::Replace);
+        assert_eq!(stencil.back.func, ComparisonFunc::Always);
+        assert_eq!(stencil.back.pass, StencilOp::Replace);
+        assert_eq!(stencil.back.fail, StencilOp::Keep);
+
+        let scissor = desc.scissor.unwrap();
+        assert_eq!((scissor.left(), scissor.top()), (10, 20));
+        assert_eq!(scissor.dims(), (100, 200));
+
+        // Transparent blending stops depth writes, and depth presets keep the stencil.
+        let desc = MaterialDesc::parse(
+            "vertex_shader a.hlsl
+            pixel_shader b.hlsl
+            stencil func=not_equal
+            blend premultiplied
+            depth test",
+        )
+        .unwrap();
+        assert_eq!(desc.blend_mode, BlendMode::Premultiplied);
+        assert!(desc.depth_stencil.depth_write);
+        assert!(desc.depth_stencil.stencil.is_some());
+        let desc =
+            MaterialDesc::parse("vertex_shader a.hlsl\npixel_shader b.hlsl\nblend alpha").unwrap();
+        assert_eq!(desc.depth_stencil, DepthStencilDesc::transparent());
+    }
+
+    #[test]
+    fn defaults() {
+        let desc = MaterialDesc::parse("vertex_shader a.hlsl\npixel_shader b.hlsl").unwrap();
+        assert!(desc.textures.is_empty());
+        assert!(!desc.instancing);
+        assert_eq!(desc.raster, RasterDesc::default());
+        assert_eq!(desc.depth_stencil, DepthStencilDesc::default());
+        assert_eq!(desc.blend_mode, BlendMode::Opaque);
+        assert!(desc.scissor.is_none());
+    }
+
+    #[test]
+    fn errors() {
+        let shaders = "vertex_shader a.hlsl\npixel_shader b.hlsl\n";
+        let error = |text: &str| {
+            MaterialDesc::parse(&format!("{}{}", shaders, text))
+                .unwrap_err()
+                .to_string()
+        };
+
+        assert!(error("blend sideways").contains("line 3"));
+        assert!(error("texture Color").contains("needs a path"));
+        assert!(error("sampler Color anisotropy=32").contains("Anisotropy"));
+        assert!(error("sampler Color border=1,1").contains("Border"));
+        assert!(error("constant color 1.0").contains("cbuffer.variable"));
+        assert!(error("constant info.count int 1.5").contains("1.5"));
+        assert!(error("constant info.count").contains("needs a value"));
+        assert!(error("depth_write maybe").contains("on or off"));
+        assert!(error("stencil pass=explode").contains("stencil op"));
+        assert!(error("stencil side_pass=keep").contains("stencil setting"));
+        assert!(error("scissor 0 0 10").contains("left top right bottom"));
+        assert!(error("shininess 30").contains("Unknown setting"));
+        assert!(MaterialDesc::parse("vertex_shader a.hlsl").is_err());
+    }
+}
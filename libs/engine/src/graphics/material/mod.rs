@@ -1,46 +1,244 @@
+pub mod file;
 mod template;
 mod texture;
 
-pub use template::Template;
+pub use file::{MaterialDesc, MaterialFile, MaterialFileManager, TextureKind, MATERIAL_EXTENSION};
+pub use template::{keyword_combinations, precompile, Template, INSTANCED};
 pub use texture::Texture;
 
-use crate::error::Result;
-use crate::graphics::render::{ConstantBuffer, Render};
-use crate::graphics::resource::shader::{self, Shader};
+pub use crate::graphics::render::{BlendMode, CullMode};
+
+use crate::error::{self, Result};
+use crate::graphics::render::{
+    ConstantBuffer, Context, DepthStencilDesc, Device, FillMode, RasterDesc, Render, Sampler,
+};
+use crate::graphics::resource::shader::{
+    self, check_constants, Constants, Shader, ShaderReflection,
+};
 use crate::graphics::Graphics;
+use crate::math::Rect;
 use std::any::{Any, TypeId};
 use std::sync::Arc;
 
 pub struct Material {
     pub vs: Arc<Shader<shader::Vertex>>,
+    /// The variant of `vs` compiled with `INSTANCED`. Entities drawn with materials
+    /// that have one are batched into instanced draws.
+    pub instanced_vs: Option<Arc<Shader<shader::Vertex>>>,
     pub ps: Arc<Shader<shader::Pixel>>,
     pub const_buffs: Vec<Option<(ConstantBuffer<dyn Any + Send + Sync>, TypeId)>>,
+    /// Constants set by name with `set_variable`, by slot. Ignored in slots that
+    /// `const_buffs` fills.
+    pub raw_constants: Vec<Option<RawConstants>>,
     pub textures: Vec<Option<Arc<dyn Texture>>>,
-    pub cull_mode: CullMode,
+    /// Overrides the sampler of the texture in the same slot.
+    pub samplers: Vec<Option<Arc<Sampler>>>,
+    pub blend_mode: BlendMode,
+    pub depth_stencil: DepthStencilDesc,
+    /// What stencil tests compare with.
+    pub stencil_ref: u32,
+    /// `scissor` is ignored; it follows `Material::scissor`.
+    pub raster: RasterDesc,
+    /// Pixels outside are discarded, in pixels from the top left of the target.
+    pub scissor: Option<Rect<i32>>,
+}
+
+/// The bytes of a `cbuffer` without a Rust type, uploaded when the material is next set.
+pub struct RawConstants {
+    data: Vec<u8>,
+    buffer: Option<ConstantBuffer<[u8]>>,
+    changed: bool,
 }
 
-#[derive(Clone, Debug)]
-pub enum CullMode {
-    Front,
-    Back,
+impl RawConstants {
+    /// Zeroed constants of `size` bytes.
+    pub fn new(size: usize) -> Self {
+        Self {
+            data: vec![0; size],
+            buffer: None,
+            changed: true,
+        }
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Writes `value` at `offset`.
+    pub fn write(&mut self, offset: usize, value: &[u8]) {
+        self.data[offset..offset + value.len()].copy_from_slice(value);
+        self.changed = true;
+    }
+
+    /// Uploads any changes, creating the buffer the first time, and returns it to bind.
+    pub fn upload(
+        &mut self,
+        device: &Device,
+        context: &Context,
+    ) -> Result<&mut ConstantBuffer<[u8]>> {
+        let buffer = match self.buffer.take() {
+            Some(mut buffer) => {
+                if self.changed {
+                    buffer.update(context, &mut self.data[..]);
+                }
+                buffer
+            }
+            None => device.new_constant_buffer(&mut self.data[..])?,
+        };
+        self.changed = false;
+        Ok(self.buffer.insert(buffer))
+    }
+}
+
+impl Clone for RawConstants {
+    /// Clones share the values but not the buffer.
+    fn clone(&self) -> Self {
+        Self {
+            data: self.data.clone(),
+            buffer: None,
+            changed: true,
+        }
+    }
 }
 
 impl Material {
     pub fn new<T: Template>(graphics: &mut Graphics) -> Result<Self> {
-        let vertex_shader = graphics.get_vertex_shader_from_file(T::VERTEX_SHADER_PATH)?;
-        let pixel_shader = graphics.get_pixel_shader_from_file(T::PIXEL_SHADER_PATH)?;
+        Self::with_keywords::<T>(graphics, &[])
+    }
+
+    /// Uses the variant of the template's shaders compiled with `keywords`,
+    /// which must be in `T::KEYWORDS`.
+    pub fn with_keywords<T: Template>(graphics: &mut Graphics, keywords: &[&str]) -> Result<Self> {
+        if let Some(keyword) = keywords
+            .iter()
+            .find(|&&keyword| !T::KEYWORDS.iter().any(|&known| known == keyword))
+        {
+            return Err(error::Custom(format!(
+                "{} is not a keyword of {}",
+                keyword,
+                std::any::type_name::<T>()
+            )));
+        }
+        let vertex_shader = graphics.get_vertex_shader_variant(T::VERTEX_SHADER_PATH, keywords)?;
+        let pixel_shader = graphics.get_pixel_shader_variant(T::PIXEL_SHADER_PATH, keywords)?;
+
+        let mut material = Self::from_shaders(vertex_shader, pixel_shader);
+        if T::KEYWORDS.contains(&INSTANCED) && !keywords.contains(&INSTANCED) {
+            let keywords: Vec<_> = keywords.iter().copied().chain([INSTANCED]).collect();
+            material.instanced_vs =
+                Some(graphics.get_vertex_shader_variant(T::VERTEX_SHADER_PATH, &keywords)?);
+        }
+        // Mismatched layouts fail here rather than drawing garbage.
+        let reflection = material.reflection()?;
+        if let Some(buffer) = reflection.constant_buffer(T::Environment::NAME) {
+            check_constants::<T::Environment>(buffer)?;
+        }
+        Ok(material)
+    }
+
+    /// Builds the material a material file describes, loading its shaders and textures.
+    /// Textures load in the background.
+    pub fn from_desc(graphics: &mut Graphics, desc: &MaterialDesc) -> Result<Self> {
+        let defines: Vec<&str> = desc.defines.iter().map(String::as_str).collect();
+        let vertex_shader = graphics.get_vertex_shader_variant(&desc.vertex_shader, &defines)?;
+        let pixel_shader = graphics.get_pixel_shader_variant(&desc.pixel_shader, &defines)?;
+
+        let mut material = Self::from_shaders(vertex_shader, pixel_shader);
+        if desc.instancing && !defines.contains(&INSTANCED) {
+            let defines: Vec<_> = defines.iter().copied().chain([INSTANCED]).collect();
+            material.instanced_vs =
+                Some(graphics.get_vertex_shader_variant(&desc.vertex_shader, &defines)?);
+        }
+        material.blend_mode = desc.blend_mode;
+        material.depth_stencil = desc.depth_stencil;
+        material.stencil_ref = desc.stencil_ref;
+        material.raster = desc.raster;
+        material.scissor = desc.scissor.clone();
 
-        Ok(Self {
-            vs: vertex_shader,
-            ps: pixel_shader,
+        for texture in &desc.textures {
+            let loaded: Arc<dyn Texture + Send + Sync> = match texture.kind {
+                TextureKind::Texture => graphics.load_texture_async(&texture.path)?.resource(),
+                TextureKind::CubeMap => graphics.load_cube_map_async(&texture.path)?.resource(),
+            };
+            material.set_texture(&texture.name, loaded)?;
+        }
+        for (name, sampler) in &desc.samplers {
+            let idx = material.texture_slot(name)?;
+            material.set_sampler(idx, Some(graphics.get_sampler(sampler)?));
+        }
+        for constant in &desc.constants {
+            material.set_variable(
+                &constant.buffer,
+                &constant.variable,
+                &constant.value.to_bytes(),
+            )?;
+        }
+        Ok(material)
+    }
+
+    fn from_shaders(vs: Arc<Shader<shader::Vertex>>, ps: Arc<Shader<shader::Pixel>>) -> Self {
+        Self {
+            vs,
+            instanced_vs: None,
+            ps,
             const_buffs: Vec::new(),
+            raw_constants: Vec::new(),
             textures: Vec::new(),
-            cull_mode: CullMode::Back,
-        })
+            samplers: Vec::new(),
+            blend_mode: BlendMode::Opaque,
+            depth_stencil: DepthStencilDesc::default(),
+            stencil_ref: 0,
+            raster: RasterDesc::default(),
+            scissor: None,
+        }
     }
 
+    /// Everything the vertex and pixel shaders declare. Errors if they declare
+    /// the same binding differently.
+    pub fn reflection(&self) -> Result<ShaderReflection> {
+        self.vs.reflection().merge(&self.ps.reflection())
+    }
+
+    /// Draws the inside of meshes, without clipping them at the far plane, for skies.
     pub fn with_frontface_culling(mut self) -> Self {
-        self.cull_mode = CullMode::Front;
+        self.raster.cull = CullMode::Front;
+        self.raster.depth_clip = false;
+        self
+    }
+
+    pub fn with_background_depth(mut self) -> Self {
+        self.depth_stencil = DepthStencilDesc::background();
+        self
+    }
+
+    /// Alpha blended or additive materials also stop writing depth, so they don't
+    /// hide each other.
+    pub fn with_blend_mode(mut self, blend_mode: BlendMode) -> Self {
+        self.blend_mode = blend_mode;
+        if blend_mode != BlendMode::Opaque {
+            self.depth_stencil.depth_write = false;
+        }
+        self
+    }
+
+    pub fn with_depth_stencil(mut self, depth_stencil: DepthStencilDesc, stencil_ref: u32) -> Self {
+        self.depth_stencil = depth_stencil;
+        self.stencil_ref = stencil_ref;
+        self
+    }
+
+    pub fn with_raster(mut self, raster: RasterDesc) -> Self {
+        self.raster = raster;
+        self
+    }
+
+    pub fn with_wireframe(mut self) -> Self {
+        self.raster.fill = FillMode::Wireframe;
+        self
+    }
+
+    pub fn with_scissor(mut self, scissor: Rect<i32>) -> Self {
+        self.scissor = Some(scissor);
         self
     }
 
@@ -49,12 +247,112 @@ impl Material {
         self.textures.len() - 1
     }
 
+    pub fn add_texture_with_sampler(
+        &mut self,
+        texture: Arc<dyn Texture + Send + Sync>,
+        sampler: Arc<Sampler>,
+    ) -> usize {
+        let idx = self.add_texture(texture);
+        self.set_sampler(idx, Some(sampler));
+        idx
+    }
+
+    /// Puts a texture in the slot of the pixel shader texture called `name`.
+    /// Returns the slot.
+    pub fn set_texture(
+        &mut self,
+        name: &str,
+        texture: Arc<dyn Texture + Send + Sync>,
+    ) -> Result<usize> {
+        let idx = self.texture_slot(name)?;
+        if self.textures.len() <= idx {
+            self.textures.resize_with(idx + 1, || None);
+        }
+        self.textures[idx] = Some(texture);
+        Ok(idx)
+    }
+
+    /// The slot of the pixel shader texture called `name`.
+    pub fn texture_slot(&self, name: &str) -> Result<usize> {
+        self.ps
+            .reflection()
+            .texture_slot(name)
+            .map(|slot| slot as usize)
+            .ok_or_else(|| error::Custom(format!("Pixel shader has no texture {}", name)))
+    }
+
     pub fn remove_texture(&mut self, idx: usize) {
         if let Some(tex) = self.textures.get_mut(idx) {
             *tex = None;
         }
+        self.set_sampler(idx, None);
+    }
+
+    /// Samples slot `idx` with `sampler`, or the texture's own sampler when `None`.
+    pub fn set_sampler(&mut self, idx: usize, sampler: Option<Arc<Sampler>>) {
+        if self.samplers.len() <= idx {
+            self.samplers.resize_with(idx + 1, || None);
+        }
+        self.samplers[idx] = sampler;
+    }
+
+    /// Updates the `cbuffer` called `C::NAME`, wherever the shaders put it. Its layout
+    /// is checked when it is first set. Does nothing if neither shader uses it.
+    pub fn set_constants<C: Constants>(&mut self, render: &Render, data: &mut C) -> Result<()> {
+        let (vs, ps) = (self.vs.reflection(), self.ps.reflection());
+        let buffer = match vs
+            .constant_buffer(C::NAME)
+            .or_else(|| ps.constant_buffer(C::NAME))
+        {
+            Some(buffer) => buffer,
+            None => return Ok(()),
+        };
+
+        let idx = buffer.slot as usize;
+        let created = matches!(
+            self.const_buffs.get(idx),
+            Some(Some((_, type_id))) if *type_id == TypeId::of::<C>()
+        );
+        if !created {
+            check_constants::<C>(buffer)?;
+        }
+        self.set_data(render, idx, data)
+    }
+
+    /// Sets one variable of the `cbuffer` called `buffer` from its bytes, for constants
+    /// without a Rust type. Constants set with `set_constants` take the slot over.
+    pub fn set_variable(&mut self, buffer: &str, variable: &str, value: &[u8]) -> Result<()> {
+        let (vs, ps) = (self.vs.reflection(), self.ps.reflection());
+        let buffer_desc = vs
+            .constant_buffer(buffer)
+            .or_else(|| ps.constant_buffer(buffer))
+            .ok_or_else(|| error::Custom(format!("Shaders have no cbuffer {}", buffer)))?;
+        let variable_desc = buffer_desc
+            .variables
+            .iter()
+            .find(|desc| desc.name == variable)
+            .ok_or_else(|| error::Custom(format!("cbuffer {} has no {}", buffer, variable)))?;
+        if value.len() > variable_desc.size {
+            return Err(error::Custom(format!(
+                "{} bytes don't fit in {}.{}, which has {}",
+                value.len(),
+                buffer,
+                variable,
+                variable_desc.size
+            )));
+        }
+
+        let idx = buffer_desc.slot as usize;
+        if self.raw_constants.len() <= idx {
+            self.raw_constants.resize_with(idx + 1, || None);
+        }
+        self.raw_constants[idx]
+            .get_or_insert_with(|| RawConstants::new(buffer_desc.size))
+            .write(variable_desc.offset, value);
+        Ok(())
     }
 
+    /// Updates the constant buffer in slot `idx`. Prefer `set_constants`, which finds the slot.
     pub fn set_data<A: Any + Send + Sync>(
         &mut self,
         render: &Render,
@@ -90,10 +388,17 @@ impl Clone for Material {
     fn clone(&self) -> Self {
         Self {
             vs: self.vs.clone(),
+            instanced_vs: self.instanced_vs.clone(),
             ps: self.ps.clone(),
             const_buffs: Vec::new(),
+            raw_constants: self.raw_constants.clone(),
             textures: self.textures.clone(),
-            cull_mode: self.cull_mode.clone(),
+            samplers: self.samplers.clone(),
+            blend_mode: self.blend_mode,
+            depth_stencil: self.depth_stencil,
+            stencil_ref: self.stencil_ref,
+            raster: self.raster,
+            scissor: self.scissor.clone(),
         }
     }
 }
@@ -1,7 +1,71 @@
+use crate::error;
+use crate::graphics::resource::shader::{self, Constants, Pixel, ShaderType, Vertex};
+
+use std::path::Path;
+
+/// Keyword of vertex shaders that read each instance's world matrix and color from a
+/// second vertex stream, instead of the `transform` and `mesh_info` constant buffers.
+/// Templates listing it in `KEYWORDS` make materials that can be instanced.
+pub const INSTANCED: &str = "INSTANCED";
+
 /// Trait used to show that a struct is able to be used as input for a vertex shader
 pub trait Template {
     const PIXEL_SHADER_PATH: &'static str;
     const VERTEX_SHADER_PATH: &'static str;
+    /// Features the shaders can be compiled with, each `#define`d to 1.
+    /// Materials pick some, and every combination in use is compiled once.
+    const KEYWORDS: &'static [&'static str] = &[];
+
+    /// Checked against the shaders' `cbuffer` of the same name when a material is made.
+    type Environment: Constants;
+}
+
+/// Every combination of `keywords`, starting with none of them.
+pub fn keyword_combinations(keywords: &[&'static str]) -> Vec<Vec<&'static str>> {
+    let mut combinations = vec![Vec::new()];
+    for &keyword in keywords {
+        for idx in 0..combinations.len() {
+            let mut combination = combinations[idx].clone();
+            combination.push(keyword);
+            combinations.push(combination);
+        }
+    }
+    combinations
+}
+
+/// Compiles both shaders of every variant of a template into `cache_dir`.
+/// Returns how many shaders were compiled.
+pub fn precompile<T: Template>(cache_dir: &Path) -> error::Result<usize> {
+    let mut compiled = 0;
+    for keywords in keyword_combinations(T::KEYWORDS) {
+        shader::precompile(
+            shader::variant_path(T::VERTEX_SHADER_PATH, &keywords)?,
+            Vertex::ENTRY_POINT,
+            Vertex::TARGET,
+            cache_dir,
+        )?;
+        shader::precompile(
+            shader::variant_path(T::PIXEL_SHADER_PATH, &keywords)?,
+            Pixel::ENTRY_POINT,
+            Pixel::TARGET,
+            cache_dir,
+        )?;
+        compiled += 2;
+    }
+    Ok(compiled)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
 
-    type Environment;
+    #[test]
+    fn combinations() {
+        assert_eq!(keyword_combinations(&[]), [Vec::<&str>::new()]);
+        assert_eq!(
+            keyword_combinations(&["A", "B"]),
+            [vec![], vec!["A"], vec!["B"], vec!["A", "B"]]
+        );
+        assert_eq!(keyword_combinations(&["A", "B", "C"]).len(), 8);
+    }
 }
@@ -1,20 +1,28 @@
 pub mod color;
+pub mod light_list;
 pub mod material;
 pub mod render;
 pub mod resource;
 pub mod vertex;
 
-use material::Material;
-use render::Render;
+use material::{Material, MaterialFileManager};
+use render::{Render, Sampler, SamplerDesc};
 use resource::mesh::{Mesh, MeshManager};
-use resource::shader::{Pixel, Shader, ShaderManager, Vertex};
-use resource::texture::{Texture, TextureManager};
+use resource::shader::{self, Pixel, Shader, ShaderManager, Vertex};
+use resource::texture::{CubeMap, CubeMapManager, Texture, TextureAtlas, TextureManager};
+use resource::{LoadHandle, LoadProgress, ResourceStats};
 
 use crate::error;
+use crate::vfs::VFS;
 
 use std::path::Path;
 use std::sync::{Arc, Mutex};
 
+/// Processed meshes are kept here, relative to the root of the virtual file system.
+pub const MESH_CACHE_DIR: &str = "cache/meshes";
+/// Compiled shaders, by a hash of their source. `shaderc` fills it ahead of time.
+pub const SHADER_CACHE_DIR: &str = "cache/shaders";
+
 lazy_static! {
     pub static ref GRAPHICS: Mutex<Graphics> = Mutex::new(Graphics::new().unwrap());
 }
@@ -23,18 +31,24 @@ pub struct Graphics {
     pub render: Render,
     pub mesh_manager: MeshManager,
     pub texture_manager: TextureManager,
+    pub cube_map_manager: CubeMapManager,
     pub vs_manager: ShaderManager<Vertex>,
     pub ps_manager: ShaderManager<Pixel>,
+    pub material_manager: MaterialFileManager,
 }
 
 impl Graphics {
     pub fn new() -> error::Result<Self> {
+        let root = VFS.read().unwrap().root().to_owned();
+        let shader_cache_dir = root.join(SHADER_CACHE_DIR);
         Ok(Self {
             render: Render::new()?,
-            mesh_manager: MeshManager::new(),
+            mesh_manager: MeshManager::with_cache_dir(root.join(MESH_CACHE_DIR)),
             texture_manager: TextureManager::new(),
-            vs_manager: ShaderManager::new(),
-            ps_manager: ShaderManager::new(),
+            cube_map_manager: CubeMapManager::new(),
+            vs_manager: ShaderManager::with_cache_dir(&shader_cache_dir),
+            ps_manager: ShaderManager::with_cache_dir(shader_cache_dir),
+            material_manager: MaterialFileManager::new(),
         })
     }
 
@@ -43,11 +57,58 @@ impl Graphics {
             .get_resource_from_file(self.render.device(), path)
     }
 
+    pub fn get_atlas_from_file(&mut self, path: impl AsRef<Path>) -> error::Result<TextureAtlas> {
+        self.texture_manager
+            .get_atlas_from_file(self.render.device(), path)
+    }
+
+    pub fn get_cube_map_from_file(
+        &mut self,
+        path: impl AsRef<Path>,
+    ) -> error::Result<Arc<CubeMap>> {
+        self.cube_map_manager
+            .get_resource_from_file(self.render.device(), path)
+    }
+
+    /// Equal descriptions return the same sampler.
+    pub fn get_sampler(&self, desc: &SamplerDesc) -> error::Result<Arc<Sampler>> {
+        self.render.device().sampler(desc)
+    }
+
     pub fn get_mesh_from_file(&mut self, path: impl AsRef<Path>) -> error::Result<Arc<Mesh>> {
         self.mesh_manager
             .get_resource_from_file(self.render.device(), path)
     }
 
+    /// Starts loading a mesh in the background. See `ResourceManager::load_resource_async`.
+    pub fn load_mesh_async(&mut self, path: impl AsRef<Path>) -> error::Result<LoadHandle<Mesh>> {
+        self.mesh_manager
+            .load_resource_async(self.render.device(), path)
+    }
+
+    pub fn load_texture_async(
+        &mut self,
+        path: impl AsRef<Path>,
+    ) -> error::Result<LoadHandle<Texture>> {
+        self.texture_manager
+            .load_resource_async(self.render.device(), path)
+    }
+
+    pub fn load_cube_map_async(
+        &mut self,
+        path: impl AsRef<Path>,
+    ) -> error::Result<LoadHandle<CubeMap>> {
+        self.cube_map_manager
+            .load_resource_async(self.render.device(), path)
+    }
+
+    /// Progress of every background load so far.
+    pub fn loading_progress(&self) -> LoadProgress {
+        self.mesh_manager.progress()
+            + self.texture_manager.progress()
+            + self.cube_map_manager.progress()
+    }
+
     pub fn get_vertex_shader_from_file(
         &mut self,
         path: impl AsRef<Path>,
@@ -64,7 +125,94 @@ impl Graphics {
             .get_resource_from_file(self.render.device(), path)
     }
 
+    /// The shader at `path` compiled with each keyword defined. Every combination
+    /// of keywords is compiled once.
+    pub fn get_vertex_shader_variant(
+        &mut self,
+        path: impl AsRef<Path>,
+        keywords: &[&str],
+    ) -> error::Result<Arc<Shader<Vertex>>> {
+        self.vs_manager
+            .get_resource_from_file(self.render.device(), shader::variant_path(path, keywords)?)
+    }
+
+    pub fn get_pixel_shader_variant(
+        &mut self,
+        path: impl AsRef<Path>,
+        keywords: &[&str],
+    ) -> error::Result<Arc<Shader<Pixel>>> {
+        self.ps_manager
+            .get_resource_from_file(self.render.device(), shader::variant_path(path, keywords)?)
+    }
+
+    /// Uploads finished background loads, reloads changed files and keeps
+    /// managers within their budgets.
+    /// The window calls this once a frame, before the application updates.
+    pub fn update_resources(&mut self) {
+        self.finish_loading();
+        self.reload_changed_resources();
+        self.mesh_manager.enforce_budget();
+        self.texture_manager.enforce_budget();
+        self.cube_map_manager.enforce_budget();
+        self.vs_manager.enforce_budget();
+        self.ps_manager.enforce_budget();
+        self.material_manager.enforce_budget();
+    }
+
+    /// Drops every cached resource nothing else holds. Returns how many were dropped.
+    pub fn purge_unused_resources(&mut self) -> usize {
+        self.mesh_manager.purge_unused()
+            + self.texture_manager.purge_unused()
+            + self.cube_map_manager.purge_unused()
+            + self.vs_manager.purge_unused()
+            + self.ps_manager.purge_unused()
+            + self.material_manager.purge_unused()
+    }
+
+    /// What each resource manager holds, by name.
+    pub fn resource_stats(&self) -> [(&'static str, ResourceStats); 6] {
+        [
+            ("meshes", self.mesh_manager.stats()),
+            ("textures", self.texture_manager.stats()),
+            ("cube maps", self.cube_map_manager.stats()),
+            ("vertex shaders", self.vs_manager.stats()),
+            ("pixel shaders", self.ps_manager.stats()),
+            ("material files", self.material_manager.stats()),
+        ]
+    }
+
+    /// Uploads resources decoded in the background. Returns how many finished.
+    pub fn finish_loading(&mut self) -> usize {
+        let device = self.render.device();
+        self.mesh_manager.finish_loading(device)
+            + self.texture_manager.finish_loading(device)
+            + self.cube_map_manager.finish_loading(device)
+    }
+
+    /// Reloads every resource whose files changed on disk since the last call.
+    /// Failures are logged and the old versions kept.
+    pub fn reload_changed_resources(&mut self) -> usize {
+        let device = self.render.device();
+        self.mesh_manager.reload_changed(device)
+            + self.texture_manager.reload_changed(device)
+            + self.cube_map_manager.reload_changed(device)
+            + self.vs_manager.reload_changed(device)
+            + self.ps_manager.reload_changed(device)
+            + self.material_manager.reload_changed(device)
+    }
+
     pub fn new_material<T: material::Template>(&mut self) -> error::Result<Material> {
         Material::new::<T>(self)
     }
+
+    /// Builds a material from a `.material` file. The file is parsed once, but each
+    /// call returns a new material, so changing one doesn't change the others.
+    /// Reloading the file only affects materials built afterwards.
+    pub fn get_material_from_file(&mut self, path: impl AsRef<Path>) -> error::Result<Material> {
+        let file = self
+            .material_manager
+            .get_resource_from_file(self.render.device(), path)?;
+        let desc = file.desc();
+        Material::from_desc(self, &desc)
+    }
 }
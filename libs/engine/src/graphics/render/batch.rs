@@ -0,0 +1,39 @@
+use std::collections::HashMap;
+
+/// Groups sorted draws into batches drawn with one instanced draw call each.
+///
+/// Draws with equal keys join the batch of the first of them, wherever they are in
+/// the order; draws without a key are batches of their own. Batches are in the order
+/// of their first draw, and hold indices into `keys`.
+pub fn batches(keys: impl IntoIterator<Item = Option<u64>>) -> Vec<Vec<usize>> {
+    let mut batches: Vec<Vec<usize>> = Vec::new();
+    let mut by_key: HashMap<u64, usize> = HashMap::new();
+    for (idx, key) in keys.into_iter().enumerate() {
+        match key.and_then(|key| by_key.get(&key)) {
+            Some(&batch) => batches[batch].push(idx),
+            None => {
+                if let Some(key) = key {
+                    by_key.insert(key, batches.len());
+                }
+                batches.push(vec![idx]);
+            }
+        }
+    }
+    batches
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn groups_keys() {
+        let keys = [Some(1), None, Some(2), Some(1), None, Some(2), Some(3)];
+        assert_eq!(
+            batches(keys),
+            [vec![0, 3], vec![1], vec![2, 5], vec![4], vec![6]]
+        );
+        assert!(batches(None).is_empty());
+        assert_eq!(batches([None, None]), [vec![0], vec![1]]);
+    }
+}
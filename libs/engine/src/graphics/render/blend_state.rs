@@ -0,0 +1,93 @@
+use super::{Device, DeviceState};
+
+use crate::error;
+use crate::util::get_output;
+
+use std::ptr::NonNull;
+
+use winapi::shared::minwindef;
+use winapi::um::d3d11;
+
+/// How a pixel shader's output is combined with what is already drawn.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    /// Replaces what is behind it.
+    #[default]
+    Opaque,
+    /// Mixed with what is behind it by alpha, for glass and fading sprites.
+    Alpha,
+    /// Added to what is behind it, scaled by alpha, for glows and particles.
+    Additive,
+    /// Color already multiplied by alpha, added over what is behind it faded by alpha.
+    /// Mixes alpha blended and additive pixels in one material.
+    Premultiplied,
+}
+
+impl BlendMode {
+    /// Source and destination factors, or `None` if blending is off.
+    fn factors(self) -> Option<(d3d11::D3D11_BLEND, d3d11::D3D11_BLEND)> {
+        match self {
+            Self::Opaque => None,
+            Self::Alpha => Some((
+                d3d11::D3D11_BLEND_SRC_ALPHA,
+                d3d11::D3D11_BLEND_INV_SRC_ALPHA,
+            )),
+            Self::Additive => Some((d3d11::D3D11_BLEND_SRC_ALPHA, d3d11::D3D11_BLEND_ONE)),
+            Self::Premultiplied => Some((d3d11::D3D11_BLEND_ONE, d3d11::D3D11_BLEND_INV_SRC_ALPHA)),
+        }
+    }
+}
+
+pub struct BlendState(NonNull<d3d11::ID3D11BlendState>);
+
+//TODO FIXME Verify
+unsafe impl Send for BlendState {}
+unsafe impl Sync for BlendState {}
+
+impl BlendState {
+    pub fn as_ptr(&self) -> *mut d3d11::ID3D11BlendState {
+        self.0.as_ptr()
+    }
+}
+
+impl DeviceState for BlendState {
+    type Desc = BlendMode;
+
+    fn create(device: &Device, mode: &BlendMode) -> error::Result<Self> {
+        unsafe {
+            let factors = mode.factors();
+            let (src, dest) = factors.unwrap_or((d3d11::D3D11_BLEND_ONE, d3d11::D3D11_BLEND_ZERO));
+            let mut desc = d3d11::D3D11_BLEND_DESC::default();
+            desc.RenderTarget[0] = d3d11::D3D11_RENDER_TARGET_BLEND_DESC {
+                BlendEnable: if factors.is_some() {
+                    minwindef::TRUE
+                } else {
+                    minwindef::FALSE
+                },
+                SrcBlend: src,
+                DestBlend: dest,
+                BlendOp: d3d11::D3D11_BLEND_OP_ADD,
+                SrcBlendAlpha: d3d11::D3D11_BLEND_ONE,
+                DestBlendAlpha: d3d11::D3D11_BLEND_INV_SRC_ALPHA,
+                BlendOpAlpha: d3d11::D3D11_BLEND_OP_ADD,
+                RenderTargetWriteMask: d3d11::D3D11_COLOR_WRITE_ENABLE_ALL as u8,
+            };
+
+            get_output(|ptr| device.as_ref().CreateBlendState(&desc, ptr)).map(Self)
+        }
+    }
+}
+
+impl AsRef<d3d11::ID3D11BlendState> for BlendState {
+    fn as_ref(&self) -> &d3d11::ID3D11BlendState {
+        unsafe { self.0.as_ref() }
+    }
+}
+
+impl Drop for BlendState {
+    fn drop(&mut self) {
+        unsafe {
+            self.as_ref().Release();
+        }
+    }
+}
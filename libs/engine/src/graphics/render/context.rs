@@ -1,14 +1,18 @@
 use super::shader::{self, Shader, ShaderType};
-use super::{ConstantBuffer, IndexBuffer, Target, VertexBuffer};
+use super::{
+    BlendState, ConstantBuffer, DepthState, IndexBuffer, InstanceBuffer, RasterState, Sampler,
+    Target, VertexBuffer,
+};
 
 use crate::error;
 use crate::graphics::material::Texture;
 use crate::graphics::vertex::{Color, Vertex};
+use crate::math::Rect;
 
-use std::ptr::NonNull;
+use std::ptr::{self, NonNull};
 use std::sync::Arc;
 
-use winapi::shared::dxgiformat;
+use winapi::shared::{dxgiformat, windef};
 use winapi::um::d3d11;
 use winapi::um::d3dcommon;
 
@@ -87,12 +91,39 @@ impl Context {
         }
     }
 
+    /// Binds vertices in slot 0 and instances in slot 1, with the layout reading both.
+    pub fn set_instanced_vertex_buffers<V: Vertex, I: Vertex>(
+        &self,
+        vertex_buffer: &mut VertexBuffer<V>,
+        instance_buffer: &mut InstanceBuffer<I>,
+    ) {
+        unsafe {
+            self.as_ref().IASetVertexBuffers(
+                0,
+                2,
+                [vertex_buffer.buffer_ptr(), instance_buffer.buffer_ptr()].as_ptr(),
+                [
+                    std::mem::size_of::<V>() as u32,
+                    std::mem::size_of::<I>() as u32,
+                ]
+                .as_ptr(),
+                [0, 0].as_ptr(),
+            );
+            self.as_ref().IASetInputLayout(instance_buffer.layout_ptr());
+        }
+    }
+
     pub fn set_shader<S: ShaderType>(&self, shader: Arc<Shader<S>>) {
-        S::set_shader(self, shader.as_ref().as_ref());
+        let interface = shader.interface();
+        S::set_shader(self, unsafe { interface.as_ref() });
     }
 
-    pub fn set_textures<S: ShaderType>(&self, textures: &mut [Option<Arc<dyn Texture>>]) {
-        S::set_textures(self, textures);
+    pub fn set_textures<S: ShaderType>(
+        &self,
+        textures: &mut [Option<Arc<dyn Texture>>],
+        samplers: &[Option<Arc<Sampler>>],
+    ) {
+        S::set_textures(self, textures, samplers);
     }
 
     pub fn draw_triangle_list(&self, vertices_len: usize, vertices_start: usize) {
@@ -113,6 +144,15 @@ impl Context {
         }
     }
 
+    /// Draws one triangle over the whole viewport without any buffers.
+    /// The vertex shader places it from `SV_VertexID`.
+    pub fn draw_fullscreen_triangle(&self) {
+        unsafe {
+            self.as_ref().IASetInputLayout(ptr::null_mut());
+        }
+        self.draw_triangle_list(3, 0);
+    }
+
     pub fn draw_indexed_triangle_list(
         &self,
         indices_len: usize,
@@ -130,6 +170,29 @@ impl Context {
         }
     }
 
+    /// Draws `instances_len` copies of the indexed triangles, reading instances from
+    /// `instances_start` on.
+    pub fn draw_indexed_instanced_triangle_list(
+        &self,
+        indices_len: usize,
+        indices_start: usize,
+        vertices_offset: isize,
+        instances_len: usize,
+        instances_start: usize,
+    ) {
+        unsafe {
+            self.as_ref()
+                .IASetPrimitiveTopology(d3dcommon::D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST);
+            self.as_ref().DrawIndexedInstanced(
+                indices_len as u32,
+                instances_len as u32,
+                indices_start as u32,
+                vertices_offset as i32,
+                instances_start as u32,
+            );
+        }
+    }
+
     pub fn set_viewport_size(&self, width: f32, height: f32) {
         unsafe {
             let vp = d3d11::D3D11_VIEWPORT {
@@ -143,6 +206,40 @@ impl Context {
             self.as_ref().RSSetViewports(1, &vp);
         }
     }
+
+    pub fn set_blend_state(&self, state: &BlendState) {
+        unsafe {
+            self.as_ref()
+                .OMSetBlendState(state.as_ptr(), &[0.0; 4], 0xffff_ffff);
+        }
+    }
+
+    /// `stencil_ref` is what stencil tests compare with and `StencilOp::Replace` writes.
+    pub fn set_depth_stencil_state(&self, state: &DepthState, stencil_ref: u32) {
+        unsafe {
+            self.as_ref()
+                .OMSetDepthStencilState(state.as_ptr(), stencil_ref);
+        }
+    }
+
+    pub fn set_raster_state(&self, state: &RasterState) {
+        unsafe {
+            self.as_ref().RSSetState(state.as_ptr());
+        }
+    }
+
+    /// Pixels outside `rect` are discarded by raster states with scissor on.
+    pub fn set_scissor_rect(&self, rect: &Rect<i32>) {
+        unsafe {
+            let rect = windef::RECT {
+                left: rect.0[0].start,
+                top: rect.0[1].start,
+                right: rect.0[0].end,
+                bottom: rect.0[1].end,
+            };
+            self.as_ref().RSSetScissorRects(1, &rect);
+        }
+    }
 }
 
 impl AsRef<d3d11::ID3D11DeviceContext> for Context {
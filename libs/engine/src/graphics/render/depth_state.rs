@@ -0,0 +1,205 @@
+use super::{ComparisonFunc, Device, DeviceState};
+
+use crate::error;
+use crate::util::get_output;
+
+use std::ptr::NonNull;
+
+use winapi::shared::minwindef;
+use winapi::um::d3d11;
+
+/// What happens to the stencil value of a pixel.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum StencilOp {
+    #[default]
+    Keep,
+    Zero,
+    /// Writes the material's stencil reference.
+    Replace,
+    /// Adds one, stopping at the maximum.
+    IncrementClamp,
+    /// Subtracts one, stopping at zero.
+    DecrementClamp,
+    Invert,
+    /// Adds one, wrapping to zero.
+    Increment,
+    /// Subtracts one, wrapping to the maximum.
+    Decrement,
+}
+
+impl StencilOp {
+    fn to_d3d(self) -> d3d11::D3D11_STENCIL_OP {
+        match self {
+            Self::Keep => d3d11::D3D11_STENCIL_OP_KEEP,
+            Self::Zero => d3d11::D3D11_STENCIL_OP_ZERO,
+            Self::Replace => d3d11::D3D11_STENCIL_OP_REPLACE,
+            Self::IncrementClamp => d3d11::D3D11_STENCIL_OP_INCR_SAT,
+            Self::DecrementClamp => d3d11::D3D11_STENCIL_OP_DECR_SAT,
+            Self::Invert => d3d11::D3D11_STENCIL_OP_INVERT,
+            Self::Increment => d3d11::D3D11_STENCIL_OP_INCR,
+            Self::Decrement => d3d11::D3D11_STENCIL_OP_DECR,
+        }
+    }
+}
+
+/// The stencil test for triangles facing one way.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct StencilFace {
+    /// Compares the stencil reference with the stored value.
+    pub func: ComparisonFunc,
+    /// When the stencil test fails.
+    pub fail: StencilOp,
+    /// When the stencil test passes but the depth test fails.
+    pub depth_fail: StencilOp,
+    /// When both tests pass.
+    pub pass: StencilOp,
+}
+
+impl StencilFace {
+    fn to_d3d(self) -> d3d11::D3D11_DEPTH_STENCILOP_DESC {
+        d3d11::D3D11_DEPTH_STENCILOP_DESC {
+            StencilFailOp: self.fail.to_d3d(),
+            StencilDepthFailOp: self.depth_fail.to_d3d(),
+            StencilPassOp: self.pass.to_d3d(),
+            StencilFunc: self.func.to_d3d(),
+        }
+    }
+}
+
+/// Always passes and changes nothing.
+impl Default for StencilFace {
+    fn default() -> Self {
+        Self {
+            func: ComparisonFunc::Always,
+            fail: StencilOp::Keep,
+            depth_fail: StencilOp::Keep,
+            pass: StencilOp::Keep,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct StencilDesc {
+    /// Bits of the stored value and the reference that are compared.
+    pub read_mask: u8,
+    /// Bits of the stored value that are changed.
+    pub write_mask: u8,
+    pub front: StencilFace,
+    pub back: StencilFace,
+}
+
+impl Default for StencilDesc {
+    fn default() -> Self {
+        Self {
+            read_mask: d3d11::D3D11_DEFAULT_STENCIL_READ_MASK as u8,
+            write_mask: d3d11::D3D11_DEFAULT_STENCIL_WRITE_MASK as u8,
+            front: StencilFace::default(),
+            back: StencilFace::default(),
+        }
+    }
+}
+
+/// How a material is tested against and writes the depth-stencil buffer.
+/// Equal descriptions share one state on the device.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DepthStencilDesc {
+    pub depth_test: bool,
+    pub depth_write: bool,
+    pub depth_func: ComparisonFunc,
+    /// Stencil testing is off when `None`.
+    pub stencil: Option<StencilDesc>,
+}
+
+impl DepthStencilDesc {
+    /// Drawn on the far plane behind everything else, without writing depth.
+    pub fn background() -> Self {
+        Self {
+            depth_write: false,
+            depth_func: ComparisonFunc::LessEqual,
+            ..Self::default()
+        }
+    }
+
+    /// Hidden behind opaque geometry, without hiding what is drawn after it.
+    pub fn transparent() -> Self {
+        Self {
+            depth_write: false,
+            ..Self::default()
+        }
+    }
+
+    /// Drawn over everything, without testing or writing depth.
+    pub fn off() -> Self {
+        Self {
+            depth_test: false,
+            depth_write: false,
+            ..Self::default()
+        }
+    }
+
+    fn to_d3d(self) -> d3d11::D3D11_DEPTH_STENCIL_DESC {
+        let stencil = self.stencil.unwrap_or_default();
+        d3d11::D3D11_DEPTH_STENCIL_DESC {
+            DepthEnable: self.depth_test as minwindef::BOOL,
+            DepthWriteMask: if self.depth_write {
+                d3d11::D3D11_DEPTH_WRITE_MASK_ALL
+            } else {
+                d3d11::D3D11_DEPTH_WRITE_MASK_ZERO
+            },
+            DepthFunc: self.depth_func.to_d3d(),
+            StencilEnable: self.stencil.is_some() as minwindef::BOOL,
+            StencilReadMask: stencil.read_mask,
+            StencilWriteMask: stencil.write_mask,
+            FrontFace: stencil.front.to_d3d(),
+            BackFace: stencil.back.to_d3d(),
+        }
+    }
+}
+
+/// Depth tested and written.
+impl Default for DepthStencilDesc {
+    fn default() -> Self {
+        Self {
+            depth_test: true,
+            depth_write: true,
+            depth_func: ComparisonFunc::Less,
+            stencil: None,
+        }
+    }
+}
+
+pub struct DepthState(NonNull<d3d11::ID3D11DepthStencilState>);
+
+//TODO FIXME Verify
+unsafe impl Send for DepthState {}
+unsafe impl Sync for DepthState {}
+
+impl DepthState {
+    pub fn as_ptr(&self) -> *mut d3d11::ID3D11DepthStencilState {
+        self.0.as_ptr()
+    }
+}
+
+impl DeviceState for DepthState {
+    type Desc = DepthStencilDesc;
+
+    fn create(device: &Device, desc: &DepthStencilDesc) -> error::Result<Self> {
+        unsafe {
+            get_output(|ptr| device.as_ref().CreateDepthStencilState(&desc.to_d3d(), ptr)).map(Self)
+        }
+    }
+}
+
+impl AsRef<d3d11::ID3D11DepthStencilState> for DepthState {
+    fn as_ref(&self) -> &d3d11::ID3D11DepthStencilState {
+        unsafe { self.0.as_ref() }
+    }
+}
+
+impl Drop for DepthState {
+    fn drop(&mut self) {
+        unsafe {
+            self.as_ref().Release();
+        }
+    }
+}
@@ -1,18 +1,23 @@
 use crate::prelude::*;
 
 use crate::error;
-use crate::graphics::render::{ConstantBuffer, IndexBuffer, SwapChain, VertexBuffer};
+use crate::graphics::render::{
+    BlendMode, BlendState, ConstantBuffer, DepthState, DepthStencilDesc, IndexBuffer,
+    InstanceBuffer, RasterDesc, RasterState, Sampler, SamplerDesc, StateCaches, SwapChain,
+    VertexBuffer,
+};
 use crate::graphics::vertex::Vertex;
 use crate::util::get_output;
 use crate::window::Hwnd;
 
 use std::ptr::{self, NonNull};
+use std::sync::Arc;
 
 use winapi::shared::dxgi;
 use winapi::um::d3d11;
 use winapi::um::d3d11sdklayers::{ID3D11Debug, D3D11_RLDO_DETAIL};
 
-pub struct Device(NonNull<d3d11::ID3D11Device>);
+pub struct Device(NonNull<d3d11::ID3D11Device>, StateCaches);
 
 // https://docs.microsoft.com/en-us/windows/win32/direct3d11/overviews-direct3d-11-render-multi-thread-intro
 unsafe impl Send for Device {}
@@ -23,7 +28,7 @@ impl Device {
     ///
     /// `device` must point to a valid `ID3D11Device`
     pub unsafe fn from_nonnull(device: NonNull<d3d11::ID3D11Device>) -> error::Result<Self> {
-        Ok(Self(device))
+        Ok(Self(device, StateCaches::default()))
     }
 
     pub fn new_swapchain(&mut self, hwnd: &Hwnd) -> error::Result<SwapChain> {
@@ -65,6 +70,45 @@ impl Device {
         VertexBuffer::new(self, vertices, bytecode)
     }
 
+    /// Room for `capacity` instances of `I`, drawn with vertex buffers of `V`.
+    pub fn new_instance_buffer<V: Vertex, I: Vertex>(
+        &self,
+        capacity: usize,
+    ) -> error::Result<InstanceBuffer<I>> {
+        InstanceBuffer::new::<V>(self, capacity)
+    }
+
+    /// Sampler state for `desc`, shared with every other user of the same description.
+    pub fn sampler(&self, desc: &SamplerDesc) -> error::Result<Arc<Sampler>> {
+        self.1.samplers.get(self, desc)
+    }
+
+    /// Blend state for `mode`, shared like samplers.
+    pub fn blend_state(&self, mode: BlendMode) -> error::Result<Arc<BlendState>> {
+        self.1.blend.get(self, &mode)
+    }
+
+    /// Depth-stencil state for `desc`, shared like samplers.
+    pub fn depth_stencil_state(&self, desc: &DepthStencilDesc) -> error::Result<Arc<DepthState>> {
+        self.1.depth_stencil.get(self, desc)
+    }
+
+    /// Rasterizer state for `desc`, shared like samplers.
+    pub fn raster_state(&self, desc: &RasterDesc) -> error::Result<Arc<RasterState>> {
+        self.1.raster.get(self, desc)
+    }
+
+    /// How many different pipeline states have been created, as
+    /// samplers, blend, depth-stencil and rasterizer states.
+    pub fn state_counts(&self) -> [usize; 4] {
+        [
+            self.1.samplers.count(),
+            self.1.blend.count(),
+            self.1.depth_stencil.count(),
+            self.1.raster.count(),
+        ]
+    }
+
     pub fn debug(&self) -> error::Result<()> {
         unsafe {
             let debug = self.as_ref().query_interface::<ID3D11Debug>()?;
@@ -0,0 +1,157 @@
+use super::{Context, Device};
+
+use crate::error::{self, HResultToResult};
+use crate::graphics::resource::shader;
+use crate::graphics::vertex::{self, input_signature, SemanticIndexFix, Vertex};
+use crate::util::get_output;
+
+use std::ptr::{self, NonNull};
+
+use winapi::um::d3d11;
+
+//needed for custom derive
+use crate::{self as engine};
+
+/// What `INSTANCED` shaders read for each instance, in place of the `transform` and
+/// `mesh_info` constant buffers.
+#[derive(Clone, Debug, Default, Vertex)]
+#[vertex(instance)]
+#[repr(C)]
+pub struct Instance {
+    pub world: vertex::World,
+    pub color: vertex::Color,
+    /// Set by `RenderQueue` from its `LightList`.
+    pub lights: vertex::LightIndices,
+}
+
+/// Per-instance data drawn alongside a vertex buffer, rewritten every frame.
+pub struct InstanceBuffer<I: Vertex> {
+    len: usize,
+    capacity: usize,
+    buffer: NonNull<d3d11::ID3D11Buffer>,
+    /// Vertices in slot 0 and instances in slot 1.
+    layout: NonNull<d3d11::ID3D11InputLayout>,
+    _phantom: std::marker::PhantomData<I>,
+}
+
+//TODO FIXME Verify
+unsafe impl<I> Send for InstanceBuffer<I> where I: Vertex + Send {}
+unsafe impl<I> Sync for InstanceBuffer<I> where I: Vertex + Sync {}
+
+impl<I: Vertex> InstanceBuffer<I> {
+    /// Room for `capacity` instances, drawn with vertex buffers of `V`.
+    pub fn new<V: Vertex>(device: &Device, capacity: usize) -> error::Result<Self> {
+        let layout_desc: Vec<_> = V::desc(0)
+            .chain(I::desc(0).map(|mut desc| {
+                desc.InputSlot = 1;
+                desc
+            }))
+            .semantic_index_fix()
+            .collect();
+        let signature = input_signature(&layout_desc)?;
+        let bytecode = shader::compile_shader(signature.as_bytes(), "vsmain", "vs_5_0")?;
+
+        unsafe {
+            let layout = get_output(|ptr| {
+                device.as_ref().CreateInputLayout(
+                    layout_desc.as_ptr(),
+                    layout_desc.len() as u32,
+                    bytecode.as_ptr().cast(),
+                    bytecode.len(),
+                    ptr,
+                )
+            })?;
+            let buffer = match Self::create_buffer(device, capacity) {
+                Ok(buffer) => buffer,
+                Err(e) => {
+                    layout.as_ref().Release();
+                    return Err(e);
+                }
+            };
+
+            Ok(Self {
+                len: 0,
+                capacity: capacity.max(1),
+                buffer,
+                layout,
+                _phantom: Default::default(),
+            })
+        }
+    }
+
+    fn create_buffer(
+        device: &Device,
+        capacity: usize,
+    ) -> error::Result<NonNull<d3d11::ID3D11Buffer>> {
+        let buff_desc = d3d11::D3D11_BUFFER_DESC {
+            Usage: d3d11::D3D11_USAGE_DYNAMIC,
+            ByteWidth: (capacity.max(1) * std::mem::size_of::<I>()) as u32,
+            BindFlags: d3d11::D3D11_BIND_VERTEX_BUFFER,
+            CPUAccessFlags: d3d11::D3D11_CPU_ACCESS_WRITE,
+            MiscFlags: 0,
+            ..Default::default()
+        };
+        unsafe { get_output(|ptr| device.as_ref().CreateBuffer(&buff_desc, ptr::null(), ptr)) }
+    }
+
+    /// Replaces the instances, growing the buffer if they don't fit.
+    pub fn write(
+        &mut self,
+        device: &Device,
+        context: &Context,
+        instances: &[I],
+    ) -> error::Result<()> {
+        if instances.len() > self.capacity {
+            let capacity = instances.len().next_power_of_two();
+            let buffer = Self::create_buffer(device, capacity)?;
+            unsafe {
+                self.buffer.as_ref().Release();
+            }
+            self.buffer = buffer;
+            self.capacity = capacity;
+        }
+
+        unsafe {
+            let resource = self.buffer.as_ptr().cast();
+            let mut mapped = d3d11::D3D11_MAPPED_SUBRESOURCE::default();
+            context
+                .as_ref()
+                .Map(resource, 0, d3d11::D3D11_MAP_WRITE_DISCARD, 0, &mut mapped)
+                .result()?;
+            ptr::copy_nonoverlapping(instances.as_ptr(), mapped.pData.cast(), instances.len());
+            context.as_ref().Unmap(resource, 0);
+        }
+        self.len = instances.len();
+        Ok(())
+    }
+
+    pub fn buffer_ptr(&mut self) -> *mut d3d11::ID3D11Buffer {
+        self.buffer.as_ptr()
+    }
+
+    pub fn layout_ptr(&mut self) -> *mut d3d11::ID3D11InputLayout {
+        self.layout.as_ptr()
+    }
+
+    /// Instances last written.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        0 == self.len
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+impl<I: Vertex> Drop for InstanceBuffer<I> {
+    fn drop(&mut self) {
+        unsafe {
+            self.buffer.as_ref().Release();
+            self.layout.as_ref().Release();
+        }
+    }
+}
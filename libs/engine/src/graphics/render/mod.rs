@@ -1,38 +1,77 @@
+mod batch;
+mod blend_state;
 mod constant_buffer;
 mod context;
+mod depth_state;
 mod device;
 mod index_buffer;
+mod instance_buffer;
+mod queue;
 mod raster_state;
 pub mod rendered_texture;
+mod sampler;
+mod sort_key;
+mod state_cache;
+mod stats;
 mod swapchain;
 mod target;
 mod vertex_buffer;
 
+pub use blend_state::{BlendMode, BlendState};
 pub use constant_buffer::ConstantBuffer;
 pub use context::Context;
+pub use depth_state::{DepthState, DepthStencilDesc, StencilDesc, StencilFace, StencilOp};
 pub use device::Device;
 pub use index_buffer::IndexBuffer;
-use raster_state::RasterState;
+pub use instance_buffer::{Instance, InstanceBuffer};
+pub use queue::{render_layer, RenderQueue};
+pub use raster_state::{CullMode, FillMode, RasterDesc, RasterState};
 pub use rendered_texture::RenderedTexture;
+pub use sampler::{AddressMode, ComparisonFunc, FilterMode, Sampler, SamplerDesc};
+pub use sort_key::{RenderLayer, SortKey};
+pub use state_cache::DeviceState;
+use state_cache::StateCaches;
+pub use stats::RenderStats;
 pub use swapchain::{SwapChain, WindowState};
 pub use target::Target;
 pub use vertex_buffer::VertexBuffer;
 
 use crate::error;
-use crate::graphics::material::{CullMode, Material};
+use crate::graphics::material::{Material, Texture};
+use crate::graphics::resource::mesh::{MeshInner, MeshVertex};
 use crate::graphics::resource::{shader, Mesh};
+use crate::graphics::vertex::Vertex;
 use crate::util::get_output2;
 
 use log::warn;
+use std::ops::Range;
 use std::ptr::null_mut;
+use std::sync::Arc;
 use winapi::um::{d3d11, d3dcommon};
 
 pub struct Render {
     device: Device,
     _feature_level: d3dcommon::D3D_FEATURE_LEVEL,
     context: Context,
-    raster_front: RasterState,
-    raster_back: RasterState,
+    bound: Bindings,
+    stats: RenderStats,
+    /// Instances of every instanced draw of the last queue, made on first use.
+    instances: Option<InstanceBuffer<Instance>>,
+}
+
+/// What was last bound on the context, by address, so binding it again can be skipped.
+/// Reset before each batch of draws, as resources can change in place between them.
+#[derive(Default)]
+struct Bindings {
+    vs: usize,
+    ps: usize,
+    raster: usize,
+    depth_stencil: (usize, u32),
+    blend: usize,
+    scissor: Option<[i32; 4]>,
+    /// And whether the instance buffer was bound with it.
+    vertex_buffer: (usize, bool),
+    index_buffer: usize,
 }
 
 const DRIVER_TYPES: [d3dcommon::D3D_DRIVER_TYPE; 3] = [
@@ -72,15 +111,14 @@ impl Render {
             }
             let (device, context) = result?;
             let device = Device::from_nonnull(device)?;
-            let raster_front = RasterState::new_front(&device)?;
-            let raster_back = RasterState::new_back(&device)?;
 
             Ok(Self {
                 device,
                 _feature_level: feature_level,
                 context: Context::from_nonnull(context)?,
-                raster_front,
-                raster_back,
+                bound: Bindings::default(),
+                stats: RenderStats::default(),
+                instances: None,
             })
         }
     }
@@ -97,53 +135,342 @@ impl Render {
         &self.context
     }
 
+    /// What drawing cost since the last call.
+    pub fn take_stats(&mut self) -> RenderStats {
+        std::mem::take(&mut self.stats)
+    }
+
     pub fn set_material(&mut self, material: &mut Material) {
+        self.bound = Bindings::default();
+        self.bind_material(material, false);
+    }
+
+    /// Binds a material, skipping shaders and states that are already bound. `instanced`
+    /// binds its `instanced_vs`, if it has one.
+    fn bind_material(&mut self, material: &mut Material, instanced: bool) {
+        self.stats.material_changes += 1;
+        for (idx, raw_constants) in material.raw_constants.iter_mut().enumerate() {
+            let typed = matches!(material.const_buffs.get(idx), Some(Some(_)));
+            if let (Some(raw_constants), false) = (raw_constants, typed) {
+                match raw_constants.upload(&self.device, &self.context) {
+                    Ok(buffer) => self.context.set_constant_buffer(idx as u32, buffer),
+                    Err(e) => warn!("Could not upload constants in slot {}: {}", idx, e),
+                }
+            }
+        }
         for (idx, const_buff) in material.const_buffs.iter_mut().enumerate() {
             if let Some((const_buff, _)) = const_buff {
                 self.context.set_constant_buffer(idx as u32, const_buff);
             }
         }
 
-        match material.cull_mode {
-            CullMode::Front => self.set_front_face_culling(),
-            CullMode::Back => self.set_back_face_culling(),
-        };
+        if let Err(e) = self.set_pipeline_state(material) {
+            warn!("Could not set pipeline state: {}", e);
+        }
 
-        self.context.set_shader(material.vs.clone());
-        self.context.set_shader(material.ps.clone());
+        let vs = match (&material.instanced_vs, instanced) {
+            (Some(instanced_vs), true) => instanced_vs,
+            _ => &material.vs,
+        };
+        if self.bound.vs != Arc::as_ptr(vs) as usize {
+            self.context.set_shader(vs.clone());
+            self.bound.vs = Arc::as_ptr(vs) as usize;
+            self.stats.shader_changes += 1;
+        }
+        let ps = Arc::as_ptr(&material.ps) as usize;
+        if self.bound.ps != ps {
+            self.context.set_shader(material.ps.clone());
+            self.bound.ps = ps;
+            self.stats.shader_changes += 1;
+        }
         self.context
-            .set_textures::<shader::Pixel>(&mut material.textures);
+            .set_textures::<shader::Pixel>(&mut material.textures, &material.samplers);
     }
 
     pub fn draw_mesh_and_materials(&mut self, mesh: &Mesh, materials: &mut [Material]) {
+        self.draw_mesh_lod_and_materials(mesh, 0, materials);
+    }
+
+    /// Draws a level of detail of a mesh. Falls back to the full mesh if `lod` doesn't exist.
+    pub fn draw_mesh_lod_and_materials(
+        &mut self,
+        mesh: &Mesh,
+        lod: usize,
+        materials: &mut [Material],
+    ) {
         let mut mesh_inner = mesh.inner();
-        for material_id in &mesh_inner.material_ids.clone() {
+        let MeshInner {
+            vertex_buffer,
+            index_buffer,
+            material_ids,
+            lods,
+            ..
+        } = &mut *mesh_inner;
+        let simplified = lod.checked_sub(1).and_then(|i| lods.get_mut(i));
+        let (index_buffer, material_ids) = match simplified {
+            Some(simplified) => (&mut simplified.index_buffer, &simplified.material_ids),
+            None => (index_buffer, &*material_ids),
+        };
+
+        self.bound = Bindings::default();
+        for material_id in material_ids {
             if let Some(material) = materials.get_mut(material_id.id) {
-                self.set_material(material);
+                self.bind_material(material, false);
             } else {
                 // TODO: set default material
                 warn!("Missing material for: {:#?}", material_id.name);
                 continue;
             };
 
-            self.context
-                .set_vertex_buffer(&mut mesh_inner.vertex_buffer);
-            self.context.set_index_buffer(&mut mesh_inner.index_buffer);
+            self.draw_indexed(
+                vertex_buffer,
+                index_buffer,
+                material_id.len,
+                material_id.offset,
+            );
+        }
+    }
+
+    /// Sorts and draws everything in `queue`. Opaque draws sharing shaders and
+    /// materials are drawn together, then backgrounds, then transparent draws from
+    /// back to front. Draws of the same submesh with materials that can be instanced
+    /// are drawn with one instanced draw call, using the constants of the first.
+    pub fn draw_queue(&mut self, mut queue: RenderQueue) {
+        queue.sort();
+        let RenderQueue {
+            culled,
+            drawn,
+            meshes,
+            mut materials,
+            instances,
+            items,
+            ..
+        } = queue;
+        self.stats.entities_drawn += drawn;
+        self.stats.entities_culled += culled;
+
+        // The instances of each instanced batch, one batch after another
+        let batches = batch::batches(items.iter().map(|item| item.batch));
+        let mut batch_instances = Vec::new();
+        let batch_ranges: Vec<Range<usize>> = batches
+            .iter()
+            .map(|batch| {
+                let start = batch_instances.len();
+                let first = &items[batch[0]];
+                if materials[first.object][first.material]
+                    .instanced_vs
+                    .is_some()
+                {
+                    for &idx in batch {
+                        batch_instances.extend_from_slice(&instances[items[idx].instances.clone()]);
+                    }
+                }
+                start..batch_instances.len()
+            })
+            .collect();
+        if !batch_instances.is_empty() {
+            if let Err(e) = self.write_instances(&batch_instances) {
+                warn!("Could not write instances: {}", e);
+                return;
+            }
+        }
 
+        let mut mesh_inners: Vec<_> = meshes.iter().map(|mesh| mesh.inner()).collect();
+
+        self.bound = Bindings::default();
+        let mut bound_material = None;
+        for (batch, instances) in batches.iter().zip(batch_ranges) {
+            let item = &items[batch[0]];
+            let instanced = !instances.is_empty();
+            if bound_material != Some((item.object, item.material, instanced)) {
+                self.bind_material(&mut materials[item.object][item.material], instanced);
+                bound_material = Some((item.object, item.material, instanced));
+            }
+
+            let MeshInner {
+                vertex_buffer,
+                index_buffer,
+                lods,
+                ..
+            } = &mut *mesh_inners[item.mesh];
+            let index_buffer = match item.lod.checked_sub(1).and_then(|i| lods.get_mut(i)) {
+                Some(simplified) => &mut simplified.index_buffer,
+                None => index_buffer,
+            };
+            if instanced {
+                self.draw_indexed_instanced(
+                    vertex_buffer,
+                    index_buffer,
+                    item.len,
+                    item.offset,
+                    instances,
+                );
+            } else {
+                self.draw_indexed(vertex_buffer, index_buffer, item.len, item.offset);
+            }
+        }
+    }
+
+    fn write_instances(&mut self, instances: &[Instance]) -> error::Result<()> {
+        let instance_buffer = match self.instances.take() {
+            Some(instance_buffer) => instance_buffer,
+            None => self
+                .device
+                .new_instance_buffer::<MeshVertex, Instance>(instances.len())?,
+        };
+        self.instances
+            .insert(instance_buffer)
+            .write(&self.device, &self.context, instances)
+    }
+
+    fn draw_indexed<V: Vertex>(
+        &mut self,
+        vertex_buffer: &mut VertexBuffer<V>,
+        index_buffer: &mut IndexBuffer,
+        len: usize,
+        offset: usize,
+    ) {
+        let vertex_buffer_addr = (vertex_buffer as *const _ as usize, false);
+        if self.bound.vertex_buffer != vertex_buffer_addr {
+            self.context.set_vertex_buffer(vertex_buffer);
+            self.bound.vertex_buffer = vertex_buffer_addr;
+            self.stats.buffer_changes += 1;
+        }
+        self.bind_index_buffer(index_buffer);
+
+        self.context.draw_indexed_triangle_list(len, offset, 0);
+        self.stats.draw_calls += 1;
+        self.stats.triangles += len / 3;
+    }
+
+    /// Draws `instances` of the instance buffer, which `MeshVertex` vertex buffers
+    /// are drawn with.
+    fn draw_indexed_instanced(
+        &mut self,
+        vertex_buffer: &mut VertexBuffer<MeshVertex>,
+        index_buffer: &mut IndexBuffer,
+        len: usize,
+        offset: usize,
+        instances: Range<usize>,
+    ) {
+        let instance_buffer = match &mut self.instances {
+            Some(instance_buffer) => instance_buffer,
+            None => return,
+        };
+        let vertex_buffer_addr = (vertex_buffer as *const _ as usize, true);
+        if self.bound.vertex_buffer != vertex_buffer_addr {
             self.context
-                .draw_indexed_triangle_list(material_id.len, material_id.offset, 0);
+                .set_instanced_vertex_buffers(vertex_buffer, instance_buffer);
+            self.bound.vertex_buffer = vertex_buffer_addr;
+            self.stats.buffer_changes += 1;
         }
+        self.bind_index_buffer(index_buffer);
+
+        self.context.draw_indexed_instanced_triangle_list(
+            len,
+            offset,
+            0,
+            instances.len(),
+            instances.start,
+        );
+        self.stats.draw_calls += 1;
+        self.stats.instances += instances.len();
+        self.stats.triangles += len / 3 * instances.len();
     }
 
-    pub fn set_front_face_culling(&mut self) {
-        unsafe {
-            self.context.as_ref().RSSetState(self.raster_front.as_mut());
+    fn bind_index_buffer(&mut self, index_buffer: &mut IndexBuffer) {
+        let index_buffer_addr = index_buffer as *const _ as usize;
+        if self.bound.index_buffer != index_buffer_addr {
+            self.context.set_index_buffer(index_buffer);
+            self.bound.index_buffer = index_buffer_addr;
+            self.stats.buffer_changes += 1;
         }
     }
 
-    pub fn set_back_face_culling(&mut self) {
-        unsafe {
-            self.context.as_ref().RSSetState(self.raster_back.as_mut());
+    /// Draws `material` over the current target, for post processing. Depth is not
+    /// tested, whatever the material's depth-stencil state, and its textures are unbound
+    /// afterwards so render targets they were read from can be drawn to again.
+    pub fn draw_fullscreen(&mut self, material: &mut Material) {
+        self.set_material(material);
+        match self.device.depth_stencil_state(&DepthStencilDesc::off()) {
+            Ok(depth_stencil) => {
+                self.context.set_depth_stencil_state(&depth_stencil, 0);
+                self.bound.depth_stencil = (depth_stencil.as_ptr() as usize, 0);
+                self.stats.state_changes += 1;
+            }
+            Err(e) => warn!("Could not set depth-stencil state: {}", e),
+        }
+        self.context.draw_fullscreen_triangle();
+        self.stats.draw_calls += 1;
+        self.stats.triangles += 1;
+
+        let mut unbound: Vec<Option<Arc<dyn Texture>>> = vec![None; material.textures.len()];
+        self.context
+            .set_textures::<shader::Pixel>(&mut unbound, &material.samplers);
+    }
+
+    /// Sets the blend, depth-stencil and rasterizer states of `material`.
+    fn set_pipeline_state(&mut self, material: &Material) -> error::Result<()> {
+        if let Some(scissor) = &material.scissor {
+            let edges = [
+                scissor.0[0].start,
+                scissor.0[1].start,
+                scissor.0[0].end,
+                scissor.0[1].end,
+            ];
+            if self.bound.scissor != Some(edges) {
+                self.context.set_scissor_rect(scissor);
+                self.bound.scissor = Some(edges);
+                self.stats.state_changes += 1;
+            }
         }
+
+        let raster = self.device.raster_state(&RasterDesc {
+            scissor: material.scissor.is_some(),
+            ..material.raster
+        })?;
+        self.set_raster_state(&raster);
+
+        let depth_stencil = self.device.depth_stencil_state(&material.depth_stencil)?;
+        let depth_stencil_key = (depth_stencil.as_ptr() as usize, material.stencil_ref);
+        if self.bound.depth_stencil != depth_stencil_key {
+            self.context
+                .set_depth_stencil_state(&depth_stencil, material.stencil_ref);
+            self.bound.depth_stencil = depth_stencil_key;
+            self.stats.state_changes += 1;
+        }
+
+        let blend = self.device.blend_state(material.blend_mode)?;
+        if self.bound.blend != blend.as_ptr() as usize {
+            self.context.set_blend_state(&blend);
+            self.bound.blend = blend.as_ptr() as usize;
+            self.stats.state_changes += 1;
+        }
+        Ok(())
+    }
+
+    fn set_raster_state(&mut self, raster: &RasterState) {
+        if self.bound.raster != raster.as_ptr() as usize {
+            self.context.set_raster_state(raster);
+            self.bound.raster = raster.as_ptr() as usize;
+            self.stats.state_changes += 1;
+        }
+    }
+
+    pub fn set_front_face_culling(&mut self) -> error::Result<()> {
+        self.set_cull_mode(CullMode::Front)
+    }
+
+    pub fn set_back_face_culling(&mut self) -> error::Result<()> {
+        self.set_cull_mode(CullMode::Back)
+    }
+
+    fn set_cull_mode(&mut self, cull: CullMode) -> error::Result<()> {
+        let raster = self.device.raster_state(&RasterDesc {
+            cull,
+            ..RasterDesc::default()
+        })?;
+        self.set_raster_state(&raster);
+        Ok(())
     }
 }
@@ -0,0 +1,307 @@
+use super::sort_key::{RenderLayer, SortKey};
+use super::{DepthStencilDesc, Instance};
+
+use crate::graphics::light_list::{LightList, LightSelection, FIRST_LIGHTS};
+use crate::graphics::material::{BlendMode, Material, RawConstants};
+use crate::graphics::resource::Mesh;
+use crate::math::Vector3d;
+use crate::physics::{BoundingSphere, Bounds, Frustum};
+
+use log::warn;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::sync::Arc;
+
+/// One submesh drawn with one material, once for each of its instances.
+#[derive(Clone, Debug)]
+pub(super) struct DrawItem {
+    pub key: SortKey,
+    /// Items with equal batches are drawn together with one instanced draw call.
+    /// `None` for materials that can't be instanced, and transparent ones, which are
+    /// drawn in order.
+    pub batch: Option<u64>,
+    /// Index into `RenderQueue::instances`.
+    pub instances: Range<usize>,
+    /// Index into `RenderQueue::meshes`.
+    pub mesh: usize,
+    /// Index into `RenderQueue::materials`, then into the slice there.
+    pub object: usize,
+    pub material: usize,
+    pub lod: usize,
+    pub offset: usize,
+    pub len: usize,
+}
+
+/// Draws collected over a frame, then sorted and drawn together by `Render::draw_queue`.
+pub struct RenderQueue<'a> {
+    camera_pos: Vector3d,
+    frustum: Option<Frustum>,
+    lights: Option<LightList>,
+    /// Objects and instances skipped by `cull` and `push_instances`.
+    pub(super) culled: usize,
+    /// Objects and instances queued.
+    pub(super) drawn: usize,
+    /// Each mesh once, however many times it is queued.
+    pub(super) meshes: Vec<&'a Mesh>,
+    pub(super) materials: Vec<&'a mut [Material]>,
+    pub(super) instances: Vec<Instance>,
+    pub(super) items: Vec<DrawItem>,
+}
+
+impl<'a> RenderQueue<'a> {
+    /// Draws are sorted by their distance from `camera_pos`.
+    pub fn new(camera_pos: Vector3d) -> Self {
+        Self {
+            camera_pos,
+            frustum: None,
+            lights: None,
+            culled: 0,
+            drawn: 0,
+            meshes: Vec::new(),
+            materials: Vec::new(),
+            instances: Vec::new(),
+            items: Vec::new(),
+        }
+    }
+
+    /// Objects outside `frustum` will be culled.
+    pub fn with_frustum(mut self, frustum: Frustum) -> Self {
+        self.frustum = Some(frustum);
+        self
+    }
+
+    /// Objects will be lit by the lights of `lights` that reach them most.
+    pub fn with_lights(mut self, lights: LightList) -> Self {
+        self.lights = Some(lights);
+        self
+    }
+
+    /// The lights of `with_lights` that light an object in `sphere`, or the first
+    /// lights bound if there are none.
+    pub fn select_lights(&self, sphere: &BoundingSphere) -> LightSelection {
+        match &self.lights {
+            Some(lights) => lights.select(sphere),
+            None => FIRST_LIGHTS,
+        }
+    }
+
+    /// Whether an object with world space `bounds` can't be seen and shouldn't be
+    /// pushed. Culled objects are counted in the statistics.
+    pub fn cull(&mut self, bounds: &Bounds) -> bool {
+        let culled = match &self.frustum {
+            Some(frustum) => !frustum.intersects(bounds),
+            None => false,
+        };
+        if culled {
+            self.culled += 1;
+        }
+        culled
+    }
+
+    /// Queues every submesh of a level of detail of `mesh`, placed and lit by
+    /// `instance`. Falls back to the full mesh if `lod` doesn't exist.
+    pub fn push(
+        &mut self,
+        mesh: &'a Mesh,
+        lod: usize,
+        materials: &'a mut [Material],
+        instance: Instance,
+    ) {
+        let center = mesh.inner().bounds.sphere.center;
+        let depth = (instance.world.transform_point(center) - self.camera_pos).magnitude();
+        let start = self.instances.len();
+        self.instances.push(instance);
+        self.drawn += 1;
+        self.push_items(mesh, lod, materials, start..start + 1, depth);
+    }
+
+    /// Queues every submesh of a level of detail of `mesh` once for each of `instances`
+    /// in view, drawn together with instanced draw calls. Materials need an
+    /// `instanced_vs`, and transparent ones aren't sorted between instances. Each
+    /// instance is lit by the lights from `select_lights`.
+    pub fn push_instances<'i>(
+        &mut self,
+        mesh: &'a Mesh,
+        lod: usize,
+        materials: &'a mut [Material],
+        instances: impl IntoIterator<Item = &'i Instance>,
+    ) {
+        let start = self.instances.len();
+        let mut depth = f32::INFINITY;
+        {
+            let bounds = mesh.inner().bounds;
+            for instance in instances {
+                let world_bounds = bounds.transform(&instance.world);
+                if let Some(frustum) = &self.frustum {
+                    if !frustum.intersects(&world_bounds) {
+                        self.culled += 1;
+                        continue;
+                    }
+                }
+                depth = depth.min((world_bounds.sphere.center - self.camera_pos).magnitude());
+                let mut instance = instance.clone();
+                *instance.lights = self.select_lights(&world_bounds.sphere);
+                self.instances.push(instance);
+            }
+        }
+        let end = self.instances.len();
+        if start == end {
+            return;
+        }
+        self.drawn += end - start;
+        self.push_items(mesh, lod, materials, start..end, depth);
+    }
+
+    fn push_items(
+        &mut self,
+        mesh: &'a Mesh,
+        lod: usize,
+        materials: &'a mut [Material],
+        instances: Range<usize>,
+        depth: f32,
+    ) {
+        let mesh_idx = match self
+            .meshes
+            .iter()
+            .position(|queued| std::ptr::eq(*queued, mesh))
+        {
+            Some(idx) => idx,
+            None => {
+                self.meshes.push(mesh);
+                self.meshes.len() - 1
+            }
+        };
+        let object = self.materials.len();
+
+        {
+            let inner = mesh.inner();
+            let simplified = lod.checked_sub(1).and_then(|i| inner.lods.get(i));
+            let (lod, material_ids) = match simplified {
+                Some(simplified) => (lod, &simplified.material_ids),
+                None => (0, &inner.material_ids),
+            };
+            // Only single instances are placed precisely enough to sort submeshes.
+            let world = match instances.len() {
+                1 => Some(&self.instances[instances.start].world),
+                _ => None,
+            };
+
+            for (submesh, material_id) in material_ids.iter().enumerate() {
+                let material = match materials.get(material_id.id) {
+                    Some(material) => material,
+                    None => {
+                        // TODO: set default material
+                        warn!("Missing material for: {:#?}", material_id.name);
+                        continue;
+                    }
+                };
+                if instances.len() > 1 && material.instanced_vs.is_none() {
+                    warn!("Material for {:#?} can't be instanced", material_id.name);
+                    continue;
+                }
+                // Submeshes of the full mesh have their own bounds, so a window sorts
+                // apart from the house around it.
+                let depth = match (lod, inner.submesh_bounds.get(submesh), world) {
+                    (0, Some(bounds), Some(world)) => {
+                        (world.transform_point(bounds.sphere.center) - self.camera_pos).magnitude()
+                    }
+                    _ => depth,
+                };
+
+                let layer = render_layer(material);
+                let shader = shader_hash(material);
+                let state = state_hash(material);
+                let batch = match (&material.instanced_vs, layer) {
+                    (Some(_), RenderLayer::Opaque | RenderLayer::Background) => {
+                        let mut batch = DefaultHasher::new();
+                        (mesh_idx, lod, material_id.offset, material_id.len).hash(&mut batch);
+                        (shader, state).hash(&mut batch);
+                        Some(batch.finish())
+                    }
+                    _ => None,
+                };
+
+                self.items.push(DrawItem {
+                    key: SortKey::new(layer, shader as u32, state as u32, depth),
+                    batch,
+                    instances: instances.clone(),
+                    mesh: mesh_idx,
+                    object,
+                    material: material_id.id,
+                    lod,
+                    offset: material_id.offset,
+                    len: material_id.len,
+                });
+            }
+        }
+        self.materials.push(materials);
+    }
+
+    /// Submeshes queued.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Draws with equal keys stay in the order they were queued.
+    pub(super) fn sort(&mut self) {
+        self.items.sort_by_key(|item| item.key);
+    }
+}
+
+/// Transparent materials blend; background materials are drawn on the far plane.
+pub fn render_layer(material: &Material) -> RenderLayer {
+    if material.blend_mode != BlendMode::Opaque {
+        RenderLayer::Transparent
+    } else if material.depth_stencil == DepthStencilDesc::background() {
+        RenderLayer::Background
+    } else {
+        RenderLayer::Opaque
+    }
+}
+
+fn shader_hash(material: &Material) -> u64 {
+    let mut shader = DefaultHasher::new();
+    (
+        Arc::as_ptr(&material.vs),
+        material.instanced_vs.as_ref().map(Arc::as_ptr),
+        Arc::as_ptr(&material.ps),
+    )
+        .hash(&mut shader);
+    shader.finish()
+}
+
+/// Materials that bind the same things hash the same, even if they aren't the same
+/// `Material`. Typed constants aren't included, as only their buffers are kept.
+fn state_hash(material: &Material) -> u64 {
+    let mut state = DefaultHasher::new();
+    for texture in &material.textures {
+        texture
+            .as_ref()
+            .map(|texture| Arc::as_ptr(texture) as *const ())
+            .hash(&mut state);
+    }
+    for sampler in &material.samplers {
+        sampler.as_ref().map(Arc::as_ptr).hash(&mut state);
+    }
+    for raw_constants in &material.raw_constants {
+        raw_constants
+            .as_ref()
+            .map(RawConstants::data)
+            .hash(&mut state);
+    }
+    material.blend_mode.hash(&mut state);
+    material.depth_stencil.hash(&mut state);
+    material.stencil_ref.hash(&mut state);
+    material.raster.hash(&mut state);
+    material
+        .scissor
+        .as_ref()
+        .map(|scissor| scissor.0.clone())
+        .hash(&mut state);
+    state.finish()
+}
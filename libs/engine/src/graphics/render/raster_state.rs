@@ -1,13 +1,117 @@
-use super::Device;
+use super::{Device, DeviceState};
 
 use crate::error;
 use crate::util::get_output;
 
+use std::hash::{Hash, Hasher};
 use std::ptr::NonNull;
 
 use winapi::shared::minwindef;
 use winapi::um::d3d11;
 
+/// Which triangles aren't drawn.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum CullMode {
+    /// Draws both sides.
+    None,
+    /// Draws the inside of closed meshes.
+    Front,
+    #[default]
+    Back,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum FillMode {
+    #[default]
+    Solid,
+    /// Only the edges of triangles, for debugging.
+    Wireframe,
+}
+
+/// How triangles are turned into pixels. Equal descriptions share one state on the device.
+#[derive(Clone, Copy, Debug)]
+pub struct RasterDesc {
+    pub cull: CullMode,
+    pub fill: FillMode,
+    /// Added to the depth of every pixel, in the smallest steps the depth buffer holds.
+    /// Keeps decals and shadow casters from fighting with the surface under them.
+    pub depth_bias: i32,
+    /// The most bias added, or no limit when zero.
+    pub depth_bias_clamp: f32,
+    /// Bias added for how steeply a triangle faces away from the camera.
+    pub slope_scaled_depth_bias: f32,
+    /// Clips geometry past the far plane. Skies reaching past it need this off.
+    pub depth_clip: bool,
+    /// Discards pixels outside the scissor rectangle set on the context.
+    pub scissor: bool,
+}
+
+impl RasterDesc {
+    fn to_d3d(self) -> d3d11::D3D11_RASTERIZER_DESC {
+        d3d11::D3D11_RASTERIZER_DESC {
+            FillMode: match self.fill {
+                FillMode::Solid => d3d11::D3D11_FILL_SOLID,
+                FillMode::Wireframe => d3d11::D3D11_FILL_WIREFRAME,
+            },
+            CullMode: match self.cull {
+                CullMode::None => d3d11::D3D11_CULL_NONE,
+                CullMode::Front => d3d11::D3D11_CULL_FRONT,
+                CullMode::Back => d3d11::D3D11_CULL_BACK,
+            },
+            DepthBias: self.depth_bias,
+            DepthBiasClamp: self.depth_bias_clamp,
+            SlopeScaledDepthBias: self.slope_scaled_depth_bias,
+            DepthClipEnable: self.depth_clip as minwindef::BOOL,
+            ScissorEnable: self.scissor as minwindef::BOOL,
+            ..Default::default()
+        }
+    }
+
+    /// Floats compared by their bits, so descriptions can key a `HashMap`.
+    fn key(&self) -> (CullMode, FillMode, i32, [u32; 2], bool, bool) {
+        (
+            self.cull,
+            self.fill,
+            self.depth_bias,
+            [
+                self.depth_bias_clamp.to_bits(),
+                self.slope_scaled_depth_bias.to_bits(),
+            ],
+            self.depth_clip,
+            self.scissor,
+        )
+    }
+}
+
+/// Solid, back face culled triangles.
+impl Default for RasterDesc {
+    fn default() -> Self {
+        Self {
+            cull: CullMode::Back,
+            fill: FillMode::Solid,
+            depth_bias: 0,
+            depth_bias_clamp: 0.0,
+            slope_scaled_depth_bias: 0.0,
+            depth_clip: true,
+            scissor: false,
+        }
+    }
+}
+
+impl PartialEq for RasterDesc {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for RasterDesc {}
+
+impl Hash for RasterDesc {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
 pub struct RasterState(NonNull<d3d11::ID3D11RasterizerState>);
 
 //TODO FIXME Verify
@@ -15,29 +119,17 @@ unsafe impl Send for RasterState {}
 unsafe impl Sync for RasterState {}
 
 impl RasterState {
-    pub fn new_front(device: &Device) -> error::Result<Self> {
-        unsafe {
-            let desc = d3d11::D3D11_RASTERIZER_DESC {
-                CullMode: d3d11::D3D11_CULL_FRONT,
-                DepthClipEnable: minwindef::FALSE,
-                FillMode: d3d11::D3D11_FILL_SOLID,
-                ..Default::default()
-            };
-
-            get_output(|ptr| device.as_ref().CreateRasterizerState(&desc, ptr)).map(Self)
-        }
+    pub fn as_ptr(&self) -> *mut d3d11::ID3D11RasterizerState {
+        self.0.as_ptr()
     }
+}
+
+impl DeviceState for RasterState {
+    type Desc = RasterDesc;
 
-    pub fn new_back(device: &Device) -> error::Result<Self> {
+    fn create(device: &Device, desc: &RasterDesc) -> error::Result<Self> {
         unsafe {
-            let desc = d3d11::D3D11_RASTERIZER_DESC {
-                CullMode: d3d11::D3D11_CULL_BACK,
-                DepthClipEnable: minwindef::TRUE,
-                FillMode: d3d11::D3D11_FILL_SOLID,
-                ..Default::default()
-            };
-
-            get_output(|ptr| device.as_ref().CreateRasterizerState(&desc, ptr)).map(Self)
+            get_output(|ptr| device.as_ref().CreateRasterizerState(&desc.to_d3d(), ptr)).map(Self)
         }
     }
 }
@@ -48,12 +140,6 @@ impl AsRef<d3d11::ID3D11RasterizerState> for RasterState {
     }
 }
 
-impl AsMut<d3d11::ID3D11RasterizerState> for RasterState {
-    fn as_mut(&mut self) -> &mut d3d11::ID3D11RasterizerState {
-        unsafe { self.0.as_mut() }
-    }
-}
-
 impl Drop for RasterState {
     fn drop(&mut self) {
         unsafe {
@@ -2,10 +2,11 @@ use crate::graphics::material;
 use crate::math::Rect;
 
 use crate::error;
-use crate::graphics::render::{self, Device};
+use crate::graphics::render::{self, Device, Sampler, SamplerDesc};
 use crate::util::get_output;
 
 use std::ptr::{self, NonNull};
+use std::sync::Arc;
 
 use winapi::shared::{dxgiformat, dxgitype};
 use winapi::um::d3d11;
@@ -18,11 +19,21 @@ pub enum Flavor {
     DepthStencil,
 }
 
+/// Texel format of `Flavor::Normal` and `Flavor::RenderTarget` textures.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorFormat {
+    #[default]
+    Rgba8,
+    /// Half floats, for light brighter than white that is tone mapped later.
+    Rgba16Float,
+}
+
 #[derive(Default)]
 pub struct RenderedTexture {
     pub flavor: Flavor,
+    pub format: ColorFormat,
     texture: Option<NonNull<d3d11::ID3D11Resource>>,
-    sampler_state: Option<NonNull<d3d11::ID3D11SamplerState>>,
+    sampler_state: Option<Arc<Sampler>>,
     shader_res_view: Option<NonNull<d3d11::ID3D11ShaderResourceView>>,
     render_target_view: Option<NonNull<d3d11::ID3D11RenderTargetView>>,
     depth_stencil_view: Option<NonNull<d3d11::ID3D11DepthStencilView>>,
@@ -33,15 +44,25 @@ unsafe impl Sync for RenderedTexture {}
 
 impl RenderedTexture {
     pub fn new(rect: Rect<u32>, flavor: Flavor, device: &Device) -> error::Result<Self> {
+        Self::with_format(rect, flavor, ColorFormat::default(), device)
+    }
+
+    pub fn with_format(
+        rect: Rect<u32>,
+        flavor: Flavor,
+        format: ColorFormat,
+        device: &Device,
+    ) -> error::Result<Self> {
         unsafe {
             let tex_desc = d3d11::D3D11_TEXTURE2D_DESC {
                 Width: rect.width(),
                 Height: rect.height(),
                 MipLevels: 1,
                 ArraySize: 1,
-                Format: match flavor {
-                    Flavor::Normal | Flavor::RenderTarget => dxgiformat::DXGI_FORMAT_R8G8B8A8_UNORM,
-                    Flavor::DepthStencil => dxgiformat::DXGI_FORMAT_D24_UNORM_S8_UINT,
+                Format: match (flavor, format) {
+                    (Flavor::DepthStencil, _) => dxgiformat::DXGI_FORMAT_D24_UNORM_S8_UINT,
+                    (_, ColorFormat::Rgba8) => dxgiformat::DXGI_FORMAT_R8G8B8A8_UNORM,
+                    (_, ColorFormat::Rgba16Float) => dxgiformat::DXGI_FORMAT_R16G16B16A16_FLOAT,
                 },
                 Usage: d3d11::D3D11_USAGE_DEFAULT,
 
@@ -68,18 +89,10 @@ impl RenderedTexture {
             })?
             .cast::<d3d11::ID3D11Resource>();
 
-            let sampler_desc = d3d11::D3D11_SAMPLER_DESC {
-                AddressU: d3d11::D3D11_TEXTURE_ADDRESS_WRAP,
-                AddressV: d3d11::D3D11_TEXTURE_ADDRESS_WRAP,
-                AddressW: d3d11::D3D11_TEXTURE_ADDRESS_WRAP,
-                Filter: d3d11::D3D11_FILTER_ANISOTROPIC,
-                MinLOD: 0.0,
-                MaxLOD: 1.0,
-                ..d3d11::D3D11_SAMPLER_DESC::default()
-            };
-
-            let sampler_state =
-                get_output(|ptr| device.as_ref().CreateSamplerState(&sampler_desc, ptr))?;
+            let sampler_state = device.sampler(&SamplerDesc {
+                max_lod: 1.0,
+                ..SamplerDesc::default()
+            })?;
 
             match flavor {
                 Flavor::Normal => {
@@ -93,6 +106,7 @@ impl RenderedTexture {
 
                     Ok(Self {
                         flavor,
+                        format,
                         sampler_state: Some(sampler_state),
                         texture: Some(texture),
                         shader_res_view: Some(srv),
@@ -118,6 +132,7 @@ impl RenderedTexture {
 
                     Ok(Self {
                         flavor,
+                        format,
                         texture: Some(texture),
                         sampler_state: Some(sampler_state),
                         shader_res_view: Some(srv),
@@ -136,6 +151,7 @@ impl RenderedTexture {
 
                     Ok(Self {
                         flavor,
+                        format,
                         sampler_state: Some(sampler_state),
                         texture: Some(texture),
                         depth_stencil_view: Some(dsv),
@@ -149,7 +165,7 @@ impl RenderedTexture {
 
 impl material::Texture for RenderedTexture {
     fn sampler_state_ptr(&self) -> *mut d3d11::ID3D11SamplerState {
-        self.sampler_state.unwrap().as_ptr()
+        self.sampler_state.as_ref().unwrap().as_ptr()
     }
 
     fn resource_view_ptr(&self) -> *mut d3d11::ID3D11ShaderResourceView {
@@ -180,7 +196,6 @@ impl Drop for RenderedTexture {
             shader_res_view,
             render_target_view,
             depth_stencil_view,
-            sampler_state,
             ..
         } = self;
 
@@ -189,7 +204,6 @@ impl Drop for RenderedTexture {
             shader_res_view.map(|s| s.as_ref().Release());
             render_target_view.map(|r| r.as_ref().Release());
             depth_stencil_view.map(|d| d.as_ref().Release());
-            sampler_state.map(|s| s.as_ref().Release());
         }
     }
 }
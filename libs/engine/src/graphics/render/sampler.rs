@@ -0,0 +1,250 @@
+use super::{Device, DeviceState};
+
+use crate::error;
+use crate::util::get_output;
+
+use std::hash::{Hash, Hasher};
+use std::ptr::NonNull;
+
+use winapi::um::d3d11;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AddressMode {
+    Wrap,
+    Mirror,
+    Clamp,
+    /// Outside the texture reads `SamplerDesc::border_color`.
+    Border,
+    MirrorOnce,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FilterMode {
+    Point,
+    Linear,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ComparisonFunc {
+    Never,
+    Less,
+    Equal,
+    LessEqual,
+    Greater,
+    NotEqual,
+    GreaterEqual,
+    Always,
+}
+
+impl ComparisonFunc {
+    pub(super) fn to_d3d(self) -> d3d11::D3D11_COMPARISON_FUNC {
+        match self {
+            Self::Never => d3d11::D3D11_COMPARISON_NEVER,
+            Self::Less => d3d11::D3D11_COMPARISON_LESS,
+            Self::Equal => d3d11::D3D11_COMPARISON_EQUAL,
+            Self::LessEqual => d3d11::D3D11_COMPARISON_LESS_EQUAL,
+            Self::Greater => d3d11::D3D11_COMPARISON_GREATER,
+            Self::NotEqual => d3d11::D3D11_COMPARISON_NOT_EQUAL,
+            Self::GreaterEqual => d3d11::D3D11_COMPARISON_GREATER_EQUAL,
+            Self::Always => d3d11::D3D11_COMPARISON_ALWAYS,
+        }
+    }
+}
+
+type SamplerKey = (
+    [AddressMode; 3],
+    [FilterMode; 3],
+    u32,
+    [u32; 7],
+    Option<ComparisonFunc>,
+);
+
+/// How a texture slot is sampled. Equal descriptions share one sampler state on the device.
+#[derive(Clone, Copy, Debug)]
+pub struct SamplerDesc {
+    pub address_u: AddressMode,
+    pub address_v: AddressMode,
+    pub address_w: AddressMode,
+    pub min_filter: FilterMode,
+    pub mag_filter: FilterMode,
+    pub mip_filter: FilterMode,
+    /// Above 1 filters anisotropically, up to 16 samples.
+    pub max_anisotropy: u32,
+    pub mip_lod_bias: f32,
+    pub min_lod: f32,
+    pub max_lod: f32,
+    pub border_color: [f32; 4],
+    /// Makes a comparison sampler, for `SampleCmp` on shadow maps.
+    pub comparison: Option<ComparisonFunc>,
+}
+
+impl SamplerDesc {
+    /// Sets the address mode of every axis.
+    pub fn with_address(mut self, mode: AddressMode) -> Self {
+        self.address_u = mode;
+        self.address_v = mode;
+        self.address_w = mode;
+        self
+    }
+
+    /// Sets the min, mag and mip filters and turns off anisotropy.
+    pub fn with_filter(mut self, filter: FilterMode) -> Self {
+        self.min_filter = filter;
+        self.mag_filter = filter;
+        self.mip_filter = filter;
+        self.max_anisotropy = 1;
+        self
+    }
+
+    /// Nearest texel, for pixel art and lookup tables.
+    pub fn point() -> Self {
+        Self::default().with_filter(FilterMode::Point)
+    }
+
+    /// Linear, clamped sampling for screen sized render targets.
+    pub fn clamp() -> Self {
+        Self::default()
+            .with_filter(FilterMode::Linear)
+            .with_address(AddressMode::Clamp)
+    }
+
+    /// Filtered depth comparison that treats everything outside the map as lit.
+    pub fn shadow() -> Self {
+        Self {
+            border_color: [1.0; 4],
+            comparison: Some(ComparisonFunc::LessEqual),
+            ..Self::default()
+                .with_filter(FilterMode::Linear)
+                .with_address(AddressMode::Border)
+        }
+    }
+
+    fn filter(&self) -> d3d11::D3D11_FILTER {
+        let reduction = u32::from(self.comparison.is_some());
+        if self.max_anisotropy > 1 {
+            return d3d11::D3D11_FILTER_ANISOTROPIC | reduction << 7;
+        }
+        let bit = |filter: FilterMode| match filter {
+            FilterMode::Point => 0,
+            FilterMode::Linear => 1,
+        };
+        bit(self.min_filter) << 4
+            | bit(self.mag_filter) << 2
+            | bit(self.mip_filter)
+            | reduction << 7
+    }
+
+    fn to_d3d(self) -> d3d11::D3D11_SAMPLER_DESC {
+        let address = |mode: AddressMode| match mode {
+            AddressMode::Wrap => d3d11::D3D11_TEXTURE_ADDRESS_WRAP,
+            AddressMode::Mirror => d3d11::D3D11_TEXTURE_ADDRESS_MIRROR,
+            AddressMode::Clamp => d3d11::D3D11_TEXTURE_ADDRESS_CLAMP,
+            AddressMode::Border => d3d11::D3D11_TEXTURE_ADDRESS_BORDER,
+            AddressMode::MirrorOnce => d3d11::D3D11_TEXTURE_ADDRESS_MIRROR_ONCE,
+        };
+        let comparison = self.comparison.unwrap_or(ComparisonFunc::Never).to_d3d();
+
+        d3d11::D3D11_SAMPLER_DESC {
+            Filter: self.filter(),
+            AddressU: address(self.address_u),
+            AddressV: address(self.address_v),
+            AddressW: address(self.address_w),
+            MipLODBias: self.mip_lod_bias,
+            MaxAnisotropy: self.max_anisotropy.clamp(1, 16),
+            ComparisonFunc: comparison,
+            BorderColor: self.border_color,
+            MinLOD: self.min_lod,
+            MaxLOD: self.max_lod,
+        }
+    }
+
+    /// Floats compared by their bits, so descriptions can key a `HashMap`.
+    fn key(&self) -> SamplerKey {
+        let [r, g, b, a] = self.border_color.map(f32::to_bits);
+        (
+            [self.address_u, self.address_v, self.address_w],
+            [self.min_filter, self.mag_filter, self.mip_filter],
+            self.max_anisotropy,
+            [
+                self.mip_lod_bias.to_bits(),
+                self.min_lod.to_bits(),
+                self.max_lod.to_bits(),
+                r,
+                g,
+                b,
+                a,
+            ],
+            self.comparison,
+        )
+    }
+}
+
+/// Wrapped, anisotropic sampling of every mip level.
+impl Default for SamplerDesc {
+    fn default() -> Self {
+        Self {
+            address_u: AddressMode::Wrap,
+            address_v: AddressMode::Wrap,
+            address_w: AddressMode::Wrap,
+            min_filter: FilterMode::Linear,
+            mag_filter: FilterMode::Linear,
+            mip_filter: FilterMode::Linear,
+            max_anisotropy: 16,
+            mip_lod_bias: 0.0,
+            min_lod: 0.0,
+            max_lod: d3d11::D3D11_FLOAT32_MAX,
+            border_color: [0.0; 4],
+            comparison: None,
+        }
+    }
+}
+
+impl PartialEq for SamplerDesc {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for SamplerDesc {}
+
+impl Hash for SamplerDesc {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+pub struct Sampler(NonNull<d3d11::ID3D11SamplerState>);
+
+//TODO FIXME Verify
+unsafe impl Send for Sampler {}
+unsafe impl Sync for Sampler {}
+
+impl Sampler {
+    pub fn as_ptr(&self) -> *mut d3d11::ID3D11SamplerState {
+        self.0.as_ptr()
+    }
+}
+
+impl DeviceState for Sampler {
+    type Desc = SamplerDesc;
+
+    fn create(device: &Device, desc: &SamplerDesc) -> error::Result<Self> {
+        unsafe {
+            get_output(|ptr| device.as_ref().CreateSamplerState(&desc.to_d3d(), ptr)).map(Self)
+        }
+    }
+}
+
+impl AsRef<d3d11::ID3D11SamplerState> for Sampler {
+    fn as_ref(&self) -> &d3d11::ID3D11SamplerState {
+        unsafe { self.0.as_ref() }
+    }
+}
+
+impl Drop for Sampler {
+    fn drop(&mut self) {
+        unsafe {
+            self.as_ref().Release();
+        }
+    }
+}
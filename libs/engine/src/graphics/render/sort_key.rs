@@ -0,0 +1,120 @@
+//! Orders the draws in a `RenderQueue`.
+//!
+//! A key packs, from the highest bits down, the layer, then for opaque layers the
+//! shader, the material and a coarse front to back depth, and for the transparent
+//! layer the back to front depth, the shader and the material. Sorting the keys
+//! draws each layer in turn, groups opaque draws that share state, and blends
+//! transparent draws over whatever is behind them.
+
+/// Which pass a draw belongs to. Layers draw in this order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RenderLayer {
+    Opaque,
+    /// Skies, drawn after opaque geometry so only the pixels left over are shaded.
+    Background,
+    Transparent,
+}
+
+const LAYER_SHIFT: u32 = 62;
+
+const SHADER_BITS: u32 = 20;
+const MATERIAL_BITS: u32 = 24;
+const DEPTH_BITS: u32 = 18;
+
+const TRANSPARENT_DEPTH_BITS: u32 = 31;
+const TRANSPARENT_SHADER_BITS: u32 = 15;
+const TRANSPARENT_MATERIAL_BITS: u32 = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SortKey(pub u64);
+
+impl SortKey {
+    /// `shader` and `material` identify state shared between draws; only their low
+    /// bits are kept, so unequal ids may rarely share a group. `depth` is the distance
+    /// from the camera.
+    pub fn new(layer: RenderLayer, shader: u32, material: u32, depth: f32) -> Self {
+        let layer_bits = (layer as u64) << LAYER_SHIFT;
+        let depth = depth_bits(depth);
+        match layer {
+            RenderLayer::Transparent => {
+                let far_first = (1 << TRANSPARENT_DEPTH_BITS) - 1 - depth;
+                Self(
+                    layer_bits
+                        | far_first << (TRANSPARENT_SHADER_BITS + TRANSPARENT_MATERIAL_BITS)
+                        | low_bits(shader, TRANSPARENT_SHADER_BITS) << TRANSPARENT_MATERIAL_BITS
+                        | low_bits(material, TRANSPARENT_MATERIAL_BITS),
+                )
+            }
+            RenderLayer::Opaque | RenderLayer::Background => Self(
+                layer_bits
+                    | low_bits(shader, SHADER_BITS) << (MATERIAL_BITS + DEPTH_BITS)
+                    | low_bits(material, MATERIAL_BITS) << DEPTH_BITS
+                    | depth >> (TRANSPARENT_DEPTH_BITS - DEPTH_BITS),
+            ),
+        }
+    }
+
+    pub fn layer(self) -> RenderLayer {
+        match self.0 >> LAYER_SHIFT {
+            0 => RenderLayer::Opaque,
+            1 => RenderLayer::Background,
+            _ => RenderLayer::Transparent,
+        }
+    }
+}
+
+fn low_bits(id: u32, bits: u32) -> u64 {
+    u64::from(id) & ((1 << bits) - 1)
+}
+
+/// The bits of a non-negative float sort like the float, in 31 bits.
+fn depth_bits(depth: f32) -> u64 {
+    let depth = if depth.is_nan() { 0.0 } else { depth.max(0.0) };
+    u64::from(depth.to_bits())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn layers() {
+        let opaque = SortKey::new(RenderLayer::Opaque, u32::MAX, u32::MAX, f32::MAX);
+        let background = SortKey::new(RenderLayer::Background, 0, 0, 0.0);
+        let transparent = SortKey::new(RenderLayer::Transparent, 0, 0, f32::MAX);
+        assert!(opaque < background);
+        assert!(background < transparent);
+
+        assert_eq!(opaque.layer(), RenderLayer::Opaque);
+        assert_eq!(background.layer(), RenderLayer::Background);
+        assert_eq!(transparent.layer(), RenderLayer::Transparent);
+    }
+
+    #[test]
+    fn opaque_by_state() {
+        let key =
+            |shader, material, depth| SortKey::new(RenderLayer::Opaque, shader, material, depth);
+        // Shader first, then material, then near before far.
+        assert!(key(1, 9, 100.0) < key(2, 0, 0.0));
+        assert!(key(1, 1, 100.0) < key(1, 2, 0.0));
+        assert!(key(1, 1, 1.0) < key(1, 1, 100.0));
+        // Depth is coarse, but still orders distant draws.
+        assert!(key(1, 1, 1.0) < key(1, 1, 1.1));
+        assert!(key(1, 1, 0.0) <= key(1, 1, -5.0));
+        assert_eq!(key(1, 1, f32::NAN), key(1, 1, 0.0));
+    }
+
+    #[test]
+    fn transparent_back_to_front() {
+        let key = |shader, material, depth| {
+            SortKey::new(RenderLayer::Transparent, shader, material, depth)
+        };
+        // Far before near, whatever the state.
+        assert!(key(9, 9, 100.0) < key(0, 0, 10.0));
+        assert!(key(0, 0, 10.001) < key(0, 0, 10.0));
+        assert!(key(0, 0, f32::INFINITY) < key(0, 0, 0.0));
+        // Equal depths fall back to grouping by state.
+        assert!(key(1, 5, 10.0) < key(2, 0, 10.0));
+        assert!(key(1, 5, 10.0) < key(1, 6, 10.0));
+    }
+}
@@ -0,0 +1,49 @@
+use super::{BlendState, DepthState, Device, RasterState, Sampler};
+
+use crate::error;
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+
+/// An immutable state object, created on a device from a description.
+pub trait DeviceState: Sized {
+    type Desc: Clone + Eq + Hash;
+
+    fn create(device: &Device, desc: &Self::Desc) -> error::Result<Self>;
+}
+
+/// States already created on a device, by description.
+pub struct StateCache<S: DeviceState>(Mutex<HashMap<S::Desc, Arc<S>>>);
+
+impl<S: DeviceState> StateCache<S> {
+    pub fn get(&self, device: &Device, desc: &S::Desc) -> error::Result<Arc<S>> {
+        let mut states = self.0.lock().unwrap();
+        if let Some(state) = states.get(desc) {
+            return Ok(state.clone());
+        }
+        let state = Arc::new(S::create(device, desc)?);
+        states.insert(desc.clone(), state.clone());
+        Ok(state)
+    }
+
+    /// How many different states have been created.
+    pub fn count(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+}
+
+impl<S: DeviceState> Default for StateCache<S> {
+    fn default() -> Self {
+        Self(Mutex::default())
+    }
+}
+
+/// Every kind of state a device deduplicates.
+#[derive(Default)]
+pub struct StateCaches {
+    pub samplers: StateCache<Sampler>,
+    pub blend: StateCache<BlendState>,
+    pub depth_stencil: StateCache<DepthState>,
+    pub raster: StateCache<RasterState>,
+}
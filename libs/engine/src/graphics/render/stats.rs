@@ -0,0 +1,39 @@
+use std::fmt;
+
+/// What drawing cost, counted by `Render` until taken with `Render::take_stats`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RenderStats {
+    pub draw_calls: usize,
+    /// Instances drawn by instanced draw calls.
+    pub instances: usize,
+    pub triangles: usize,
+    /// Materials bound, with their constants and textures.
+    pub material_changes: usize,
+    pub shader_changes: usize,
+    /// Blend, depth-stencil and rasterizer states and scissor rectangles bound.
+    pub state_changes: usize,
+    /// Vertex and index buffers bound.
+    pub buffer_changes: usize,
+    /// Entities and instances queued to be drawn.
+    pub entities_drawn: usize,
+    /// Entities and instances outside the view, skipped.
+    pub entities_culled: usize,
+}
+
+impl fmt::Display for RenderStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} entities in {} draw calls ({} instanced), {} triangles, {} material, {} shader, {} state and {} buffer changes",
+            self.entities_drawn,
+            self.entities_drawn + self.entities_culled,
+            self.draw_calls,
+            self.instances,
+            self.triangles,
+            self.material_changes,
+            self.shader_changes,
+            self.state_changes,
+            self.buffer_changes
+        )
+    }
+}
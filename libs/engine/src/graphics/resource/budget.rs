@@ -0,0 +1,104 @@
+//! Memory accounting and eviction for resource managers.
+
+/// What a resource manager holds, for budgets and debug overlays.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ResourceStats {
+    /// Resources held, including placeholders still loading.
+    pub count: usize,
+    /// Resources held somewhere besides the manager.
+    pub in_use: usize,
+    /// GPU memory of every resource held, in bytes.
+    pub bytes: usize,
+    pub in_use_bytes: usize,
+    pub budget: Option<usize>,
+    /// Resources unloaded, purged or evicted since the manager was created.
+    pub evicted: usize,
+}
+
+impl ResourceStats {
+    pub fn over_budget(&self) -> bool {
+        self.budget.is_some_and(|budget| self.bytes > budget)
+    }
+}
+
+/// A resource nothing else holds, which can be dropped to save memory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Candidate<K> {
+    pub key: K,
+    pub bytes: usize,
+    /// When the resource was last in use. Larger is more recent.
+    pub last_used: u64,
+}
+
+/// Chooses the least recently used candidates to evict until `bytes` fits in `budget`.
+/// Candidates that take no memory are never chosen, since evicting them doesn't help.
+pub fn select_evictions<K>(
+    mut candidates: Vec<Candidate<K>>,
+    bytes: usize,
+    budget: usize,
+) -> Vec<K> {
+    let mut excess = bytes.saturating_sub(budget);
+    candidates.retain(|candidate| candidate.bytes > 0);
+    candidates.sort_by_key(|candidate| candidate.last_used);
+
+    let mut evictions = Vec::new();
+    for candidate in candidates {
+        if excess == 0 {
+            break;
+        }
+        excess = excess.saturating_sub(candidate.bytes);
+        evictions.push(candidate.key);
+    }
+    evictions
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn candidate(key: &'static str, bytes: usize, last_used: u64) -> Candidate<&'static str> {
+        Candidate {
+            key,
+            bytes,
+            last_used,
+        }
+    }
+
+    #[test]
+    fn evicts_least_recently_used_first() {
+        let candidates = vec![
+            candidate("recent", 100, 9),
+            candidate("oldest", 100, 1),
+            candidate("old", 100, 4),
+        ];
+        assert_eq!(
+            select_evictions(candidates.clone(), 450, 300),
+            ["oldest", "old"]
+        );
+        assert_eq!(select_evictions(candidates.clone(), 400, 300), ["oldest"]);
+        assert!(select_evictions(candidates, 300, 300).is_empty());
+    }
+
+    #[test]
+    fn skips_empty_candidates() {
+        let candidates = vec![candidate("shader", 0, 0), candidate("texture", 64, 5)];
+        assert_eq!(select_evictions(candidates, 100, 50), ["texture"]);
+    }
+
+    #[test]
+    fn evicts_everything_it_can_when_in_use_resources_are_over_budget() {
+        let candidates = vec![candidate("a", 10, 0), candidate("b", 10, 1)];
+        assert_eq!(select_evictions(candidates, 1000, 100), ["a", "b"]);
+    }
+
+    #[test]
+    fn over_budget() {
+        let mut stats = ResourceStats {
+            bytes: 200,
+            ..Default::default()
+        };
+        assert!(!stats.over_budget());
+        stats.budget = Some(100);
+        assert!(stats.over_budget());
+    }
+}
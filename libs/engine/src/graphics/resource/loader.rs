@@ -0,0 +1,258 @@
+//! Loads resources in the background. Files are read and decoded on worker threads,
+//! then uploaded on the render thread by `ResourceManager::finish_loading`.
+
+use crate::error;
+
+use std::future::Future;
+use std::ops::Add;
+use std::pin::Pin;
+use std::sync::{mpsc, Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+lazy_static! {
+    static ref WORKERS: Mutex<mpsc::Sender<Job>> = Mutex::new(start_workers());
+}
+
+/// Runs `job` on a worker thread.
+pub fn spawn(job: impl FnOnce() + Send + 'static) {
+    // Workers run until the process exits, so there is always one to send to.
+    WORKERS.lock().unwrap().send(Box::new(job)).unwrap();
+}
+
+fn start_workers() -> mpsc::Sender<Job> {
+    let (sender, receiver) = mpsc::channel::<Job>();
+    let receiver = Arc::new(Mutex::new(receiver));
+    // Leave a core for the render thread.
+    let count = thread::available_parallelism().map_or(1, |count| count.get().max(2) - 1);
+    for i in 0..count {
+        let receiver = receiver.clone();
+        thread::Builder::new()
+            .name(format!("resource loader {}", i))
+            .spawn(move || loop {
+                let job = receiver.lock().unwrap().recv();
+                match job {
+                    Ok(job) => job(),
+                    Err(_) => break,
+                }
+            })
+            .expect("Could not start resource loader");
+    }
+    sender
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum LoadState {
+    #[default]
+    Loading,
+    Ready,
+    Failed(String),
+}
+
+#[derive(Default)]
+struct Status {
+    state: LoadState,
+    wakers: Vec<Waker>,
+}
+
+/// Shared between a manager and the handles it gave out for one resource.
+#[derive(Clone, Default)]
+pub struct LoadStatus(Arc<Mutex<Status>>);
+
+impl LoadStatus {
+    pub fn ready() -> Self {
+        let status = Self::default();
+        status.finish(LoadState::Ready);
+        status
+    }
+
+    pub fn state(&self) -> LoadState {
+        self.0.lock().unwrap().state.clone()
+    }
+
+    /// Wakes everything waiting on the resource.
+    pub fn finish(&self, state: LoadState) {
+        let mut status = self.0.lock().unwrap();
+        status.state = state;
+        for waker in status.wakers.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+/// A resource loading in the background. Until it's ready the resource is a placeholder,
+/// which the real one replaces in place, so it can be drawn straight away.
+///
+/// Awaiting the handle resolves once the render thread has uploaded the resource.
+pub struct LoadHandle<R> {
+    resource: Arc<R>,
+    status: LoadStatus,
+}
+
+impl<R> LoadHandle<R> {
+    pub fn new(resource: Arc<R>, status: LoadStatus) -> Self {
+        Self { resource, status }
+    }
+
+    /// The resource, or its placeholder while it loads.
+    pub fn resource(&self) -> Arc<R> {
+        self.resource.clone()
+    }
+
+    pub fn state(&self) -> LoadState {
+        self.status.state()
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.state() == LoadState::Ready
+    }
+
+    /// The resource once it has loaded.
+    pub fn get(&self) -> Option<Arc<R>> {
+        self.is_ready().then(|| self.resource())
+    }
+}
+
+impl<R> Clone for LoadHandle<R> {
+    fn clone(&self) -> Self {
+        Self::new(self.resource.clone(), self.status.clone())
+    }
+}
+
+impl<R> Future for LoadHandle<R> {
+    type Output = error::Result<Arc<R>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let mut status = self.status.0.lock().unwrap();
+        match &status.state {
+            LoadState::Loading => {
+                if !status
+                    .wakers
+                    .iter()
+                    .any(|waker| waker.will_wake(cx.waker()))
+                {
+                    status.wakers.push(cx.waker().clone());
+                }
+                Poll::Pending
+            }
+            LoadState::Ready => Poll::Ready(Ok(self.resource.clone())),
+            LoadState::Failed(e) => Poll::Ready(Err(error::Custom(e.clone()))),
+        }
+    }
+}
+
+/// How far background loading has got.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LoadProgress {
+    pub requested: usize,
+    pub loaded: usize,
+    pub failed: usize,
+}
+
+impl LoadProgress {
+    pub fn pending(&self) -> usize {
+        self.requested - self.loaded - self.failed
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.pending() == 0
+    }
+
+    /// Fraction of requested resources that have finished, whether or not they loaded.
+    pub fn fraction(&self) -> f32 {
+        if self.requested == 0 {
+            1.0
+        } else {
+            (self.loaded + self.failed) as f32 / self.requested as f32
+        }
+    }
+}
+
+impl Add for LoadProgress {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            requested: self.requested + other.requested,
+            loaded: self.loaded + other.loaded,
+            failed: self.failed + other.failed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::task::Wake;
+    use std::time::Duration;
+
+    struct Flag(AtomicBool);
+
+    impl Wake for Flag {
+        fn wake(self: Arc<Self>) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn jobs_run_on_workers() {
+        let (sender, receiver) = mpsc::channel();
+        for i in 0..8 {
+            let sender = sender.clone();
+            spawn(move || sender.send(i).unwrap());
+        }
+        let mut done: Vec<_> = (0..8)
+            .map(|_| receiver.recv_timeout(Duration::from_secs(5)).unwrap())
+            .collect();
+        done.sort();
+        assert_eq!(done, (0..8).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn handles_resolve_when_finished() {
+        let status = LoadStatus::default();
+        let mut handle = LoadHandle::new(Arc::new(7), status.clone());
+        let flag = Arc::new(Flag(AtomicBool::new(false)));
+        let waker = Waker::from(flag.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        assert!(Pin::new(&mut handle).poll(&mut cx).is_pending());
+        assert_eq!(handle.get(), None);
+        assert_eq!(*handle.resource(), 7);
+
+        status.finish(LoadState::Ready);
+        assert!(flag.0.load(Ordering::SeqCst));
+        match Pin::new(&mut handle).poll(&mut cx) {
+            Poll::Ready(Ok(resource)) => assert_eq!(*resource, 7),
+            _ => panic!("Handle did not resolve"),
+        }
+
+        let mut failed = LoadHandle::new(Arc::new(7), LoadStatus::default());
+        failed.status.finish(LoadState::Failed("Missing".into()));
+        assert!(matches!(
+            Pin::new(&mut failed).poll(&mut cx),
+            Poll::Ready(Err(_))
+        ));
+    }
+
+    #[test]
+    fn progress() {
+        let progress = LoadProgress {
+            requested: 4,
+            loaded: 2,
+            failed: 1,
+        };
+        assert_eq!(progress.pending(), 1);
+        assert_eq!(progress.fraction(), 0.75);
+        assert!(!progress.is_done());
+
+        let total = progress + LoadProgress::default();
+        assert_eq!(total, progress);
+        assert_eq!(LoadProgress::default().fraction(), 1.0);
+        assert!(LoadProgress::default().is_done());
+    }
+}
@@ -1,37 +1,390 @@
-use super::Resource;
+use super::budget::{self, Candidate};
+use super::loader::{self, LoadStatus};
+use super::{
+    AsyncResource, FileWatcher, LoadHandle, LoadProgress, LoadState, Resource, ResourceStats,
+};
 
 use crate::error;
 use crate::graphics::render::Device;
+use crate::vfs;
 
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::{mpsc, Arc};
+use std::{io, panic};
 
+use log::{debug, info, warn};
+
+/// Caches resources by virtual path. The manager holds on to a resource until it is
+/// unloaded, purged once nothing else uses it, or evicted to fit the budget.
 pub struct ResourceManager<R: Resource> {
-    map: HashMap<PathBuf, Arc<R>>,
+    map: HashMap<PathBuf, Entry<R>>,
+    cache_dir: Option<PathBuf>,
+    /// Watches loaded files for hot reloading. On by default in debug builds.
+    watcher: Option<FileWatcher>,
+    /// Resources still loading in the background, by path.
+    loading: HashMap<PathBuf, Loading>,
+    uploads: (mpsc::Sender<Decoded<R>>, mpsc::Receiver<Decoded<R>>),
+    progress: LoadProgress,
+    /// Counts background loads, to tell uploads of unloaded resources from new ones.
+    generation: u64,
+    /// Bytes of GPU memory to keep unused resources within.
+    budget: Option<usize>,
+    /// Counts calls to `enforce_budget`, to order resources by when they were last used.
+    tick: u64,
+    evicted: usize,
+}
+
+struct Entry<R> {
+    resource: Arc<R>,
+    last_used: u64,
+}
+
+struct Loading {
+    /// Which load the status is for. Uploads from any other are stale.
+    generation: u64,
+    status: LoadStatus,
 }
 
+/// Decoded data on its way back from a worker, ready to upload, with the generation of
+/// its load.
+type Decoded<R> = (
+    PathBuf,
+    u64,
+    Box<dyn FnOnce(&Device) -> error::Result<R> + Send>,
+);
+
 impl<R: Resource> ResourceManager<R> {
     pub fn new() -> Self {
         Self {
             map: HashMap::new(),
+            cache_dir: None,
+            watcher: cfg!(debug_assertions).then(FileWatcher::default),
+            loading: HashMap::new(),
+            uploads: mpsc::channel(),
+            progress: LoadProgress::default(),
+            generation: 0,
+            budget: None,
+            tick: 0,
+            evicted: 0,
+        }
+    }
+
+    /// Creates a manager that keeps preprocessed resources in `cache_dir`.
+    pub fn with_cache_dir(cache_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            cache_dir: Some(cache_dir.into()),
+            ..Self::new()
         }
     }
 
+    pub fn set_cache_dir(&mut self, cache_dir: Option<PathBuf>) {
+        self.cache_dir = cache_dir;
+    }
+
+    /// Starts or stops watching loaded files. Resources loaded while hot reloading
+    /// was off are watched from when it is turned on.
+    pub fn set_hot_reload(&mut self, enabled: bool) {
+        if !enabled {
+            self.watcher = None;
+        } else if self.watcher.is_none() {
+            let mut watcher = FileWatcher::default();
+            for (path, entry) in &self.map {
+                watch(&mut watcher, path, &entry.resource);
+            }
+            self.watcher = Some(watcher);
+        }
+    }
+
+    pub fn hot_reload(&self) -> bool {
+        self.watcher.is_some()
+    }
+
     pub fn get_resource_from_file(
         &mut self,
         device: &Device,
         path: impl AsRef<Path>,
     ) -> error::Result<Arc<R>> {
-        let path = path.as_ref().canonicalize()?;
-        if let Some(resource) = self.map.get(&path) {
-            Ok(resource.clone())
+        let path = PathBuf::from(vfs::normalize(path)?);
+        if let Some(entry) = self.map.get_mut(&path) {
+            entry.last_used = self.tick;
+            Ok(entry.resource.clone())
         } else {
-            let resource = R::load_resource_from_file(device, &path)?;
-            self.map.insert(path, resource.clone());
+            let resource = self.load(device, &path)?;
+            if let Some(watcher) = &mut self.watcher {
+                watch(watcher, &path, &resource);
+            }
+            self.insert(path, resource.clone());
             Ok(resource)
         }
     }
+
+    /// Stops caching a resource. Handles to it stay valid,
+    /// but the next request for the path loads it again.
+    /// A resource still loading fails, and its upload is dropped when it arrives.
+    pub fn unload(&mut self, path: impl AsRef<Path>) -> bool {
+        let path = match vfs::normalize(path) {
+            Ok(path) => PathBuf::from(path),
+            Err(_) => return false,
+        };
+        if let Some(loading) = self.loading.remove(&path) {
+            self.progress.failed += 1;
+            loading.status.finish(LoadState::Failed("unloaded".into()));
+        }
+        let unloaded = self.map.remove(&path).is_some();
+        self.evicted += usize::from(unloaded);
+        unloaded
+    }
+
+    /// Drops every resource nothing else holds. Returns how many were dropped.
+    pub fn purge_unused(&mut self) -> usize {
+        let unused: Vec<_> = self
+            .map
+            .iter()
+            .filter(|(path, entry)| self.is_unused(path, entry))
+            .map(|(path, _)| path.clone())
+            .collect();
+        self.remove(&unused)
+    }
+
+    /// Bytes of GPU memory to keep the manager within, or `None` for no limit.
+    /// Only unused resources are evicted, so resources in use can exceed it.
+    pub fn set_budget(&mut self, budget: Option<usize>) {
+        self.budget = budget;
+    }
+
+    pub fn budget(&self) -> Option<usize> {
+        self.budget
+    }
+
+    /// Marks the resources in use as used now, then evicts the least recently used
+    /// of the rest until the manager fits its budget. Call once a frame.
+    /// Returns how many were evicted.
+    pub fn enforce_budget(&mut self) -> usize {
+        self.tick += 1;
+        let mut bytes = 0;
+        let mut candidates = Vec::new();
+        for (path, entry) in &mut self.map {
+            let size = entry.resource.memory_size();
+            bytes += size;
+            if Arc::strong_count(&entry.resource) > 1 {
+                entry.last_used = self.tick;
+            } else if !self.loading.contains_key(path) {
+                candidates.push(Candidate {
+                    key: path.clone(),
+                    bytes: size,
+                    last_used: entry.last_used,
+                });
+            }
+        }
+
+        let budget = match self.budget {
+            Some(budget) => budget,
+            None => return 0,
+        };
+        let evictions = budget::select_evictions(candidates, bytes, budget);
+        self.remove(&evictions)
+    }
+
+    pub fn stats(&self) -> ResourceStats {
+        let mut stats = ResourceStats {
+            count: self.map.len(),
+            budget: self.budget,
+            evicted: self.evicted,
+            ..Default::default()
+        };
+        for entry in self.map.values() {
+            let size = entry.resource.memory_size();
+            stats.bytes += size;
+            if Arc::strong_count(&entry.resource) > 1 {
+                stats.in_use += 1;
+                stats.in_use_bytes += size;
+            }
+        }
+        stats
+    }
+
+    /// Reloads resources whose files changed on disk, in place. A resource that fails
+    /// to reload keeps its old version, so a bad edit can be fixed while running.
+    /// Returns how many resources were reloaded.
+    pub fn reload_changed(&mut self, device: &Device) -> usize {
+        let watcher = match &mut self.watcher {
+            Some(watcher) => watcher,
+            None => return 0,
+        };
+        let changed = watcher.changed();
+        if changed.is_empty() {
+            return 0;
+        }
+
+        let mut reloaded = 0;
+        for (path, entry) in &self.map {
+            let resource = &entry.resource;
+            if self.loading.contains_key(path) {
+                continue;
+            }
+            let dependencies = resource.dependencies();
+            if !changed
+                .iter()
+                .any(|file| file == path || dependencies.contains(file))
+            {
+                continue;
+            }
+            match self.reload(device, path, resource) {
+                Ok(()) => {
+                    info!("Reloaded {}", path.display());
+                    reloaded += 1;
+                }
+                Err(e) => warn!("Could not reload {}: {}", path.display(), e),
+            }
+            // New dependencies, like a material file added to a mesh, are watched too.
+            if let Some(watcher) = &mut self.watcher {
+                watch(watcher, path, resource);
+            }
+        }
+        reloaded
+    }
+
+    /// Uploads resources decoded in the background into their placeholders.
+    /// Call from the render thread. Returns how many finished, loaded or not.
+    pub fn finish_loading(&mut self, device: &Device) -> usize {
+        let mut finished = 0;
+        while let Ok((path, generation, upload)) = self.uploads.1.try_recv() {
+            let resource = match (self.loading.get(&path), self.map.get(&path)) {
+                (Some(loading), Some(entry)) if loading.generation == generation => &entry.resource,
+                // Unloaded since, and maybe loading again.
+                _ => {
+                    debug!("Dropping stale upload of {}", path.display());
+                    continue;
+                }
+            };
+            let status = self.loading.remove(&path).unwrap().status;
+            match upload(device).and_then(|fresh| resource.replace(device, fresh)) {
+                Ok(()) => {
+                    self.progress.loaded += 1;
+                    status.finish(LoadState::Ready);
+                }
+                Err(e) => {
+                    warn!("Could not load {}: {}", path.display(), e);
+                    self.progress.failed += 1;
+                    status.finish(LoadState::Failed(e.to_string()));
+                }
+            }
+            // Failed resources are watched too, so fixing the file loads them.
+            if let Some(watcher) = &mut self.watcher {
+                watch(watcher, &path, resource);
+            }
+            finished += 1;
+        }
+        finished
+    }
+
+    /// Counts every background load since the manager was created.
+    pub fn progress(&self) -> LoadProgress {
+        self.progress
+    }
+
+    fn insert(&mut self, path: PathBuf, resource: Arc<R>) {
+        let last_used = self.tick;
+        self.map.insert(
+            path,
+            Entry {
+                resource,
+                last_used,
+            },
+        );
+    }
+
+    fn remove(&mut self, paths: &[PathBuf]) -> usize {
+        for path in paths {
+            debug!("Unloading {}", path.display());
+            self.map.remove(path);
+        }
+        self.evicted += paths.len();
+        paths.len()
+    }
+
+    fn is_unused(&self, path: &Path, entry: &Entry<R>) -> bool {
+        Arc::strong_count(&entry.resource) == 1 && !self.loading.contains_key(path)
+    }
+
+    fn reload(&self, device: &Device, path: &Path, resource: &R) -> error::Result<()> {
+        let fresh = Arc::try_unwrap(self.load(device, path)?)
+            .map_err(|_| "Reloaded resource is already shared")?;
+        resource.replace(device, fresh)
+    }
+
+    fn load(&self, device: &Device, path: &Path) -> error::Result<Arc<R>> {
+        match &self.cache_dir {
+            Some(cache_dir) => R::load_resource_cached(device, path, cache_dir),
+            None => R::load_resource_from_file(device, path),
+        }
+    }
+}
+
+fn watch<R: Resource>(watcher: &mut FileWatcher, path: &Path, resource: &R) {
+    watcher.watch(path);
+    for dependency in resource.dependencies() {
+        watcher.watch(dependency);
+    }
+}
+
+impl<R: AsyncResource> ResourceManager<R> {
+    /// Starts loading a resource in the background and returns straight away.
+    /// The handle holds a placeholder until `finish_loading` replaces it with the resource.
+    pub fn load_resource_async(
+        &mut self,
+        device: &Device,
+        path: impl AsRef<Path>,
+    ) -> error::Result<LoadHandle<R>> {
+        let path = PathBuf::from(vfs::normalize(path)?);
+        if let Some(entry) = self.map.get_mut(&path) {
+            entry.last_used = self.tick;
+            let resource = entry.resource.clone();
+            let status = self
+                .loading
+                .get(&path)
+                .map(|loading| loading.status.clone())
+                .unwrap_or_else(LoadStatus::ready);
+            return Ok(LoadHandle::new(resource, status));
+        }
+
+        // Missing files fail now rather than leaving a placeholder forever.
+        if !vfs::exists(&path) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} is not in any mount", path.display()),
+            )
+            .into());
+        }
+
+        let resource = Arc::new(R::placeholder(device)?);
+        let status = LoadStatus::default();
+        self.insert(path.clone(), resource.clone());
+        self.generation += 1;
+        let generation = self.generation;
+        self.loading.insert(
+            path.clone(),
+            Loading {
+                generation,
+                status: status.clone(),
+            },
+        );
+        self.progress.requested += 1;
+
+        let sender = self.uploads.0.clone();
+        let cache_dir = self.cache_dir.clone();
+        loader::spawn(move || {
+            // Errors aren't `Send`, so only their messages make it back.
+            let decoded = panic::catch_unwind(|| R::decode(&path, cache_dir.as_deref()))
+                .unwrap_or_else(|_| Err("Decoding panicked".into()))
+                .map_err(|e| e.to_string());
+            let upload = move |device: &Device| R::upload(device, decoded.map_err(error::Custom)?);
+            // The manager may have been dropped, leaving nothing to upload to.
+            let _ = sender.send((path, generation, Box::new(upload)));
+        });
+        Ok(LoadHandle::new(resource, status))
+    }
 }
 
 impl<R: Resource> Default for ResourceManager<R> {
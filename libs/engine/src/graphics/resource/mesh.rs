@@ -1,18 +1,30 @@
-use super::{shader, Resource, ResourceManager};
+mod cache;
+mod export;
+mod optimize;
+mod simplify;
+
+pub use export::{write_mtl, write_obj, write_ply, PlyFormat};
+pub use optimize::{
+    analyze_vertex_cache, optimize_overdraw, optimize_vertex_cache, optimize_vertex_fetch,
+    remap_vertices, VertexCacheStats, CACHE_SIZE,
+};
+pub use simplify::{simplify, Simplified};
+
+use super::{shader, AsyncResource, Resource, ResourceManager};
 
 use crate::error;
 use crate::graphics::render::{Device, IndexBuffer, VertexBuffer};
 use crate::graphics::vertex;
 use crate::math::{Matrix, Vector2d, Vector3d};
+use crate::physics::Bounds;
+use crate::vfs;
 
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::Read;
 use std::mem;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex, MutexGuard};
 
-use log::warn;
+use log::{debug, warn};
 use wavefront_obj::{mtl, obj};
 
 pub type MeshManager = ResourceManager<Mesh>;
@@ -24,23 +36,139 @@ impl Resource for Mesh {
         device: &Device,
         path: impl AsRef<Path>,
     ) -> error::Result<Arc<Self>> {
-        let mut file = File::open(path.as_ref())?;
-        let mut string = String::new();
-        file.read_to_string(&mut string)?;
-        let obj_set = obj::parse(&string)?;
+        Self::from_data(device, MeshData::load_obj(path)?)
+    }
 
-        let mut material_map = MaterialMap(HashMap::new());
+    fn load_resource_cached(
+        device: &Device,
+        path: impl AsRef<Path>,
+        cache_dir: &Path,
+    ) -> error::Result<Arc<Self>> {
+        Self::from_data(device, MeshData::load_cached(path, cache_dir)?)
+    }
+
+    /// Levels of detail are simplified again, keeping the same fraction of triangles.
+    fn replace(&self, device: &Device, fresh: Self) -> error::Result<()> {
+        let targets: Vec<_> = {
+            let inner = self.inner();
+            let triangles = inner.triangle_count(0).max(1) as f32;
+            let fresh_triangles = fresh.inner().triangle_count(0) as f32;
+            inner
+                .lods
+                .iter()
+                .map(|lod| LodTarget {
+                    triangles: (fresh_triangles * (lod.indices.len() / 3) as f32 / triangles)
+                        as usize,
+                    screen_size: lod.screen_size,
+                })
+                .collect()
+        };
+        if !targets.is_empty() {
+            fresh.generate_lods(device, &targets)?;
+        }
+        *self.inner() = fresh.0.into_inner().unwrap();
+        Ok(())
+    }
+
+    fn memory_size(&self) -> usize {
+        self.inner().memory_size()
+    }
+
+    fn dependencies(&self) -> Vec<PathBuf> {
+        self.inner().sources.clone()
+    }
+}
+
+impl AsyncResource for Mesh {
+    type Data = MeshData;
+
+    fn decode(path: &Path, cache_dir: Option<&Path>) -> error::Result<MeshData> {
+        match cache_dir {
+            Some(cache_dir) => MeshData::load_cached(path, cache_dir),
+            None => MeshData::load_obj(path),
+        }
+    }
+
+    fn upload(device: &Device, data: MeshData) -> error::Result<Self> {
+        Self::new(device, data)
+    }
+
+    /// A single degenerate triangle with no submeshes, so nothing is drawn.
+    fn placeholder(device: &Device) -> error::Result<Self> {
+        let vertex = MeshVertex::from_vertex(&obj::Vertex {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        });
+        let data = MeshData {
+            vertices: vec![vertex],
+            indices: vec![0; 3],
+            material_ids: Vec::new(),
+            bounds: Bounds::default(),
+            submesh_bounds: Vec::new(),
+            sources: Vec::new(),
+        };
+        Self::new(device, data)
+    }
+}
+
+/// A processed mesh on the CPU, ready to be uploaded or written to the mesh cache.
+pub struct MeshData {
+    pub vertices: Vec<MeshVertex>,
+    pub indices: Vec<u32>,
+    pub material_ids: Vec<MaterialId>,
+    /// Local space bounds of the whole mesh.
+    pub bounds: Bounds,
+    /// Local space bounds of each submesh, in the same order as `material_ids`.
+    pub submesh_bounds: Vec<Bounds>,
+    /// Files the mesh was built from; the cache is stale if any of them change.
+    pub sources: Vec<PathBuf>,
+}
+
+impl MeshData {
+    pub fn load_obj(path: impl AsRef<Path>) -> error::Result<Self> {
+        let obj_set = obj::parse(&vfs::read_to_string(path.as_ref())?)?;
+        let mut sources = vec![path.as_ref().to_path_buf()];
+
+        let mut mtl_set = None;
         if let Some(mtl_file) = obj_set.material_library.as_ref() {
-            if let Ok(mtl_set) = load_material(path.as_ref().parent().unwrap().join(mtl_file)) {
-                for (index, mtl) in mtl_set.materials.iter().enumerate() {
-                    material_map.0.insert(mtl.name.clone(), index);
-                }
+            let mtl_path = PathBuf::from(vfs::normalize(
+                path.as_ref().parent().unwrap().join(mtl_file),
+            )?);
+            if let Ok(set) = load_material(&mtl_path) {
+                mtl_set = Some(set);
+                sources.push(mtl_path);
             } else {
                 warn!("Material not found for object: {}", path.as_ref().display());
-                warn!(
-                    "Looked for {}",
-                    path.as_ref().parent().unwrap().join(mtl_file).display()
-                );
+                warn!("Looked for {}", mtl_path.display());
+            }
+        }
+
+        let mut data = Self::from_obj(&obj_set, mtl_set.as_ref())?;
+        data.sources = sources;
+        debug!("Loaded {}", path.as_ref().display());
+        Ok(data)
+    }
+
+    /// Builds a mesh from parsed OBJ data. Submeshes are ordered by their index in `mtl_set`.
+    /// Reads the mesh from `cache_dir`, or loads and caches it if the cache is missing or stale.
+    pub fn load_cached(path: impl AsRef<Path>, cache_dir: &Path) -> error::Result<Self> {
+        let path = path.as_ref();
+        if let Some(data) = cache::read(cache_dir, path) {
+            return Ok(data);
+        }
+        let data = Self::load_obj(path)?;
+        if let Err(e) = cache::write(cache_dir, path, &data) {
+            warn!("Could not cache mesh {}: {}", path.display(), e);
+        }
+        Ok(data)
+    }
+
+    pub fn from_obj(obj_set: &obj::ObjSet, mtl_set: Option<&mtl::MtlSet>) -> error::Result<Self> {
+        let mut material_map = MaterialMap(HashMap::new());
+        if let Some(mtl_set) = mtl_set {
+            for (index, mtl) in mtl_set.materials.iter().enumerate() {
+                material_map.0.insert(mtl.name.clone(), index);
             }
         }
 
@@ -79,9 +207,10 @@ impl Resource for Mesh {
             .collect();
         let mut vertex_metadata = vec![VertexMetadata::default(); vertices.len()];
 
+        let first_geometry = geometries.first().ok_or("Empty Object")?.1;
         let mut material_id = MaterialId {
-            id: material_map.id_of(&geometries[0].1.material_name),
-            name: geometries[0].1.material_name.clone(),
+            id: material_map.id_of(&first_geometry.material_name),
+            name: first_geometry.material_name.clone(),
             offset: 0,
             len: 0,
         };
@@ -160,33 +289,44 @@ impl Resource for Mesh {
         material_id.len = indices.len() - material_id.offset;
         material_ids.push(material_id);
 
+        let before = analyze_vertex_cache(&indices, vertices.len(), CACHE_SIZE);
+        let vertices = optimize(vertices, &mut indices, &material_ids);
+        let after = analyze_vertex_cache(&indices, vertices.len(), CACHE_SIZE);
+        debug!(
+            "Optimized mesh: ACMR {:.3} -> {:.3}, ATVR {:.3} -> {:.3}",
+            before.acmr, after.acmr, before.atvr, after.atvr
+        );
+
         if vertices.is_empty() {
             return Err(error::Custom("Empty Object".to_string()));
         }
 
-        let vs = shader::compile_shader(
-            include_bytes!("vertex_mesh_layout.hlsl"),
-            "vsmain",
-            "vs_5_0",
-        )?;
-        let vertex_buffer = device.new_vertex_buffer(&vertices, &vs)?;
-        let index_buffer = device.new_index_buffer(&indices)?;
+        let positions = vertices.iter().map(|v| v.position.to_3d_unchecked());
+        let bounds = Bounds::from_points(positions).unwrap_or_default();
+        let submesh_bounds = material_ids
+            .iter()
+            .map(|material_id| {
+                let indices = &indices[material_id.offset..material_id.offset + material_id.len];
+                let positions = indices
+                    .iter()
+                    .map(|&i| vertices[i as usize].position.to_3d_unchecked());
+                Bounds::from_points(positions).unwrap_or_default()
+            })
+            .collect();
 
-        Ok(Arc::new(Self(Mutex::new(MeshInner {
+        Ok(Self {
             vertices,
-            vertex_buffer,
             indices,
-            index_buffer,
             material_ids,
-        }))))
+            bounds,
+            submesh_bounds,
+            sources: Vec::new(),
+        })
     }
 }
 
 fn load_material<P: AsRef<Path>>(path: P) -> error::Result<mtl::MtlSet> {
-    let mut string = String::new();
-    let mut file = File::open(path)?;
-    file.read_to_string(&mut string)?;
-    Ok(mtl::parse(&string)?)
+    Ok(mtl::parse(&vfs::read_to_string(path)?)?)
 }
 
 fn calc_normal(object: &obj::Object, indices: [&obj::VTNIndex; 3]) -> vertex::Normal {
@@ -223,10 +363,125 @@ fn calc_tangents(
     Some((tangent.into(), binormal.into()))
 }
 
+/// Reorders each submesh for the vertex cache and overdraw, then lays out vertices in the order
+/// they are used. Vertices no triangle uses are dropped.
+fn optimize(
+    vertices: Vec<MeshVertex>,
+    indices: &mut [u32],
+    material_ids: &[MaterialId],
+) -> Vec<MeshVertex> {
+    let positions: Vec<_> = vertices
+        .iter()
+        .map(|v| v.position.to_3d_unchecked())
+        .collect();
+    for material_id in material_ids {
+        optimize_overdraw(
+            &mut indices[material_id.offset..material_id.offset + material_id.len],
+            &positions,
+        );
+    }
+    let remap = optimize_vertex_fetch(indices, vertices.len());
+    remap_vertices(vertices, &remap)
+}
+
 impl Mesh {
+    /// Uploads processed mesh data to the GPU.
+    pub fn from_data(device: &Device, data: MeshData) -> error::Result<Arc<Self>> {
+        Self::new(device, data).map(Arc::new)
+    }
+
+    pub fn new(device: &Device, data: MeshData) -> error::Result<Self> {
+        let MeshData {
+            vertices,
+            indices,
+            material_ids,
+            bounds,
+            submesh_bounds,
+            sources,
+        } = data;
+
+        let vs = shader::compile_shader(
+            include_bytes!("vertex_mesh_layout.hlsl"),
+            "vsmain",
+            "vs_5_0",
+        )?;
+        let vertex_buffer = device.new_vertex_buffer(&vertices, &vs)?;
+        let index_buffer = device.new_index_buffer(&indices)?;
+
+        Ok(Self(Mutex::new(MeshInner {
+            vertices,
+            vertex_buffer,
+            indices,
+            index_buffer,
+            material_ids,
+            lods: Vec::new(),
+            bounds,
+            submesh_bounds,
+            sources,
+        })))
+    }
+
     pub fn inner(&self) -> MutexGuard<MeshInner> {
         self.0.lock().unwrap()
     }
+
+    /// Replaces the levels of detail of this mesh with simplified versions.
+    /// Each level is simplified from the one before it, so targets should be in decreasing order.
+    pub fn generate_lods(&self, device: &Device, targets: &[LodTarget]) -> error::Result<()> {
+        let mut inner = self.inner();
+        let positions: Vec<_> = inner
+            .vertices
+            .iter()
+            .map(|v| v.position.to_3d_unchecked())
+            .collect();
+
+        let mut lods: Vec<MeshLod> = Vec::new();
+        for target in targets {
+            let (indices, material_ids) = lods
+                .last()
+                .map_or((&inner.indices, &inner.material_ids), |lod| {
+                    (&lod.indices, &lod.material_ids)
+                });
+            let submeshes: Vec<_> = material_ids
+                .iter()
+                .map(|id| id.offset..id.offset + id.len)
+                .collect();
+
+            let mut simplified = simplify(&positions, indices, &submeshes, target.triangles);
+            if simplified.indices.is_empty() {
+                warn!(
+                    "Mesh simplified to nothing, stopping at {} LODs",
+                    lods.len()
+                );
+                break;
+            }
+
+            for range in &simplified.submeshes {
+                optimize_vertex_cache(&mut simplified.indices[range.clone()], positions.len());
+            }
+
+            let material_ids = material_ids
+                .iter()
+                .zip(&simplified.submeshes)
+                .map(|(material_id, range)| MaterialId {
+                    offset: range.start,
+                    len: range.len(),
+                    ..material_id.clone()
+                })
+                .collect();
+            let index_buffer = device.new_index_buffer(&simplified.indices)?;
+
+            lods.push(MeshLod {
+                indices: simplified.indices,
+                index_buffer,
+                material_ids,
+                screen_size: target.screen_size,
+            });
+        }
+
+        inner.lods = lods;
+        Ok(())
+    }
 }
 
 // impl PartialEq for Mesh {
@@ -322,6 +577,78 @@ pub struct MeshInner {
     pub indices: Vec<u32>,
     pub index_buffer: IndexBuffer,
     pub material_ids: Vec<MaterialId>,
+    /// Simplified versions of the mesh, from most to least detailed. LOD 0 is the mesh itself.
+    pub lods: Vec<MeshLod>,
+    /// Local space bounds of the whole mesh. LODs fit inside them too.
+    pub bounds: Bounds,
+    /// Local space bounds of each submesh, in the same order as `material_ids`.
+    pub submesh_bounds: Vec<Bounds>,
+    /// Files the mesh was loaded from, watched for hot reloading.
+    pub sources: Vec<PathBuf>,
+}
+
+impl MeshInner {
+    pub fn lod_count(&self) -> usize {
+        self.lods.len() + 1
+    }
+
+    /// Chooses the LOD to draw when the mesh covers `screen_size` of the screen height.
+    pub fn select_lod(&self, screen_size: f32) -> usize {
+        self.lods
+            .iter()
+            .take_while(|lod| screen_size < lod.screen_size)
+            .count()
+    }
+
+    /// Bytes of vertex and index buffers, including every LOD.
+    pub fn memory_size(&self) -> usize {
+        let indices =
+            self.indices.len() + self.lods.iter().map(|lod| lod.indices.len()).sum::<usize>();
+        self.vertices.len() * mem::size_of::<MeshVertex>() + indices * mem::size_of::<u32>()
+    }
+
+    pub fn triangle_count(&self, lod: usize) -> usize {
+        self.lod_indices(lod).len() / 3
+    }
+
+    /// Measures how well a LOD uses the post-transform vertex cache.
+    pub fn cache_stats(&self, lod: usize) -> VertexCacheStats {
+        analyze_vertex_cache(self.lod_indices(lod), self.vertices.len(), CACHE_SIZE)
+    }
+
+    fn lod_indices(&self, lod: usize) -> &[u32] {
+        match lod.checked_sub(1) {
+            Some(i) => &self.lods[i].indices,
+            None => &self.indices,
+        }
+    }
+}
+
+/// A simplified level of detail, drawn with the vertex buffer of the full mesh.
+pub struct MeshLod {
+    pub indices: Vec<u32>,
+    pub index_buffer: IndexBuffer,
+    pub material_ids: Vec<MaterialId>,
+    /// Fraction of the screen height below which this LOD is used.
+    pub screen_size: f32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct LodTarget {
+    pub triangles: usize,
+    pub screen_size: f32,
+}
+
+impl LodTarget {
+    /// `levels` targets, each with half the triangles and half the screen size of the one before.
+    pub fn halving(triangles: usize, levels: usize) -> Vec<Self> {
+        (1..=levels)
+            .map(|level| Self {
+                triangles: triangles >> level,
+                screen_size: 0.5f32.powi(level as i32),
+            })
+            .collect()
+    }
 }
 
 //TODO Verify
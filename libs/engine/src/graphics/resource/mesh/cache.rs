@@ -0,0 +1,388 @@
+//! Binary mesh cache, so source files only have to be parsed once.
+//!
+//! Layout, all little endian:
+//! magic, version, source keys, vertices, indices, submesh table, bounds.
+//! A cache file is only used if every source it was built from still has the same path,
+//! modification time and content hash.
+
+use super::{MaterialId, MeshData, MeshVertex};
+
+use crate::error;
+use crate::physics::{Aabb, BoundingSphere, Bounds};
+use crate::util::fnv1a;
+use crate::vfs;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use log::debug;
+
+const MAGIC: [u8; 4] = *b"TEMC";
+/// Bump whenever the layout or the mesh processing changes.
+const VERSION: u32 = 2;
+const EXTENSION: &str = "mesh";
+
+/// Identifies the contents of a source file at the time the cache was written.
+#[derive(Debug, PartialEq)]
+struct SourceKey {
+    path: PathBuf,
+    modified: u64,
+    hash: u64,
+}
+
+impl SourceKey {
+    fn of(path: &Path) -> error::Result<Self> {
+        let modified = vfs::modified(path)
+            .ok_or("Source has no modification time")?
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_nanos() as u64);
+        let hash = fnv1a(&vfs::read(path)?);
+        Ok(Self {
+            path: path.to_path_buf(),
+            modified,
+            hash,
+        })
+    }
+
+    fn is_current(&self) -> bool {
+        Self::of(&self.path).is_ok_and(|key| key == *self)
+    }
+}
+
+/// Where the cache of `source` lives in `cache_dir`.
+pub fn cache_path(cache_dir: &Path, source: &Path) -> PathBuf {
+    let name = fnv1a(source.to_string_lossy().as_bytes());
+    cache_dir.join(format!("{:016x}.{}", name, EXTENSION))
+}
+
+/// Reads the cached mesh of `source`, if there is one and it is up to date.
+pub fn read(cache_dir: &Path, source: &Path) -> Option<MeshData> {
+    let cache_path = cache_path(cache_dir, source);
+    let bytes = fs::read(&cache_path).ok()?;
+    let data =
+        decode(&bytes).filter(|data| data.sources.first().map(PathBuf::as_path) == Some(source));
+    if data.is_some() {
+        debug!("Loaded {} from cache", source.display());
+    } else {
+        debug!("Ignoring stale mesh cache {}", cache_path.display());
+    }
+    data
+}
+
+pub fn write(cache_dir: &Path, source: &Path, data: &MeshData) -> error::Result<()> {
+    let keys = data
+        .sources
+        .iter()
+        .map(|path| SourceKey::of(path))
+        .collect::<error::Result<Vec<_>>>()?;
+
+    fs::create_dir_all(cache_dir)?;
+    fs::write(cache_path(cache_dir, source), encode(&keys, data))?;
+    Ok(())
+}
+
+fn encode(keys: &[SourceKey], data: &MeshData) -> Vec<u8> {
+    let mut writer = Writer(Vec::new());
+    writer.bytes(&MAGIC);
+    writer.u32(VERSION);
+
+    writer.len(keys.len());
+    for key in keys {
+        writer.string(&key.path.to_string_lossy());
+        writer.u64(key.modified);
+        writer.u64(key.hash);
+    }
+
+    writer.len(data.vertices.len());
+    for vertex in &data.vertices {
+        writer.floats(&(*vertex.position).0);
+        writer.floats(&(*vertex.texture).0);
+        writer.floats(&(*vertex.tangent).0);
+        writer.floats(&(*vertex.binormal).0);
+        writer.floats(&(*vertex.normal).0);
+    }
+
+    writer.len(data.indices.len());
+    for &index in &data.indices {
+        writer.u32(index);
+    }
+
+    writer.len(data.material_ids.len());
+    for (material_id, bounds) in data.material_ids.iter().zip(&data.submesh_bounds) {
+        writer.len(material_id.id);
+        match &material_id.name {
+            Some(name) => {
+                writer.u32(1);
+                writer.string(name);
+            }
+            None => writer.u32(0),
+        }
+        writer.len(material_id.offset);
+        writer.len(material_id.len);
+        writer.bounds(bounds);
+    }
+
+    writer.bounds(&data.bounds);
+    writer.0
+}
+
+/// Returns `None` if the data is corrupt, from another version or its sources changed.
+fn decode(bytes: &[u8]) -> Option<MeshData> {
+    let (data, keys) = decode_unchecked(bytes)?;
+    if keys.iter().all(SourceKey::is_current) {
+        Some(data)
+    } else {
+        None
+    }
+}
+
+fn decode_unchecked(bytes: &[u8]) -> Option<(MeshData, Vec<SourceKey>)> {
+    let mut reader = Reader(bytes);
+    if reader.bytes(MAGIC.len())? != MAGIC || reader.u32()? != VERSION {
+        return None;
+    }
+
+    let keys = (0..reader.len()?)
+        .map(|_| {
+            Some(SourceKey {
+                path: reader.string()?.into(),
+                modified: reader.u64()?,
+                hash: reader.u64()?,
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    let vertices = (0..reader.len()?)
+        .map(|_| {
+            Some(MeshVertex {
+                position: reader.floats::<4>()?.into(),
+                texture: reader.floats::<2>()?.into(),
+                tangent: reader.floats::<3>()?.into(),
+                binormal: reader.floats::<3>()?.into(),
+                normal: reader.floats::<3>()?.into(),
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    let indices = (0..reader.len()?)
+        .map(|_| reader.u32())
+        .collect::<Option<Vec<_>>>()?;
+    if indices.iter().any(|&i| i as usize >= vertices.len()) {
+        return None;
+    }
+
+    let (material_ids, submesh_bounds) = (0..reader.len()?)
+        .map(|_| {
+            let id = reader.len()?;
+            let name = match reader.u32()? {
+                0 => None,
+                _ => Some(reader.string()?),
+            };
+            let material_id = MaterialId {
+                id,
+                name,
+                offset: reader.len()?,
+                len: reader.len()?,
+            };
+            Some((material_id, reader.bounds()?))
+        })
+        .collect::<Option<(Vec<_>, Vec<_>)>>()?;
+    if material_ids
+        .iter()
+        .any(|id| id.offset + id.len > indices.len())
+    {
+        return None;
+    }
+
+    let bounds = reader.bounds()?;
+    if !reader.0.is_empty() {
+        return None;
+    }
+
+    let sources = keys.iter().map(|key| key.path.clone()).collect();
+    let data = MeshData {
+        vertices,
+        indices,
+        material_ids,
+        bounds,
+        submesh_bounds,
+        sources,
+    };
+    Some((data, keys))
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn len(&mut self, len: usize) {
+        self.u32(len as u32);
+    }
+
+    fn floats(&mut self, floats: &[f32]) {
+        for float in floats {
+            self.bytes(&float.to_le_bytes());
+        }
+    }
+
+    fn string(&mut self, string: &str) {
+        self.len(string.len());
+        self.bytes(string.as_bytes());
+    }
+
+    fn bounds(&mut self, bounds: &Bounds) {
+        self.floats(&bounds.aabb.min.0);
+        self.floats(&bounds.aabb.max.0);
+        self.floats(&bounds.sphere.center.0);
+        self.floats(&[bounds.sphere.radius]);
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        self.bytes(N)?.try_into().ok()
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.array().map(u32::from_le_bytes)
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        self.array().map(u64::from_le_bytes)
+    }
+
+    fn len(&mut self) -> Option<usize> {
+        self.u32().map(|len| len as usize)
+    }
+
+    fn floats<const N: usize>(&mut self) -> Option<[f32; N]> {
+        let mut floats = [0.0; N];
+        for float in &mut floats {
+            *float = f32::from_le_bytes(self.array()?);
+        }
+        Some(floats)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = self.len()?;
+        String::from_utf8(self.bytes(len)?.to_vec()).ok()
+    }
+
+    fn bounds(&mut self) -> Option<Bounds> {
+        let aabb = Aabb::new(self.floats::<3>()?, self.floats::<3>()?);
+        let center = self.floats::<3>()?;
+        let [radius] = self.floats::<1>()?;
+        Some(Bounds {
+            aabb,
+            sphere: BoundingSphere::new(center, radius),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn vertex(x: f32) -> MeshVertex {
+        MeshVertex {
+            position: [x, 2.0, 3.0, 1.0].into(),
+            texture: [0.5, x].into(),
+            tangent: [1.0, 0.0, 0.0].into(),
+            binormal: [0.0, 1.0, 0.0].into(),
+            normal: [0.0, 0.0, x].into(),
+        }
+    }
+
+    fn mesh() -> (MeshData, Vec<SourceKey>) {
+        let keys = vec![SourceKey {
+            path: "assets/cube.obj".into(),
+            modified: 1234,
+            hash: 5678,
+        }];
+        let data = MeshData {
+            vertices: vec![vertex(0.0), vertex(1.0), vertex(2.0)],
+            indices: vec![0, 1, 2, 2, 1, 0],
+            material_ids: vec![
+                MaterialId {
+                    id: 0,
+                    name: Some("Stone".into()),
+                    offset: 0,
+                    len: 3,
+                },
+                MaterialId {
+                    id: 1,
+                    name: None,
+                    offset: 3,
+                    len: 3,
+                },
+            ],
+            bounds: Bounds {
+                aabb: Aabb::new([0.0, 2.0, 3.0], [2.0, 2.0, 3.0]),
+                sphere: BoundingSphere::new([1.0, 2.0, 3.0], 1.5),
+            },
+            submesh_bounds: vec![Bounds::default(); 2],
+            sources: vec!["assets/cube.obj".into()],
+        };
+        (data, keys)
+    }
+
+    #[test]
+    fn round_trip() {
+        let (data, keys) = mesh();
+        let (decoded, decoded_keys) = decode_unchecked(&encode(&keys, &data)).unwrap();
+
+        assert_eq!(decoded_keys, keys);
+        assert_eq!(decoded.sources, data.sources);
+        assert_eq!(decoded.indices, data.indices);
+        assert_eq!(decoded.vertices.len(), data.vertices.len());
+        for (a, b) in decoded.vertices.iter().zip(&data.vertices) {
+            assert_eq!((*a.position).0, (*b.position).0);
+            assert_eq!((*a.texture).0, (*b.texture).0);
+            assert_eq!((*a.normal).0, (*b.normal).0);
+        }
+        assert_eq!(decoded.material_ids[0].name.as_deref(), Some("Stone"));
+        assert_eq!(decoded.material_ids[1].name, None);
+        assert_eq!(decoded.material_ids[1].offset, 3);
+        assert_eq!(decoded.bounds, data.bounds);
+        assert_eq!(decoded.submesh_bounds, data.submesh_bounds);
+    }
+
+    #[test]
+    fn rejects_bad_data() {
+        let (data, keys) = mesh();
+        let bytes = encode(&keys, &data);
+
+        let mut wrong_version = bytes.clone();
+        wrong_version[4] ^= 0xff;
+        assert!(decode_unchecked(&wrong_version).is_none());
+        assert!(decode_unchecked(&bytes[..bytes.len() - 1]).is_none());
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(decode_unchecked(&trailing).is_none());
+        // The source does not exist on disk, so the cache is stale.
+        assert!(decode(&bytes).is_none());
+    }
+}
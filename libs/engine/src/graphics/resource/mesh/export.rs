@@ -0,0 +1,308 @@
+//! Writes processed meshes back out, mostly for inspecting generated geometry in other tools.
+
+use super::{MaterialId, MeshData, MeshVertex};
+
+use crate::error;
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+}
+
+impl MeshData {
+    /// Writes the mesh as OBJ. Named submeshes get their materials in an MTL file beside it.
+    pub fn save_obj(&self, path: impl AsRef<Path>) -> error::Result<()> {
+        let path = path.as_ref();
+        let mtl_path = path.with_extension("mtl");
+        let mtl_library = if named_submeshes(self).next().is_some() {
+            write_mtl(self, BufWriter::new(File::create(&mtl_path)?))?;
+            mtl_path.file_name().and_then(|name| name.to_str())
+        } else {
+            None
+        };
+
+        write_obj(self, mtl_library, BufWriter::new(File::create(path)?))?;
+        Ok(())
+    }
+
+    pub fn save_ply(&self, path: impl AsRef<Path>, format: PlyFormat) -> error::Result<()> {
+        write_ply(self, format, BufWriter::new(File::create(path)?))?;
+        Ok(())
+    }
+}
+
+fn named_submeshes(data: &MeshData) -> impl Iterator<Item = (&MaterialId, &str)> {
+    data.material_ids
+        .iter()
+        .filter_map(|material_id| Some((material_id, material_id.name.as_deref()?)))
+}
+
+/// Every vertex is written with its own position, texture coordinate and normal,
+/// so the importer rebuilds the same vertices.
+pub fn write_obj(
+    data: &MeshData,
+    mtl_library: Option<&str>,
+    mut writer: impl Write,
+) -> io::Result<()> {
+    if let Some(mtl_library) = mtl_library {
+        writeln!(writer, "mtllib {}", mtl_library)?;
+    }
+    writeln!(writer, "o mesh")?;
+
+    for vertex in &data.vertices {
+        let [x, y, z, _] = (*vertex.position).0;
+        writeln!(writer, "v {} {} {}", x, y, z)?;
+    }
+    for vertex in &data.vertices {
+        let [u, v] = (*vertex.texture).0;
+        writeln!(writer, "vt {} {}", u, v)?;
+    }
+    for vertex in &data.vertices {
+        let [x, y, z] = (*vertex.normal).0;
+        writeln!(writer, "vn {} {} {}", x, y, z)?;
+    }
+
+    // Faces after a `usemtl` belong to that material, so unnamed submeshes go first.
+    let mut material_ids: Vec<_> = data.material_ids.iter().collect();
+    material_ids.sort_by_key(|material_id| material_id.name.is_some());
+    for material_id in material_ids {
+        if let Some(name) = &material_id.name {
+            writeln!(writer, "usemtl {}", name)?;
+        }
+        let indices = &data.indices[material_id.offset..material_id.offset + material_id.len];
+        for triangle in indices.chunks_exact(3) {
+            let [a, b, c] = [triangle[0] + 1, triangle[1] + 1, triangle[2] + 1];
+            writeln!(writer, "f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}")?;
+        }
+    }
+    writer.flush()
+}
+
+/// Writes placeholder materials for the named submeshes, in submesh order.
+pub fn write_mtl(data: &MeshData, mut writer: impl Write) -> io::Result<()> {
+    for (_, name) in named_submeshes(data) {
+        writeln!(writer, "newmtl {}", name)?;
+        writeln!(writer, "Ns 100.0")?;
+        writeln!(writer, "Ka 1.0 1.0 1.0")?;
+        writeln!(writer, "Kd 0.8 0.8 0.8")?;
+        writeln!(writer, "Ks 0.5 0.5 0.5")?;
+        writeln!(writer, "d 1.0")?;
+        writeln!(writer, "illum 2")?;
+        writeln!(writer)?;
+    }
+    writer.flush()
+}
+
+/// Faces carry the index of their submesh in a `material_index` property.
+pub fn write_ply(data: &MeshData, format: PlyFormat, mut writer: impl Write) -> io::Result<()> {
+    let format_name = match format {
+        PlyFormat::Ascii => "ascii",
+        PlyFormat::BinaryLittleEndian => "binary_little_endian",
+    };
+    writeln!(writer, "ply")?;
+    writeln!(writer, "format {} 1.0", format_name)?;
+    writeln!(writer, "element vertex {}", data.vertices.len())?;
+    for property in ["x", "y", "z", "nx", "ny", "nz", "s", "t"] {
+        writeln!(writer, "property float {}", property)?;
+    }
+    writeln!(writer, "element face {}", data.indices.len() / 3)?;
+    writeln!(writer, "property list uchar uint vertex_indices")?;
+    writeln!(writer, "property uint material_index")?;
+    writeln!(writer, "end_header")?;
+
+    for vertex in &data.vertices {
+        let floats = vertex_floats(vertex);
+        match format {
+            PlyFormat::Ascii => {
+                let line: Vec<_> = floats.iter().map(f32::to_string).collect();
+                writeln!(writer, "{}", line.join(" "))?;
+            }
+            PlyFormat::BinaryLittleEndian => {
+                for float in floats {
+                    writer.write_all(&float.to_le_bytes())?;
+                }
+            }
+        }
+    }
+
+    for (submesh, material_id) in data.material_ids.iter().enumerate() {
+        let indices = &data.indices[material_id.offset..material_id.offset + material_id.len];
+        for triangle in indices.chunks_exact(3) {
+            match format {
+                PlyFormat::Ascii => writeln!(
+                    writer,
+                    "3 {} {} {} {}",
+                    triangle[0], triangle[1], triangle[2], submesh
+                )?,
+                PlyFormat::BinaryLittleEndian => {
+                    writer.write_all(&[3])?;
+                    for index in triangle {
+                        writer.write_all(&index.to_le_bytes())?;
+                    }
+                    writer.write_all(&(submesh as u32).to_le_bytes())?;
+                }
+            }
+        }
+    }
+    writer.flush()
+}
+
+fn vertex_floats(vertex: &MeshVertex) -> [f32; 8] {
+    let [x, y, z, _] = (*vertex.position).0;
+    let [nx, ny, nz] = (*vertex.normal).0;
+    let [s, t] = (*vertex.texture).0;
+    [x, y, z, nx, ny, nz, s, t]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use wavefront_obj::{mtl, obj};
+
+    const OBJ: &str = "\
+mtllib box.mtl
+o box
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+v 0 0 1
+v 1 0 1
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 -1
+vn 0 -1 0
+usemtl Stone
+f 1/1/1 3/3/1 2/2/1
+f 1/1/1 4/4/1 3/3/1
+usemtl Wood
+f 1/1/2 2/2/2 6/3/2
+f 1/1/2 6/3/2 5/4/2
+";
+
+    const MTL: &str = "\
+newmtl Stone
+Ns 10.0
+Ka 1.0 1.0 1.0
+Kd 0.5 0.5 0.5
+Ks 0.5 0.5 0.5
+d 1.0
+illum 2
+
+newmtl Wood
+Ns 10.0
+Ka 1.0 1.0 1.0
+Kd 0.5 0.3 0.1
+Ks 0.5 0.5 0.5
+d 1.0
+illum 2
+";
+
+    fn load(obj: &str, mtl: &str) -> MeshData {
+        let obj_set = obj::parse(obj).unwrap();
+        let mtl_set = mtl::parse(mtl).unwrap();
+        MeshData::from_obj(&obj_set, Some(&mtl_set)).unwrap()
+    }
+
+    /// Corners of a triangle as the bits of their attributes.
+    type Triangle = Vec<[u32; 8]>;
+
+    /// Triangles of each submesh as exact vertex attributes, independent of vertex order.
+    fn triangles(data: &MeshData) -> Vec<(Option<String>, Vec<Triangle>)> {
+        data.material_ids
+            .iter()
+            .map(|material_id| {
+                let indices =
+                    &data.indices[material_id.offset..material_id.offset + material_id.len];
+                let mut triangles: Vec<_> = indices
+                    .chunks_exact(3)
+                    .map(|triangle| {
+                        let mut corners: Vec<_> = triangle
+                            .iter()
+                            .map(|&i| vertex_floats(&data.vertices[i as usize]).map(f32::to_bits))
+                            .collect();
+                        // Rotate the smallest corner to the front, keeping the winding.
+                        let first = (0..3).min_by_key(|&i| corners[i]).unwrap();
+                        corners.rotate_left(first);
+                        corners
+                    })
+                    .collect();
+                triangles.sort();
+                (material_id.name.clone(), triangles)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn obj_round_trip() {
+        let original = load(OBJ, MTL);
+        assert_eq!(original.material_ids.len(), 2);
+
+        let mut obj = Vec::new();
+        let mut mtl = Vec::new();
+        write_obj(&original, Some("box.mtl"), &mut obj).unwrap();
+        write_mtl(&original, &mut mtl).unwrap();
+        let exported = load(
+            &String::from_utf8(obj).unwrap(),
+            &String::from_utf8(mtl).unwrap(),
+        );
+
+        assert_eq!(exported.vertices.len(), original.vertices.len());
+        assert_eq!(exported.indices.len(), original.indices.len());
+        assert_eq!(triangles(&exported), triangles(&original));
+    }
+
+    #[test]
+    fn ply_ascii() {
+        let data = load(OBJ, MTL);
+        let mut ply = Vec::new();
+        write_ply(&data, PlyFormat::Ascii, &mut ply).unwrap();
+        let ply = String::from_utf8(ply).unwrap();
+
+        let (header, body) = ply.split_once("end_header\n").unwrap();
+        assert!(header.contains(&format!("element vertex {}", data.vertices.len())));
+        assert!(header.contains("element face 4"));
+
+        let lines: Vec<_> = body.lines().collect();
+        assert_eq!(lines.len(), data.vertices.len() + 4);
+        for (line, vertex) in lines.iter().zip(&data.vertices) {
+            let floats: Vec<f32> = line.split(' ').map(|f| f.parse().unwrap()).collect();
+            assert_eq!(floats, vertex_floats(vertex));
+        }
+        let faces = &lines[data.vertices.len()..];
+        assert!(faces[..2].iter().all(|face| face.ends_with(" 0")));
+        assert!(faces[2..].iter().all(|face| face.ends_with(" 1")));
+    }
+
+    #[test]
+    fn ply_binary() {
+        let data = load(OBJ, MTL);
+        let mut ply = Vec::new();
+        write_ply(&data, PlyFormat::BinaryLittleEndian, &mut ply).unwrap();
+
+        let end = b"end_header\n";
+        let body_start = ply.windows(end.len()).position(|w| w == end).unwrap() + end.len();
+        let body = &ply[body_start..];
+        assert_eq!(body.len(), data.vertices.len() * 32 + 4 * 17);
+
+        let float = |i: usize| f32::from_le_bytes(body[i * 4..i * 4 + 4].try_into().unwrap());
+        assert_eq!(
+            [0, 1, 2].map(float),
+            [0, 1, 2].map(|i| vertex_floats(&data.vertices[0])[i])
+        );
+
+        let face = &body[data.vertices.len() * 32..][..17];
+        assert_eq!(face[0], 3);
+        let index = |i: usize| u32::from_le_bytes(face[1 + i * 4..5 + i * 4].try_into().unwrap());
+        assert_eq!([0, 1, 2, 3].map(index)[..3], data.indices[..3]);
+        assert_eq!(index(3), 0);
+    }
+}
@@ -0,0 +1,389 @@
+//! Index and vertex reordering for the post-transform vertex cache, overdraw and vertex fetch.
+//!
+//! Triangle ordering uses Tipsify from Sander, Nehab and Barczak,
+//! "Fast Triangle Reordering for Vertex Locality and Reduced Overdraw".
+//! Every function works on one index list; call them once per submesh so the ranges stay valid.
+
+use crate::math::Vector3d;
+
+use std::cmp::Ordering;
+
+/// Cache size used when reordering and measuring. Modern hardware has at least this many entries.
+pub const CACHE_SIZE: usize = 16;
+
+/// Triangles are split into a new cluster once the ACMR of the cluster so far is this close to
+/// the ACMR of the whole cluster. Higher values give more clusters and less overdraw.
+const OVERDRAW_THRESHOLD: f32 = 1.05;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct VertexCacheStats {
+    /// Vertex shader invocations.
+    pub transformed: usize,
+    /// Average cache miss ratio, transformed vertices per triangle. 0.5 is the best possible.
+    pub acmr: f32,
+    /// Average transform to vertex ratio, transformed vertices per unique vertex. 1.0 is perfect.
+    pub atvr: f32,
+}
+
+/// Simulates a FIFO cache of `cache_size` entries.
+pub fn analyze_vertex_cache(
+    indices: &[u32],
+    vertex_count: usize,
+    cache_size: usize,
+) -> VertexCacheStats {
+    let mut cache = Fifo::new(vertex_count, cache_size);
+    let mut used = vec![false; vertex_count];
+    let transformed = indices.iter().filter(|&&i| cache.miss(i)).count();
+    for &index in indices {
+        used[index as usize] = true;
+    }
+    let unique = used.into_iter().filter(|&u| u).count();
+
+    VertexCacheStats {
+        transformed,
+        acmr: transformed as f32 / (indices.len() / 3).max(1) as f32,
+        atvr: transformed as f32 / unique.max(1) as f32,
+    }
+}
+
+/// Reorders triangles for the post-transform cache.
+pub fn optimize_vertex_cache(indices: &mut [u32], vertex_count: usize) {
+    let (reordered, _) = tipsify(indices, vertex_count, CACHE_SIZE);
+    indices.copy_from_slice(&reordered);
+}
+
+/// Reorders triangles for the post-transform cache, then sorts clusters of triangles so that
+/// outward facing ones, which are likely to occlude the rest, are drawn first.
+pub fn optimize_overdraw(indices: &mut [u32], positions: &[Vector3d]) {
+    let (reordered, hard_boundaries) = tipsify(indices, positions.len(), CACHE_SIZE);
+    let clusters = soft_boundaries(&reordered, positions.len(), &hard_boundaries);
+
+    let triangle = |t: usize| {
+        let tri = &reordered[t * 3..t * 3 + 3];
+        [tri[0], tri[1], tri[2]].map(|i| positions[i as usize])
+    };
+
+    let triangle_count = reordered.len() / 3;
+    let mesh_centroid = (0..triangle_count)
+        .map(|t| {
+            let [a, b, c] = triangle(t);
+            (a + b + c) / 3.0
+        })
+        .fold(Vector3d::ORIGIN, |sum, p| sum + p)
+        / triangle_count.max(1) as f32;
+
+    let mut sorted: Vec<(f32, usize, usize)> = clusters
+        .iter()
+        .enumerate()
+        .map(|(n, &start)| {
+            let end = clusters.get(n + 1).copied().unwrap_or(triangle_count);
+            let mut normal = Vector3d::ORIGIN;
+            let mut centroid = Vector3d::ORIGIN;
+            let mut area = 0.0;
+            for t in start..end {
+                let [a, b, c] = triangle(t);
+                // Cross product is twice the area, pointing along the normal
+                let cross = (b - a).cross(c - a);
+                let weight = cross.magnitude();
+                normal += cross;
+                centroid += (a + b + c) * (weight / 3.0);
+                area += weight;
+            }
+            if area > 0.0 {
+                centroid /= area;
+            }
+            let magnitude = normal.magnitude();
+            let outwardness = if magnitude > 0.0 {
+                (centroid - mesh_centroid).dot(normal / magnitude)
+            } else {
+                0.0
+            };
+            (outwardness, start, end)
+        })
+        .collect();
+    sorted.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+
+    let mut offset = 0;
+    for (_, start, end) in sorted {
+        let len = (end - start) * 3;
+        indices[offset..offset + len].copy_from_slice(&reordered[start * 3..end * 3]);
+        offset += len;
+    }
+}
+
+/// Finds the order vertices are first used in, so they can be laid out in fetch order.
+/// Returns the new location of every vertex, or `None` for vertices no index refers to.
+/// `indices` is rewritten to use the new locations.
+pub fn optimize_vertex_fetch(indices: &mut [u32], vertex_count: usize) -> Vec<Option<u32>> {
+    let mut remap = vec![None; vertex_count];
+    let mut next = 0;
+    for index in indices.iter_mut() {
+        let new_index = *remap[*index as usize].get_or_insert_with(|| {
+            next += 1;
+            next - 1
+        });
+        *index = new_index;
+    }
+    remap
+}
+
+/// Moves every item to the location given by `remap`, dropping the ones without one.
+pub fn remap_vertices<T>(vertices: Vec<T>, remap: &[Option<u32>]) -> Vec<T> {
+    let mut remapped: Vec<Option<T>> = Vec::new();
+    remapped.resize_with(remap.iter().flatten().count(), || None);
+    for (vertex, new_index) in vertices.into_iter().zip(remap) {
+        if let Some(new_index) = new_index {
+            remapped[*new_index as usize] = Some(vertex);
+        }
+    }
+    remapped.into_iter().flatten().collect()
+}
+
+/// Returns the reordered indices and the triangles at which the algorithm had to jump to a
+/// vertex outside the cache.
+fn tipsify(indices: &[u32], vertex_count: usize, cache_size: usize) -> (Vec<u32>, Vec<usize>) {
+    let triangle_count = indices.len() / 3;
+
+    // Vertex to triangle adjacency
+    let mut offsets = vec![0; vertex_count + 1];
+    for &index in indices {
+        offsets[index as usize + 1] += 1;
+    }
+    for i in 0..vertex_count {
+        offsets[i + 1] += offsets[i];
+    }
+    let mut live: Vec<usize> = (0..vertex_count)
+        .map(|v| offsets[v + 1] - offsets[v])
+        .collect();
+    let mut fill = offsets.clone();
+    let mut adjacency = vec![0; indices.len()];
+    for (t, tri) in indices.chunks_exact(3).enumerate() {
+        for &index in tri {
+            adjacency[fill[index as usize]] = t;
+            fill[index as usize] += 1;
+        }
+    }
+
+    let mut cache_time = vec![0usize; vertex_count];
+    let mut emitted = vec![false; triangle_count];
+    let mut dead_end = Vec::new();
+    let mut time = cache_size + 1;
+    let mut cursor = 0;
+
+    let mut output = Vec::with_capacity(indices.len());
+    let mut boundaries = Vec::new();
+
+    let mut fanning = indices.first().copied();
+    if fanning.is_some() {
+        boundaries.push(0);
+    }
+    while let Some(vertex) = fanning {
+        let vertex = vertex as usize;
+        let mut ring = Vec::new();
+
+        for &t in &adjacency[offsets[vertex]..offsets[vertex + 1]] {
+            if emitted[t] {
+                continue;
+            }
+            emitted[t] = true;
+            for &index in &indices[t * 3..t * 3 + 3] {
+                output.push(index);
+                dead_end.push(index);
+                ring.push(index);
+                live[index as usize] -= 1;
+                if time - cache_time[index as usize] > cache_size {
+                    cache_time[index as usize] = time;
+                    time += 1;
+                }
+            }
+        }
+
+        // Prefer a vertex still in the cache that will use up its remaining triangles there
+        let mut best = None;
+        let mut best_priority = 0;
+        for &candidate in &ring {
+            let candidate = candidate as usize;
+            if live[candidate] == 0 {
+                continue;
+            }
+            let age = time - cache_time[candidate];
+            let priority = if age + 2 * live[candidate] <= cache_size {
+                age
+            } else {
+                0
+            };
+            if priority > best_priority {
+                best_priority = priority;
+                best = Some(candidate as u32);
+            }
+        }
+
+        fanning = best.or_else(|| {
+            let next = skip_dead_end(&live, &mut dead_end, &mut cursor);
+            if next.is_some() {
+                boundaries.push(output.len() / 3);
+            }
+            next
+        });
+    }
+
+    boundaries.dedup();
+    (output, boundaries)
+}
+
+fn skip_dead_end(live: &[usize], dead_end: &mut Vec<u32>, cursor: &mut usize) -> Option<u32> {
+    while let Some(vertex) = dead_end.pop() {
+        if live[vertex as usize] > 0 {
+            return Some(vertex);
+        }
+    }
+    while *cursor < live.len() {
+        if live[*cursor] > 0 {
+            return Some(*cursor as u32);
+        }
+        *cursor += 1;
+    }
+    None
+}
+
+/// Splits clusters further where the cache would not suffer much from being flushed.
+fn soft_boundaries(indices: &[u32], vertex_count: usize, hard: &[usize]) -> Vec<usize> {
+    let triangle_count = indices.len() / 3;
+    let mut boundaries = Vec::new();
+
+    for (n, &start) in hard.iter().enumerate() {
+        let end = hard.get(n + 1).copied().unwrap_or(triangle_count);
+        let cluster = &indices[start * 3..end * 3];
+        let cluster_acmr = analyze_vertex_cache(cluster, vertex_count, CACHE_SIZE).acmr;
+
+        let mut cache = Fifo::new(vertex_count, CACHE_SIZE);
+        let mut split = start;
+        let mut misses = 0;
+        boundaries.push(start);
+        for t in start..end {
+            misses += indices[t * 3..t * 3 + 3]
+                .iter()
+                .filter(|&&i| cache.miss(i))
+                .count();
+            let triangles = t + 1 - split;
+            let acmr = misses as f32 / triangles as f32;
+            if t + 1 < end && acmr <= cluster_acmr * OVERDRAW_THRESHOLD {
+                split = t + 1;
+                misses = 0;
+                cache = Fifo::new(vertex_count, CACHE_SIZE);
+                boundaries.push(split);
+            }
+        }
+    }
+
+    boundaries
+}
+
+/// FIFO cache simulated with timestamps.
+struct Fifo {
+    stamps: Vec<usize>,
+    time: usize,
+    size: usize,
+}
+
+impl Fifo {
+    fn new(vertex_count: usize, size: usize) -> Self {
+        Self {
+            stamps: vec![0; vertex_count],
+            time: size + 1,
+            size,
+        }
+    }
+
+    /// Looks up a vertex, adding it on a miss.
+    fn miss(&mut self, index: u32) -> bool {
+        let stamp = &mut self.stamps[index as usize];
+        if self.time - *stamp > self.size {
+            *stamp = self.time;
+            self.time += 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn grid(n: u32) -> (Vec<Vector3d>, Vec<u32>) {
+        let positions = (0..=n)
+            .flat_map(|y| (0..=n).map(move |x| Vector3d::new(x as f32, y as f32, 0.0)))
+            .collect();
+        let mut indices = Vec::new();
+        for y in 0..n {
+            for x in 0..n {
+                let i = y * (n + 1) + x;
+                indices.extend([i, i + 1, i + n + 2, i, i + n + 2, i + n + 1]);
+            }
+        }
+        (positions, indices)
+    }
+
+    fn sorted_triangles(indices: &[u32]) -> Vec<[u32; 3]> {
+        let mut triangles: Vec<_> = indices
+            .chunks_exact(3)
+            .map(|t| {
+                // Rotate so the smallest index is first, keeping the winding
+                let min = (0..3).min_by_key(|&i| t[i]).unwrap();
+                [t[min], t[(min + 1) % 3], t[(min + 2) % 3]]
+            })
+            .collect();
+        triangles.sort_unstable();
+        triangles
+    }
+
+    #[test]
+    fn cache_order_keeps_triangles() {
+        let (positions, indices) = grid(32);
+        let mut optimized = indices.clone();
+        optimize_vertex_cache(&mut optimized, positions.len());
+
+        assert_eq!(sorted_triangles(&indices), sorted_triangles(&optimized));
+    }
+
+    #[test]
+    fn cache_order_improves_acmr() {
+        let (positions, indices) = grid(32);
+        // Row by row order thrashes the cache once rows are longer than it
+        let before = analyze_vertex_cache(&indices, positions.len(), CACHE_SIZE);
+        let mut optimized = indices.clone();
+        optimize_vertex_cache(&mut optimized, positions.len());
+        let after = analyze_vertex_cache(&optimized, positions.len(), CACHE_SIZE);
+
+        assert!(after.acmr < before.acmr);
+        assert!(after.atvr < before.atvr);
+        assert!(after.acmr >= 0.5);
+    }
+
+    #[test]
+    fn overdraw_keeps_triangles() {
+        let (positions, indices) = grid(32);
+        let mut optimized = indices.clone();
+        optimize_overdraw(&mut optimized, &positions);
+
+        assert_eq!(sorted_triangles(&indices), sorted_triangles(&optimized));
+    }
+
+    #[test]
+    fn fetch_order() {
+        let mut indices = vec![5, 3, 1, 3, 5, 0];
+        let remap = optimize_vertex_fetch(&mut indices, 7);
+
+        assert_eq!(indices, vec![0, 1, 2, 1, 0, 3]);
+        assert_eq!(
+            remap,
+            vec![Some(3), Some(2), None, Some(1), None, Some(0), None]
+        );
+        assert_eq!(
+            remap_vertices(vec!['a', 'b', 'c', 'd', 'e', 'f', 'g'], &remap),
+            vec!['f', 'd', 'b', 'a']
+        );
+    }
+}
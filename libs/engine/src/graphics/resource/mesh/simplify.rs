@@ -0,0 +1,448 @@
+//! Quadric error metric simplification.
+//!
+//! Based on Garland and Heckbert, "Surface Simplification Using Quadric Error Metrics".
+//! Edges are collapsed onto one of their existing vertices, so the vertex buffer can be shared
+//! between every level of detail and only the indices change.
+
+use crate::math::Vector3d;
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Fraction of the sorted collapse candidates that may be used in a single pass.
+/// Keeping this low stops expensive collapses from happening before cheap ones are re-evaluated.
+const PASS_FRACTION: usize = 3;
+
+/// Symmetric 4x4 error matrix, stored as its upper triangle.
+#[derive(Clone, Copy, Debug, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    fn from_plane(a: f64, b: f64, c: f64, d: f64, weight: f64) -> Self {
+        Self(
+            [
+                a * a,
+                a * b,
+                a * c,
+                a * d,
+                b * b,
+                b * c,
+                b * d,
+                c * c,
+                c * d,
+                d * d,
+            ]
+            .map(|q| q * weight),
+        )
+    }
+
+    fn add(&mut self, other: &Self) {
+        for (q, o) in self.0.iter_mut().zip(other.0) {
+            *q += o;
+        }
+    }
+
+    fn error(&self, point: Vector3d) -> f64 {
+        let [a2, ab, ac, ad, b2, bc, bd, c2, cd, d2] = self.0;
+        let (x, y, z) = (point.x() as f64, point.y() as f64, point.z() as f64);
+
+        a2 * x * x
+            + 2.0 * ab * x * y
+            + 2.0 * ac * x * z
+            + 2.0 * ad * x
+            + b2 * y * y
+            + 2.0 * bc * y * z
+            + 2.0 * bd * y
+            + c2 * z * z
+            + 2.0 * cd * z
+            + d2
+    }
+}
+
+struct Collapse {
+    from: u32,
+    to: u32,
+    cost: f64,
+}
+
+/// Result of simplifying a mesh.
+#[derive(Clone, Debug, Default)]
+pub struct Simplified {
+    pub indices: Vec<u32>,
+    /// Index ranges matching the submeshes passed in, in the same order.
+    pub submeshes: Vec<Range<usize>>,
+    /// Largest quadric error of any collapse performed.
+    pub error: f32,
+}
+
+/// Reduces a triangle list to roughly `target_triangles` triangles.
+///
+/// `submeshes` are ranges into `indices`; triangles never move between submeshes.
+/// Vertices on open edges, UV or normal seams (which show up as open edges, since the two sides
+/// use different vertices), and on the border between two submeshes are never moved.
+pub fn simplify(
+    positions: &[Vector3d],
+    indices: &[u32],
+    submeshes: &[Range<usize>],
+    target_triangles: usize,
+) -> Simplified {
+    let mut triangles: Vec<Vec<[u32; 3]>> = submeshes
+        .iter()
+        .map(|range| {
+            indices[range.clone()]
+                .chunks_exact(3)
+                .map(|tri| [tri[0], tri[1], tri[2]])
+                .collect()
+        })
+        .collect();
+
+    let locked = locked_vertices(positions.len(), &triangles);
+    let mut quadrics = vertex_quadrics(positions, &triangles);
+    let mut max_error = 0.0f64;
+
+    let mut triangle_count: usize = triangles.iter().map(Vec::len).sum();
+    while triangle_count > target_triangles {
+        let collapses = collapse_candidates(positions, &triangles, &locked, &quadrics);
+        if collapses.is_empty() {
+            break;
+        }
+        let adjacency = Adjacency::new(positions.len(), &triangles);
+
+        let mut remap: Vec<u32> = (0..positions.len() as u32).collect();
+        let mut touched = vec![false; positions.len()];
+        let mut removed = 0;
+
+        let limit = (collapses.len() / PASS_FRACTION).max(1);
+        for collapse in collapses.iter().take(limit) {
+            let (from, to) = (collapse.from as usize, collapse.to as usize);
+            if touched[from] || touched[to] {
+                continue;
+            }
+            if adjacency.flips(positions, &triangles, collapse.from, collapse.to) {
+                continue;
+            }
+
+            let quadric = quadrics[from];
+            quadrics[to].add(&quadric);
+            remap[from] = collapse.to;
+            max_error = max_error.max(collapse.cost);
+
+            touched[to] = true;
+            for &(submesh, triangle) in adjacency.triangles_of(collapse.from) {
+                let tri = triangles[submesh][triangle];
+                if tri.contains(&collapse.to) {
+                    removed += 1;
+                }
+                for vertex in tri {
+                    touched[vertex as usize] = true;
+                }
+            }
+
+            if triangle_count - removed <= target_triangles {
+                break;
+            }
+        }
+
+        if removed == 0 {
+            break;
+        }
+
+        for submesh in &mut triangles {
+            submesh.retain_mut(|tri| {
+                for vertex in tri.iter_mut() {
+                    *vertex = remap[*vertex as usize];
+                }
+                tri[0] != tri[1] && tri[1] != tri[2] && tri[2] != tri[0]
+            });
+        }
+        triangle_count = triangles.iter().map(Vec::len).sum();
+    }
+
+    let mut simplified = Simplified {
+        error: max_error as f32,
+        ..Simplified::default()
+    };
+    for submesh in triangles {
+        let start = simplified.indices.len();
+        simplified.indices.extend(submesh.into_iter().flatten());
+        simplified.submeshes.push(start..simplified.indices.len());
+    }
+    simplified
+}
+
+/// Vertices that must not move: anything on an edge not shared by exactly two triangles,
+/// and anything used by more than one submesh.
+fn locked_vertices(vertex_count: usize, triangles: &[Vec<[u32; 3]>]) -> Vec<bool> {
+    let mut locked = vec![false; vertex_count];
+
+    let mut edges: HashMap<(u32, u32), u32> = HashMap::new();
+    for tri in triangles.iter().flatten() {
+        for (a, b) in edges_of(tri) {
+            *edges.entry((a.min(b), a.max(b))).or_default() += 1;
+        }
+    }
+    for ((a, b), count) in edges {
+        if count != 2 {
+            locked[a as usize] = true;
+            locked[b as usize] = true;
+        }
+    }
+
+    let mut owner: Vec<Option<usize>> = vec![None; vertex_count];
+    for (submesh, tris) in triangles.iter().enumerate() {
+        for &vertex in tris.iter().flatten() {
+            match owner[vertex as usize] {
+                None => owner[vertex as usize] = Some(submesh),
+                Some(other) if other != submesh => locked[vertex as usize] = true,
+                Some(_) => {}
+            }
+        }
+    }
+
+    locked
+}
+
+fn vertex_quadrics(positions: &[Vector3d], triangles: &[Vec<[u32; 3]>]) -> Vec<Quadric> {
+    let mut quadrics = vec![Quadric::default(); positions.len()];
+    for tri in triangles.iter().flatten() {
+        let [a, b, c] = tri.map(|i| positions[i as usize]);
+        let cross = (b - a).cross(c - a);
+        let area = cross.magnitude();
+        if area <= f32::EPSILON {
+            continue;
+        }
+        let normal = cross / area;
+        let d = -normal.dot(a);
+        let quadric = Quadric::from_plane(
+            normal.x() as f64,
+            normal.y() as f64,
+            normal.z() as f64,
+            d as f64,
+            area as f64 * 0.5,
+        );
+        for vertex in tri {
+            quadrics[*vertex as usize].add(&quadric);
+        }
+    }
+    quadrics
+}
+
+fn collapse_candidates(
+    positions: &[Vector3d],
+    triangles: &[Vec<[u32; 3]>],
+    locked: &[bool],
+    quadrics: &[Quadric],
+) -> Vec<Collapse> {
+    let mut collapses = Vec::new();
+    for tri in triangles.iter().flatten() {
+        for (from, to) in edges_of(tri) {
+            // Each undirected edge shows up in two triangles, once in each direction
+            if locked[from as usize] {
+                continue;
+            }
+            let mut quadric = quadrics[from as usize];
+            quadric.add(&quadrics[to as usize]);
+            let cost = quadric.error(positions[to as usize]);
+            collapses.push(Collapse { from, to, cost });
+        }
+    }
+    collapses.sort_by(|a, b| a.cost.partial_cmp(&b.cost).unwrap_or(Ordering::Equal));
+    collapses
+}
+
+fn edges_of(tri: &[u32; 3]) -> [(u32, u32); 3] {
+    [(tri[0], tri[1]), (tri[1], tri[2]), (tri[2], tri[0])]
+}
+
+/// Maps each vertex to the triangles that use it.
+struct Adjacency {
+    offsets: Vec<usize>,
+    triangles: Vec<(usize, usize)>,
+}
+
+impl Adjacency {
+    fn new(vertex_count: usize, triangles: &[Vec<[u32; 3]>]) -> Self {
+        let mut offsets = vec![0; vertex_count + 1];
+        for &vertex in triangles.iter().flatten().flatten() {
+            offsets[vertex as usize + 1] += 1;
+        }
+        for i in 0..vertex_count {
+            offsets[i + 1] += offsets[i];
+        }
+
+        let mut fill = offsets.clone();
+        let mut adjacent = vec![(0, 0); offsets[vertex_count]];
+        for (submesh, tris) in triangles.iter().enumerate() {
+            for (triangle, tri) in tris.iter().enumerate() {
+                for &vertex in tri {
+                    adjacent[fill[vertex as usize]] = (submesh, triangle);
+                    fill[vertex as usize] += 1;
+                }
+            }
+        }
+
+        Self {
+            offsets,
+            triangles: adjacent,
+        }
+    }
+
+    fn triangles_of(&self, vertex: u32) -> &[(usize, usize)] {
+        let vertex = vertex as usize;
+        &self.triangles[self.offsets[vertex]..self.offsets[vertex + 1]]
+    }
+
+    /// Checks if moving `from` onto `to` would turn any surviving triangle inside out.
+    fn flips(
+        &self,
+        positions: &[Vector3d],
+        triangles: &[Vec<[u32; 3]>],
+        from: u32,
+        to: u32,
+    ) -> bool {
+        self.triangles_of(from).iter().any(|&(submesh, triangle)| {
+            let tri = triangles[submesh][triangle];
+            if tri.contains(&to) {
+                return false;
+            }
+            let before = tri.map(|i| positions[i as usize]);
+            let after = tri.map(|i| positions[if i == from { to } else { i } as usize]);
+
+            let normal_before = (before[1] - before[0]).cross(before[2] - before[0]);
+            let normal_after = (after[1] - after[0]).cross(after[2] - after[0]);
+            normal_before.dot(normal_after) <= 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::slice;
+
+    /// A closed, subdivided cube, `n` quads per side.
+    fn grid_cube(n: u32) -> (Vec<Vector3d>, Vec<u32>) {
+        let mut positions = Vec::new();
+        let mut lookup = HashMap::new();
+        let mut indices = Vec::new();
+        let mut vertex = |p: [i32; 3], positions: &mut Vec<Vector3d>| {
+            *lookup.entry(p).or_insert_with(|| {
+                let scale = 1.0 / n as f32;
+                positions.push(Vector3d::new(
+                    p[0] as f32 * scale,
+                    p[1] as f32 * scale,
+                    p[2] as f32 * scale,
+                ));
+                positions.len() as u32 - 1
+            })
+        };
+
+        let n = n as i32;
+        for axis in 0..3 {
+            for side in [0, n] {
+                for i in 0..n {
+                    for j in 0..n {
+                        let corner = |di: i32, dj: i32| {
+                            let mut p = [0; 3];
+                            p[axis] = side;
+                            p[(axis + 1) % 3] = i + di;
+                            p[(axis + 2) % 3] = j + dj;
+                            p
+                        };
+                        let quad = [corner(0, 0), corner(1, 0), corner(1, 1), corner(0, 1)]
+                            .map(|p| vertex(p, &mut positions));
+                        if side == 0 {
+                            indices.extend([quad[0], quad[2], quad[1], quad[0], quad[3], quad[2]]);
+                        } else {
+                            indices.extend([quad[0], quad[1], quad[2], quad[0], quad[2], quad[3]]);
+                        }
+                    }
+                }
+            }
+        }
+        (positions, indices)
+    }
+
+    #[test]
+    fn flat_faces_collapse() {
+        let (positions, indices) = grid_cube(4);
+        let triangles = indices.len() / 3;
+
+        let simplified = simplify(
+            &positions,
+            &indices,
+            slice::from_ref(&(0..indices.len())),
+            triangles / 4,
+        );
+
+        assert!(simplified.indices.len() / 3 <= triangles / 4);
+        assert!(simplified.error < 1e-4);
+        assert_eq!(simplified.submeshes, vec![0..simplified.indices.len()]);
+    }
+
+    #[test]
+    fn submesh_borders_stay() {
+        let (positions, indices) = grid_cube(4);
+        let half = indices.len() / 2;
+
+        let simplified = simplify(&positions, &indices, &[0..half, half..indices.len()], 0);
+
+        let border = |range: Range<usize>, indices: &[u32]| {
+            let other: Vec<_> = if range.start == 0 {
+                indices[half..].to_vec()
+            } else {
+                indices[..half].to_vec()
+            };
+            let mut shared: Vec<_> = indices[range]
+                .iter()
+                .copied()
+                .filter(|i| other.contains(i))
+                .collect();
+            shared.sort_unstable();
+            shared.dedup();
+            shared
+        };
+        let before = border(0..half, &indices);
+        let [first, second] = [&simplified.submeshes[0], &simplified.submeshes[1]];
+        let after_first: Vec<_> = simplified.indices[first.clone()].to_vec();
+        let after_second: Vec<_> = simplified.indices[second.clone()].to_vec();
+
+        for vertex in before {
+            assert!(after_first.contains(&vertex));
+            assert!(after_second.contains(&vertex));
+        }
+    }
+
+    #[test]
+    fn open_edges_locked() {
+        // A single flat grid, all vertices on the outside must survive
+        let n = 4;
+        let positions: Vec<_> = (0..=n)
+            .flat_map(|y| (0..=n).map(move |x| Vector3d::new(x as f32, y as f32, 0.0)))
+            .collect();
+        let mut indices = Vec::new();
+        for y in 0..n {
+            for x in 0..n {
+                let i = y * (n + 1) + x;
+                indices.extend([i, i + 1, i + n + 2, i, i + n + 2, i + n + 1]);
+            }
+        }
+
+        let simplified = simplify(
+            &positions,
+            &indices,
+            slice::from_ref(&(0..indices.len())),
+            0,
+        );
+
+        for (i, position) in positions.iter().enumerate() {
+            let on_edge =
+                [0.0, n as f32].contains(&position.x()) || [0.0, n as f32].contains(&position.y());
+            if on_edge {
+                assert!(simplified.indices.contains(&(i as u32)));
+            }
+        }
+        assert!(simplified.indices.len() < indices.len());
+    }
+}
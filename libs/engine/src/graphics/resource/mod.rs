@@ -1,19 +1,68 @@
+pub mod budget;
+pub mod loader;
 pub mod manager;
 pub mod mesh;
 pub mod shader;
 pub mod texture;
+pub mod watch;
 
+pub use budget::ResourceStats;
+pub use loader::{LoadHandle, LoadProgress, LoadState};
 pub use manager::ResourceManager;
 pub use mesh::Mesh;
 pub use texture::Texture;
+pub use watch::FileWatcher;
 
 use crate::error;
 use crate::graphics::render::Device;
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 pub trait Resource {
     fn load_resource_from_file(device: &Device, path: impl AsRef<Path>)
         -> error::Result<Arc<Self>>;
+
+    /// Loads through preprocessed data kept in `cache_dir`, falling back to the source file.
+    /// Resources without a cached form load straight from the source.
+    fn load_resource_cached(
+        device: &Device,
+        path: impl AsRef<Path>,
+        _cache_dir: &Path,
+    ) -> error::Result<Arc<Self>> {
+        Self::load_resource_from_file(device, path)
+    }
+
+    /// Moves a freshly loaded version into this resource, so every existing handle sees it.
+    /// Resources that cannot change in place report an error and stay as they are.
+    fn replace(&self, _device: &Device, _fresh: Self) -> error::Result<()>
+    where
+        Self: Sized,
+    {
+        Err("Resource cannot be reloaded in place".into())
+    }
+
+    /// Bytes of GPU memory the resource holds, counted against budgets.
+    /// Resources that aren't tracked report nothing.
+    fn memory_size(&self) -> usize {
+        0
+    }
+
+    /// Other files this resource was built from. Changing them reloads it too.
+    fn dependencies(&self) -> Vec<PathBuf> {
+        Vec::new()
+    }
+}
+
+/// Resources that can be read and decoded away from the render thread.
+pub trait AsyncResource: Resource + Sized + 'static {
+    type Data: Send + 'static;
+
+    /// Reads and decodes `path` on a worker thread. The device isn't available here.
+    fn decode(path: &Path, cache_dir: Option<&Path>) -> error::Result<Self::Data>;
+
+    fn upload(device: &Device, data: Self::Data) -> error::Result<Self>;
+
+    /// Stands in until the real resource replaces it.
+    fn placeholder(device: &Device) -> error::Result<Self>;
 }
@@ -0,0 +1,146 @@
+//! Compiled bytecode cache, so shaders are only compiled once.
+//!
+//! Layout, all little endian: magic, version, key, then the bytecode.
+//! The key hashes everything the bytecode depends on: the preprocessed source,
+//! entry point, target and defines. Changing any of them misses the cache.
+
+use crate::error;
+use crate::util::fnv1a;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::debug;
+
+const MAGIC: [u8; 4] = *b"TESC";
+/// Bump whenever the layout or the compile flags change.
+const VERSION: u32 = 1;
+const EXTENSION: &str = "cso";
+
+/// Identifies one compilation of a shader.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BytecodeKey(u64);
+
+impl BytecodeKey {
+    /// Defines are sorted, so their order doesn't matter.
+    pub fn new(source: &[u8], entry_point: &str, target: &str, defines: &[(&str, &str)]) -> Self {
+        let mut defines = defines.to_vec();
+        defines.sort_unstable();
+
+        // Each part is followed by a null, so moving text from one part to the next
+        // changes the key.
+        let mut bytes = Vec::with_capacity(source.len() + 64);
+        for part in [source, entry_point.as_bytes(), target.as_bytes()] {
+            bytes.extend_from_slice(part);
+            bytes.push(0);
+        }
+        for (name, value) in defines {
+            bytes.extend_from_slice(name.as_bytes());
+            bytes.push(b'=');
+            bytes.extend_from_slice(value.as_bytes());
+            bytes.push(0);
+        }
+        Self(fnv1a(&bytes))
+    }
+}
+
+/// Where the bytecode for `key` lives in `cache_dir`.
+pub fn cache_path(cache_dir: &Path, key: BytecodeKey) -> PathBuf {
+    cache_dir.join(format!("{:016x}.{}", key.0, EXTENSION))
+}
+
+/// Reads cached bytecode, if there is any for `key`.
+pub fn read(cache_dir: &Path, key: BytecodeKey) -> Option<Vec<u8>> {
+    let cache_path = cache_path(cache_dir, key);
+    let bytes = fs::read(&cache_path).ok()?;
+    let bytecode = decode(&bytes, key);
+    if bytecode.is_none() {
+        debug!("Ignoring invalid shader cache {}", cache_path.display());
+    }
+    bytecode
+}
+
+pub fn write(cache_dir: &Path, key: BytecodeKey, bytecode: &[u8]) -> error::Result<()> {
+    fs::create_dir_all(cache_dir)?;
+    fs::write(cache_path(cache_dir, key), encode(key, bytecode))?;
+    Ok(())
+}
+
+fn encode(key: BytecodeKey, bytecode: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(16 + bytecode.len());
+    bytes.extend_from_slice(&MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.extend_from_slice(&key.0.to_le_bytes());
+    bytes.extend_from_slice(bytecode);
+    bytes
+}
+
+/// Returns `None` if the data is corrupt, from another version or for another key.
+fn decode(bytes: &[u8], key: BytecodeKey) -> Option<Vec<u8>> {
+    let (magic, rest) = bytes.split_first_chunk::<4>()?;
+    let (version, rest) = rest.split_first_chunk::<4>()?;
+    let (stored_key, bytecode) = rest.split_first_chunk::<8>()?;
+    if *magic != MAGIC
+        || u32::from_le_bytes(*version) != VERSION
+        || u64::from_le_bytes(*stored_key) != key.0
+        || bytecode.is_empty()
+    {
+        return None;
+    }
+    Some(bytecode.to_vec())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::env;
+
+    #[test]
+    fn keys() {
+        let key = BytecodeKey::new(b"float4 psmain", "psmain", "ps_5_0", &[]);
+        assert_eq!(
+            key,
+            BytecodeKey::new(b"float4 psmain", "psmain", "ps_5_0", &[])
+        );
+        assert_ne!(
+            key,
+            BytecodeKey::new(b"float4 psmain ", "psmain", "ps_5_0", &[])
+        );
+        assert_ne!(
+            key,
+            BytecodeKey::new(b"float4 psmain", "psmain", "ps_4_0", &[])
+        );
+        assert_ne!(
+            key,
+            BytecodeKey::new(b"float4 psmain", "psmain", "ps_5_0", &[("SPECULAR", "1")])
+        );
+        assert_eq!(
+            BytecodeKey::new(b"", "psmain", "ps_5_0", &[("A", "1"), ("B", "1")]),
+            BytecodeKey::new(b"", "psmain", "ps_5_0", &[("B", "1"), ("A", "1")])
+        );
+        assert_ne!(
+            BytecodeKey::new(b"ab", "c", "ps_5_0", &[]),
+            BytecodeKey::new(b"a", "bc", "ps_5_0", &[])
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let key = BytecodeKey::new(b"float4 psmain", "psmain", "ps_5_0", &[]);
+        let bytes = encode(key, b"DXBC");
+        assert_eq!(decode(&bytes, key).unwrap(), b"DXBC");
+
+        let other = BytecodeKey::new(b"float4 vsmain", "vsmain", "vs_5_0", &[]);
+        assert!(decode(&bytes, other).is_none());
+        assert!(decode(&bytes[..15], key).is_none());
+        assert!(decode(&encode(key, b""), key).is_none());
+
+        let cache_dir = env::temp_dir().join(format!("shader_cache_{}", std::process::id()));
+        assert!(read(&cache_dir, key).is_none());
+        write(&cache_dir, key, b"DXBC").unwrap();
+        assert_eq!(read(&cache_dir, key).unwrap(), b"DXBC");
+        assert!(read(&cache_dir, other).is_none());
+        fs::remove_dir_all(cache_dir).unwrap();
+    }
+}
@@ -43,17 +43,25 @@ macro_rules! shader_generate {
             fn set_textures(
                 context: &Context,
                 textures: &mut [Option<Arc<dyn $crate::graphics::material::Texture>>],
+                samplers: &[Option<Arc<$crate::graphics::render::Sampler>>],
             ) {
                 unsafe {
+                    // Empty slots are unbound, so textures stay in the slots they were put in.
                     let texture_pointers: Vec<_> = textures
                         .iter_mut()
-                        .flatten()
-                        .map(|tex| tex.resource_view_ptr())
+                        .map(|tex| match tex {
+                            Some(tex) => tex.resource_view_ptr(),
+                            None => std::ptr::null_mut(),
+                        })
                         .collect();
                     let sampler_pointers: Vec<_> = textures
                         .iter_mut()
-                        .flatten()
-                        .map(|tex| tex.sampler_state_ptr())
+                        .enumerate()
+                        .map(|(idx, tex)| match (tex, samplers.get(idx)) {
+                            (None, _) => std::ptr::null_mut(),
+                            (Some(_), Some(Some(sampler))) => sampler.as_ptr(),
+                            (Some(tex), _) => tex.sampler_state_ptr(),
+                        })
                         .collect();
                     context.as_ref().$set_shader_resource(
                         0,
@@ -0,0 +1,411 @@
+//! What a compiled shader expects to be bound, and checks that Rust types match it.
+
+use crate::error;
+use crate::math::{Matrix4x4, Vector};
+
+use std::any::{self, Any};
+use std::mem;
+
+/// Constant buffers, textures and samplers a shader declares, found by reflection.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ShaderReflection {
+    pub constant_buffers: Vec<ConstantBufferDesc>,
+    pub textures: Vec<ResourceBinding>,
+    pub samplers: Vec<ResourceBinding>,
+}
+
+/// A `cbuffer` and where each of its members is.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConstantBufferDesc {
+    pub name: String,
+    pub slot: u32,
+    /// Size in bytes, a multiple of 16.
+    pub size: usize,
+    /// Members in declaration order.
+    pub variables: Vec<VariableDesc>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VariableDesc {
+    pub name: String,
+    pub offset: usize,
+    pub size: usize,
+}
+
+/// A texture or sampler and the register it is bound to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResourceBinding {
+    pub name: String,
+    pub slot: u32,
+}
+
+impl ShaderReflection {
+    pub fn constant_buffer(&self, name: &str) -> Option<&ConstantBufferDesc> {
+        self.constant_buffers
+            .iter()
+            .find(|buffer| buffer.name == name)
+    }
+
+    pub fn texture_slot(&self, name: &str) -> Option<u32> {
+        find_slot(&self.textures, name)
+    }
+
+    pub fn sampler_slot(&self, name: &str) -> Option<u32> {
+        find_slot(&self.samplers, name)
+    }
+
+    /// Combines the bindings of shaders used together, like the vertex and pixel shader
+    /// of a material. Anything declared by both has to be declared the same way.
+    pub fn merge(&self, other: &Self) -> error::Result<Self> {
+        let mut merged = self.clone();
+        for buffer in &other.constant_buffers {
+            match self.constant_buffer(&buffer.name) {
+                Some(existing) if existing == buffer => {}
+                Some(existing) if existing.slot != buffer.slot => {
+                    return Err(error::Custom(format!(
+                        "cbuffer {} is in b{} in one shader and b{} in another",
+                        buffer.name, existing.slot, buffer.slot
+                    )));
+                }
+                Some(_) => {
+                    return Err(error::Custom(format!(
+                        "cbuffer {} is declared differently in each shader",
+                        buffer.name
+                    )));
+                }
+                None => merged.constant_buffers.push(buffer.clone()),
+            }
+        }
+        merge_bindings(&mut merged.textures, &other.textures, "Texture", 't')?;
+        merge_bindings(&mut merged.samplers, &other.samplers, "Sampler", 's')?;
+        Ok(merged)
+    }
+}
+
+fn find_slot(bindings: &[ResourceBinding], name: &str) -> Option<u32> {
+    bindings
+        .iter()
+        .find(|binding| binding.name == name)
+        .map(|binding| binding.slot)
+}
+
+fn merge_bindings(
+    merged: &mut Vec<ResourceBinding>,
+    other: &[ResourceBinding],
+    kind: &str,
+    register: char,
+) -> error::Result<()> {
+    for binding in other {
+        match find_slot(merged, &binding.name) {
+            Some(slot) if slot != binding.slot => {
+                return Err(error::Custom(format!(
+                    "{} {} is in {}{} in one shader and {}{} in another",
+                    kind, binding.name, register, slot, register, binding.slot
+                )));
+            }
+            Some(_) => {}
+            None => merged.push(binding.clone()),
+        }
+    }
+    Ok(())
+}
+
+/// Where a member of a Rust type is, to compare with the matching `cbuffer` member.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FieldLayout {
+    pub name: &'static str,
+    pub offset: usize,
+    pub size: usize,
+}
+
+/// Data for a named `cbuffer`. Materials bind it to whichever slot their shaders declare it in.
+pub trait Constants: Any + Send + Sync {
+    /// Name of the `cbuffer` in HLSL.
+    const NAME: &'static str;
+
+    /// Members in declaration order, matched one to one with the `cbuffer` members.
+    /// Without them only the size is checked.
+    fn fields() -> Vec<FieldLayout> {
+        Vec::new()
+    }
+}
+
+/// A Rust type with an HLSL equivalent that can go in a `cbuffer`.
+/// `#[derive(ConstantBuffer)]` uses this to check packing at compile time.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` has no HLSL type that packs the same way",
+    note = "HLSL arrays pad each element to 16 bytes, so scalar arrays must be `[Vector<f32, 4>; N]`"
+)]
+pub trait HlslType {
+    /// Bytes the HLSL type takes up in a `cbuffer`.
+    const SIZE: usize;
+    /// Whether HLSL starts it on a new 16 byte register, as it does matrices and arrays.
+    const REGISTER_ALIGNED: bool = false;
+}
+
+macro_rules! hlsl_vectors {
+    ($($scalar: ty),*) => {$(
+        impl HlslType for $scalar {
+            const SIZE: usize = 4;
+        }
+
+        hlsl_vectors!($scalar; 1, 2, 3, 4);
+    )*};
+    ($scalar: ty; $($n: literal),*) => {$(
+        impl HlslType for Vector<$scalar, $n> {
+            const SIZE: usize = 4 * $n;
+        }
+
+        // Packs like the vector, so only up to 4. As an HLSL array each element would take
+        // a whole register, which Rust can only match with `[Vector<f32, 4>; N]`.
+        impl HlslType for [$scalar; $n] {
+            const SIZE: usize = 4 * $n;
+        }
+    )*};
+}
+
+hlsl_vectors!(f32, i32, u32);
+
+impl HlslType for Matrix4x4 {
+    const SIZE: usize = 64;
+    const REGISTER_ALIGNED: bool = true;
+}
+
+/// HLSL pads array elements to whole registers, so only arrays of whole registers match Rust.
+impl<const N: usize> HlslType for [Vector<f32, 4>; N] {
+    const SIZE: usize = 16 * N;
+    const REGISTER_ALIGNED: bool = true;
+}
+
+impl<const N: usize> HlslType for [Matrix4x4; N] {
+    const SIZE: usize = 64 * N;
+    const REGISTER_ALIGNED: bool = true;
+}
+
+/// Where HLSL puts a member of `size` bytes that follows one ending at `end`.
+/// Members never straddle a 16 byte register.
+pub const fn hlsl_offset(end: usize, size: usize, register_aligned: bool) -> usize {
+    let register_end = end.next_multiple_of(16);
+    if register_aligned || (!end.is_multiple_of(16) && end + size > register_end) {
+        register_end
+    } else {
+        end
+    }
+}
+
+/// Checks that `C` is laid out the way `buffer` expects.
+pub fn check_constants<C: Constants>(buffer: &ConstantBufferDesc) -> error::Result<()> {
+    check_layout(
+        any::type_name::<C>(),
+        mem::size_of::<C>(),
+        &C::fields(),
+        buffer,
+    )
+}
+
+/// Checks a type of `size` bytes with `fields` against `buffer`, naming `type_name` in errors.
+pub fn check_layout(
+    type_name: &str,
+    size: usize,
+    fields: &[FieldLayout],
+    buffer: &ConstantBufferDesc,
+) -> error::Result<()> {
+    let mismatch = |message: String| {
+        Err(error::Custom(format!(
+            "{} does not match cbuffer {}: {}",
+            type_name, buffer.name, message
+        )))
+    };
+
+    // Buffers are created from the whole type, so padding at the end counts too.
+    if size != buffer.size {
+        return mismatch(format!(
+            "it is {} bytes, but the cbuffer is {}",
+            size, buffer.size
+        ));
+    }
+    if fields.is_empty() {
+        return Ok(());
+    }
+
+    for (idx, variable) in buffer.variables.iter().enumerate() {
+        let field = match fields.get(idx) {
+            Some(field) => field,
+            None => {
+                return mismatch(format!("nothing matches {}", variable.name));
+            }
+        };
+        if field.offset != variable.offset {
+            return mismatch(format!(
+                "{} is at offset {}, but {} is at {}",
+                field.name, field.offset, variable.name, variable.offset
+            ));
+        }
+        if field.size != variable.size {
+            return mismatch(format!(
+                "{} is {} bytes, but {} is {}",
+                field.name, field.size, variable.name, variable.size
+            ));
+        }
+    }
+    if let Some(field) = fields.get(buffer.variables.len()) {
+        return mismatch(format!("nothing matches {}", field.name));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[repr(C, align(16))]
+    struct Light {
+        direction: [f32; 4],
+        radius: f32,
+        time: f32,
+    }
+
+    impl Constants for Light {
+        const NAME: &'static str = "light";
+
+        fn fields() -> Vec<FieldLayout> {
+            vec![
+                field("direction", 0, 16),
+                field("radius", 16, 4),
+                field("time", 20, 4),
+            ]
+        }
+    }
+
+    fn field(name: &'static str, offset: usize, size: usize) -> FieldLayout {
+        FieldLayout { name, offset, size }
+    }
+
+    fn variable(name: &str, offset: usize, size: usize) -> VariableDesc {
+        VariableDesc {
+            name: name.to_owned(),
+            offset,
+            size,
+        }
+    }
+
+    fn light_buffer(variables: Vec<VariableDesc>) -> ConstantBufferDesc {
+        ConstantBufferDesc {
+            name: "light".to_owned(),
+            slot: 0,
+            size: 32,
+            variables,
+        }
+    }
+
+    fn binding(name: &str, slot: u32) -> ResourceBinding {
+        ResourceBinding {
+            name: name.to_owned(),
+            slot,
+        }
+    }
+
+    #[test]
+    fn hlsl_packing() {
+        // float3 then float share a register.
+        assert_eq!(hlsl_offset(12, 4, false), 12);
+        // float then float3 do too, but two floats push a float3 to the next one.
+        assert_eq!(hlsl_offset(4, 12, false), 4);
+        assert_eq!(hlsl_offset(8, 12, false), 16);
+        // Matrices always start a register.
+        assert_eq!(hlsl_offset(4, 64, true), 16);
+        assert_eq!(hlsl_offset(32, 64, true), 32);
+        assert_eq!(hlsl_offset(0, 16, false), 0);
+        assert_eq!(hlsl_offset(16, 8, false), 16);
+    }
+
+    #[test]
+    fn hlsl_types() {
+        fn packing<T: HlslType>() -> (usize, bool) {
+            (T::SIZE, T::REGISTER_ALIGNED)
+        }
+        // Scalar arrays are vectors, arrays of vectors take a register each.
+        assert_eq!(packing::<[f32; 3]>(), (12, false));
+        assert_eq!(packing::<[u32; 4]>(), (16, false));
+        assert_eq!(packing::<[Vector<f32, 4>; 3]>(), (48, true));
+        assert_eq!(packing::<[Matrix4x4; 2]>(), (128, true));
+    }
+
+    #[test]
+    fn matching_layout() {
+        let buffer = light_buffer(vec![
+            variable("m_light_dir", 0, 16),
+            variable("m_light_rad", 16, 4),
+            variable("m_time", 20, 4),
+        ]);
+        check_constants::<Light>(&buffer).unwrap();
+    }
+
+    #[test]
+    fn mismatched_offset() {
+        // A float3 direction packs the radius into the same register.
+        let buffer = light_buffer(vec![
+            variable("m_light_dir", 0, 12),
+            variable("m_light_rad", 12, 4),
+            variable("m_time", 16, 4),
+        ]);
+        let error = check_constants::<Light>(&buffer).unwrap_err().to_string();
+        assert!(error.contains("cbuffer light"), "{}", error);
+        assert!(error.contains("direction is 16 bytes"), "{}", error);
+    }
+
+    #[test]
+    fn mismatched_members() {
+        let buffer = light_buffer(vec![variable("m_light_dir", 0, 16)]);
+        let error = check_constants::<Light>(&buffer).unwrap_err().to_string();
+        assert!(error.contains("nothing matches radius"), "{}", error);
+
+        let mut buffer = light_buffer(vec![
+            variable("m_light_dir", 0, 16),
+            variable("m_light_rad", 16, 4),
+            variable("m_time", 20, 4),
+            variable("m_intensity", 24, 4),
+        ]);
+        let error = check_constants::<Light>(&buffer).unwrap_err().to_string();
+        assert!(error.contains("nothing matches m_intensity"), "{}", error);
+
+        buffer.size = 48;
+        let error = check_constants::<Light>(&buffer).unwrap_err().to_string();
+        assert!(error.contains("32 bytes"), "{}", error);
+    }
+
+    #[test]
+    fn size_only_without_fields() {
+        let buffer = light_buffer(Vec::new());
+        check_layout("Light", 32, &[], &buffer).unwrap();
+        assert!(check_layout("Light", 24, &[], &buffer).is_err());
+        assert!(check_layout("Light", 48, &[], &buffer).is_err());
+    }
+
+    #[test]
+    fn merges_shaders() {
+        let vertex = ShaderReflection {
+            constant_buffers: vec![light_buffer(Vec::new())],
+            ..Default::default()
+        };
+        let pixel = ShaderReflection {
+            constant_buffers: vec![light_buffer(Vec::new())],
+            textures: vec![binding("Color", 0), binding("Normal", 1)],
+            samplers: vec![binding("ColorSampler", 0)],
+        };
+        let merged = vertex.merge(&pixel).unwrap();
+        assert_eq!(merged.constant_buffers.len(), 1);
+        assert_eq!(merged.texture_slot("Normal"), Some(1));
+        assert_eq!(merged.sampler_slot("ColorSampler"), Some(0));
+        assert_eq!(merged.texture_slot("Missing"), None);
+
+        let mut moved = pixel.clone();
+        moved.constant_buffers[0].slot = 2;
+        let error = vertex.merge(&moved).unwrap_err().to_string();
+        assert!(error.contains("b0 in one shader and b2"), "{}", error);
+
+        let mut resized = pixel;
+        resized.constant_buffers[0].size = 48;
+        assert!(vertex.merge(&resized).is_err());
+    }
+}
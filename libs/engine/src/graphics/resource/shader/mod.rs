@@ -2,23 +2,40 @@
 mod generate;
 
 mod blob;
+mod cache;
+mod layout;
+mod preprocess;
+mod reflect;
+
 pub use blob::Blob;
+pub use layout::{
+    check_constants, check_layout, hlsl_offset, ConstantBufferDesc, Constants, FieldLayout,
+    HlslType, ResourceBinding, ShaderReflection, VariableDesc,
+};
+pub use preprocess::{
+    preprocess, preprocess_file, split_variant, variant_path, Preprocessed, INCLUDE_DIR,
+};
+pub use reflect::reflect;
+
+use cache::BytecodeKey;
 
 use super::{Resource, ResourceManager};
 
 use crate::error;
 use crate::graphics::material::Texture;
-use crate::graphics::render::{ConstantBuffer, Context, Device};
+use crate::graphics::render::{ConstantBuffer, Context, Device, Sampler};
 use crate::prelude::*;
 use crate::util::get_output;
 
 use std::ffi::CString;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::ptr::{null, null_mut, NonNull};
-use std::sync::Arc;
-use std::{convert, fs, ops};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::{mem, ops};
 
+use log::{debug, warn};
 use winapi::um::d3d11;
+use winapi::um::d3dcommon::D3D_SHADER_MACRO;
 use winapi::um::d3dcompiler;
 
 /// Trait used to define new shaders.
@@ -33,7 +50,12 @@ pub trait ShaderType {
     ) -> error::Result<NonNull<Self::ShaderInterface>>;
 
     fn set_shader(context: &Context, shader: &Self::ShaderInterface);
-    fn set_textures(context: &Context, textures: &mut [Option<Arc<dyn Texture>>]);
+    /// Binds each texture with the sampler in the same slot, or its own sampler.
+    fn set_textures(
+        context: &Context,
+        textures: &mut [Option<Arc<dyn Texture>>],
+        samplers: &[Option<Arc<Sampler>>],
+    );
 
     fn set_constant_buffer<C: ?Sized>(
         context: &Context,
@@ -44,10 +66,14 @@ pub trait ShaderType {
     const ENTRY_POINT: &'static str;
     const TARGET: &'static str;
 }
+/// Caches shaders by path. Each variant has its own path, see `variant_path`.
 pub type ShaderManager<T> = ResourceManager<Shader<T>>;
 
 pub struct Shader<T: ShaderType> {
-    pub shader: NonNull<T::ShaderInterface>,
+    shader: Mutex<NonNull<T::ShaderInterface>>,
+    reflection: Mutex<Arc<ShaderReflection>>,
+    /// The source file and everything it includes.
+    sources: Mutex<Vec<PathBuf>>,
 }
 
 impl<T: ShaderType> Resource for Shader<T> {
@@ -58,6 +84,27 @@ impl<T: ShaderType> Resource for Shader<T> {
         let (inner, _) = Self::new(device, path)?;
         Ok(Arc::new(inner))
     }
+
+    fn load_resource_cached(
+        device: &Device,
+        path: impl AsRef<Path>,
+        cache_dir: &Path,
+    ) -> error::Result<Arc<Self>> {
+        let (inner, _) = Self::new_cached(device, path, cache_dir)?;
+        Ok(Arc::new(inner))
+    }
+
+    fn replace(&self, _device: &Device, mut fresh: Self) -> error::Result<()> {
+        // The old shader is released when `fresh` drops.
+        mem::swap(&mut *self.interface(), fresh.shader.get_mut().unwrap());
+        *self.reflection.lock().unwrap() = fresh.reflection();
+        *self.sources.lock().unwrap() = mem::take(fresh.sources.get_mut().unwrap());
+        Ok(())
+    }
+
+    fn dependencies(&self) -> Vec<PathBuf> {
+        self.sources.lock().unwrap().clone()
+    }
 }
 
 shader_generate!( unsafe {
@@ -89,44 +136,193 @@ unsafe impl<T> Send for Shader<T> where T: ShaderType + Send {}
 unsafe impl<T> Sync for Shader<T> where T: ShaderType + Sync {}
 
 impl<T: ShaderType> Shader<T> {
-    pub fn new(device: &Device, location: impl AsRef<Path>) -> error::Result<(Self, Blob)> {
-        let bytecode = compile_shader_from_location(location, T::ENTRY_POINT, T::TARGET)?;
+    /// Compiles the shader at `location`, which may be a variant path.
+    pub fn new(device: &Device, location: impl AsRef<Path>) -> error::Result<(Self, Vec<u8>)> {
+        Self::load(device, location.as_ref(), None)
+    }
+
+    /// Like `new`, but takes the bytecode from `cache_dir` if the same source was
+    /// compiled before, and caches it if not.
+    pub fn new_cached(
+        device: &Device,
+        location: impl AsRef<Path>,
+        cache_dir: &Path,
+    ) -> error::Result<(Self, Vec<u8>)> {
+        Self::load(device, location.as_ref(), Some(cache_dir))
+    }
+
+    fn load(
+        device: &Device,
+        location: &Path,
+        cache_dir: Option<&Path>,
+    ) -> error::Result<(Self, Vec<u8>)> {
+        let source = VariantSource::load(location)?;
+        let bytecode = match cache_dir {
+            Some(cache_dir) => source.compile_cached(T::ENTRY_POINT, T::TARGET, cache_dir)?,
+            None => source.compile(T::ENTRY_POINT, T::TARGET)?.to_vec(),
+        };
+        let reflection = reflect(&bytecode)?;
         let shader = T::create_shader(device, &bytecode)?;
 
-        Ok((Self { shader }, bytecode))
+        Ok((
+            Self {
+                shader: Mutex::new(shader),
+                reflection: Mutex::new(Arc::new(reflection)),
+                sources: Mutex::new(source.sources()),
+            },
+            bytecode,
+        ))
     }
-}
 
-impl<T: ShaderType> convert::AsRef<T::ShaderInterface> for Shader<T> {
-    fn as_ref(&self) -> &T::ShaderInterface {
-        unsafe { self.shader.as_ref() }
+    /// What the shader declares. Reloading the shader replaces it.
+    pub fn reflection(&self) -> Arc<ShaderReflection> {
+        self.reflection.lock().unwrap().clone()
     }
-}
 
-impl<T: ShaderType> convert::AsMut<T::ShaderInterface> for Shader<T> {
-    fn as_mut(&mut self) -> &mut T::ShaderInterface {
-        unsafe { self.shader.as_mut() }
+    /// The compiled shader. Reloading swaps it out, so only hold this while binding it.
+    pub fn interface(&self) -> MutexGuard<NonNull<T::ShaderInterface>> {
+        self.shader.lock().unwrap()
     }
 }
 
 impl<T: ShaderType> ops::Drop for Shader<T> {
     fn drop(&mut self) {
         unsafe {
-            self.shader.as_ref().Release();
+            self.shader.get_mut().unwrap().as_ref().Release();
         }
     }
 }
 
+/// A shader file with its includes pasted in, and the keywords of its variant.
+struct VariantSource {
+    preprocessed: Preprocessed,
+    keywords: Vec<String>,
+}
+
+impl VariantSource {
+    fn load(location: &Path) -> error::Result<Self> {
+        let (path, keywords) = split_variant(location);
+        let defines: Vec<_> = keywords.iter().map(String::as_str).collect();
+        Ok(Self {
+            preprocessed: preprocess_file(path, &defines)?,
+            keywords,
+        })
+    }
+
+    fn name(&self) -> &str {
+        &self.preprocessed.files[0]
+    }
+
+    fn defines(&self) -> Vec<(&str, &str)> {
+        self.keywords
+            .iter()
+            .map(|keyword| (keyword.as_str(), "1"))
+            .collect()
+    }
+
+    fn key(&self, entry_point: &str, target: &str) -> BytecodeKey {
+        BytecodeKey::new(
+            self.preprocessed.source.as_bytes(),
+            entry_point,
+            target,
+            &self.defines(),
+        )
+    }
+
+    fn compile(&self, entry_point: &str, target: &str) -> error::Result<Blob> {
+        compile_shader_with_defines(
+            self.preprocessed.source.as_bytes(),
+            self.name(),
+            &self.defines(),
+            entry_point,
+            target,
+        )
+    }
+
+    fn compile_cached(
+        &self,
+        entry_point: &str,
+        target: &str,
+        cache_dir: &Path,
+    ) -> error::Result<Vec<u8>> {
+        let key = self.key(entry_point, target);
+        if let Some(bytecode) = cache::read(cache_dir, key) {
+            debug!("Loaded {} from cache", self.name());
+            return Ok(bytecode);
+        }
+        let bytecode = self.compile(entry_point, target)?.to_vec();
+        if let Err(e) = cache::write(cache_dir, key, &bytecode) {
+            warn!("Could not cache shader {}: {}", self.name(), e);
+        }
+        Ok(bytecode)
+    }
+
+    /// Every file the shader was compiled from.
+    fn sources(self) -> Vec<PathBuf> {
+        self.preprocessed
+            .files
+            .into_iter()
+            .map(PathBuf::from)
+            .collect()
+    }
+}
+
+/// Preprocesses and compiles a shader file, or a variant of one with its keywords defined.
 pub fn compile_shader_from_location(
     location: impl AsRef<Path>,
     entry_point: &str,
     target: &str,
 ) -> error::Result<Blob> {
-    let uncompiled = fs::read(location)?;
-    compile_shader(&uncompiled, entry_point, target)
+    VariantSource::load(location.as_ref())?.compile(entry_point, target)
+}
+
+/// Compiles a shader file or variant into `cache_dir` ahead of time, so loading it
+/// through the cache doesn't compile anything.
+pub fn precompile(
+    location: impl AsRef<Path>,
+    entry_point: &str,
+    target: &str,
+    cache_dir: &Path,
+) -> error::Result<()> {
+    let source = VariantSource::load(location.as_ref())?;
+    let bytecode = source.compile(entry_point, target)?;
+    cache::write(cache_dir, source.key(entry_point, target), &bytecode)
 }
 
 pub fn compile_shader(uncompiled: &[u8], entry_point: &str, target: &str) -> error::Result<Blob> {
+    compile_shader_with_defines(uncompiled, "", &[], entry_point, target)
+}
+
+/// Compiles with each `(name, value)` in `defines` set as if by `#define`.
+/// `source_name` names the source in compile errors.
+pub fn compile_shader_with_defines(
+    uncompiled: &[u8],
+    source_name: &str,
+    defines: &[(&str, &str)],
+    entry_point: &str,
+    target: &str,
+) -> error::Result<Blob> {
+    let c_string = |text: &str| {
+        CString::new(text).map_err(|_| error::Custom(format!("Bad shader string {:?}", text)))
+    };
+    let source_name = c_string(source_name)?;
+    let defines = defines
+        .iter()
+        .map(|&(name, value)| Ok((c_string(name)?, c_string(value)?)))
+        .collect::<error::Result<Vec<_>>>()?;
+    // The list ends with a null macro.
+    let macros: Vec<_> = defines
+        .iter()
+        .map(|(name, value)| D3D_SHADER_MACRO {
+            Name: name.as_ptr(),
+            Definition: value.as_ptr(),
+        })
+        .chain(Some(D3D_SHADER_MACRO {
+            Name: null(),
+            Definition: null(),
+        }))
+        .collect();
+
     unsafe {
         let entry_point =
             CString::new(entry_point).map_err(|_| error::Custom("Bad Entry Point".to_owned()))?;
@@ -138,8 +334,8 @@ pub fn compile_shader(uncompiled: &[u8], entry_point: &str, target: &str) -> err
         let result = d3dcompiler::D3DCompile(
             uncompiled.as_ptr().cast(),
             uncompiled.len(),
-            null_mut(),
-            null(),
+            source_name.as_ptr(),
+            macros.as_ptr(),
             null_mut(),
             entry_point.as_ptr(),
             target.as_ptr(),
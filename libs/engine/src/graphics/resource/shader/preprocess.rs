@@ -0,0 +1,639 @@
+//! Resolves `#include`s before a shader is compiled, and names the variants
+//! compiled with different keywords.
+
+use crate::error;
+use crate::vfs;
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Searched for includes that aren't next to the file including them.
+pub const INCLUDE_DIR: &str = "shaders/include";
+
+/// Separates the source file from the keywords in the path of a variant.
+/// `?` can't be in a file name, so it never clashes with a real file.
+const VARIANT_SEPARATOR: char = '?';
+
+/// Shader source with every include pasted in.
+#[derive(Debug)]
+pub struct Preprocessed {
+    pub source: String,
+    /// The file itself, then every file it included, as virtual paths.
+    pub files: Vec<String>,
+}
+
+/// Preprocesses a file in the virtual file system, with `keywords` defined.
+pub fn preprocess_file(path: impl AsRef<Path>, keywords: &[&str]) -> error::Result<Preprocessed> {
+    preprocess(path, keywords, |file| vfs::read_to_string(file))
+}
+
+/// Pastes in every `#include "file"`, looking next to the including file first, then
+/// in `INCLUDE_DIR`. Each file is only included once, as if it had `#pragma once`.
+/// `#line` directives keep compile errors pointing at the file they came from.
+///
+/// Conditionals are followed with `keywords` defined, so includes in branches that
+/// are compiled out are left out. Includes under conditions that can't be worked out
+/// until compiling are pasted each time, without counting as the one include.
+pub fn preprocess(
+    path: impl AsRef<Path>,
+    keywords: &[&str],
+    read: impl FnMut(&str) -> io::Result<String>,
+) -> error::Result<Preprocessed> {
+    let path = vfs::normalize(path)?;
+    let mut expander = Expander {
+        read,
+        conditions: Conditions::new(keywords),
+        included: vec![path.clone()],
+        stack: Vec::new(),
+        preprocessed: Preprocessed {
+            source: String::new(),
+            files: Vec::new(),
+        },
+    };
+    let text = (expander.read)(&path)?;
+    expander.expand(&path, &text)?;
+    if !expander.conditions.groups.is_empty() {
+        return Err(error::Custom(format!("{}: unterminated #if", path)));
+    }
+    Ok(expander.preprocessed)
+}
+
+struct Expander<R> {
+    read: R,
+    conditions: Conditions,
+    /// Files included from active branches, never pasted again.
+    included: Vec<String>,
+    /// Files being expanded, innermost last.
+    stack: Vec<String>,
+    preprocessed: Preprocessed,
+}
+
+impl<R: FnMut(&str) -> io::Result<String>> Expander<R> {
+    fn expand(&mut self, path: &str, text: &str) -> error::Result<()> {
+        if !self.preprocessed.files.iter().any(|file| file == path) {
+            self.preprocessed.files.push(path.to_owned());
+        }
+        self.stack.push(path.to_owned());
+        self.preprocessed
+            .source
+            .push_str(&format!("#line 1 \"{}\"\n", path));
+
+        for (idx, line) in text.lines().enumerate() {
+            let line_number = idx + 1;
+            let error =
+                |message: &str| error::Custom(format!("{}({}): {}", path, line_number, message));
+            let name = match directive(line) {
+                Some(("include", rest)) => {
+                    include_name(rest.trim()).ok_or_else(|| error("expected #include \"file\""))?
+                }
+                Some((name, rest)) => {
+                    self.conditions.follow(name, rest).map_err(error)?;
+                    self.preprocessed.source.push_str(line);
+                    self.preprocessed.source.push('\n');
+                    continue;
+                }
+                None => {
+                    self.preprocessed.source.push_str(line);
+                    self.preprocessed.source.push('\n');
+                    continue;
+                }
+            };
+
+            let branch = self.conditions.branch();
+            if branch == Branch::Inactive {
+                // Compiled out, but the line still counts.
+                self.preprocessed.source.push('\n');
+                continue;
+            }
+
+            let mut found = false;
+            for candidate in include_candidates(path, name) {
+                if self.included.contains(&candidate) || self.stack.contains(&candidate) {
+                    found = true;
+                    break;
+                }
+                match (self.read)(&candidate) {
+                    Ok(included) => {
+                        if branch == Branch::Active {
+                            self.included.push(candidate.clone());
+                        }
+                        self.expand(&candidate, &included)?;
+                        found = true;
+                        break;
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            if !found {
+                return Err(error(&format!("cannot find include {}", name)));
+            }
+            // Back in this file, on the line after the include.
+            self.preprocessed
+                .source
+                .push_str(&format!("#line {} \"{}\"\n", line_number + 1, path));
+        }
+        self.stack.pop();
+        Ok(())
+    }
+}
+
+/// The name of a preprocessor directive and what follows it, if the line is one.
+fn directive(line: &str) -> Option<(&str, &str)> {
+    let directive = line.trim_start().strip_prefix('#')?.trim_start();
+    let end = directive
+        .find(|c: char| !is_identifier_char(c))
+        .unwrap_or(directive.len());
+    Some(directive.split_at(end))
+}
+
+/// Whether lines reach the compiler, as far as can be told before compiling.
+/// Ordered so the stricter of two is the greater.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Branch {
+    Active,
+    Unknown,
+    Inactive,
+}
+
+impl Branch {
+    fn new(active: bool) -> Self {
+        if active {
+            Branch::Active
+        } else {
+            Branch::Inactive
+        }
+    }
+
+    fn not(self) -> Self {
+        match self {
+            Branch::Active => Branch::Inactive,
+            Branch::Unknown => Branch::Unknown,
+            Branch::Inactive => Branch::Active,
+        }
+    }
+}
+
+/// An `#if` with its `#elif`s and `#else`.
+struct Group {
+    branch: Branch,
+    /// Whether any branch so far was taken.
+    taken: Branch,
+}
+
+/// Follows `#if`s, `#ifdef`s and `#define`s through the source.
+struct Conditions {
+    groups: Vec<Group>,
+    defined: HashMap<String, Branch>,
+    /// Keywords not redefined since, so still 1.
+    keywords: Vec<String>,
+}
+
+impl Conditions {
+    fn new(keywords: &[&str]) -> Self {
+        Self {
+            groups: Vec::new(),
+            defined: keywords
+                .iter()
+                .map(|keyword| (keyword.to_string(), Branch::Active))
+                .collect(),
+            keywords: keywords.iter().map(|keyword| keyword.to_string()).collect(),
+        }
+    }
+
+    /// Whether the current line is compiled.
+    fn branch(&self) -> Branch {
+        self.groups
+            .iter()
+            .map(|group| group.branch)
+            .max()
+            .unwrap_or(Branch::Active)
+    }
+
+    fn defined(&self, name: &str) -> Branch {
+        self.defined.get(name).copied().unwrap_or(Branch::Inactive)
+    }
+
+    /// An identifier in an `#if`. Undefined ones are 0.
+    fn value(&self, name: &str) -> Branch {
+        if self.keywords.iter().any(|keyword| keyword == name) {
+            Branch::Active
+        } else {
+            self.defined(name).max(Branch::Unknown)
+        }
+    }
+
+    /// Follows a directive. Ones other than conditionals and defines are ignored.
+    fn follow(&mut self, name: &str, rest: &str) -> Result<(), &'static str> {
+        let rest = rest.split("//").next().unwrap_or_default().trim();
+        match name {
+            "if" | "ifdef" | "ifndef" => {
+                let branch = match name {
+                    "if" => self.eval(rest),
+                    "ifdef" => self.defined(rest),
+                    _ => self.defined(rest).not(),
+                };
+                self.groups.push(Group {
+                    branch,
+                    taken: branch,
+                });
+            }
+            "elif" | "else" => {
+                let branch = match name {
+                    "elif" => self.eval(rest),
+                    _ => Branch::Active,
+                };
+                let group = self.groups.last_mut().ok_or("#else without #if")?;
+                group.branch = branch.max(group.taken.not());
+                group.taken = group.taken.min(branch);
+            }
+            "endif" => {
+                self.groups.pop().ok_or("#endif without #if")?;
+            }
+            "define" | "undef" => {
+                let branch = self.branch();
+                let end = rest
+                    .find(|c: char| !is_identifier_char(c))
+                    .unwrap_or(rest.len());
+                let macro_name = &rest[..end];
+                if branch == Branch::Inactive || macro_name.is_empty() {
+                    return Ok(());
+                }
+                self.keywords.retain(|keyword| keyword != macro_name);
+                let defined = match (name, branch) {
+                    ("define", Branch::Active) => Branch::Active,
+                    ("undef", Branch::Active) => Branch::Inactive,
+                    _ => self.defined(macro_name).min(Branch::Unknown),
+                };
+                self.defined.insert(macro_name.to_owned(), defined);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Evaluates an `#if` of `defined`, `!`, `&&`, `||`, parentheses, numbers and
+    /// keywords. Anything else is only known when compiling.
+    fn eval(&self, expression: &str) -> Branch {
+        let mut expression = Expression {
+            tokens: tokens(expression).unwrap_or_default(),
+            next: 0,
+            conditions: self,
+        };
+        match expression.or() {
+            Some(branch) if expression.next == expression.tokens.len() => branch,
+            _ => Branch::Unknown,
+        }
+    }
+}
+
+fn tokens(expression: &str) -> Option<Vec<&str>> {
+    let mut tokens = Vec::new();
+    let mut rest = expression.trim_start();
+    while !rest.is_empty() {
+        let len = if rest.starts_with("&&") || rest.starts_with("||") {
+            2
+        } else if rest.starts_with(['(', ')']) || rest.starts_with('!') && !rest.starts_with("!=") {
+            1
+        } else {
+            rest.find(|c: char| !is_identifier_char(c))
+                .unwrap_or(rest.len())
+        };
+        if len == 0 {
+            return None;
+        }
+        tokens.push(&rest[..len]);
+        rest = rest[len..].trim_start();
+    }
+    Some(tokens)
+}
+
+struct Expression<'a> {
+    tokens: Vec<&'a str>,
+    next: usize,
+    conditions: &'a Conditions,
+}
+
+impl<'a> Expression<'a> {
+    fn take(&mut self) -> Option<&'a str> {
+        let token = self.tokens.get(self.next).copied();
+        self.next += 1;
+        token
+    }
+
+    fn skip(&mut self, token: &str) -> bool {
+        let skipped = self.tokens.get(self.next) == Some(&token);
+        if skipped {
+            self.next += 1;
+        }
+        skipped
+    }
+
+    fn or(&mut self) -> Option<Branch> {
+        let mut branch = self.and()?;
+        while self.skip("||") {
+            branch = branch.min(self.and()?);
+        }
+        Some(branch)
+    }
+
+    fn and(&mut self) -> Option<Branch> {
+        let mut branch = self.unary()?;
+        while self.skip("&&") {
+            branch = branch.max(self.unary()?);
+        }
+        Some(branch)
+    }
+
+    fn unary(&mut self) -> Option<Branch> {
+        match self.take()? {
+            "!" => Some(self.unary()?.not()),
+            "(" => {
+                let branch = self.or()?;
+                self.skip(")").then_some(branch)
+            }
+            "defined" => {
+                let parenthesized = self.skip("(");
+                let name = self.take().filter(|name| is_identifier(name))?;
+                (!parenthesized || self.skip(")")).then(|| self.conditions.defined(name))
+            }
+            number if number.starts_with(|c: char| c.is_ascii_digit()) => number
+                .parse::<u64>()
+                .ok()
+                .map(|number| Branch::new(number != 0)),
+            name if is_identifier(name) => Some(self.conditions.value(name)),
+            _ => None,
+        }
+    }
+}
+
+/// The file name of `"file"` or `<file>`.
+fn include_name(directive: &str) -> Option<&str> {
+    let name = directive
+        .strip_prefix('"')
+        .and_then(|name| name.strip_suffix('"'))
+        .or_else(|| {
+            directive
+                .strip_prefix('<')
+                .and_then(|name| name.strip_suffix('>'))
+        })?;
+    (!name.is_empty()).then_some(name)
+}
+
+fn include_candidates(path: &str, name: &str) -> Vec<String> {
+    let dir = path.rfind('/').map_or("", |end| &path[..end]);
+    [
+        format!("{}/{}", dir, name),
+        format!("{}/{}", INCLUDE_DIR, name),
+    ]
+    .iter()
+    .filter_map(|candidate| vfs::normalize(candidate).ok())
+    .collect()
+}
+
+/// Path the variant of a shader compiled with `keywords` is cached under.
+/// Keywords are sorted, so any order names the same variant.
+pub fn variant_path(path: impl AsRef<Path>, keywords: &[&str]) -> error::Result<PathBuf> {
+    let path = path.as_ref();
+    if keywords.is_empty() {
+        return Ok(path.to_owned());
+    }
+
+    let mut keywords = keywords.to_vec();
+    keywords.sort_unstable();
+    keywords.dedup();
+    if let Some(keyword) = keywords.iter().find(|keyword| !is_identifier(keyword)) {
+        return Err(error::Custom(format!(
+            "Shader keyword {:?} is not an identifier",
+            keyword
+        )));
+    }
+    Ok(PathBuf::from(format!(
+        "{}{}{}",
+        path.display(),
+        VARIANT_SEPARATOR,
+        keywords.join(",")
+    )))
+}
+
+/// The source file and keywords of a variant path.
+pub fn split_variant(path: &Path) -> (PathBuf, Vec<String>) {
+    let text = path.to_string_lossy();
+    match text.split_once(VARIANT_SEPARATOR) {
+        Some((source, keywords)) => (
+            PathBuf::from(source),
+            keywords.split(',').map(str::to_owned).collect(),
+        ),
+        None => (path.to_owned(), Vec::new()),
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(is_identifier_char)
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn files(files: &[(&str, &str)]) -> impl FnMut(&str) -> io::Result<String> {
+        let files: HashMap<String, String> = files
+            .iter()
+            .map(|(path, text)| (path.to_string(), text.to_string()))
+            .collect();
+        move |path| {
+            files
+                .get(path)
+                .cloned()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, path))
+        }
+    }
+
+    #[test]
+    fn includes() {
+        let read = files(&[
+            (
+                "shaders/light/pixel_shader.hlsl",
+                "#include \"common.hlsl\"\n  #  include <environment.hlsl>\nfloat4 psmain",
+            ),
+            ("shaders/light/common.hlsl", "float3 color;"),
+            ("shaders/include/environment.hlsl", "float time;"),
+        ]);
+        let preprocessed = preprocess("shaders\\light\\pixel_shader.hlsl", &[], read).unwrap();
+
+        assert_eq!(
+            preprocessed.files,
+            [
+                "shaders/light/pixel_shader.hlsl",
+                "shaders/light/common.hlsl",
+                "shaders/include/environment.hlsl",
+            ]
+        );
+        assert_eq!(
+            preprocessed.source,
+            "#line 1 \"shaders/light/pixel_shader.hlsl\"\n\
+             #line 1 \"shaders/light/common.hlsl\"\n\
+             float3 color;\n\
+             #line 2 \"shaders/light/pixel_shader.hlsl\"\n\
+             #line 1 \"shaders/include/environment.hlsl\"\n\
+             float time;\n\
+             #line 3 \"shaders/light/pixel_shader.hlsl\"\n\
+             float4 psmain\n"
+        );
+    }
+
+    #[test]
+    fn includes_once() {
+        let read = files(&[
+            ("shaders/a.hlsl", "#include \"b.hlsl\"\n#include \"c.hlsl\""),
+            ("shaders/b.hlsl", "#include \"c.hlsl\"\nb"),
+            ("shaders/c.hlsl", "#include \"a.hlsl\"\nc"),
+        ]);
+        let preprocessed = preprocess("shaders/a.hlsl", &[], read).unwrap();
+
+        assert_eq!(
+            preprocessed.files,
+            ["shaders/a.hlsl", "shaders/b.hlsl", "shaders/c.hlsl"]
+        );
+        assert_eq!(preprocessed.source.matches("\nc\n").count(), 1);
+        assert!(!preprocessed.source.contains("#include"));
+    }
+
+    #[test]
+    fn conditional_includes() {
+        let read = || {
+            files(&[
+                (
+                    "shaders/a.hlsl",
+                    "#ifdef SHADOWS\n\
+                     #include \"b.hlsl\"\n\
+                     #elif defined(FOG) && !SHADOWS\n\
+                     #include \"c.hlsl\"\n\
+                     #endif\n\
+                     #include \"b.hlsl\"\n\
+                     #if QUALITY > 1\n\
+                     #include \"c.hlsl\"\n\
+                     #endif\n\
+                     #include \"c.hlsl\"",
+                ),
+                ("shaders/b.hlsl", "b"),
+                ("shaders/c.hlsl", "c"),
+            ])
+        };
+
+        // The include under #ifdef is compiled out, so the later one still pastes b.
+        let preprocessed = preprocess("shaders/a.hlsl", &[], read()).unwrap();
+        assert_eq!(preprocessed.source.matches("\nb\n").count(), 1);
+        assert!(preprocessed
+            .source
+            .contains("#endif\n#line 1 \"shaders/b.hlsl\"\nb\n"));
+        // c under an #if that can't be evaluated is pasted again after it.
+        assert_eq!(preprocessed.source.matches("\nc\n").count(), 2);
+        assert_eq!(
+            preprocessed.files,
+            ["shaders/a.hlsl", "shaders/b.hlsl", "shaders/c.hlsl"]
+        );
+
+        let preprocessed = preprocess("shaders/a.hlsl", &["SHADOWS"], read()).unwrap();
+        assert_eq!(preprocessed.source.matches("\nb\n").count(), 1);
+        assert!(preprocessed
+            .source
+            .contains("#ifdef SHADOWS\n#line 1 \"shaders/b.hlsl\""));
+
+        // The #elif include of c counts, so neither later one pastes it again.
+        let preprocessed = preprocess("shaders/a.hlsl", &["FOG"], read()).unwrap();
+        assert_eq!(preprocessed.source.matches("\nc\n").count(), 1);
+        assert!(preprocessed
+            .source
+            .ends_with("#endif\n#line 11 \"shaders/a.hlsl\"\n"));
+
+        let read = files(&[("shaders/a.hlsl", "#if 1\n#else\n#else")]);
+        assert!(preprocess("shaders/a.hlsl", &[], read).is_err());
+        let read = files(&[("shaders/a.hlsl", "#endif")]);
+        assert!(preprocess("shaders/a.hlsl", &[], read).is_err());
+    }
+
+    #[test]
+    fn conditions() {
+        let mut conditions = Conditions::new(&["A"]);
+        for (expression, branch) in [
+            ("A", Branch::Active),
+            ("B", Branch::Inactive),
+            ("defined A && !defined(B)", Branch::Active),
+            ("B || (A && 0)", Branch::Inactive),
+            ("!(A)", Branch::Inactive),
+            ("A > 0", Branch::Unknown),
+            ("A &&", Branch::Unknown),
+            ("C || A", Branch::Active),
+        ] {
+            assert_eq!(conditions.eval(expression), branch, "{}", expression);
+        }
+
+        conditions.follow("define", " C 0").unwrap();
+        conditions.follow("undef", " A").unwrap();
+        assert_eq!(conditions.eval("defined(C)"), Branch::Active);
+        assert_eq!(conditions.eval("C"), Branch::Unknown);
+        assert_eq!(conditions.eval("A"), Branch::Inactive);
+
+        conditions.follow("if", " C").unwrap();
+        conditions.follow("define", " D").unwrap();
+        conditions.follow("elif", " 1").unwrap();
+        assert_eq!(conditions.branch(), Branch::Unknown);
+        conditions.follow("else", "").unwrap();
+        assert_eq!(conditions.branch(), Branch::Inactive);
+        conditions.follow("endif", "").unwrap();
+        assert_eq!(conditions.eval("defined D"), Branch::Unknown);
+    }
+
+    #[test]
+    fn bad_includes() {
+        let read = files(&[("shaders/a.hlsl", "float x;\n#include \"missing.hlsl\"")]);
+        let e = preprocess("shaders/a.hlsl", &[], read).unwrap_err();
+        assert!(e.to_string().contains("shaders/a.hlsl(2)"));
+        assert!(e.to_string().contains("missing.hlsl"));
+
+        let read = files(&[("shaders/a.hlsl", "#include missing.hlsl")]);
+        assert!(preprocess("shaders/a.hlsl", &[], read).is_err());
+
+        // Not an include at all
+        let read = files(&[("shaders/a.hlsl", "#includes\n// #include \"x\"")]);
+        assert!(preprocess("shaders/a.hlsl", &[], read).is_ok());
+    }
+
+    #[test]
+    fn variants() {
+        let path = Path::new("shaders/light/pixel_shader.hlsl");
+        assert_eq!(variant_path(path, &[]).unwrap(), path);
+
+        let variant = variant_path(path, &["SPECULAR", "NORMAL_MAP", "SPECULAR"]).unwrap();
+        assert_eq!(
+            variant,
+            Path::new("shaders/light/pixel_shader.hlsl?NORMAL_MAP,SPECULAR")
+        );
+        assert_eq!(
+            variant,
+            variant_path(path, &["NORMAL_MAP", "SPECULAR"]).unwrap()
+        );
+        assert_eq!(
+            split_variant(&variant),
+            (
+                path.to_owned(),
+                vec!["NORMAL_MAP".to_owned(), "SPECULAR".to_owned()]
+            )
+        );
+        assert_eq!(split_variant(path), (path.to_owned(), Vec::new()));
+
+        assert!(variant_path(path, &["TWO WORDS"]).is_err());
+        assert!(variant_path(path, &["1ST"]).is_err());
+        assert!(variant_path(path, &[""]).is_err());
+    }
+}
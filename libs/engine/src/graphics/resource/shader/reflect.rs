@@ -0,0 +1,91 @@
+use super::{ConstantBufferDesc, ResourceBinding, ShaderReflection, VariableDesc};
+
+use crate::error::{self, HResultToResult};
+use crate::util::get_output;
+
+use std::ffi::CStr;
+use std::mem;
+use std::os::raw::c_char;
+
+use winapi::um::d3d11shader::{self, ID3D11ShaderReflection};
+use winapi::um::{d3dcommon, d3dcompiler};
+use winapi::Interface;
+
+/// Reads what a compiled shader declares from its bytecode.
+pub fn reflect(bytecode: &[u8]) -> error::Result<ShaderReflection> {
+    unsafe {
+        let reflector = get_output(|reflector| {
+            d3dcompiler::D3DReflect(
+                bytecode.as_ptr().cast(),
+                bytecode.len(),
+                &ID3D11ShaderReflection::uuidof(),
+                reflector,
+            )
+        })?
+        .cast::<ID3D11ShaderReflection>();
+        let reflection = read_reflection(reflector.as_ref());
+        reflector.as_ref().Release();
+        reflection
+    }
+}
+
+unsafe fn read_reflection(reflector: &ID3D11ShaderReflection) -> error::Result<ShaderReflection> {
+    let mut shader_desc: d3d11shader::D3D11_SHADER_DESC = mem::zeroed();
+    reflector.GetDesc(&mut shader_desc).result()?;
+
+    let mut reflection = ShaderReflection::default();
+    for idx in 0..shader_desc.BoundResources {
+        let mut bind_desc: d3d11shader::D3D11_SHADER_INPUT_BIND_DESC = mem::zeroed();
+        reflector
+            .GetResourceBindingDesc(idx, &mut bind_desc)
+            .result()?;
+        let name = string(bind_desc.Name);
+        let slot = bind_desc.BindPoint;
+
+        match bind_desc.Type {
+            d3dcommon::D3D_SIT_CBUFFER => {
+                let buffer = reflector.GetConstantBufferByName(bind_desc.Name);
+                reflection
+                    .constant_buffers
+                    .push(read_constant_buffer(&*buffer, name, slot)?);
+            }
+            d3dcommon::D3D_SIT_TEXTURE => reflection.textures.push(ResourceBinding { name, slot }),
+            d3dcommon::D3D_SIT_SAMPLER => reflection.samplers.push(ResourceBinding { name, slot }),
+            _ => {}
+        }
+    }
+    Ok(reflection)
+}
+
+unsafe fn read_constant_buffer(
+    buffer: &d3d11shader::ID3D11ShaderReflectionConstantBuffer,
+    name: String,
+    slot: u32,
+) -> error::Result<ConstantBufferDesc> {
+    let mut buffer_desc: d3d11shader::D3D11_SHADER_BUFFER_DESC = mem::zeroed();
+    buffer.GetDesc(&mut buffer_desc).result()?;
+
+    let mut variables = Vec::new();
+    for idx in 0..buffer_desc.Variables {
+        let mut variable_desc: d3d11shader::D3D11_SHADER_VARIABLE_DESC = mem::zeroed();
+        (*buffer.GetVariableByIndex(idx))
+            .GetDesc(&mut variable_desc)
+            .result()?;
+        variables.push(VariableDesc {
+            name: string(variable_desc.Name),
+            offset: variable_desc.StartOffset as usize,
+            size: variable_desc.Size as usize,
+        });
+    }
+
+    Ok(ConstantBufferDesc {
+        name,
+        slot,
+        size: buffer_desc.Size as usize,
+        variables,
+    })
+}
+
+unsafe fn string(name: *const c_char) -> String {
+    CStr::from_ptr(name).to_string_lossy().into_owned()
+}
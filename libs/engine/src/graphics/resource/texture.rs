@@ -1,121 +1,392 @@
-use super::{Resource, ResourceManager};
+mod atlas;
+mod bc;
+mod cube;
+mod data;
+mod dds;
+mod exr;
+mod hdr;
+mod inflate;
+mod ktx2;
+mod process;
+
+pub use atlas::{
+    Atlas, AtlasImage, AtlasManifest, AtlasOptions, AtlasPage, AtlasRegion, ManifestPage,
+    MANIFEST_EXTENSION,
+};
+pub use cube::{direction_to_face, face_direction, CubeLayout};
+pub use data::{f32_to_half, half_to_f32, ColorSpace, MipLevel, TextureData, TextureFormat};
+pub use process::{
+    linear_to_srgb, nearest_pow2, process, process_hdr, srgb_to_linear, MipFilter, ProcessOptions,
+    TextureKind,
+};
+
+use super::{AsyncResource, Resource, ResourceManager};
 
 use crate::error;
 use crate::graphics::material;
-use crate::graphics::render::Device;
+use crate::graphics::render::{Device, Sampler, SamplerDesc};
 use crate::util::get_output;
 
+use std::collections::HashMap;
 use std::path::Path;
-use std::ptr::{self, NonNull};
-use std::sync::Arc;
+use std::ptr::NonNull;
+use std::sync::{Arc, Mutex, MutexGuard};
 
-use image::io::Reader;
+use log::warn;
 use winapi::shared::{dxgiformat, dxgitype};
-use winapi::um::d3d11;
+use winapi::um::{d3d11, d3dcommon};
 
 pub type TextureManager = ResourceManager<Texture>;
+pub type CubeMapManager = ResourceManager<CubeMap>;
+
+/// Shown while textures load in the background.
+const PLACEHOLDER_COLOR: [u8; 4] = [128, 128, 128, 255];
+
+fn placeholder_data() -> TextureData {
+    TextureData::rgba8(
+        ColorSpace::Srgb,
+        vec![MipLevel {
+            width: 1,
+            height: 1,
+            data: PLACEHOLDER_COLOR.to_vec(),
+        }],
+    )
+}
+
+/// A texture on the GPU. Its views are swapped out when it is reloaded.
+pub struct Texture(Mutex<TextureInner>);
 
 //TODO Verify
-pub struct Texture {
+struct TextureInner {
     texture: NonNull<d3d11::ID3D11Texture2D>,
-    sampler_state: NonNull<d3d11::ID3D11SamplerState>,
+    sampler_state: Arc<Sampler>,
     resource_view: NonNull<d3d11::ID3D11ShaderResourceView>,
+    /// Bytes uploaded, over every layer and mip level.
+    size: usize,
 }
 
-unsafe impl Send for Texture {}
-unsafe impl Sync for Texture {}
+unsafe impl Send for TextureInner {}
+unsafe impl Sync for TextureInner {}
 
 impl Resource for Texture {
     fn load_resource_from_file(
         device: &Device,
         path: impl AsRef<Path>,
     ) -> error::Result<Arc<Self>> {
+        Self::from_data(device, &TextureData::load(path)?)
+    }
+
+    fn replace(&self, _device: &Device, fresh: Self) -> error::Result<()> {
+        *self.inner() = fresh.0.into_inner().unwrap();
+        Ok(())
+    }
+
+    fn memory_size(&self) -> usize {
+        self.inner().size
+    }
+}
+
+impl AsyncResource for Texture {
+    type Data = TextureData;
+
+    fn decode(path: &Path, _cache_dir: Option<&Path>) -> error::Result<TextureData> {
+        TextureData::load(path)
+    }
+
+    fn upload(device: &Device, data: TextureData) -> error::Result<Self> {
+        Self::new(device, &data)
+    }
+
+    fn placeholder(device: &Device) -> error::Result<Self> {
+        Self::new(device, &placeholder_data())
+    }
+}
+
+impl Texture {
+    /// Uploads a texture with all of its layers and mip levels. Block compressed
+    /// formats the device cannot sample are decoded on the CPU first.
+    pub fn from_data(device: &Device, data: &TextureData) -> error::Result<Arc<Self>> {
+        Self::new(device, data).map(Arc::new)
+    }
+
+    pub fn new(device: &Device, data: &TextureData) -> error::Result<Self> {
+        data.validate()?;
+        let format = dxgi_format(data.format);
+        if !supports_format(device, format) {
+            warn!(
+                "{:?} textures are not supported, decoding on the CPU",
+                data.format
+            );
+            let data = match data.format {
+                TextureFormat::Bc6hUfloat | TextureFormat::Bc6hSfloat => data.decompress()?,
+                _ => data.to_rgba8()?,
+            };
+            return Self::create(device, &data, dxgi_format(data.format));
+        }
+        Self::create(device, data, format)
+    }
+
+    fn create(
+        device: &Device,
+        data: &TextureData,
+        format: dxgiformat::DXGI_FORMAT,
+    ) -> error::Result<Self> {
         unsafe {
-            let image = Reader::open(path.as_ref())?.decode()?.to_rgba8();
             let sample_desc = dxgitype::DXGI_SAMPLE_DESC {
                 Count: 1,
                 Quality: 0,
             };
 
+            let mip_levels = data.mip_count() as u32;
+            let array_size = data.layers.len() as u32;
             let desc = d3d11::D3D11_TEXTURE2D_DESC {
-                Width: image.width(),
-                Height: image.height(),
-                MipLevels: 1,
-                ArraySize: 1,
-                Format: dxgiformat::DXGI_FORMAT_R8G8B8A8_UNORM,
+                Width: data.width(),
+                Height: data.height(),
+                MipLevels: mip_levels,
+                ArraySize: array_size,
+                Format: format,
                 Usage: d3d11::D3D11_USAGE_DEFAULT,
                 SampleDesc: sample_desc,
                 BindFlags: d3d11::D3D11_BIND_SHADER_RESOURCE,
                 CPUAccessFlags: 0,
-                MiscFlags: 0,
+                MiscFlags: if data.cube {
+                    d3d11::D3D11_RESOURCE_MISC_TEXTURECUBE
+                } else {
+                    0
+                },
             };
 
-            let pitch = image
-                .sample_layout()
-                .height_stride
-                .max(image.sample_layout().width_stride) as u32;
-            let buffer: Vec<u8> = image.into_raw();
-            let data = d3d11::D3D11_SUBRESOURCE_DATA {
-                pSysMem: buffer.as_ptr().cast(),
-                SysMemPitch: pitch,
+            // Subresources are ordered by layer, then mip level.
+            let subresources: Vec<_> = data
+                .layers
+                .iter()
+                .flatten()
+                .map(|mip| d3d11::D3D11_SUBRESOURCE_DATA {
+                    pSysMem: mip.data.as_ptr().cast(),
+                    SysMemPitch: data.format.row_pitch(mip.width) as u32,
+                    ..Default::default()
+                })
+                .collect();
+            let texture = get_output(|ptr| {
+                device
+                    .as_ref()
+                    .CreateTexture2D(&desc, subresources.as_ptr(), ptr)
+            })?;
+
+            // Materials can override this per slot.
+            let sampler_state = device.sampler(&SamplerDesc::default())?;
+
+            let mut view_desc = d3d11::D3D11_SHADER_RESOURCE_VIEW_DESC {
+                Format: format,
                 ..Default::default()
             };
-            let texture = get_output(|ptr| device.as_ref().CreateTexture2D(&desc, &data, ptr))?;
-            drop(buffer);
-
-            let sampler_desc = d3d11::D3D11_SAMPLER_DESC {
-                AddressU: d3d11::D3D11_TEXTURE_ADDRESS_WRAP,
-                AddressV: d3d11::D3D11_TEXTURE_ADDRESS_WRAP,
-                AddressW: d3d11::D3D11_TEXTURE_ADDRESS_WRAP,
-                Filter: d3d11::D3D11_FILTER_ANISOTROPIC,
-                MinLOD: 0.0,
-                MaxLOD: 1.0,
-                ..d3d11::D3D11_SAMPLER_DESC::default()
-            };
-
-            let sampler_state =
-                get_output(|ptr| device.as_ref().CreateSamplerState(&sampler_desc, ptr))?;
+            match (data.cube, array_size) {
+                (true, 6) => {
+                    view_desc.ViewDimension = d3dcommon::D3D11_SRV_DIMENSION_TEXTURECUBE;
+                    view_desc.u.TextureCube_mut().MipLevels = mip_levels;
+                }
+                (true, _) => {
+                    view_desc.ViewDimension = d3dcommon::D3D11_SRV_DIMENSION_TEXTURECUBEARRAY;
+                    let view = view_desc.u.TextureCubeArray_mut();
+                    view.MipLevels = mip_levels;
+                    view.NumCubes = array_size / 6;
+                }
+                (false, 1) => {
+                    view_desc.ViewDimension = d3dcommon::D3D11_SRV_DIMENSION_TEXTURE2D;
+                    view_desc.u.Texture2D_mut().MipLevels = mip_levels;
+                }
+                (false, _) => {
+                    view_desc.ViewDimension = d3dcommon::D3D11_SRV_DIMENSION_TEXTURE2DARRAY;
+                    let view = view_desc.u.Texture2DArray_mut();
+                    view.MipLevels = mip_levels;
+                    view.ArraySize = array_size;
+                }
+            }
 
             let resource_view = get_output(|ptr| {
                 device.as_ref().CreateShaderResourceView(
                     &**texture.as_ref() as *const d3d11::ID3D11Resource as *mut _,
-                    ptr::null(),
+                    &view_desc,
                     ptr,
                 )
             })?;
 
-            Ok(Arc::new(Self {
+            Ok(Self(Mutex::new(TextureInner {
                 texture,
                 sampler_state,
                 resource_view,
-            }))
+                size: data.layers.iter().flatten().map(|mip| mip.data.len()).sum(),
+            })))
         }
     }
+
+    fn inner(&self) -> MutexGuard<TextureInner> {
+        self.0.lock().unwrap()
+    }
 }
 
-impl material::Texture for Texture {
+/// Pages of packed images on the GPU, with their regions looked up by name.
+pub struct TextureAtlas {
+    pub pages: Vec<Arc<Texture>>,
+    regions: HashMap<String, AtlasRegion>,
+}
+
+impl TextureAtlas {
+    /// Uploads an atlas packed at runtime.
+    pub fn from_atlas(
+        device: &Device,
+        atlas: &Atlas,
+        options: &ProcessOptions,
+    ) -> error::Result<Self> {
+        let pages = (0..atlas.pages.len())
+            .map(|page| Texture::from_data(device, &atlas.page_data(page, options)?))
+            .collect::<error::Result<_>>()?;
+        Ok(Self::new(pages, atlas.regions.clone()))
+    }
+
+    fn new(pages: Vec<Arc<Texture>>, regions: Vec<AtlasRegion>) -> Self {
+        let regions = regions
+            .into_iter()
+            .map(|region| (region.name.clone(), region))
+            .collect();
+        Self { pages, regions }
+    }
+
+    pub fn region(&self, name: &str) -> Option<&AtlasRegion> {
+        self.regions.get(name)
+    }
+
+    /// The region and the page it is on.
+    pub fn get(&self, name: &str) -> Option<(&AtlasRegion, Arc<Texture>)> {
+        let region = self.region(name)?;
+        Some((region, self.pages.get(region.page)?.clone()))
+    }
+
+    pub fn regions(&self) -> impl Iterator<Item = &AtlasRegion> {
+        self.regions.values()
+    }
+}
+
+impl TextureManager {
+    /// Loads a saved atlas. Its pages are shared with any other use of the same files.
+    pub fn get_atlas_from_file(
+        &mut self,
+        device: &Device,
+        path: impl AsRef<Path>,
+    ) -> error::Result<TextureAtlas> {
+        let manifest = AtlasManifest::load(path)?;
+        let pages = manifest
+            .pages
+            .iter()
+            .map(|page| self.get_resource_from_file(device, &page.path))
+            .collect::<error::Result<_>>()?;
+        Ok(TextureAtlas::new(pages, manifest.regions))
+    }
+}
+
+/// A texture bound as a `TextureCube`, sampled by direction.
+/// Skyboxes and reflections can share one through any material texture slot.
+pub struct CubeMap(Texture);
+
+impl Resource for CubeMap {
+    fn load_resource_from_file(
+        device: &Device,
+        path: impl AsRef<Path>,
+    ) -> error::Result<Arc<Self>> {
+        Self::from_data(device, &TextureData::load_cube(path)?)
+    }
+
+    fn replace(&self, device: &Device, fresh: Self) -> error::Result<()> {
+        self.0.replace(device, fresh.0)
+    }
+
+    fn memory_size(&self) -> usize {
+        self.0.memory_size()
+    }
+}
+
+impl AsyncResource for CubeMap {
+    type Data = TextureData;
+
+    fn decode(path: &Path, _cache_dir: Option<&Path>) -> error::Result<TextureData> {
+        TextureData::load_cube(path)
+    }
+
+    fn upload(device: &Device, data: TextureData) -> error::Result<Self> {
+        Self::new(device, &data)
+    }
+
+    fn placeholder(device: &Device) -> error::Result<Self> {
+        Self::new(
+            device,
+            &TextureData::cube_from_faces(vec![placeholder_data(); 6])?,
+        )
+    }
+}
+
+impl CubeMap {
+    pub fn from_data(device: &Device, data: &TextureData) -> error::Result<Arc<Self>> {
+        Self::new(device, data).map(Arc::new)
+    }
+
+    pub fn new(device: &Device, data: &TextureData) -> error::Result<Self> {
+        if !data.cube {
+            return Err("Texture data is not a cube map".into());
+        }
+        Texture::new(device, data).map(Self)
+    }
+}
+
+impl material::Texture for CubeMap {
     fn sampler_state_ptr(&self) -> *mut d3d11::ID3D11SamplerState {
-        //TODO Fix Shared Mutability
-        self.sampler_state.as_ptr()
+        material::Texture::sampler_state_ptr(&self.0)
     }
 
     fn resource_view_ptr(&self) -> *mut d3d11::ID3D11ShaderResourceView {
-        //TODO Fix Shared Mutability
-        self.resource_view.as_ptr()
+        material::Texture::resource_view_ptr(&self.0)
     }
 }
 
-impl AsRef<d3d11::ID3D11Texture2D> for Texture {
-    fn as_ref(&self) -> &d3d11::ID3D11Texture2D {
-        unsafe { self.texture.as_ref() }
+/// The renderer works in gamma space, so sRGB data is sampled as is.
+/// The color space only decides how mips are filtered for now.
+fn dxgi_format(format: TextureFormat) -> dxgiformat::DXGI_FORMAT {
+    match format {
+        TextureFormat::Rgba8 => dxgiformat::DXGI_FORMAT_R8G8B8A8_UNORM,
+        TextureFormat::Bgra8 => dxgiformat::DXGI_FORMAT_B8G8R8A8_UNORM,
+        TextureFormat::Rgba16Float => dxgiformat::DXGI_FORMAT_R16G16B16A16_FLOAT,
+        TextureFormat::Bc1 => dxgiformat::DXGI_FORMAT_BC1_UNORM,
+        TextureFormat::Bc2 => dxgiformat::DXGI_FORMAT_BC2_UNORM,
+        TextureFormat::Bc3 => dxgiformat::DXGI_FORMAT_BC3_UNORM,
+        TextureFormat::Bc4 => dxgiformat::DXGI_FORMAT_BC4_UNORM,
+        TextureFormat::Bc5 => dxgiformat::DXGI_FORMAT_BC5_UNORM,
+        TextureFormat::Bc6hUfloat => dxgiformat::DXGI_FORMAT_BC6H_UF16,
+        TextureFormat::Bc6hSfloat => dxgiformat::DXGI_FORMAT_BC6H_SF16,
+        TextureFormat::Bc7 => dxgiformat::DXGI_FORMAT_BC7_UNORM,
+    }
+}
+
+fn supports_format(device: &Device, format: dxgiformat::DXGI_FORMAT) -> bool {
+    let mut support = 0;
+    let result = unsafe { device.as_ref().CheckFormatSupport(format, &mut support) };
+    result >= 0 && support & d3d11::D3D11_FORMAT_SUPPORT_TEXTURE2D != 0
+}
+
+impl material::Texture for Texture {
+    fn sampler_state_ptr(&self) -> *mut d3d11::ID3D11SamplerState {
+        //TODO Fix Shared Mutability
+        self.inner().sampler_state.as_ptr()
+    }
+
+    fn resource_view_ptr(&self) -> *mut d3d11::ID3D11ShaderResourceView {
+        //TODO Fix Shared Mutability
+        self.inner().resource_view.as_ptr()
     }
 }
 
-impl Drop for Texture {
+impl Drop for TextureInner {
     fn drop(&mut self) {
         unsafe {
             self.texture.as_ref().Release();
-            self.sampler_state.as_ref().Release();
             self.resource_view.as_ref().Release();
         }
     }
@@ -0,0 +1,670 @@
+//! Packs many small images into a few large pages, for sprites, UI and particle sheets.
+//!
+//! Images are placed with a skyline packer, bottom left first. Each image is surrounded
+//! by copies of its edge pixels so filtering near a border does not pick up its neighbours.
+
+use super::{process, ProcessOptions, TextureData};
+
+use crate::error;
+use crate::math::Rect;
+use crate::vfs;
+
+use std::cmp::Reverse;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use image::io::Reader;
+
+/// The extension of atlas manifests.
+pub const MANIFEST_EXTENSION: &str = "atlas";
+
+#[derive(Clone, Debug)]
+pub struct AtlasOptions {
+    /// Largest width and height of a page.
+    pub max_size: u32,
+    /// Empty pixels between images and around the page.
+    pub padding: u32,
+    /// Pixels of edge repeated around each image, inside the padding.
+    pub extrude: u32,
+    /// Rounds pages up to a power of two on each side.
+    pub power_of_two: bool,
+}
+
+impl Default for AtlasOptions {
+    fn default() -> Self {
+        Self {
+            max_size: 2048,
+            padding: 2,
+            extrude: 1,
+            power_of_two: true,
+        }
+    }
+}
+
+/// Tightly packed RGBA8 pixels waiting to be packed.
+#[derive(Clone, Debug)]
+pub struct AtlasImage {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+impl AtlasImage {
+    /// Loads an image, named after its file stem.
+    pub fn load(path: impl AsRef<Path>) -> error::Result<Self> {
+        let path = path.as_ref();
+        let name = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or("Atlas images need a file name")?
+            .to_owned();
+        let image = Reader::open(path)?.decode()?.to_rgba8();
+        Ok(Self {
+            name,
+            width: image.width(),
+            height: image.height(),
+            rgba: image.into_raw(),
+        })
+    }
+}
+
+/// Where an image ended up.
+#[derive(Clone, Debug)]
+pub struct AtlasRegion {
+    pub name: String,
+    pub page: usize,
+    /// Pixels of the image on its page, without extrusion.
+    pub pixels: Rect<u32>,
+    /// `pixels` divided by the size of the page.
+    pub uv: Rect<f32>,
+}
+
+impl AtlasRegion {
+    fn new(name: String, page: usize, pixels: Rect<u32>, page_size: (u32, u32)) -> Self {
+        let (width, height) = (page_size.0 as f32, page_size.1 as f32);
+        let [x, y] = &pixels.0;
+        let uv = Rect([
+            (x.start as f32 / width)..(x.end as f32 / width),
+            (y.start as f32 / height)..(y.end as f32 / height),
+        ]);
+        Self {
+            name,
+            page,
+            pixels,
+            uv,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AtlasPage {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Atlas {
+    pub pages: Vec<AtlasPage>,
+    pub regions: Vec<AtlasRegion>,
+}
+
+impl Atlas {
+    /// Packs `images` into as few pages as fit, tallest first.
+    pub fn build(images: &[AtlasImage], options: &AtlasOptions) -> error::Result<Self> {
+        let border = options
+            .extrude
+            .checked_mul(2)
+            .and_then(|extrude| extrude.checked_add(options.padding))
+            .ok_or("Atlas extrusion and padding are too large")?;
+        let inner = options
+            .max_size
+            .checked_sub(options.padding)
+            .ok_or("Atlas padding is larger than a page")?;
+
+        let mut order: Vec<_> = (0..images.len()).collect();
+        order.sort_by_key(|&idx| {
+            let image = &images[idx];
+            (Reverse(image.height), Reverse(image.width))
+        });
+
+        let mut skylines: Vec<Skyline> = Vec::new();
+        let mut placements = vec![(0, 0, 0); images.len()];
+        for idx in order {
+            let image = &images[idx];
+            if image.width == 0 || image.height == 0 {
+                return Err(error::Custom(format!("{} is empty", image.name)));
+            }
+            if rgba_len(image.width, image.height) != Some(image.rgba.len()) {
+                return Err(error::Custom(format!(
+                    "{} has {} bytes of pixels, not {}x{} RGBA8",
+                    image.name,
+                    image.rgba.len(),
+                    image.width,
+                    image.height
+                )));
+            }
+            let fits = |size: u32| size.checked_add(border).filter(|&size| size <= inner);
+            let (width, height) = match (fits(image.width), fits(image.height)) {
+                (Some(width), Some(height)) => (width, height),
+                _ => {
+                    return Err(error::Custom(format!(
+                        "{} does not fit in a {} pixel atlas page",
+                        image.name, options.max_size
+                    )))
+                }
+            };
+
+            let placed = skylines
+                .iter_mut()
+                .enumerate()
+                .find_map(|(page, skyline)| Some((page, skyline.insert(width, height)?)));
+            let (page, (x, y)) = match placed {
+                Some(placed) => placed,
+                None => {
+                    let mut skyline = Skyline::new(inner, inner);
+                    let position = skyline
+                        .insert(width, height)
+                        .ok_or("Atlas image does not fit in an empty page")?;
+                    skylines.push(skyline);
+                    (skylines.len() - 1, position)
+                }
+            };
+            placements[idx] = (page, x + options.padding, y + options.padding);
+        }
+
+        let mut pages = skylines
+            .iter()
+            .map(|skyline| -> error::Result<_> {
+                // Each image already carries the padding on its right and bottom.
+                let (mut width, mut height) = skyline.used();
+                width += options.padding;
+                height += options.padding;
+                if options.power_of_two {
+                    width = width.checked_next_power_of_two().unwrap_or(u32::MAX);
+                    height = height.checked_next_power_of_two().unwrap_or(u32::MAX);
+                }
+                let (width, height) = (width.min(options.max_size), height.min(options.max_size));
+                let len = rgba_len(width, height).ok_or("Atlas page is too large")?;
+                Ok(AtlasPage {
+                    width,
+                    height,
+                    rgba: vec![0; len],
+                })
+            })
+            .collect::<error::Result<Vec<_>>>()?;
+
+        let regions = images
+            .iter()
+            .zip(placements)
+            .map(|(image, (page, x, y))| {
+                let target = &mut pages[page];
+                blit_extruded(target, image, x, y, options.extrude);
+                let (x, y) = (x + options.extrude, y + options.extrude);
+                let pixels = Rect([x..x + image.width, y..y + image.height]);
+                AtlasRegion::new(
+                    image.name.clone(),
+                    page,
+                    pixels,
+                    (target.width, target.height),
+                )
+            })
+            .collect();
+
+        Ok(Self { pages, regions })
+    }
+
+    /// Loads and packs every image in `dir`.
+    pub fn build_dir(dir: impl AsRef<Path>, options: &AtlasOptions) -> error::Result<Self> {
+        let mut paths = fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        paths.retain(|path| path.is_file());
+        // Directory order is not stable, names keep rebuilt atlases identical.
+        paths.sort();
+
+        let images = paths
+            .iter()
+            .map(AtlasImage::load)
+            .collect::<error::Result<Vec<_>>>()?;
+        Self::build(&images, options)
+    }
+
+    /// Processes a page into a texture with mips.
+    pub fn page_data(&self, page: usize, options: &ProcessOptions) -> error::Result<TextureData> {
+        let page = self
+            .pages
+            .get(page)
+            .ok_or_else(|| error::Custom(format!("Atlas has no page {}", page)))?;
+        if rgba_len(page.width, page.height) != Some(page.rgba.len()) {
+            return Err(error::Custom(format!(
+                "Atlas page has {} bytes of pixels, not {}x{} RGBA8",
+                page.rgba.len(),
+                page.width,
+                page.height
+            )));
+        }
+        Ok(process(page.width, page.height, &page.rgba, options))
+    }
+
+    /// Writes each page as `<name>_<page>.png` and a manifest as `<name>.atlas` into `dir`.
+    pub fn save(&self, dir: impl AsRef<Path>, name: &str) -> error::Result<()> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+
+        let mut manifest = AtlasManifest {
+            pages: Vec::with_capacity(self.pages.len()),
+            regions: self.regions.clone(),
+        };
+        for (idx, page) in self.pages.iter().enumerate() {
+            let file = PathBuf::from(format!("{}_{}.png", name, idx));
+            image::save_buffer(
+                dir.join(&file),
+                &page.rgba,
+                page.width,
+                page.height,
+                image::ColorType::Rgba8,
+            )?;
+            manifest.pages.push(ManifestPage {
+                path: file,
+                width: page.width,
+                height: page.height,
+            });
+        }
+
+        let path = dir.join(name).with_extension(MANIFEST_EXTENSION);
+        fs::write(path, manifest.to_string())?;
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ManifestPage {
+    /// Relative to the manifest.
+    pub path: PathBuf,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Describes a saved atlas, one line per page or region:
+///
+/// ```text
+/// page <width> <height> <path>
+/// region <page> <x> <y> <width> <height> <name>
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct AtlasManifest {
+    pub pages: Vec<ManifestPage>,
+    pub regions: Vec<AtlasRegion>,
+}
+
+impl AtlasManifest {
+    /// Reads a manifest through the virtual file system.
+    /// Page paths are made into virtual paths next to the manifest.
+    pub fn load(path: impl AsRef<Path>) -> error::Result<Self> {
+        let path = path.as_ref();
+        let mut manifest = Self::parse(&vfs::read_to_string(path)?)?;
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        for page in &mut manifest.pages {
+            page.path = PathBuf::from(vfs::normalize(dir.join(&page.path))?);
+        }
+        Ok(manifest)
+    }
+
+    pub fn parse(text: &str) -> error::Result<Self> {
+        let mut manifest = Self::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = || error::Custom(format!("Malformed atlas manifest line {}", number + 1));
+
+            let (kind, rest) = line.split_once(' ').ok_or_else(error)?;
+            match kind {
+                "page" => {
+                    let mut fields = rest.splitn(3, ' ');
+                    let mut size = || -> Option<u32> { fields.next()?.parse().ok() };
+                    let (width, height) = (size().ok_or_else(error)?, size().ok_or_else(error)?);
+                    let path = fields.next().ok_or_else(error)?;
+                    manifest.pages.push(ManifestPage {
+                        path: path.into(),
+                        width,
+                        height,
+                    });
+                }
+                "region" => {
+                    let mut fields = rest.splitn(6, ' ');
+                    let mut values = [0u32; 5];
+                    for value in &mut values {
+                        *value = fields
+                            .next()
+                            .and_then(|field| field.parse().ok())
+                            .ok_or_else(error)?;
+                    }
+                    let name = fields.next().ok_or_else(error)?;
+                    let [page, x, y, width, height] = values;
+                    let (right, bottom) = (
+                        x.checked_add(width).ok_or_else(error)?,
+                        y.checked_add(height).ok_or_else(error)?,
+                    );
+                    let page_size = manifest
+                        .pages
+                        .get(page as usize)
+                        .map(|page| (page.width, page.height))
+                        .ok_or_else(error)?;
+                    manifest.regions.push(AtlasRegion::new(
+                        name.to_owned(),
+                        page as usize,
+                        Rect([x..right, y..bottom]),
+                        page_size,
+                    ));
+                }
+                _ => return Err(error()),
+            }
+        }
+        Ok(manifest)
+    }
+}
+
+impl std::fmt::Display for AtlasManifest {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for page in &self.pages {
+            writeln!(
+                f,
+                "page {} {} {}",
+                page.width,
+                page.height,
+                page.path.display()
+            )?;
+        }
+        for region in &self.regions {
+            writeln!(
+                f,
+                "region {} {} {} {} {} {}",
+                region.page,
+                region.pixels.left(),
+                region.pixels.top(),
+                region.pixels.width(),
+                region.pixels.height(),
+                region.name
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Segment {
+    x: u32,
+    y: u32,
+    width: u32,
+}
+
+/// The top edge of everything placed so far, as segments from left to right.
+struct Skyline {
+    width: u32,
+    height: u32,
+    segments: Vec<Segment>,
+}
+
+impl Skyline {
+    fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            segments: vec![Segment { x: 0, y: 0, width }],
+        }
+    }
+
+    /// Places a rectangle as low as possible, then as far left, and returns its corner.
+    fn insert(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        let (idx, y) = (0..self.segments.len())
+            .filter_map(|idx| Some((idx, self.fit(idx, width, height)?)))
+            .min_by_key(|&(idx, y)| (y, self.segments[idx].x))?;
+        let x = self.segments[idx].x;
+
+        self.segments.insert(
+            idx,
+            Segment {
+                x,
+                y: y + height,
+                width,
+            },
+        );
+        // Trim the segments now covered by the new one.
+        let right = x + width;
+        while let Some(next) = self.segments.get_mut(idx + 1) {
+            if next.x >= right {
+                break;
+            }
+            let covered = right - next.x;
+            if covered >= next.width {
+                self.segments.remove(idx + 1);
+            } else {
+                next.x += covered;
+                next.width -= covered;
+                break;
+            }
+        }
+        self.segments.dedup_by(|next, prev| {
+            let merge = prev.y == next.y;
+            if merge {
+                prev.width += next.width;
+            }
+            merge
+        });
+
+        Some((x, y))
+    }
+
+    /// The height a rectangle would rest at if its left edge started segment `idx`.
+    fn fit(&self, idx: usize, width: u32, height: u32) -> Option<u32> {
+        let x = self.segments[idx].x;
+        if x + width > self.width {
+            return None;
+        }
+        let mut y = 0;
+        let mut remaining = width;
+        for segment in &self.segments[idx..] {
+            if remaining == 0 {
+                break;
+            }
+            y = y.max(segment.y);
+            remaining = remaining.saturating_sub(segment.width);
+        }
+        (y + height <= self.height).then_some(y)
+    }
+
+    /// The width and height covered by rectangles.
+    fn used(&self) -> (u32, u32) {
+        self.segments.iter().filter(|segment| segment.y > 0).fold(
+            (0, 0),
+            |(width, height), segment| {
+                (width.max(segment.x + segment.width), height.max(segment.y))
+            },
+        )
+    }
+}
+
+/// Bytes of tightly packed RGBA8 pixels, if they can be addressed.
+fn rgba_len(width: u32, height: u32) -> Option<usize> {
+    (width as usize)
+        .checked_mul(height as usize)?
+        .checked_mul(4)
+}
+
+/// Copies `image` so its top left is `extrude` pixels in from `(x, y)`, repeating its
+/// edges out to `(x, y)` and as far on the other sides.
+fn blit_extruded(page: &mut AtlasPage, image: &AtlasImage, x: u32, y: u32, extrude: u32) {
+    let (width, height) = (image.width + extrude * 2, image.height + extrude * 2);
+    for row in 0..height {
+        let src_row = row.saturating_sub(extrude).min(image.height - 1);
+        for column in 0..width {
+            let src_column = column.saturating_sub(extrude).min(image.width - 1);
+            let src = ((src_row * image.width + src_column) * 4) as usize;
+            let dst = (((y + row) * page.width + x + column) * 4) as usize;
+            page.rgba[dst..dst + 4].copy_from_slice(&image.rgba[src..src + 4]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn solid(name: &str, width: u32, height: u32, value: u8) -> AtlasImage {
+        AtlasImage {
+            name: name.to_owned(),
+            width,
+            height,
+            rgba: vec![value; (width * height * 4) as usize],
+        }
+    }
+
+    fn overlaps(a: &Rect<u32>, b: &Rect<u32>) -> bool {
+        let [ax, ay] = &a.0;
+        let [bx, by] = &b.0;
+        ax.start < bx.end && bx.start < ax.end && ay.start < by.end && by.start < ay.end
+    }
+
+    #[test]
+    fn skyline_fills_bottom_left() {
+        let mut skyline = Skyline::new(10, 10);
+        assert_eq!(skyline.insert(6, 4), Some((0, 0)));
+        assert_eq!(skyline.insert(4, 2), Some((6, 0)));
+        assert_eq!(skyline.insert(4, 2), Some((6, 2)));
+        // Both columns are now 4 high and merge into one segment.
+        assert_eq!(skyline.segments.len(), 1);
+        assert_eq!(skyline.insert(10, 6), Some((0, 4)));
+        assert_eq!(skyline.insert(1, 1), None);
+        assert_eq!(skyline.used(), (10, 10));
+    }
+
+    #[test]
+    fn regions_do_not_overlap() {
+        let images: Vec<_> = (0..20)
+            .map(|i| solid(&i.to_string(), 3 + i % 7, 2 + i * 3 % 11, i as u8))
+            .collect();
+        let options = AtlasOptions::default();
+        let atlas = Atlas::build(&images, &options).unwrap();
+
+        assert_eq!(atlas.pages.len(), 1);
+        let page = &atlas.pages[0];
+        assert!(page.width.is_power_of_two() && page.height.is_power_of_two());
+
+        // Extruded cells plus padding keep every pair this far apart.
+        let gap = options.extrude * 2 + options.padding;
+        for (i, a) in atlas.regions.iter().enumerate() {
+            assert_eq!(a.name, images[i].name);
+            assert_eq!(a.pixels.dims(), (images[i].width, images[i].height));
+            assert!(a.uv.0[0].end <= 1.0 && a.uv.0[1].end <= 1.0);
+            let [x, y] = &a.pixels.0;
+            let grown = Rect([
+                x.start - gap / 2..x.end + gap / 2,
+                y.start - gap / 2..y.end + gap / 2,
+            ]);
+            for b in &atlas.regions[i + 1..] {
+                assert!(
+                    !overlaps(&grown, &b.pixels),
+                    "{} overlaps {}",
+                    a.name,
+                    b.name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn edges_are_extruded() {
+        let mut image = solid("gradient", 2, 2, 0);
+        for (i, texel) in image.rgba.chunks_exact_mut(4).enumerate() {
+            texel.copy_from_slice(&[i as u8 + 1; 4]);
+        }
+        let options = AtlasOptions {
+            padding: 1,
+            extrude: 2,
+            power_of_two: false,
+            ..AtlasOptions::default()
+        };
+        let atlas = Atlas::build(&[image], &options).unwrap();
+        let page = &atlas.pages[0];
+        assert_eq!((page.width, page.height), (8, 8));
+        assert_eq!(atlas.regions[0].pixels.left(), 3);
+
+        let texel = |x: u32, y: u32| page.rgba[((y * page.width + x) * 4) as usize];
+        let row: Vec<_> = (0..8).map(|x| texel(x, 1)).collect();
+        assert_eq!(row, [0, 1, 1, 1, 2, 2, 2, 0]);
+        let column: Vec<_> = (0..8).map(|y| texel(6, y)).collect();
+        assert_eq!(column, [0, 2, 2, 2, 4, 4, 4, 0]);
+    }
+
+    #[test]
+    fn overflows_into_pages() {
+        let images: Vec<_> = (0..5).map(|i| solid(&i.to_string(), 28, 28, 1)).collect();
+        let options = AtlasOptions {
+            max_size: 66,
+            ..AtlasOptions::default()
+        };
+        let atlas = Atlas::build(&images, &options).unwrap();
+        assert_eq!(atlas.pages.len(), 2);
+        assert_eq!(atlas.regions[4].page, 1);
+
+        let images = [solid("huge", 64, 8, 1)];
+        assert!(Atlas::build(&images, &options).is_err());
+    }
+
+    #[test]
+    fn rejects_bad_sizes() {
+        let options = AtlasOptions::default();
+        let mut short = solid("short", 4, 4, 1);
+        short.rgba.pop();
+        assert!(Atlas::build(&[short], &options).is_err());
+
+        let huge = AtlasImage {
+            name: "huge".to_owned(),
+            width: u32::MAX,
+            height: u32::MAX,
+            rgba: Vec::new(),
+        };
+        assert!(Atlas::build(&[huge], &options).is_err());
+
+        let options = AtlasOptions {
+            extrude: u32::MAX,
+            ..AtlasOptions::default()
+        };
+        assert!(Atlas::build(&[solid("a", 1, 1, 1)], &options).is_err());
+
+        let mut atlas = Atlas::build(&[solid("a", 1, 1, 1)], &AtlasOptions::default()).unwrap();
+        assert!(atlas.page_data(1, &ProcessOptions::default()).is_err());
+        atlas.pages[0].width += 1;
+        assert!(atlas.page_data(0, &ProcessOptions::default()).is_err());
+    }
+
+    #[test]
+    fn manifest_round_trip() {
+        let images = [solid("a", 4, 4, 1), solid("b c", 8, 2, 2)];
+        let atlas = Atlas::build(&images, &AtlasOptions::default()).unwrap();
+        let manifest = AtlasManifest {
+            pages: vec![ManifestPage {
+                path: "sheet_0.png".into(),
+                width: atlas.pages[0].width,
+                height: atlas.pages[0].height,
+            }],
+            regions: atlas.regions.clone(),
+        };
+
+        let parsed = AtlasManifest::parse(&manifest.to_string()).unwrap();
+        assert_eq!(parsed.pages, manifest.pages);
+        for (parsed, region) in parsed.regions.iter().zip(&atlas.regions) {
+            assert_eq!(parsed.name, region.name);
+            assert_eq!(parsed.pixels.0, region.pixels.0);
+            assert_eq!(parsed.uv.0, region.uv.0);
+        }
+
+        assert!(AtlasManifest::parse("region 0 0 0 1 1 a").is_err());
+        assert!(AtlasManifest::parse("page 4 four a.png").is_err());
+        assert!(AtlasManifest::parse("page 4 4 a.png\nregion 0 4294967295 0 1 1 a").is_err());
+    }
+}
@@ -0,0 +1,1226 @@
+//! CPU decoder for block compressed textures, for when the device cannot sample them.
+
+use super::{f32_to_half, TextureFormat};
+
+use crate::error;
+
+/// Format of the texels `decode` gives for a block compressed format: half floats for
+/// the HDR data of BC6H, RGBA8 for everything else.
+pub fn decoded_format(format: TextureFormat) -> TextureFormat {
+    match format {
+        TextureFormat::Bc6hUfloat | TextureFormat::Bc6hSfloat => TextureFormat::Rgba16Float,
+        _ => TextureFormat::Rgba8,
+    }
+}
+
+/// Decodes a block compressed surface to tightly packed texels of `decoded_format`.
+pub fn decode(
+    format: TextureFormat,
+    width: u32,
+    height: u32,
+    data: &[u8],
+) -> error::Result<Vec<u8>> {
+    match format {
+        TextureFormat::Bc1 => decode_surface(format, width, height, data, decode_bc1),
+        TextureFormat::Bc2 => decode_surface(format, width, height, data, decode_bc2),
+        TextureFormat::Bc3 => decode_surface(format, width, height, data, decode_bc3),
+        TextureFormat::Bc4 => decode_surface(format, width, height, data, decode_bc4),
+        TextureFormat::Bc5 => decode_surface(format, width, height, data, decode_bc5),
+        TextureFormat::Bc6hUfloat => decode_surface(format, width, height, data, |block| {
+            decode_bc6h(block, false)
+        }),
+        TextureFormat::Bc6hSfloat => decode_surface(format, width, height, data, |block| {
+            decode_bc6h(block, true)
+        }),
+        TextureFormat::Bc7 => decode_surface(format, width, height, data, decode_bc7),
+        TextureFormat::Rgba8 | TextureFormat::Bgra8 | TextureFormat::Rgba16Float => Err(
+            error::Custom(format!("{:?} is not block compressed", format)),
+        ),
+    }
+}
+
+/// Decodes every block of a surface, each to 16 texels of `N` bytes.
+fn decode_surface<const N: usize>(
+    format: TextureFormat,
+    width: u32,
+    height: u32,
+    data: &[u8],
+    decode_block: impl Fn(&[u8]) -> [[u8; N]; 16],
+) -> error::Result<Vec<u8>> {
+    if data.len() < format.surface_size(width, height) {
+        return Err("Block compressed data is truncated".into());
+    }
+
+    let (width, height) = (width as usize, height as usize);
+    let blocks_wide = width.div_ceil(4);
+    let mut texels = vec![0; width * height * N];
+    for (i, block) in data
+        .chunks_exact(format.unit_size())
+        .take(format.surface_size(width as u32, height as u32) / format.unit_size())
+        .enumerate()
+    {
+        let (block_x, block_y) = (i % blocks_wide * 4, i / blocks_wide * 4);
+        for (j, texel) in decode_block(block).iter().enumerate() {
+            let (x, y) = (block_x + j % 4, block_y + j / 4);
+            if x < width && y < height {
+                let offset = (y * width + x) * N;
+                texels[offset..offset + N].copy_from_slice(texel);
+            }
+        }
+    }
+    Ok(texels)
+}
+
+fn expand(value: u32, bits: u32) -> u8 {
+    let value = value << (8 - bits);
+    (value | value >> bits) as u8
+}
+
+fn rgb565(color: u16) -> [u8; 4] {
+    let color = u32::from(color);
+    [
+        expand(color >> 11, 5),
+        expand(color >> 5 & 0x3f, 6),
+        expand(color & 0x1f, 5),
+        255,
+    ]
+}
+
+/// The color half of BC1, BC2 and BC3. Only BC1 has the three color mode.
+fn decode_color(block: &[u8], allow_transparent: bool) -> [[u8; 4]; 16] {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let (e0, e1) = (rgb565(c0), rgb565(c1));
+    let mix = |a: u32, b: u32, div: u32| {
+        let mut color = [0; 4];
+        for (i, channel) in color.iter_mut().enumerate() {
+            *channel = ((u32::from(e0[i]) * a + u32::from(e1[i]) * b) / div) as u8;
+        }
+        color
+    };
+
+    let palette = if c0 > c1 || !allow_transparent {
+        [e0, e1, mix(2, 1, 3), mix(1, 2, 3)]
+    } else {
+        [e0, e1, mix(1, 1, 2), [0; 4]]
+    };
+
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    let mut texels = [[0; 4]; 16];
+    for (i, texel) in texels.iter_mut().enumerate() {
+        *texel = palette[(indices >> (2 * i) & 3) as usize];
+    }
+    texels
+}
+
+/// The interpolated single channel block of BC3, BC4 and BC5.
+fn decode_channel(block: &[u8]) -> [u8; 16] {
+    let (a0, a1) = (u32::from(block[0]), u32::from(block[1]));
+    let mut palette = [0; 8];
+    palette[0] = a0;
+    palette[1] = a1;
+    if a0 > a1 {
+        for i in 1..7 {
+            palette[i + 1] = ((7 - i as u32) * a0 + i as u32 * a1) / 7;
+        }
+    } else {
+        for i in 1..5 {
+            palette[i + 1] = ((5 - i as u32) * a0 + i as u32 * a1) / 5;
+        }
+        palette[6] = 0;
+        palette[7] = 255;
+    }
+
+    let mut bits = [0; 8];
+    bits[..6].copy_from_slice(&block[2..8]);
+    let indices = u64::from_le_bytes(bits);
+    let mut values = [0; 16];
+    for (i, value) in values.iter_mut().enumerate() {
+        *value = palette[(indices >> (3 * i) & 7) as usize] as u8;
+    }
+    values
+}
+
+fn decode_bc1(block: &[u8]) -> [[u8; 4]; 16] {
+    decode_color(block, true)
+}
+
+fn decode_bc2(block: &[u8]) -> [[u8; 4]; 16] {
+    let mut texels = decode_color(&block[8..], false);
+    let alpha = u64::from_le_bytes(block[..8].try_into().unwrap());
+    for (i, texel) in texels.iter_mut().enumerate() {
+        texel[3] = expand((alpha >> (4 * i) & 0xf) as u32, 4);
+    }
+    texels
+}
+
+fn decode_bc3(block: &[u8]) -> [[u8; 4]; 16] {
+    let mut texels = decode_color(&block[8..], false);
+    for (texel, alpha) in texels.iter_mut().zip(decode_channel(block)) {
+        texel[3] = alpha;
+    }
+    texels
+}
+
+fn decode_bc4(block: &[u8]) -> [[u8; 4]; 16] {
+    decode_channel(block).map(|red| [red, 0, 0, 255])
+}
+
+fn decode_bc5(block: &[u8]) -> [[u8; 4]; 16] {
+    let red = decode_channel(block);
+    let green = decode_channel(&block[8..]);
+    let mut texels = [[0; 4]; 16];
+    for (i, texel) in texels.iter_mut().enumerate() {
+        *texel = [red[i], green[i], 0, 255];
+    }
+    texels
+}
+
+/// Reads bits from a block, least significant first.
+struct Bits(u128, u32);
+
+impl Bits {
+    fn new(block: &[u8]) -> Self {
+        Self(u128::from_le_bytes(block[..16].try_into().unwrap()), 0)
+    }
+
+    fn read(&mut self, count: u32) -> u32 {
+        let value = (self.0 >> self.1) as u32 & ((1u64 << count) - 1) as u32;
+        self.1 += count;
+        value
+    }
+}
+
+struct Bc7Mode {
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    endpoint_pbits: bool,
+    shared_pbits: bool,
+    index_bits: u32,
+    index2_bits: u32,
+}
+
+const fn mode(
+    subsets: usize,
+    [partition_bits, rotation_bits, index_selection_bits]: [u32; 3],
+    [color_bits, alpha_bits]: [u32; 2],
+    [endpoint_pbits, shared_pbits]: [bool; 2],
+    [index_bits, index2_bits]: [u32; 2],
+) -> Bc7Mode {
+    Bc7Mode {
+        subsets,
+        partition_bits,
+        rotation_bits,
+        index_selection_bits,
+        color_bits,
+        alpha_bits,
+        endpoint_pbits,
+        shared_pbits,
+        index_bits,
+        index2_bits,
+    }
+}
+
+const BC7_MODES: [Bc7Mode; 8] = [
+    mode(3, [4, 0, 0], [4, 0], [true, false], [3, 0]),
+    mode(2, [6, 0, 0], [6, 0], [false, true], [3, 0]),
+    mode(3, [6, 0, 0], [5, 0], [false, false], [2, 0]),
+    mode(2, [6, 0, 0], [7, 0], [true, false], [2, 0]),
+    mode(1, [0, 2, 1], [5, 6], [false, false], [2, 3]),
+    mode(1, [0, 2, 0], [7, 8], [false, false], [2, 2]),
+    mode(1, [0, 0, 0], [7, 7], [true, false], [4, 0]),
+    mode(2, [6, 0, 0], [5, 5], [true, false], [2, 0]),
+];
+
+const WEIGHTS2: [u32; 4] = [0, 21, 43, 64];
+const WEIGHTS3: [u32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const WEIGHTS4: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+fn interpolate(e0: u8, e1: u8, index: u32, bits: u32) -> u8 {
+    let weight = match bits {
+        2 => WEIGHTS2[index as usize],
+        3 => WEIGHTS3[index as usize],
+        _ => WEIGHTS4[index as usize],
+    };
+    (((64 - weight) * u32::from(e0) + weight * u32::from(e1) + 32) >> 6) as u8
+}
+
+fn decode_bc7(block: &[u8]) -> [[u8; 4]; 16] {
+    let mut bits = Bits::new(block);
+    let mode_index = match (0..8).find(|_| bits.read(1) == 1) {
+        Some(mode_index) => mode_index,
+        // Reserved mode, decodes to transparent black.
+        None => return [[0; 4]; 16],
+    };
+    let mode = &BC7_MODES[mode_index];
+
+    let partition = bits.read(mode.partition_bits) as usize;
+    let rotation = bits.read(mode.rotation_bits);
+    let index_selection = bits.read(mode.index_selection_bits);
+
+    // endpoints[subset * 2 + end][channel]
+    let mut endpoints = [[0u32; 4]; 6];
+    let endpoint_count = mode.subsets * 2;
+    for channel in 0..3 {
+        for endpoint in &mut endpoints[..endpoint_count] {
+            endpoint[channel] = bits.read(mode.color_bits);
+        }
+    }
+    for endpoint in &mut endpoints[..endpoint_count] {
+        endpoint[3] = bits.read(mode.alpha_bits);
+    }
+
+    let mut pbits = [0; 6];
+    if mode.endpoint_pbits {
+        for pbit in &mut pbits[..endpoint_count] {
+            *pbit = bits.read(1);
+        }
+    } else if mode.shared_pbits {
+        for subset in 0..mode.subsets {
+            let pbit = bits.read(1);
+            pbits[subset * 2] = pbit;
+            pbits[subset * 2 + 1] = pbit;
+        }
+    }
+
+    let has_pbits = mode.endpoint_pbits || mode.shared_pbits;
+    let colors: Vec<[u8; 4]> = endpoints[..endpoint_count]
+        .iter()
+        .zip(pbits)
+        .map(|(endpoint, pbit)| {
+            let mut color = [255; 4];
+            for (channel, value) in color.iter_mut().enumerate() {
+                let channel_bits = if channel < 3 {
+                    mode.color_bits
+                } else {
+                    mode.alpha_bits
+                };
+                if channel_bits == 0 {
+                    continue;
+                }
+                *value = if has_pbits {
+                    expand(endpoint[channel] << 1 | pbit, channel_bits + 1)
+                } else {
+                    expand(endpoint[channel], channel_bits)
+                };
+            }
+            color
+        })
+        .collect();
+
+    let subset_of = |texel: usize| match mode.subsets {
+        1 => 0,
+        2 => (PARTITIONS2[partition] >> texel & 1) as usize,
+        _ => PARTITIONS3[partition][texel] as usize,
+    };
+    let is_anchor = |texel: usize| match mode.subsets {
+        1 => texel == 0,
+        2 => texel == 0 || texel == ANCHORS2[partition] as usize,
+        _ => {
+            texel == 0
+                || texel == ANCHORS3_1[partition] as usize
+                || texel == ANCHORS3_2[partition] as usize
+        }
+    };
+
+    let mut indices = [0; 16];
+    for (texel, index) in indices.iter_mut().enumerate() {
+        *index = bits.read(mode.index_bits - is_anchor(texel) as u32);
+    }
+    let mut indices2 = [0; 16];
+    if mode.index2_bits > 0 {
+        for (texel, index) in indices2.iter_mut().enumerate() {
+            *index = bits.read(mode.index2_bits - (texel == 0) as u32);
+        }
+    }
+
+    let mut texels = [[0; 4]; 16];
+    for (texel, output) in texels.iter_mut().enumerate() {
+        let subset = subset_of(texel);
+        let (e0, e1) = (colors[subset * 2], colors[subset * 2 + 1]);
+
+        let (mut color_index, mut color_bits) = (indices[texel], mode.index_bits);
+        let (mut alpha_index, mut alpha_bits) = (color_index, color_bits);
+        if mode.index2_bits > 0 {
+            (alpha_index, alpha_bits) = (indices2[texel], mode.index2_bits);
+            if index_selection == 1 {
+                std::mem::swap(&mut color_index, &mut alpha_index);
+                std::mem::swap(&mut color_bits, &mut alpha_bits);
+            }
+        }
+
+        for channel in 0..3 {
+            output[channel] = interpolate(e0[channel], e1[channel], color_index, color_bits);
+        }
+        output[3] = interpolate(e0[3], e1[3], alpha_index, alpha_bits);
+        if rotation > 0 {
+            output.swap(3, rotation as usize - 1);
+        }
+    }
+    texels
+}
+
+// Endpoint channels of BC6H, as in the format's documentation: w and x are the ends of
+// the first subset, y and z of the second.
+const RW: usize = 0;
+const GW: usize = 1;
+const BW: usize = 2;
+const RX: usize = 3;
+const GX: usize = 4;
+const BX: usize = 5;
+const RY: usize = 6;
+const GY: usize = 7;
+const BY: usize = 8;
+const RZ: usize = 9;
+const GZ: usize = 10;
+const BZ: usize = 11;
+
+struct Bc6hMode {
+    /// Whether the other endpoints are stored as deltas from the first.
+    transformed: bool,
+    partitioned: bool,
+    endpoint_bits: u32,
+    /// Per channel, the same as `endpoint_bits` when not transformed.
+    delta_bits: [u32; 3],
+    /// Bits of the endpoints in the order they are stored, as
+    /// (endpoint channel, first bit, bit count).
+    fields: &'static [(usize, u32, u32)],
+}
+
+/// Mode of a BC6H block by its 5 bit mode number. The modes with a 2 bit number are at
+/// 0 and 1, and reserved numbers are `None`.
+const BC6H_MODES: [Option<Bc6hMode>; 32] = {
+    let mut modes = [const { None }; 32];
+    modes[0b00000] = Some(Bc6hMode {
+        transformed: true,
+        partitioned: true,
+        endpoint_bits: 10,
+        delta_bits: [5, 5, 5],
+        fields: &[
+            (GY, 4, 1),
+            (BY, 4, 1),
+            (BZ, 4, 1),
+            (RW, 0, 10),
+            (GW, 0, 10),
+            (BW, 0, 10),
+            (RX, 0, 5),
+            (GZ, 4, 1),
+            (GY, 0, 4),
+            (GX, 0, 5),
+            (BZ, 0, 1),
+            (GZ, 0, 4),
+            (BX, 0, 5),
+            (BZ, 1, 1),
+            (BY, 0, 4),
+            (RY, 0, 5),
+            (BZ, 2, 1),
+            (RZ, 0, 5),
+            (BZ, 3, 1),
+        ],
+    });
+    modes[0b00001] = Some(Bc6hMode {
+        transformed: true,
+        partitioned: true,
+        endpoint_bits: 7,
+        delta_bits: [6, 6, 6],
+        fields: &[
+            (GY, 5, 1),
+            (GZ, 4, 1),
+            (GZ, 5, 1),
+            (RW, 0, 7),
+            (BZ, 0, 1),
+            (BZ, 1, 1),
+            (BY, 4, 1),
+            (GW, 0, 7),
+            (BY, 5, 1),
+            (BZ, 2, 1),
+            (GY, 4, 1),
+            (BW, 0, 7),
+            (BZ, 3, 1),
+            (BZ, 5, 1),
+            (BZ, 4, 1),
+            (RX, 0, 6),
+            (GY, 0, 4),
+            (GX, 0, 6),
+            (GZ, 0, 4),
+            (BX, 0, 6),
+            (BY, 0, 4),
+            (RY, 0, 6),
+            (RZ, 0, 6),
+        ],
+    });
+    modes[0b00010] = Some(Bc6hMode {
+        transformed: true,
+        partitioned: true,
+        endpoint_bits: 11,
+        delta_bits: [5, 4, 4],
+        fields: &[
+            (RW, 0, 10),
+            (GW, 0, 10),
+            (BW, 0, 10),
+            (RX, 0, 5),
+            (RW, 10, 1),
+            (GY, 0, 4),
+            (GX, 0, 4),
+            (GW, 10, 1),
+            (BZ, 0, 1),
+            (GZ, 0, 4),
+            (BX, 0, 4),
+            (BW, 10, 1),
+            (BZ, 1, 1),
+            (BY, 0, 4),
+            (RY, 0, 5),
+            (BZ, 2, 1),
+            (RZ, 0, 5),
+            (BZ, 3, 1),
+        ],
+    });
+    modes[0b00110] = Some(Bc6hMode {
+        transformed: true,
+        partitioned: true,
+        endpoint_bits: 11,
+        delta_bits: [4, 5, 4],
+        fields: &[
+            (RW, 0, 10),
+            (GW, 0, 10),
+            (BW, 0, 10),
+            (RX, 0, 4),
+            (RW, 10, 1),
+            (GZ, 4, 1),
+            (GY, 0, 4),
+            (GX, 0, 5),
+            (GW, 10, 1),
+            (GZ, 0, 4),
+            (BX, 0, 4),
+            (BW, 10, 1),
+            (BZ, 1, 1),
+            (BY, 0, 4),
+            (RY, 0, 4),
+            (BZ, 0, 1),
+            (BZ, 2, 1),
+            (RZ, 0, 4),
+            (GY, 4, 1),
+            (BZ, 3, 1),
+        ],
+    });
+    modes[0b01010] = Some(Bc6hMode {
+        transformed: true,
+        partitioned: true,
+        endpoint_bits: 11,
+        delta_bits: [4, 4, 5],
+        fields: &[
+            (RW, 0, 10),
+            (GW, 0, 10),
+            (BW, 0, 10),
+            (RX, 0, 4),
+            (RW, 10, 1),
+            (BY, 4, 1),
+            (GY, 0, 4),
+            (GX, 0, 4),
+            (GW, 10, 1),
+            (BZ, 0, 1),
+            (GZ, 0, 4),
+            (BX, 0, 5),
+            (BW, 10, 1),
+            (BY, 0, 4),
+            (RY, 0, 4),
+            (BZ, 1, 1),
+            (BZ, 2, 1),
+            (RZ, 0, 4),
+            (BZ, 4, 1),
+            (BZ, 3, 1),
+        ],
+    });
+    modes[0b01110] = Some(Bc6hMode {
+        transformed: true,
+        partitioned: true,
+        endpoint_bits: 9,
+        delta_bits: [5, 5, 5],
+        fields: &[
+            (RW, 0, 9),
+            (BY, 4, 1),
+            (GW, 0, 9),
+            (GY, 4, 1),
+            (BW, 0, 9),
+            (BZ, 4, 1),
+            (RX, 0, 5),
+            (GZ, 4, 1),
+            (GY, 0, 4),
+            (GX, 0, 5),
+            (BZ, 0, 1),
+            (GZ, 0, 4),
+            (BX, 0, 5),
+            (BZ, 1, 1),
+            (BY, 0, 4),
+            (RY, 0, 5),
+            (BZ, 2, 1),
+            (RZ, 0, 5),
+            (BZ, 3, 1),
+        ],
+    });
+    modes[0b10010] = Some(Bc6hMode {
+        transformed: true,
+        partitioned: true,
+        endpoint_bits: 8,
+        delta_bits: [6, 5, 5],
+        fields: &[
+            (RW, 0, 8),
+            (GZ, 4, 1),
+            (BY, 4, 1),
+            (GW, 0, 8),
+            (BZ, 2, 1),
+            (GY, 4, 1),
+            (BW, 0, 8),
+            (BZ, 3, 1),
+            (BZ, 4, 1),
+            (RX, 0, 6),
+            (GY, 0, 4),
+            (GX, 0, 5),
+            (BZ, 0, 1),
+            (GZ, 0, 4),
+            (BX, 0, 5),
+            (BZ, 1, 1),
+            (BY, 0, 4),
+            (RY, 0, 6),
+            (RZ, 0, 6),
+        ],
+    });
+    modes[0b10110] = Some(Bc6hMode {
+        transformed: true,
+        partitioned: true,
+        endpoint_bits: 8,
+        delta_bits: [5, 6, 5],
+        fields: &[
+            (RW, 0, 8),
+            (BZ, 0, 1),
+            (BY, 4, 1),
+            (GW, 0, 8),
+            (GY, 5, 1),
+            (GY, 4, 1),
+            (BW, 0, 8),
+            (GZ, 5, 1),
+            (BZ, 4, 1),
+            (RX, 0, 5),
+            (GZ, 4, 1),
+            (GY, 0, 4),
+            (GX, 0, 6),
+            (GZ, 0, 4),
+            (BX, 0, 5),
+            (BZ, 1, 1),
+            (BY, 0, 4),
+            (RY, 0, 5),
+            (BZ, 2, 1),
+            (RZ, 0, 5),
+            (BZ, 3, 1),
+        ],
+    });
+    modes[0b11010] = Some(Bc6hMode {
+        transformed: true,
+        partitioned: true,
+        endpoint_bits: 8,
+        delta_bits: [5, 5, 6],
+        fields: &[
+            (RW, 0, 8),
+            (BZ, 1, 1),
+            (BY, 4, 1),
+            (GW, 0, 8),
+            (BY, 5, 1),
+            (GY, 4, 1),
+            (BW, 0, 8),
+            (BZ, 5, 1),
+            (BZ, 4, 1),
+            (RX, 0, 5),
+            (GZ, 4, 1),
+            (GY, 0, 4),
+            (GX, 0, 5),
+            (BZ, 0, 1),
+            (GZ, 0, 4),
+            (BX, 0, 6),
+            (BY, 0, 4),
+            (RY, 0, 5),
+            (BZ, 2, 1),
+            (RZ, 0, 5),
+            (BZ, 3, 1),
+        ],
+    });
+    modes[0b11110] = Some(Bc6hMode {
+        transformed: false,
+        partitioned: true,
+        endpoint_bits: 6,
+        delta_bits: [6, 6, 6],
+        fields: &[
+            (RW, 0, 6),
+            (GZ, 4, 1),
+            (BZ, 0, 1),
+            (BZ, 1, 1),
+            (BY, 4, 1),
+            (GW, 0, 6),
+            (GY, 5, 1),
+            (BY, 5, 1),
+            (BZ, 2, 1),
+            (GY, 4, 1),
+            (BW, 0, 6),
+            (GZ, 5, 1),
+            (BZ, 3, 1),
+            (BZ, 5, 1),
+            (BZ, 4, 1),
+            (RX, 0, 6),
+            (GY, 0, 4),
+            (GX, 0, 6),
+            (GZ, 0, 4),
+            (BX, 0, 6),
+            (BY, 0, 4),
+            (RY, 0, 6),
+            (RZ, 0, 6),
+        ],
+    });
+    modes[0b00011] = Some(Bc6hMode {
+        transformed: false,
+        partitioned: false,
+        endpoint_bits: 10,
+        delta_bits: [10, 10, 10],
+        fields: &[
+            (RW, 0, 10),
+            (GW, 0, 10),
+            (BW, 0, 10),
+            (RX, 0, 10),
+            (GX, 0, 10),
+            (BX, 0, 10),
+        ],
+    });
+    modes[0b00111] = Some(Bc6hMode {
+        transformed: true,
+        partitioned: false,
+        endpoint_bits: 11,
+        delta_bits: [9, 9, 9],
+        fields: &[
+            (RW, 0, 10),
+            (GW, 0, 10),
+            (BW, 0, 10),
+            (RX, 0, 9),
+            (RW, 10, 1),
+            (GX, 0, 9),
+            (GW, 10, 1),
+            (BX, 0, 9),
+            (BW, 10, 1),
+        ],
+    });
+    // The high bits of the first endpoint are stored reversed in the last two modes.
+    modes[0b01011] = Some(Bc6hMode {
+        transformed: true,
+        partitioned: false,
+        endpoint_bits: 12,
+        delta_bits: [8, 8, 8],
+        fields: &[
+            (RW, 0, 10),
+            (GW, 0, 10),
+            (BW, 0, 10),
+            (RX, 0, 8),
+            (RW, 11, 1),
+            (RW, 10, 1),
+            (GX, 0, 8),
+            (GW, 11, 1),
+            (GW, 10, 1),
+            (BX, 0, 8),
+            (BW, 11, 1),
+            (BW, 10, 1),
+        ],
+    });
+    modes[0b01111] = Some(Bc6hMode {
+        transformed: true,
+        partitioned: false,
+        endpoint_bits: 16,
+        delta_bits: [4, 4, 4],
+        fields: &[
+            (RW, 0, 10),
+            (GW, 0, 10),
+            (BW, 0, 10),
+            (RX, 0, 4),
+            (RW, 15, 1),
+            (RW, 14, 1),
+            (RW, 13, 1),
+            (RW, 12, 1),
+            (RW, 11, 1),
+            (RW, 10, 1),
+            (GX, 0, 4),
+            (GW, 15, 1),
+            (GW, 14, 1),
+            (GW, 13, 1),
+            (GW, 12, 1),
+            (GW, 11, 1),
+            (GW, 10, 1),
+            (BX, 0, 4),
+            (BW, 15, 1),
+            (BW, 14, 1),
+            (BW, 13, 1),
+            (BW, 12, 1),
+            (BW, 11, 1),
+            (BW, 10, 1),
+        ],
+    });
+    modes
+};
+
+fn sign_extend(value: i32, bits: u32) -> i32 {
+    let shift = 32 - bits;
+    value << shift >> shift
+}
+
+/// Scales a quantized endpoint channel up to 16 bits, or 15 and a sign.
+fn unquantize(value: i32, bits: u32, signed: bool) -> i32 {
+    if !signed {
+        if bits >= 15 || value == 0 {
+            value
+        } else if value == (1 << bits) - 1 {
+            0xffff
+        } else {
+            ((value << 16) + 0x8000) >> bits
+        }
+    } else if bits >= 16 || value == 0 {
+        value
+    } else {
+        let magnitude = value.abs();
+        let magnitude = if magnitude >= (1 << (bits - 1)) - 1 {
+            0x7fff
+        } else {
+            ((magnitude << 15) + 0x4000) >> (bits - 1)
+        };
+        magnitude * value.signum()
+    }
+}
+
+/// Scales an interpolated channel to the bits of a half float.
+fn finish_unquantize(value: i32, signed: bool) -> u16 {
+    if !signed {
+        ((value * 31) >> 6) as u16
+    } else {
+        let magnitude = ((value.abs() * 31) >> 5) as u16;
+        if value < 0 {
+            0x8000 | magnitude
+        } else {
+            magnitude
+        }
+    }
+}
+
+/// Decodes to half floats, with opaque alpha.
+fn decode_bc6h(block: &[u8], signed: bool) -> [[u8; 8]; 16] {
+    let mut bits = Bits::new(block);
+    let mut mode_index = bits.read(2) as usize;
+    if mode_index > 1 {
+        mode_index |= (bits.read(3) as usize) << 2;
+    }
+    let mode = match &BC6H_MODES[mode_index] {
+        Some(mode) => mode,
+        // Reserved mode, decodes to black.
+        None => return [[0; 8]; 16],
+    };
+
+    let mut endpoints = [0i32; 12];
+    for &(channel, first_bit, count) in mode.fields {
+        endpoints[channel] |= (bits.read(count) as i32) << first_bit;
+    }
+    let partition = if mode.partitioned {
+        bits.read(5) as usize
+    } else {
+        0
+    };
+
+    let endpoint_count = if mode.partitioned { 12 } else { 6 };
+    if signed {
+        for endpoint in &mut endpoints[..3] {
+            *endpoint = sign_extend(*endpoint, mode.endpoint_bits);
+        }
+    }
+    if mode.transformed || signed {
+        for (i, endpoint) in endpoints[3..endpoint_count].iter_mut().enumerate() {
+            *endpoint = sign_extend(*endpoint, mode.delta_bits[i % 3]);
+        }
+    }
+    if mode.transformed {
+        let mask = (1 << mode.endpoint_bits) - 1;
+        for i in 3..endpoint_count {
+            endpoints[i] = (endpoints[i] + endpoints[i % 3]) & mask;
+            if signed {
+                endpoints[i] = sign_extend(endpoints[i], mode.endpoint_bits);
+            }
+        }
+    }
+    for endpoint in &mut endpoints[..endpoint_count] {
+        *endpoint = unquantize(*endpoint, mode.endpoint_bits, signed);
+    }
+
+    let index_bits = if mode.partitioned { 3 } else { 4 };
+    let mut texels = [[0; 8]; 16];
+    for (texel, output) in texels.iter_mut().enumerate() {
+        let (subset, anchor) = if mode.partitioned {
+            let subset = (PARTITIONS2[partition] >> texel & 1) as usize;
+            let anchor = texel == 0 || texel == ANCHORS2[partition] as usize;
+            (subset, anchor)
+        } else {
+            (0, texel == 0)
+        };
+        let index = bits.read(index_bits - anchor as u32) as usize;
+        let weights: &[u32] = if mode.partitioned {
+            &WEIGHTS3
+        } else {
+            &WEIGHTS4
+        };
+        let weight = weights[index] as i32;
+
+        let ends = &endpoints[subset * 6..subset * 6 + 6];
+        for channel in 0..3 {
+            let value = ((64 - weight) * ends[channel] + weight * ends[channel + 3] + 32) >> 6;
+            let half = finish_unquantize(value, signed);
+            output[channel * 2..channel * 2 + 2].copy_from_slice(&half.to_le_bytes());
+        }
+        output[6..].copy_from_slice(&f32_to_half(1.0).to_le_bytes());
+    }
+    texels
+}
+
+/// Subset of each texel for two subset partitions, one bit per texel.
+const PARTITIONS2: [u16; 64] = [
+    0xcccc, 0x8888, 0xeeee, 0xecc8, 0xc880, 0xfeec, 0xfec8, 0xec80, 0xc800, 0xffec, 0xfe80, 0xe800,
+    0xffe8, 0xff00, 0xfff0, 0xf000, 0xf710, 0x008e, 0x7100, 0x08ce, 0x008c, 0x7310, 0x3100, 0x8cce,
+    0x088c, 0x3110, 0x6666, 0x366c, 0x17e8, 0x0ff0, 0x718e, 0x399c, 0xaaaa, 0xf0f0, 0x5a5a, 0x33cc,
+    0x3c3c, 0x55aa, 0x9696, 0xa55a, 0x73ce, 0x13c8, 0x324c, 0x3bdc, 0x6996, 0xc33c, 0x9966, 0x0660,
+    0x0272, 0x04e4, 0x4e40, 0x2720, 0xc936, 0x936c, 0x39c6, 0x639c, 0x9336, 0x9cc6, 0x817e, 0xe718,
+    0xccf0, 0x0fcc, 0x7744, 0xee22,
+];
+
+/// Subset of each texel for three subset partitions.
+const PARTITIONS3: [[u8; 16]; 64] = [
+    [0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 1, 2, 2, 2, 2],
+    [0, 0, 0, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 2, 0, 0, 1, 2, 2, 1, 1, 2, 2, 1, 1],
+    [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 1, 0, 1, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2],
+    [0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 2, 2],
+    [0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1],
+    [0, 0, 1, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2],
+    [0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2],
+    [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2],
+    [0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2],
+    [0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2],
+    [0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2, 1, 2, 2, 2],
+    [0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0, 2, 2, 2, 0],
+    [0, 0, 0, 1, 0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2],
+    [0, 1, 1, 1, 0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0],
+    [0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2],
+    [0, 0, 2, 2, 0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1],
+    [0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2, 0, 2, 2, 2],
+    [0, 0, 0, 1, 0, 0, 0, 1, 2, 2, 2, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2],
+    [0, 0, 0, 0, 1, 1, 0, 0, 2, 2, 1, 0, 2, 2, 1, 0],
+    [0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1, 0, 0, 0, 0],
+    [0, 0, 1, 2, 0, 0, 1, 2, 1, 1, 2, 2, 2, 2, 2, 2],
+    [0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1, 0, 1, 1, 0],
+    [0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1],
+    [0, 0, 2, 2, 1, 1, 0, 2, 1, 1, 0, 2, 0, 0, 2, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 2, 0, 0, 2, 2, 2, 2, 2],
+    [0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1],
+    [0, 0, 0, 0, 2, 0, 0, 0, 2, 2, 1, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 2, 2, 2],
+    [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 2, 0, 0, 1, 1],
+    [0, 0, 1, 1, 0, 0, 1, 2, 0, 0, 2, 2, 0, 2, 2, 2],
+    [0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0],
+    [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0],
+    [0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0],
+    [0, 1, 2, 0, 2, 0, 1, 2, 1, 2, 0, 1, 0, 1, 2, 0],
+    [0, 0, 1, 1, 2, 2, 0, 0, 1, 1, 2, 2, 0, 0, 1, 1],
+    [0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0, 1, 1],
+    [0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1],
+    [0, 0, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2, 1, 1, 2, 2],
+    [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 1, 1],
+    [0, 2, 2, 0, 1, 2, 2, 1, 0, 2, 2, 0, 1, 2, 2, 1],
+    [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 0, 1, 0, 1],
+    [0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1],
+    [0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2],
+    [0, 2, 2, 2, 0, 1, 1, 1, 0, 2, 2, 2, 0, 1, 1, 1],
+    [0, 0, 0, 2, 1, 1, 1, 2, 0, 0, 0, 2, 1, 1, 1, 2],
+    [0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2],
+    [0, 2, 2, 2, 0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2],
+    [0, 0, 0, 2, 1, 1, 1, 2, 1, 1, 1, 2, 0, 0, 0, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2],
+    [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2],
+    [0, 0, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2],
+    [0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 1],
+    [0, 2, 2, 2, 1, 2, 2, 2, 0, 2, 2, 2, 1, 2, 2, 2],
+    [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 1, 1, 1, 2, 0, 1, 1, 2, 2, 0, 1, 2, 2, 2, 0],
+];
+
+/// Anchor texel of the second subset in two subset partitions.
+const ANCHORS2: [u8; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 2, 8, 2, 2, 8, 8, 15, 2, 8,
+    2, 2, 8, 8, 2, 2, 15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6, 6, 2, 6, 8, 15, 15, 2,
+    2, 15, 15, 15, 15, 15, 2, 2, 15,
+];
+
+/// Anchor texel of the second subset in three subset partitions.
+const ANCHORS3_1: [u8; 64] = [
+    3, 3, 15, 15, 8, 3, 15, 15, 8, 8, 6, 6, 6, 5, 3, 3, 3, 3, 8, 15, 3, 3, 6, 10, 5, 8, 8, 6, 8, 5,
+    15, 15, 8, 15, 3, 5, 6, 10, 8, 15, 15, 3, 15, 5, 15, 15, 15, 15, 3, 15, 5, 5, 5, 8, 5, 10, 5,
+    10, 8, 13, 15, 12, 3, 3,
+];
+
+/// Anchor texel of the third subset in three subset partitions.
+const ANCHORS3_2: [u8; 64] = [
+    15, 8, 8, 3, 15, 15, 3, 8, 15, 15, 15, 15, 15, 15, 15, 8, 15, 8, 15, 3, 15, 8, 15, 8, 3, 15, 6,
+    10, 15, 15, 10, 8, 15, 3, 15, 10, 10, 8, 9, 10, 6, 15, 8, 15, 3, 6, 6, 8, 15, 3, 15, 15, 15,
+    15, 15, 15, 15, 15, 15, 15, 3, 15, 15, 8,
+];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::graphics::resource::texture::half_to_f32;
+
+    /// Packs values into a block, least significant bit first.
+    struct BitWriter(u128, u32);
+
+    impl BitWriter {
+        fn write(&mut self, value: u32, count: u32) -> &mut Self {
+            self.0 |= u128::from(value) << self.1;
+            self.1 += count;
+            self
+        }
+
+        fn block(&self) -> [u8; 16] {
+            assert!(self.1 <= 128);
+            self.0.to_le_bytes()
+        }
+    }
+
+    #[test]
+    fn partition_tables_are_consistent() {
+        for partition in 0..64 {
+            let mask = PARTITIONS2[partition];
+            assert_eq!(mask & 1, 0, "texel 0 is the anchor of subset 0");
+            assert_eq!(
+                mask >> ANCHORS2[partition] & 1,
+                1,
+                "partition {}",
+                partition
+            );
+
+            let subsets = PARTITIONS3[partition];
+            assert_eq!(subsets[0], 0);
+            assert_eq!(
+                subsets[ANCHORS3_1[partition] as usize], 1,
+                "partition {}",
+                partition
+            );
+            assert_eq!(
+                subsets[ANCHORS3_2[partition] as usize], 2,
+                "partition {}",
+                partition
+            );
+        }
+    }
+
+    #[test]
+    fn bc1_modes() {
+        // Red and blue endpoints, every texel using the first third.
+        let mut block = [0x00, 0xf8, 0x1f, 0x00, 0, 0, 0, 0];
+        block[4..].copy_from_slice(&0xaaaa_aaaau32.to_le_bytes());
+        assert_eq!(decode_bc1(&block)[0], [170, 0, 85, 255]);
+
+        // Swapped endpoints select the three color mode, index 3 is transparent.
+        let mut block = [0x1f, 0x00, 0x00, 0xf8, 0, 0, 0, 0];
+        block[4..].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(decode_bc1(&block)[5], [0; 4]);
+        block[4..].copy_from_slice(&0xaaaa_aaaau32.to_le_bytes());
+        assert_eq!(decode_bc1(&block)[5], [127, 0, 127, 255]);
+    }
+
+    #[test]
+    fn bc3_alpha_and_bc5() {
+        // Eight value mode: index 1 is the second endpoint, index 2 six sevenths of the first.
+        let mut channel = [255, 0, 0, 0, 0, 0, 0, 0];
+        channel[2] = 0b0001_0001;
+        let values = decode_channel(&channel);
+        assert_eq!(values[0], 0);
+        assert_eq!(values[1], 218);
+        assert_eq!(values[2], 255);
+
+        // Six value mode has explicit 0 and 255.
+        let channel = [10, 20, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff];
+        let values = decode_channel(&channel);
+        assert_eq!((values[0], values[1]), (255, 255));
+        let channel = [10, 20, 0b0011_1110, 0, 0, 0, 0, 0];
+        assert_eq!(decode_channel(&channel)[..2], [0, 255]);
+
+        let mut block = [0; 16];
+        block[0] = 100;
+        block[8] = 200;
+        block[9] = 200;
+        assert_eq!(decode_bc5(&block)[7], [100, 200, 0, 255]);
+    }
+
+    #[test]
+    fn bc7_mode6() {
+        let mut writer = BitWriter(0, 0);
+        writer.write(1 << 6, 7);
+        // Endpoints: red 0 to 127, green 64 both, blue 127 to 0, alpha opaque.
+        writer.write(0, 7).write(127, 7);
+        writer.write(64, 7).write(64, 7);
+        writer.write(127, 7).write(0, 7);
+        writer.write(127, 7).write(127, 7);
+        // P-bits
+        writer.write(0, 1).write(1, 1);
+        // Anchor has 3 bits, the rest 4.
+        writer.write(0, 3);
+        for texel in 1..16 {
+            writer.write(texel, 4);
+        }
+        let texels = decode_bc7(&writer.block());
+
+        assert_eq!(texels[0], [0, 128, 254, 254]);
+        assert_eq!(texels[15], [255, 129, 1, 255]);
+        let red: Vec<_> = texels.iter().map(|t| t[0]).collect();
+        assert!(red.windows(2).all(|w| w[0] <= w[1]));
+    }
+
+    #[test]
+    fn bc7_mode1_partition() {
+        let partition = 13; // Top half subset 0, bottom half subset 1
+        let mut writer = BitWriter(0, 0);
+        writer.write(1 << 1, 2).write(partition, 6);
+        // Subset 0 is black, subset 1 white.
+        for _ in 0..3 {
+            writer.write(0, 6).write(0, 6).write(63, 6).write(63, 6);
+        }
+        writer.write(0, 1).write(1, 1);
+        for texel in 0..16 {
+            let anchor = texel == 0 || texel == ANCHORS2[partition as usize];
+            writer.write(0, if anchor { 2 } else { 3 });
+        }
+        let texels = decode_bc7(&writer.block());
+
+        assert!(texels[..8].iter().all(|t| *t == [0, 0, 0, 255]));
+        assert!(texels[8..].iter().all(|t| *t == [255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn bc7_mode5_rotation() {
+        let mut writer = BitWriter(0, 0);
+        writer.write(1 << 5, 6);
+        // Rotation 1 swaps red and alpha.
+        writer.write(1, 2);
+        writer.write(127, 7).write(127, 7);
+        writer.write(0, 7).write(0, 7);
+        writer.write(0, 7).write(0, 7);
+        writer.write(10, 8).write(10, 8);
+        let texels = decode_bc7(&writer.block());
+        assert_eq!(texels[3], [10, 0, 0, 255]);
+    }
+
+    #[test]
+    fn decode_surface_crops_blocks() {
+        let block = [0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0];
+        let data = block.repeat(4);
+        let rgba = decode(TextureFormat::Bc1, 5, 6, &data).unwrap();
+        assert_eq!(rgba.len(), 5 * 6 * 4);
+        assert!(rgba.iter().all(|&v| v == 255));
+        assert!(decode(TextureFormat::Rgba8, 4, 4, &[0; 64]).is_err());
+        assert!(decode(TextureFormat::Bc1, 8, 8, &data[..8]).is_err());
+
+        let halves = decode(TextureFormat::Bc6hUfloat, 6, 4, &[0; 32]).unwrap();
+        assert_eq!(halves.len(), 6 * 4 * 8);
+        assert_eq!(
+            decoded_format(TextureFormat::Bc6hSfloat),
+            TextureFormat::Rgba16Float
+        );
+    }
+
+    fn halves(texel: [u8; 8]) -> [u16; 4] {
+        [0, 1, 2, 3].map(|i| u16::from_le_bytes([texel[i * 2], texel[i * 2 + 1]]))
+    }
+
+    /// One subset with its anchor at index 0 and every other texel at `index`.
+    fn write_single_indices(writer: &mut BitWriter, index: u32) {
+        writer.write(0, 3);
+        for _ in 1..16 {
+            writer.write(index, 4);
+        }
+    }
+
+    #[test]
+    fn bc6h_mode11_endpoints() {
+        let mut writer = BitWriter(0, 0);
+        writer.write(0b00011, 5);
+        // Red 0 to max, green max to 0, blue halfway for both.
+        writer.write(0, 10).write(1023, 10).write(512, 10);
+        writer.write(1023, 10).write(0, 10).write(512, 10);
+        write_single_indices(&mut writer, 15);
+        let texels = decode_bc6h(&writer.block(), false);
+
+        assert_eq!(halves(texels[0]), [0, 0x7bff, 15887, 0x3c00]);
+        assert_eq!(halves(texels[15]), [0x7bff, 0, 15887, 0x3c00]);
+        assert_eq!(half_to_f32(0x7bff), 65504.0);
+
+        // The same bits are negative when signed.
+        let mut writer = BitWriter(0, 0);
+        writer.write(0b00011, 5);
+        writer.write(0x200, 10).write(0, 50);
+        write_single_indices(&mut writer, 0);
+        let texels = decode_bc6h(&writer.block(), true);
+        assert_eq!(halves(texels[3])[0], 0xfbff);
+        assert_eq!(half_to_f32(0xfbff), -65504.0);
+    }
+
+    #[test]
+    fn bc6h_mode14_deltas() {
+        let mut writer = BitWriter(0, 0);
+        writer.write(0b01111, 5);
+        writer.write(1000, 10).write(0, 10).write(0, 10);
+        // Red delta of -1, then the high red bits reversed.
+        writer.write(0b1111, 4).write(0, 6);
+        // No green delta, and only the top green bit, which comes first.
+        writer.write(0, 4).write(1, 1).write(0, 5);
+        writer.write(0, 4).write(0, 6);
+        write_single_indices(&mut writer, 15);
+        let texels = decode_bc6h(&writer.block(), false);
+
+        assert_eq!(halves(texels[0])[..3], [484, 15872, 0]);
+        assert_eq!(halves(texels[15])[..3], [483, 15872, 0]);
+    }
+
+    #[test]
+    fn bc6h_mode1_partition() {
+        let partition = 13; // Top half subset 0, bottom half subset 1
+        let mut writer = BitWriter(0, 0);
+        writer.write(0, 2).write(0, 3);
+        writer.write(512, 10).write(512, 10).write(512, 10);
+        // rx, gz4, gy, gx, bz0, gz, bx, bz1, by
+        writer.write(0, 5 + 1 + 4 + 5 + 1 + 4 + 5 + 1 + 4);
+        // Second subset is redder by 15 for both ends.
+        writer.write(15, 5).write(0, 1).write(15, 5).write(0, 1);
+        writer.write(partition, 5);
+        let texels = decode_bc6h(&writer.block(), false);
+
+        assert!(texels[..8].iter().all(|t| halves(*t)[..3] == [15887; 3]));
+        assert!(texels[8..]
+            .iter()
+            .all(|t| halves(*t)[..3] == [16352, 15887, 15887]));
+    }
+
+    #[test]
+    fn bc6h_reserved_mode() {
+        let mut writer = BitWriter(0, 0);
+        writer.write(0b10011, 5).write(u32::MAX, 32);
+        assert_eq!(decode_bc6h(&writer.block(), false), [[0; 8]; 16]);
+    }
+}
@@ -0,0 +1,465 @@
+//! Cube maps on the CPU: building them from single images and sampling them by direction.
+//!
+//! Faces are ordered +X, -X, +Y, -Y, +Z, -Z and addressed like D3D cube textures,
+//! so `face_direction` matches what `TextureCube.Sample` does in a shader.
+
+use super::{
+    data, linear_to_srgb, process, srgb_to_linear, ColorSpace, ProcessOptions, TextureData,
+    TextureFormat,
+};
+
+use crate::error;
+use crate::math::Vector3d;
+
+use std::f32::consts::PI;
+use std::path::Path;
+
+/// How six faces are arranged in a single image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CubeLayout {
+    /// Four faces wide, three high, with -X, +Z, +X, -Z across the middle.
+    HorizontalCross,
+    /// Three faces wide, four high, with -Z upside down at the bottom.
+    VerticalCross,
+    /// Longitude across, latitude down, twice as wide as it is high.
+    Equirectangular,
+}
+
+impl CubeLayout {
+    /// Guesses the layout from the aspect ratio of an image.
+    pub fn detect(width: u32, height: u32) -> Option<Self> {
+        if width * 3 == height * 4 {
+            Some(Self::HorizontalCross)
+        } else if width * 4 == height * 3 {
+            Some(Self::VerticalCross)
+        } else if width == height * 2 {
+            Some(Self::Equirectangular)
+        } else {
+            None
+        }
+    }
+}
+
+/// Direction through a point on a face, with `s` and `t` from 0 to 1 and `t` going down.
+pub fn face_direction(face: usize, s: f32, t: f32) -> Vector3d {
+    let (sc, tc) = (s * 2.0 - 1.0, t * 2.0 - 1.0);
+    let direction = match face {
+        0 => Vector3d::new(1.0, -tc, -sc),
+        1 => Vector3d::new(-1.0, -tc, sc),
+        2 => Vector3d::new(sc, 1.0, tc),
+        3 => Vector3d::new(sc, -1.0, -tc),
+        4 => Vector3d::new(sc, -tc, 1.0),
+        _ => Vector3d::new(-sc, -tc, -1.0),
+    };
+    direction.normalize()
+}
+
+/// The face a direction points at and where on it, the inverse of `face_direction`.
+pub fn direction_to_face(direction: Vector3d) -> (usize, f32, f32) {
+    let [x, y, z] = direction.0;
+    let (ax, ay, az) = (x.abs(), y.abs(), z.abs());
+    let (face, sc, tc, major) = if ax >= ay && ax >= az {
+        if x > 0.0 {
+            (0, -z, -y, ax)
+        } else {
+            (1, z, -y, ax)
+        }
+    } else if ay >= az {
+        if y > 0.0 {
+            (2, x, z, ay)
+        } else {
+            (3, x, -z, ay)
+        }
+    } else if z > 0.0 {
+        (4, x, -y, az)
+    } else {
+        (5, -x, -y, az)
+    };
+    let major = major.max(f32::MIN_POSITIVE);
+    (face, (sc / major + 1.0) / 2.0, (tc / major + 1.0) / 2.0)
+}
+
+impl TextureData {
+    /// Loads a cube map. DDS and KTX2 files must already be cube maps, other images
+    /// are converted with the layout their size suggests.
+    pub fn load_cube(path: impl AsRef<Path>) -> error::Result<Self> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+
+        if extension == "dds" || extension == "ktx2" {
+            let data = Self::load(path)?;
+            if !data.cube {
+                return Err(error::Custom(format!(
+                    "{} is not a cube map",
+                    path.display()
+                )));
+            }
+            return Ok(data);
+        }
+
+        let image = data::read_image(path)?;
+        let (width, height) = image.dimensions();
+        let layout = CubeLayout::detect(width, height)
+            .ok_or("Cube map images must be a cross or twice as wide as they are high")?;
+        let options = ProcessOptions::for_path(path);
+        Self::cube_from_image(width, height, image.as_raw(), layout, &options)
+    }
+
+    /// Combines six single layer textures into a cube map, in face order.
+    pub fn cube_from_faces(faces: Vec<TextureData>) -> error::Result<Self> {
+        if faces.len() != 6 {
+            return Err("Cube maps need six faces".into());
+        }
+        let first = &faces[0];
+        let (format, color_space) = (first.format, first.color_space);
+        if first.width() != first.height() {
+            return Err("Cube map faces must be square".into());
+        }
+        if faces
+            .iter()
+            .any(|face| face.format != format || face.cube || face.layers.len() != 1)
+        {
+            return Err("Cube map faces must be single images of the same format".into());
+        }
+
+        let data = Self {
+            format,
+            color_space,
+            cube: true,
+            layers: faces.into_iter().flat_map(|face| face.layers).collect(),
+        };
+        data.validate()?;
+        Ok(data)
+    }
+
+    /// Builds a cube map from a cross or an equirectangular image, then processes each face.
+    pub fn cube_from_image(
+        width: u32,
+        height: u32,
+        rgba: &[u8],
+        layout: CubeLayout,
+        options: &ProcessOptions,
+    ) -> error::Result<Self> {
+        assert_eq!(rgba.len(), (width * height * 4) as usize);
+        let faces: Vec<(u32, Vec<u8>)> = match layout {
+            CubeLayout::HorizontalCross | CubeLayout::VerticalCross => {
+                cross_faces(width, height, rgba, layout)?
+            }
+            CubeLayout::Equirectangular => {
+                // A quarter of the width keeps roughly the same texel density at the equator.
+                let size = (width / 4).max(1);
+                (0..6)
+                    .map(|face| {
+                        (
+                            size,
+                            equirect_face(width, height, rgba, face, size, options),
+                        )
+                    })
+                    .collect()
+            }
+        };
+        Self::cube_from_faces(
+            faces
+                .iter()
+                .map(|(size, face)| process(*size, *size, face, options))
+                .collect(),
+        )
+    }
+
+    /// Bilinear sample of the top mip of an RGBA8 cube map, for the software path and tests.
+    pub fn sample_cube(&self, direction: Vector3d) -> Option<[u8; 4]> {
+        if !self.cube || self.format != TextureFormat::Rgba8 {
+            return None;
+        }
+        let (face, s, t) = direction_to_face(direction);
+        let mip = &self.layers[face][0];
+        let texel = |x: u32, y: u32| {
+            let offset = ((y * mip.width + x) * 4) as usize;
+            let mut texel = [0.0; 4];
+            for (value, &byte) in texel.iter_mut().zip(&mip.data[offset..offset + 4]) {
+                *value = f32::from(byte);
+            }
+            texel
+        };
+        let sample = bilinear(mip.width, mip.height, s, t, false, texel);
+        Some(sample.map(|value| value.round() as u8))
+    }
+}
+
+fn cross_faces(
+    width: u32,
+    height: u32,
+    rgba: &[u8],
+    layout: CubeLayout,
+) -> error::Result<Vec<(u32, Vec<u8>)>> {
+    let (columns, rows) = match layout {
+        CubeLayout::HorizontalCross => (4, 3),
+        _ => (3, 4),
+    };
+    let size = width / columns;
+    if size == 0 || width != size * columns || height != size * rows {
+        return Err("Cross cube map has the wrong size".into());
+    }
+    let cells: [(u32, u32); 6] = match layout {
+        CubeLayout::HorizontalCross => [(2, 1), (0, 1), (1, 0), (1, 2), (1, 1), (3, 1)],
+        _ => [(2, 1), (0, 1), (1, 0), (1, 2), (1, 1), (1, 3)],
+    };
+
+    Ok(cells
+        .iter()
+        .enumerate()
+        .map(|(face, &(column, row))| {
+            // -Z hangs below -Y in a vertical cross, so it is stored upside down.
+            let flip = layout == CubeLayout::VerticalCross && face == 5;
+            let mut data = Vec::with_capacity((size * size * 4) as usize);
+            for y in 0..size {
+                for x in 0..size {
+                    let (x, y) = if flip {
+                        (size - 1 - x, size - 1 - y)
+                    } else {
+                        (x, y)
+                    };
+                    let offset = (((row * size + y) * width + column * size + x) * 4) as usize;
+                    data.extend_from_slice(&rgba[offset..offset + 4]);
+                }
+            }
+            (size, data)
+        })
+        .collect())
+}
+
+fn equirect_face(
+    width: u32,
+    height: u32,
+    rgba: &[u8],
+    face: usize,
+    size: u32,
+    options: &ProcessOptions,
+) -> Vec<u8> {
+    let srgb = options.color_space == ColorSpace::Srgb;
+    let mut table = [0.0; 256];
+    for (i, value) in table.iter_mut().enumerate() {
+        let value_f = i as f32 / 255.0;
+        *value = if srgb {
+            srgb_to_linear(value_f)
+        } else {
+            value_f
+        };
+    }
+    let texel = |x: u32, y: u32| {
+        let offset = ((y * width + x) * 4) as usize;
+        let pixel = &rgba[offset..offset + 4];
+        [
+            table[pixel[0] as usize],
+            table[pixel[1] as usize],
+            table[pixel[2] as usize],
+            f32::from(pixel[3]) / 255.0,
+        ]
+    };
+
+    let mut data = Vec::with_capacity((size * size * 4) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            let s = (x as f32 + 0.5) / size as f32;
+            let t = (y as f32 + 0.5) / size as f32;
+            let [dx, dy, dz] = face_direction(face, s, t).0;
+            let u = 0.5 + dx.atan2(dz) / (2.0 * PI);
+            let v = 0.5 - dy.clamp(-1.0, 1.0).asin() / PI;
+
+            let sample = bilinear(width, height, u, v, true, texel);
+            for (channel, value) in sample.iter().enumerate() {
+                let value = if srgb && channel < 3 {
+                    linear_to_srgb(*value)
+                } else {
+                    *value
+                };
+                data.push((value.clamp(0.0, 1.0) * 255.0).round() as u8);
+            }
+        }
+    }
+    data
+}
+
+/// Samples between texel centers. `wrap_x` wraps around horizontally, otherwise edges clamp.
+fn bilinear(
+    width: u32,
+    height: u32,
+    u: f32,
+    v: f32,
+    wrap_x: bool,
+    texel: impl Fn(u32, u32) -> [f32; 4],
+) -> [f32; 4] {
+    let x = u * width as f32 - 0.5;
+    let y = (v * height as f32 - 0.5).clamp(0.0, (height - 1) as f32);
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+
+    let column = |x: f32| {
+        if wrap_x {
+            (x as i64).rem_euclid(i64::from(width)) as u32
+        } else {
+            x.clamp(0.0, (width - 1) as f32) as u32
+        }
+    };
+    let (x0, x1) = (column(x0), column(x0 + 1.0));
+    let (y0, y1) = (y0 as u32, (y0 as u32 + 1).min(height - 1));
+
+    let mut sum = [0.0; 4];
+    for (texel, weight) in [
+        (texel(x0, y0), (1.0 - fx) * (1.0 - fy)),
+        (texel(x1, y0), fx * (1.0 - fy)),
+        (texel(x0, y1), (1.0 - fx) * fy),
+        (texel(x1, y1), fx * fy),
+    ] {
+        for (sum, value) in sum.iter_mut().zip(texel) {
+            *sum += value * weight;
+        }
+    }
+    sum
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const COLORS: [[u8; 4]; 6] = [
+        [255, 0, 0, 255],
+        [0, 255, 255, 255],
+        [0, 255, 0, 255],
+        [255, 0, 255, 255],
+        [0, 0, 255, 255],
+        [255, 255, 0, 255],
+    ];
+
+    fn options() -> ProcessOptions {
+        ProcessOptions {
+            color_space: ColorSpace::Linear,
+            generate_mips: false,
+            ..ProcessOptions::default()
+        }
+    }
+
+    #[test]
+    fn face_round_trip() {
+        for face in 0..6 {
+            for (s, t) in [(0.5, 0.5), (0.1, 0.8), (0.9, 0.25)] {
+                let (found, fs, ft) = direction_to_face(face_direction(face, s, t));
+                assert_eq!(found, face);
+                assert!((fs - s).abs() < 1e-5 && (ft - t).abs() < 1e-5);
+            }
+        }
+        assert_eq!(direction_to_face(Vector3d::new(0.0, 0.0, -2.0)).0, 5);
+    }
+
+    #[test]
+    fn detects_layouts() {
+        assert_eq!(
+            CubeLayout::detect(400, 300),
+            Some(CubeLayout::HorizontalCross)
+        );
+        assert_eq!(
+            CubeLayout::detect(300, 400),
+            Some(CubeLayout::VerticalCross)
+        );
+        assert_eq!(
+            CubeLayout::detect(4096, 2048),
+            Some(CubeLayout::Equirectangular)
+        );
+        assert_eq!(CubeLayout::detect(512, 512), None);
+    }
+
+    #[test]
+    fn vertical_cross_faces() {
+        // Paint each cell of a 3x4 cross with its face color; -Z gets a marker in its top left.
+        let size = 4;
+        let (width, height) = (size * 3, size * 4);
+        let cells = [(2, 1), (0, 1), (1, 0), (1, 2), (1, 1), (1, 3)];
+        let mut rgba = vec![0; (width * height * 4) as usize];
+        for (face, (column, row)) in cells.into_iter().enumerate() {
+            for y in 0..size {
+                for x in 0..size {
+                    let offset = (((row * size + y) * width + column * size + x) * 4) as usize;
+                    rgba[offset..offset + 4].copy_from_slice(&COLORS[face]);
+                }
+            }
+        }
+        let marker = (((3 * size) * width + size) * 4) as usize;
+        rgba[marker..marker + 4].copy_from_slice(&[9, 9, 9, 255]);
+
+        let data = TextureData::cube_from_image(
+            width,
+            height,
+            &rgba,
+            CubeLayout::VerticalCross,
+            &options(),
+        )
+        .unwrap();
+        assert!(data.cube);
+        assert_eq!(data.layers.len(), 6);
+        for (face, color) in COLORS.iter().enumerate() {
+            let center = face_direction(face, 0.5, 0.5);
+            assert_eq!(data.sample_cube(center), Some(*color));
+        }
+        // Upside down, so the marker ends up in the bottom right.
+        assert_eq!(data.layers[5][0].data[data.layers[5][0].data.len() - 4], 9);
+    }
+
+    #[test]
+    fn equirect_poles_and_horizon() {
+        // Top half white, bottom half black.
+        let (width, height) = (64, 32);
+        let rgba: Vec<u8> = (0..height)
+            .flat_map(|y| {
+                let value = if y < height / 2 { 255 } else { 0 };
+                [value, value, value, 255].repeat(width as usize)
+            })
+            .collect();
+        let data = TextureData::cube_from_image(
+            width,
+            height,
+            &rgba,
+            CubeLayout::Equirectangular,
+            &options(),
+        )
+        .unwrap();
+
+        assert_eq!(data.width(), 16);
+        assert_eq!(
+            data.sample_cube(Vector3d::new(0.0, 1.0, 0.0)).unwrap()[0],
+            255
+        );
+        assert_eq!(
+            data.sample_cube(Vector3d::new(0.0, -1.0, 0.0)).unwrap()[0],
+            0
+        );
+        assert_eq!(
+            data.sample_cube(Vector3d::new(1.0, 0.5, 0.0)).unwrap()[0],
+            255
+        );
+        assert_eq!(
+            data.sample_cube(Vector3d::new(-1.0, -0.5, 0.2)).unwrap()[0],
+            0
+        );
+    }
+
+    #[test]
+    fn faces_must_match() {
+        let face = |size: u32| {
+            process(
+                size,
+                size,
+                &[0; 4].repeat((size * size) as usize),
+                &options(),
+            )
+        };
+        assert!(TextureData::cube_from_faces((0..6).map(|_| face(2)).collect()).is_ok());
+        assert!(TextureData::cube_from_faces((0..5).map(|_| face(2)).collect()).is_err());
+        let mut faces: Vec<_> = (0..6).map(|_| face(2)).collect();
+        faces[3] = face(4);
+        assert!(TextureData::cube_from_faces(faces).is_err());
+    }
+}
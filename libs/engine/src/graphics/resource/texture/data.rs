@@ -0,0 +1,430 @@
+use super::{bc, dds, exr, hdr, ktx2, linear_to_srgb, process, process_hdr, ProcessOptions};
+
+use crate::error;
+use crate::vfs;
+
+use std::path::Path;
+
+use image::{ImageFormat, RgbaImage};
+
+/// Largest width or height of a texture a device can hold.
+pub const MAX_TEXTURE_SIZE: u32 = 16384;
+/// Most array layers of a texture a device can hold, counting each face of a cube.
+pub const MAX_TEXTURE_LAYERS: usize = 2048;
+
+/// Mip levels in a full chain from `width` by `height` down to 1x1.
+pub fn full_mip_count(width: u32, height: u32) -> u32 {
+    (32 - width.max(height).leading_zeros()).max(1)
+}
+
+/// Checks the size of a texture from a file header before anything is allocated for it.
+pub(super) fn check_dimensions(
+    width: u32,
+    height: u32,
+    mip_count: u32,
+    layer_count: usize,
+) -> error::Result<()> {
+    if width > MAX_TEXTURE_SIZE || height > MAX_TEXTURE_SIZE {
+        return Err(error::Custom(format!(
+            "Texture is {}x{}, larger than {}",
+            width, height, MAX_TEXTURE_SIZE
+        )));
+    }
+    if mip_count > full_mip_count(width, height) {
+        return Err(error::Custom(format!(
+            "Texture of {}x{} cannot have {} mip levels",
+            width, height, mip_count
+        )));
+    }
+    if layer_count > MAX_TEXTURE_LAYERS {
+        return Err(error::Custom(format!(
+            "Texture has {} layers, more than {}",
+            layer_count, MAX_TEXTURE_LAYERS
+        )));
+    }
+    Ok(())
+}
+
+/// How the bytes of a texture should be interpreted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+/// Layout of the texels in a `MipLevel`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureFormat {
+    Rgba8,
+    Bgra8,
+    /// Linear half floats, for HDR images and render targets.
+    Rgba16Float,
+    Bc1,
+    Bc2,
+    Bc3,
+    Bc4,
+    Bc5,
+    Bc6hUfloat,
+    Bc6hSfloat,
+    Bc7,
+}
+
+impl TextureFormat {
+    /// Compressed formats store 4x4 blocks of texels.
+    pub fn is_compressed(self) -> bool {
+        !matches!(self, Self::Rgba8 | Self::Bgra8 | Self::Rgba16Float)
+    }
+
+    /// Bytes per texel, or per block when compressed.
+    pub fn unit_size(self) -> usize {
+        match self {
+            Self::Rgba8 | Self::Bgra8 => 4,
+            Self::Rgba16Float | Self::Bc1 | Self::Bc4 => 8,
+            Self::Bc2 | Self::Bc3 | Self::Bc5 | Self::Bc6hUfloat | Self::Bc6hSfloat | Self::Bc7 => {
+                16
+            }
+        }
+    }
+
+    /// Bytes in one row of texels, or one row of blocks when compressed.
+    pub fn row_pitch(self, width: u32) -> usize {
+        self.units(width) * self.unit_size()
+    }
+
+    pub fn surface_size(self, width: u32, height: u32) -> usize {
+        self.row_pitch(width) * self.units(height)
+    }
+
+    fn units(self, texels: u32) -> usize {
+        if self.is_compressed() {
+            (texels as usize).div_ceil(4).max(1)
+        } else {
+            texels as usize
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MipLevel {
+    pub width: u32,
+    pub height: u32,
+    /// Tightly packed rows in the format of the texture.
+    pub data: Vec<u8>,
+}
+
+/// A texture on the CPU, ready to upload.
+#[derive(Clone, Debug)]
+pub struct TextureData {
+    pub format: TextureFormat,
+    pub color_space: ColorSpace,
+    /// Every six layers form a cube, with faces ordered +X, -X, +Y, -Y, +Z, -Z.
+    pub cube: bool,
+    /// Each array layer with its mip chain, largest level first.
+    pub layers: Vec<Vec<MipLevel>>,
+}
+
+impl TextureData {
+    /// A single RGBA8 image with its mip chain.
+    pub fn rgba8(color_space: ColorSpace, mips: Vec<MipLevel>) -> Self {
+        Self {
+            format: TextureFormat::Rgba8,
+            color_space,
+            cube: false,
+            layers: vec![mips],
+        }
+    }
+
+    /// Loads DDS and KTX2 files as they are stored, and Radiance HDR and OpenEXR files
+    /// as half floats. Anything else is decoded with `image`
+    /// and processed with the options for its path.
+    pub fn load(path: impl AsRef<Path>) -> error::Result<Self> {
+        let path = path.as_ref();
+        let options = ProcessOptions::for_path(path);
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+
+        match extension.as_str() {
+            "dds" => dds::parse(&vfs::read(path)?, options.color_space),
+            "ktx2" => ktx2::parse(&vfs::read(path)?),
+            "hdr" => {
+                let (width, height, pixels) = hdr::parse(&vfs::read(path)?)?;
+                Ok(process_hdr(width, height, pixels, &options))
+            }
+            "exr" => {
+                let (width, height, pixels) = exr::parse(&vfs::read(path)?)?;
+                Ok(process_hdr(width, height, pixels, &options))
+            }
+            _ => {
+                let image = read_image(path)?;
+                Ok(process(
+                    image.width(),
+                    image.height(),
+                    image.as_raw(),
+                    &options,
+                ))
+            }
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.layers[0][0].width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.layers[0][0].height
+    }
+
+    pub fn mip_count(&self) -> usize {
+        self.layers[0].len()
+    }
+
+    /// Checks that every layer has the same mip chain and enough data for its format.
+    pub fn validate(&self) -> error::Result<()> {
+        let first = self.layers.first().ok_or("Texture has no layers")?;
+        if first.is_empty() {
+            return Err("Texture has no mip levels".into());
+        }
+        if self.cube && !self.layers.len().is_multiple_of(6) {
+            return Err("Cube map layers are not a multiple of six".into());
+        }
+        for layer in &self.layers {
+            if layer.len() != first.len() {
+                return Err("Texture layers have different mip counts".into());
+            }
+            for (level, mip) in layer.iter().enumerate() {
+                let expected = (
+                    (self.width() >> level).max(1),
+                    (self.height() >> level).max(1),
+                );
+                if (mip.width, mip.height) != expected {
+                    return Err(error::Custom(format!("Mip {} has the wrong size", level)));
+                }
+                if mip.data.len() < self.format.surface_size(mip.width, mip.height) {
+                    return Err(error::Custom(format!("Mip {} is truncated", level)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Decodes block compressed surfaces on the CPU, to half floats for BC6H and RGBA8
+    /// for the rest. Uncompressed textures are returned as they are.
+    pub fn decompress(&self) -> error::Result<Self> {
+        if !self.format.is_compressed() {
+            return Ok(self.clone());
+        }
+        let layers = self
+            .layers
+            .iter()
+            .map(|layer| {
+                layer
+                    .iter()
+                    .map(|mip| {
+                        let data = bc::decode(self.format, mip.width, mip.height, &mip.data)?;
+                        Ok(MipLevel { data, ..*mip })
+                    })
+                    .collect()
+            })
+            .collect::<error::Result<_>>()?;
+        Ok(Self {
+            format: bc::decoded_format(self.format),
+            layers,
+            ..*self
+        })
+    }
+
+    /// Converts every surface to RGBA8, decoding block compressed data on the CPU.
+    pub fn to_rgba8(&self) -> error::Result<Self> {
+        if matches!(
+            self.format,
+            TextureFormat::Bc6hUfloat | TextureFormat::Bc6hSfloat
+        ) {
+            // Clipped like any other half float texture once decoded.
+            return self.decompress()?.to_rgba8();
+        }
+        let layers = self
+            .layers
+            .iter()
+            .map(|layer| {
+                layer
+                    .iter()
+                    .map(|mip| {
+                        let data = match self.format {
+                            TextureFormat::Rgba8 => mip.data.clone(),
+                            TextureFormat::Bgra8 => mip
+                                .data
+                                .chunks_exact(4)
+                                .flat_map(|p| [p[2], p[1], p[0], p[3]])
+                                .collect(),
+                            // Clipped, and encoded for the gamma space renderer.
+                            TextureFormat::Rgba16Float => mip
+                                .data
+                                .chunks_exact(2)
+                                .enumerate()
+                                .map(|(i, p)| {
+                                    let value = half_to_f32(u16::from_le_bytes([p[0], p[1]]));
+                                    let value = value.clamp(0.0, 1.0);
+                                    let value = if i % 4 == 3 {
+                                        value
+                                    } else {
+                                        linear_to_srgb(value)
+                                    };
+                                    (value * 255.0).round() as u8
+                                })
+                                .collect(),
+                            format => bc::decode(format, mip.width, mip.height, &mip.data)?,
+                        };
+                        Ok(MipLevel { data, ..*mip })
+                    })
+                    .collect()
+            })
+            .collect::<error::Result<_>>()?;
+
+        let color_space = match self.format {
+            TextureFormat::Rgba16Float => ColorSpace::Srgb,
+            _ => self.color_space,
+        };
+        Ok(Self {
+            format: TextureFormat::Rgba8,
+            color_space,
+            layers,
+            ..*self
+        })
+    }
+}
+
+/// Reads an image through the virtual file system, decoded by its extension.
+pub(super) fn read_image(path: &Path) -> error::Result<RgbaImage> {
+    let format = ImageFormat::from_path(path)?;
+    let image = image::load_from_memory_with_format(&vfs::read(path)?, format)?;
+    Ok(image.to_rgba8())
+}
+
+/// Rounds to the nearest half float. Out of range values become infinite.
+pub fn f32_to_half(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = (bits >> 16 & 0x8000) as u16;
+    let exponent = (bits >> 23 & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exponent == 0xff {
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if exponent <= 0 {
+        if exponent < -10 {
+            return sign;
+        }
+        // Subnormal, with the implicit leading bit made explicit.
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - exponent) as u32;
+        let round = mantissa >> (shift - 1) & 1;
+        return sign | ((mantissa >> shift) + round) as u16;
+    }
+    // A carry out of the mantissa correctly bumps the exponent.
+    let round = mantissa >> 12 & 1;
+    sign | (((exponent as u32) << 10 | mantissa >> 13) + round) as u16
+}
+
+pub fn half_to_f32(half: u16) -> f32 {
+    let sign = u32::from(half & 0x8000) << 16;
+    let exponent = u32::from(half >> 10 & 0x1f);
+    let mantissa = u32::from(half & 0x3ff);
+
+    let bits = match exponent {
+        0 => {
+            let value = mantissa as f32 / (1 << 24) as f32;
+            return f32::from_bits(sign | value.to_bits());
+        }
+        0x1f => sign | 0x7f80_0000 | mantissa << 13,
+        _ => sign | (exponent + 127 - 15) << 23 | mantissa << 13,
+    };
+    f32::from_bits(bits)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn dimensions() {
+        assert_eq!(full_mip_count(1, 1), 1);
+        assert_eq!(full_mip_count(0, 0), 1);
+        assert_eq!(full_mip_count(8, 3), 4);
+        assert_eq!(full_mip_count(1024, 1024), 11);
+        assert!(check_dimensions(8, 3, 4, 6).is_ok());
+        assert!(check_dimensions(8, 3, 5, 1).is_err());
+        assert!(check_dimensions(MAX_TEXTURE_SIZE + 1, 1, 1, 1).is_err());
+        assert!(check_dimensions(4, 4, 1, MAX_TEXTURE_LAYERS + 1).is_err());
+    }
+
+    #[test]
+    fn half_round_trip() {
+        for (value, half) in [
+            (0.0, 0x0000),
+            (-0.0, 0x8000),
+            (1.0, 0x3c00),
+            (-2.0, 0xc000),
+            (0.333_251_95, 0x3555),
+            (65504.0, 0x7bff),
+            (6.103_515_6e-5, 0x0400),
+            (5.960_464_5e-8, 0x0001),
+            (f32::INFINITY, 0x7c00),
+        ] {
+            assert_eq!(f32_to_half(value), half, "{}", value);
+            assert_eq!(half_to_f32(half), value);
+        }
+        assert_eq!(f32_to_half(1.0e6), 0x7c00);
+        assert_eq!(f32_to_half(1.0e-9), 0);
+        assert!(half_to_f32(f32_to_half(f32::NAN)).is_nan());
+        // Halfway between 1.0 and the next half rounds up.
+        assert_eq!(f32_to_half(1.0 + 1.0 / 2048.0), 0x3c01);
+    }
+
+    #[test]
+    fn half_float_to_rgba8() {
+        let data: Vec<u8> = [2.0, 0.5, -1.0, 0.5]
+            .iter()
+            .flat_map(|&value| f32_to_half(value).to_le_bytes())
+            .collect();
+        let mip = MipLevel {
+            width: 1,
+            height: 1,
+            data,
+        };
+        let texture = TextureData {
+            format: TextureFormat::Rgba16Float,
+            ..TextureData::rgba8(ColorSpace::Linear, vec![mip])
+        };
+        let rgba8 = texture.to_rgba8().unwrap();
+        assert_eq!(rgba8.color_space, ColorSpace::Srgb);
+        assert_eq!(rgba8.layers[0][0].data, [255, 188, 0, 128]);
+    }
+
+    #[test]
+    fn bc6h_decompresses_to_half_floats() {
+        let mip = MipLevel {
+            width: 2,
+            height: 2,
+            data: vec![0; 16],
+        };
+        let texture = TextureData {
+            format: TextureFormat::Bc6hUfloat,
+            ..TextureData::rgba8(ColorSpace::Linear, vec![mip])
+        };
+        let halves = texture.decompress().unwrap();
+        assert_eq!(halves.format, TextureFormat::Rgba16Float);
+        assert_eq!(halves.layers[0][0].data.len(), 2 * 2 * 8);
+        assert_eq!(
+            texture.to_rgba8().unwrap().layers[0][0].data,
+            [0, 0, 0, 255].repeat(4)
+        );
+    }
+}
@@ -0,0 +1,250 @@
+//! DirectDraw Surface files, with or without the DX10 header extension.
+
+use super::data::check_dimensions;
+use super::{ColorSpace, MipLevel, TextureData, TextureFormat};
+
+use crate::error;
+
+const MAGIC: &[u8; 4] = b"DDS ";
+const HEADER_SIZE: usize = 124;
+const DX10_HEADER_SIZE: usize = 20;
+
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+const DDSCAPS2_CUBEMAP: u32 = 0x200;
+const DDSCAPS2_CUBEMAP_ALL_FACES: u32 = 0xfc00;
+const DDSCAPS2_VOLUME: u32 = 0x20_0000;
+
+const D3D10_RESOURCE_DIMENSION_TEXTURE2D: u32 = 3;
+const D3D11_RESOURCE_MISC_TEXTURECUBE: u32 = 0x4;
+
+/// Parses a DDS file. `color_space` is used when the format does not say.
+pub fn parse(bytes: &[u8], color_space: ColorSpace) -> error::Result<TextureData> {
+    if bytes.get(..4) != Some(MAGIC) {
+        return Err("Not a DDS file".into());
+    }
+    let header = bytes
+        .get(4..4 + HEADER_SIZE)
+        .ok_or("DDS header is truncated")?;
+    let mut offset = 4 + HEADER_SIZE;
+
+    let height = read_u32(header, 8);
+    let width = read_u32(header, 12);
+    let mip_count = read_u32(header, 24).max(1);
+    let pf_flags = read_u32(header, 76);
+    let four_cc = &header[80..84];
+    let caps2 = read_u32(header, 108);
+
+    if caps2 & DDSCAPS2_VOLUME != 0 {
+        return Err("Volume textures are not supported".into());
+    }
+
+    let mut cube = caps2 & DDSCAPS2_CUBEMAP != 0;
+    if cube && caps2 & DDSCAPS2_CUBEMAP_ALL_FACES != DDSCAPS2_CUBEMAP_ALL_FACES {
+        return Err("Cube maps must have all six faces".into());
+    }
+    let mut array_size = 1;
+
+    let (format, color_space) = if pf_flags & DDPF_FOURCC != 0 {
+        match four_cc {
+            b"DXT1" => (TextureFormat::Bc1, color_space),
+            b"DXT2" | b"DXT3" => (TextureFormat::Bc2, color_space),
+            b"DXT4" | b"DXT5" => (TextureFormat::Bc3, color_space),
+            b"ATI1" | b"BC4U" => (TextureFormat::Bc4, ColorSpace::Linear),
+            b"ATI2" | b"BC5U" => (TextureFormat::Bc5, ColorSpace::Linear),
+            b"DX10" => {
+                let dx10 = bytes
+                    .get(offset..offset + DX10_HEADER_SIZE)
+                    .ok_or("DDS DX10 header is truncated")?;
+                offset += DX10_HEADER_SIZE;
+
+                if read_u32(dx10, 4) != D3D10_RESOURCE_DIMENSION_TEXTURE2D {
+                    return Err("Only 2D DDS textures are supported".into());
+                }
+                cube = read_u32(dx10, 8) & D3D11_RESOURCE_MISC_TEXTURECUBE != 0;
+                array_size = read_u32(dx10, 12).max(1) as usize;
+                dxgi_format(read_u32(dx10, 0))?
+            }
+            _ => {
+                return Err(error::Custom(format!(
+                    "Unsupported DDS FourCC {:?}",
+                    String::from_utf8_lossy(four_cc)
+                )))
+            }
+        }
+    } else if pf_flags & DDPF_RGB != 0 && read_u32(header, 84) == 32 {
+        let masks = [88, 92, 96, 100].map(|offset| read_u32(header, offset));
+        match masks {
+            [0xff, 0xff00, 0xff_0000, _] => (TextureFormat::Rgba8, color_space),
+            [0xff_0000, 0xff00, 0xff, _] => (TextureFormat::Bgra8, color_space),
+            _ => return Err("Unsupported DDS channel masks".into()),
+        }
+    } else {
+        return Err("Unsupported DDS pixel format".into());
+    };
+
+    let layer_count = if cube {
+        array_size.checked_mul(6).ok_or("DDS has too many layers")?
+    } else {
+        array_size
+    };
+    check_dimensions(width, height, mip_count, layer_count)?;
+
+    let mut layers = Vec::with_capacity(layer_count);
+    for _ in 0..layer_count {
+        let mut mips = Vec::with_capacity(mip_count as usize);
+        for level in 0..mip_count {
+            let (width, height) = (mip_size(width, level), mip_size(height, level));
+            let size = format.surface_size(width, height);
+            let end = offset.checked_add(size).ok_or("DDS data is truncated")?;
+            let data = bytes
+                .get(offset..end)
+                .ok_or("DDS data is truncated")?
+                .to_vec();
+            offset = end;
+            mips.push(MipLevel {
+                width,
+                height,
+                data,
+            });
+        }
+        layers.push(mips);
+    }
+
+    let data = TextureData {
+        format,
+        color_space,
+        cube,
+        layers,
+    };
+    data.validate()?;
+    Ok(data)
+}
+
+fn dxgi_format(format: u32) -> error::Result<(TextureFormat, ColorSpace)> {
+    use ColorSpace::*;
+    Ok(match format {
+        28 => (TextureFormat::Rgba8, Linear),
+        29 => (TextureFormat::Rgba8, Srgb),
+        87 => (TextureFormat::Bgra8, Linear),
+        91 => (TextureFormat::Bgra8, Srgb),
+        10 => (TextureFormat::Rgba16Float, Linear),
+        71 => (TextureFormat::Bc1, Linear),
+        72 => (TextureFormat::Bc1, Srgb),
+        74 => (TextureFormat::Bc2, Linear),
+        75 => (TextureFormat::Bc2, Srgb),
+        77 => (TextureFormat::Bc3, Linear),
+        78 => (TextureFormat::Bc3, Srgb),
+        80 => (TextureFormat::Bc4, Linear),
+        83 => (TextureFormat::Bc5, Linear),
+        95 => (TextureFormat::Bc6hUfloat, Linear),
+        96 => (TextureFormat::Bc6hSfloat, Linear),
+        98 => (TextureFormat::Bc7, Linear),
+        99 => (TextureFormat::Bc7, Srgb),
+        _ => return Err(error::Custom(format!("Unsupported DXGI format {}", format))),
+    })
+}
+
+fn mip_size(size: u32, level: u32) -> u32 {
+    size.checked_shr(level).unwrap_or(0).max(1)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn header(width: u32, height: u32, mip_count: u32, four_cc: &[u8; 4], caps2: u32) -> Vec<u8> {
+        let mut header = vec![0; HEADER_SIZE];
+        let mut put = |offset: usize, value: u32| {
+            header[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        };
+        put(0, HEADER_SIZE as u32);
+        put(8, height);
+        put(12, width);
+        put(24, mip_count);
+        put(72, 32);
+        put(76, DDPF_FOURCC);
+        put(108, caps2);
+        header[80..84].copy_from_slice(four_cc);
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(header);
+        bytes
+    }
+
+    #[test]
+    fn bc1_mip_chain() {
+        let mut bytes = header(8, 4, 4, b"DXT1", 0);
+        // 2x1 blocks, then one block for each of 4x2, 2x1 and 1x1.
+        bytes.extend((0..5 * 8).map(|i| i as u8));
+        let data = parse(&bytes, ColorSpace::Srgb).unwrap();
+
+        assert_eq!(data.format, TextureFormat::Bc1);
+        assert_eq!(data.color_space, ColorSpace::Srgb);
+        assert_eq!(data.layers.len(), 1);
+        let sizes: Vec<_> = data.layers[0]
+            .iter()
+            .map(|mip| (mip.width, mip.height, mip.data.len()))
+            .collect();
+        assert_eq!(sizes, [(8, 4, 16), (4, 2, 8), (2, 1, 8), (1, 1, 8)]);
+        assert_eq!(data.layers[0][3].data[0], 32);
+
+        bytes.pop();
+        assert!(parse(&bytes, ColorSpace::Srgb).is_err());
+    }
+
+    #[test]
+    fn legacy_cube_map() {
+        let mut bytes = header(
+            4,
+            4,
+            1,
+            b"DXT5",
+            DDSCAPS2_CUBEMAP | DDSCAPS2_CUBEMAP_ALL_FACES,
+        );
+        for face in 0..6 {
+            bytes.extend([face; 16]);
+        }
+        let data = parse(&bytes, ColorSpace::Srgb).unwrap();
+        assert!(data.cube);
+        assert_eq!(data.layers.len(), 6);
+        assert_eq!(data.layers[4][0].data, [4; 16]);
+
+        let bytes = header(4, 4, 1, b"DXT5", DDSCAPS2_CUBEMAP | 0x400);
+        assert!(parse(&bytes, ColorSpace::Srgb).is_err());
+    }
+
+    #[test]
+    fn dx10_array() {
+        let mut bytes = header(4, 4, 1, b"DX10", 0);
+        for value in [99, D3D10_RESOURCE_DIMENSION_TEXTURE2D, 0, 3, 0] {
+            bytes.extend(value.to_le_bytes());
+        }
+        bytes.extend([0; 3 * 16]);
+        let data = parse(&bytes, ColorSpace::Linear).unwrap();
+        assert_eq!(data.format, TextureFormat::Bc7);
+        assert_eq!(data.color_space, ColorSpace::Srgb);
+        assert!(!data.cube);
+        assert_eq!(data.layers.len(), 3);
+    }
+
+    #[test]
+    fn rejects_garbage_headers() {
+        let bytes = header(4, 4, 1, b"DXT1", 0);
+        assert!(parse(&bytes[..HEADER_SIZE], ColorSpace::Srgb).is_err());
+        assert!(parse(&header(4, 4, 40, b"DXT1", 0), ColorSpace::Srgb).is_err());
+        assert!(parse(&header(u32::MAX, 4, 1, b"DXT1", 0), ColorSpace::Srgb).is_err());
+        assert!(parse(&header(4, 4, 1, b"ABCD", 0), ColorSpace::Srgb).is_err());
+
+        let mut bytes = header(4, 4, 1, b"DX10", 0);
+        for value in [99, D3D10_RESOURCE_DIMENSION_TEXTURE2D, 0, u32::MAX, 0] {
+            bytes.extend(value.to_le_bytes());
+        }
+        assert!(parse(&bytes, ColorSpace::Srgb).is_err());
+        assert!(parse(&bytes[..bytes.len() - 1], ColorSpace::Srgb).is_err());
+    }
+}
@@ -0,0 +1,384 @@
+//! Single part, scanline OpenEXR files that are uncompressed or use RLE or ZIP compression.
+
+use super::{half_to_f32, inflate};
+
+use crate::error;
+
+const MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+const TILED: u32 = 0x200;
+const DEEP: u32 = 0x800;
+const MULTIPART: u32 = 0x1000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PixelType {
+    Uint,
+    Half,
+    Float,
+}
+
+impl PixelType {
+    fn size(self) -> usize {
+        match self {
+            Self::Half => 2,
+            Self::Uint | Self::Float => 4,
+        }
+    }
+
+    fn read(self, bytes: &[u8]) -> f32 {
+        match self {
+            Self::Uint => u32::from_le_bytes(bytes[..4].try_into().unwrap()) as f32,
+            Self::Half => half_to_f32(u16::from_le_bytes([bytes[0], bytes[1]])),
+            Self::Float => f32::from_le_bytes(bytes[..4].try_into().unwrap()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Compression {
+    None,
+    Rle,
+    /// One scanline per chunk.
+    Zips,
+    /// Sixteen scanlines per chunk.
+    Zip,
+}
+
+impl Compression {
+    fn lines_per_chunk(self) -> u32 {
+        match self {
+            Self::None | Self::Rle | Self::Zips => 1,
+            Self::Zip => 16,
+        }
+    }
+}
+
+struct Channel {
+    name: String,
+    pixel_type: PixelType,
+}
+
+/// Parses an OpenEXR file into linear RGBA pixels, top row first. Luminance only
+/// images are grey, and missing alpha is opaque.
+pub fn parse(bytes: &[u8]) -> error::Result<(u32, u32, Vec<[f32; 4]>)> {
+    if bytes.get(..4) != Some(&MAGIC) {
+        return Err("Not an OpenEXR file".into());
+    }
+    let mut reader = Reader { bytes, offset: 4 };
+    let version = reader.u32()?;
+    if version & 0xff != 2 {
+        return Err("Unsupported OpenEXR version".into());
+    }
+    if version & (TILED | DEEP | MULTIPART) != 0 {
+        return Err("Only single part scanline OpenEXR files are supported".into());
+    }
+
+    let mut channels = None;
+    let mut compression = None;
+    let mut data_window = None;
+    loop {
+        let name = reader.string()?;
+        if name.is_empty() {
+            break;
+        }
+        let kind = reader.string()?;
+        let size = reader.u32()? as usize;
+        let value = reader.take(size)?;
+        match (name, kind) {
+            ("channels", "chlist") => channels = Some(parse_channels(value)?),
+            ("compression", "compression") => {
+                compression = Some(match value.first() {
+                    Some(0) => Compression::None,
+                    Some(1) => Compression::Rle,
+                    Some(2) => Compression::Zips,
+                    Some(3) => Compression::Zip,
+                    _ => return Err("Unsupported OpenEXR compression".into()),
+                })
+            }
+            ("dataWindow", "box2i") => {
+                let mut value = Reader {
+                    bytes: value,
+                    offset: 0,
+                };
+                let mut coordinate = || value.u32().map(|value| value as i32);
+                data_window = Some([coordinate()?, coordinate()?, coordinate()?, coordinate()?]);
+            }
+            _ => {}
+        }
+    }
+    let channels = channels.ok_or("OpenEXR file has no channels")?;
+    let compression = compression.ok_or("OpenEXR file has no compression")?;
+    let [x_min, y_min, x_max, y_max] = data_window.ok_or("OpenEXR file has no data window")?;
+    if x_max < x_min || y_max < y_min {
+        return Err("OpenEXR data window is empty".into());
+    }
+    let (width, height) = ((x_max - x_min + 1) as u32, (y_max - y_min + 1) as u32);
+
+    let pixel_size: usize = channels.iter().map(|c| c.pixel_type.size()).sum();
+    let line_size = pixel_size * width as usize;
+    let lines_per_chunk = compression.lines_per_chunk();
+    let chunk_count = height.div_ceil(lines_per_chunk);
+
+    // Where each channel lands in an RGBA pixel.
+    let targets: Vec<&[usize]> = channels
+        .iter()
+        .map(|channel| match channel.name.as_str() {
+            "R" => &[0][..],
+            "G" => &[1],
+            "B" => &[2],
+            "A" => &[3],
+            "Y" => &[0, 1, 2],
+            _ => &[],
+        })
+        .collect();
+
+    let mut pixels = vec![[0.0, 0.0, 0.0, 1.0]; (width * height) as usize];
+    for _ in 0..chunk_count {
+        let offset = reader.u64()? as usize;
+        let mut chunk = Reader { bytes, offset };
+        let y = chunk.u32()? as i32 - y_min;
+        let size = chunk.u32()? as usize;
+        let data = chunk.take(size)?;
+
+        if y < 0 || y as u32 >= height {
+            return Err("OpenEXR chunk is outside the data window".into());
+        }
+        let lines = lines_per_chunk.min(height - y as u32) as usize;
+        let expected = line_size * lines;
+        // Chunks that would not shrink are stored as they are.
+        let data = if size < expected {
+            match compression {
+                Compression::None => return Err("OpenEXR chunk is truncated".into()),
+                Compression::Rle => reorder(rle_decompress(data, expected)?),
+                Compression::Zips | Compression::Zip => {
+                    reorder(inflate::decompress(data, expected)?)
+                }
+            }
+        } else {
+            data.to_vec()
+        };
+        if data.len() < expected {
+            return Err("OpenEXR chunk is truncated".into());
+        }
+
+        // Each line holds every sample of the first channel, then the next.
+        for (line, line_data) in data[..expected].chunks_exact(line_size).enumerate() {
+            let row = &mut pixels[(y as usize + line) * width as usize..][..width as usize];
+            let mut samples = line_data;
+            for (channel, target) in channels.iter().zip(&targets) {
+                let size = channel.pixel_type.size();
+                let (channel_data, rest) = samples.split_at(size * width as usize);
+                samples = rest;
+                for (pixel, sample) in row.iter_mut().zip(channel_data.chunks_exact(size)) {
+                    let value = channel.pixel_type.read(sample);
+                    for &target in *target {
+                        pixel[target] = value;
+                    }
+                }
+            }
+        }
+    }
+    Ok((width, height, pixels))
+}
+
+fn parse_channels(bytes: &[u8]) -> error::Result<Vec<Channel>> {
+    let mut reader = Reader { bytes, offset: 0 };
+    let mut channels = Vec::new();
+    loop {
+        let name = reader.string()?;
+        if name.is_empty() {
+            return Ok(channels);
+        }
+        let pixel_type = match reader.u32()? {
+            0 => PixelType::Uint,
+            1 => PixelType::Half,
+            2 => PixelType::Float,
+            _ => return Err("Unknown OpenEXR pixel type".into()),
+        };
+        // Linear flag and padding.
+        reader.take(4)?;
+        if (reader.u32()?, reader.u32()?) != (1, 1) {
+            return Err("Subsampled OpenEXR channels are not supported".into());
+        }
+        channels.push(Channel {
+            name: name.to_owned(),
+            pixel_type,
+        });
+    }
+}
+
+/// Runs are a signed count: negative for that many literal bytes, otherwise one byte
+/// repeated one more time than the count.
+fn rle_decompress(mut data: &[u8], size_hint: usize) -> error::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(size_hint);
+    while let Some((&count, rest)) = data.split_first() {
+        let count = count as i8;
+        if count < 0 {
+            let count = usize::from(count.unsigned_abs());
+            let literal = rest.get(..count).ok_or("OpenEXR RLE data is truncated")?;
+            out.extend_from_slice(literal);
+            data = &rest[count..];
+        } else {
+            let (&value, rest) = rest.split_first().ok_or("OpenEXR RLE data is truncated")?;
+            out.resize(out.len() + count as usize + 1, value);
+            data = rest;
+        }
+    }
+    Ok(out)
+}
+
+/// Undoes the delta predictor, then interleaves the two halves the bytes were split into.
+fn reorder(mut data: Vec<u8>) -> Vec<u8> {
+    for i in 1..data.len() {
+        data[i] = data[i - 1].wrapping_add(data[i]).wrapping_sub(128);
+    }
+    let (first, second) = data.split_at(data.len().div_ceil(2));
+    let mut out = Vec::with_capacity(data.len());
+    for (i, &byte) in first.iter().enumerate() {
+        out.push(byte);
+        if let Some(&byte) = second.get(i) {
+            out.push(byte);
+        }
+    }
+    out
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> error::Result<&'a [u8]> {
+        let bytes = self
+            .bytes
+            .get(self.offset..self.offset + count)
+            .ok_or("OpenEXR file is truncated")?;
+        self.offset += count;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> error::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> error::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> error::Result<&'a str> {
+        let rest = self.bytes.get(self.offset..).unwrap_or_default();
+        let end = rest
+            .iter()
+            .position(|&byte| byte == 0)
+            .ok_or("OpenEXR string is not terminated")?;
+        self.offset += end + 1;
+        std::str::from_utf8(&rest[..end]).map_err(|_| "OpenEXR string is not UTF-8".into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::graphics::resource::texture::f32_to_half;
+
+    fn attribute(bytes: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+        for string in [name, kind] {
+            bytes.extend(string.as_bytes());
+            bytes.push(0);
+        }
+        bytes.extend((value.len() as u32).to_le_bytes());
+        bytes.extend(value);
+    }
+
+    /// A file with `(name, pixel type)` channels and one chunk per entry of `chunks`.
+    fn build(
+        channels: &[(&str, u32)],
+        compression: u8,
+        size: [i32; 2],
+        chunks: &[Vec<u8>],
+    ) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(2u32.to_le_bytes());
+
+        let mut chlist = Vec::new();
+        for &(name, pixel_type) in channels {
+            chlist.extend(name.as_bytes());
+            chlist.push(0);
+            for value in [pixel_type, 0, 1, 1] {
+                chlist.extend(value.to_le_bytes());
+            }
+        }
+        chlist.push(0);
+        attribute(&mut bytes, "channels", "chlist", &chlist);
+        attribute(&mut bytes, "compression", "compression", &[compression]);
+        let window: Vec<u8> = [5, 10, 4 + size[0], 9 + size[1]]
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        attribute(&mut bytes, "dataWindow", "box2i", &window);
+        attribute(&mut bytes, "lineOrder", "lineOrder", &[0]);
+        bytes.push(0);
+
+        let mut offset = bytes.len() + chunks.len() * 8;
+        let mut table = Vec::new();
+        let mut data = Vec::new();
+        let lines = if compression == 3 { 16 } else { 1 };
+        for (i, chunk) in chunks.iter().enumerate() {
+            table.extend((offset as u64).to_le_bytes());
+            data.extend((10 + i as i32 * lines).to_le_bytes());
+            data.extend((chunk.len() as u32).to_le_bytes());
+            data.extend(chunk);
+            offset += 8 + chunk.len();
+        }
+        bytes.extend(table);
+        bytes.extend(data);
+        bytes
+    }
+
+    fn halves(values: &[f32]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|&value| f32_to_half(value).to_le_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn uncompressed_rgb() {
+        // Channels are stored in alphabetical order.
+        let channels = [("B", 1), ("G", 1), ("R", 2)];
+        let mut line = halves(&[0.25, 0.5, 1.0, 2.0]);
+        line.extend(2.5f32.to_le_bytes());
+        line.extend(16.0f32.to_le_bytes());
+        let mut second = halves(&[0.0; 4]);
+        second.extend([0; 8]);
+
+        let bytes = build(&channels, 0, [2, 2], &[line, second]);
+        let (width, height, pixels) = parse(&bytes).unwrap();
+        assert_eq!((width, height), (2, 2));
+        assert_eq!(pixels[0], [2.5, 1.0, 0.25, 1.0]);
+        assert_eq!(pixels[1], [16.0, 2.0, 0.5, 1.0]);
+        assert_eq!(pixels[3], [0.0, 0.0, 0.0, 1.0]);
+
+        assert!(parse(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn rle_luminance() {
+        // Eight halves of 1.0 split into eight low bytes of 0x00 and eight high bytes
+        // of 0x3c, which the predictor stores as 0, 128 x 7, 188, 128 x 7.
+        let rle = vec![0xff, 0, 6, 128, 0, 188, 6, 128];
+        let bytes = build(&[("Y", 1)], 1, [8, 1], &[rle]);
+        let (_, _, pixels) = parse(&bytes).unwrap();
+        assert_eq!(pixels, vec![[1.0, 1.0, 1.0, 1.0]; 8]);
+    }
+
+    #[test]
+    fn rejects_tiled_and_unknown_compression() {
+        let mut bytes = build(&[("R", 1)], 0, [1, 1], &[vec![0; 2]]);
+        bytes[5] |= (TILED >> 8) as u8;
+        assert!(parse(&bytes).is_err());
+
+        let bytes = build(&[("R", 1)], 4, [1, 1], &[vec![0; 2]]);
+        assert!(parse(&bytes).is_err());
+    }
+}
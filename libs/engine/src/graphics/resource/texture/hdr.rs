@@ -0,0 +1,207 @@
+//! Radiance RGBE files, flat or run length encoded.
+
+use crate::error;
+
+/// Parses a Radiance HDR file into linear RGBA pixels, top row first.
+pub fn parse(bytes: &[u8]) -> error::Result<(u32, u32, Vec<[f32; 4]>)> {
+    let mut lines = Lines { bytes, offset: 0 };
+    let magic = lines.next().ok_or("Radiance header is truncated")?;
+    if magic != "#?RADIANCE" && magic != "#?RGBE" {
+        return Err("Not a Radiance HDR file".into());
+    }
+
+    let mut exposure = 1.0;
+    loop {
+        let line = lines.next().ok_or("Radiance header is truncated")?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(error::Custom(format!(
+                    "Unsupported Radiance format {}",
+                    format
+                )));
+            }
+        } else if let Some(value) = line.strip_prefix("EXPOSURE=") {
+            exposure *= value
+                .trim()
+                .parse::<f32>()
+                .map_err(|_| "Malformed Radiance exposure")?;
+        }
+    }
+
+    let resolution = lines.next().ok_or("Radiance resolution is missing")?;
+    let (flip, height, width) = match resolution.split_whitespace().collect::<Vec<_>>()[..] {
+        ["-Y", height, "+X", width] => (false, height, width),
+        ["+Y", height, "+X", width] => (true, height, width),
+        _ => return Err("Unsupported Radiance orientation".into()),
+    };
+    let parse_size = |size: &str| -> error::Result<u32> {
+        size.parse()
+            .map_err(|_| error::Custom(format!("Malformed Radiance size {}", size)))
+    };
+    let (width, height) = (parse_size(width)?, parse_size(height)?);
+
+    let mut data = &bytes[lines.offset..];
+    let mut rows = Vec::with_capacity(height as usize);
+    for _ in 0..height {
+        rows.push(read_scanline(&mut data, width as usize)?);
+    }
+    if flip {
+        rows.reverse();
+    }
+
+    let pixels = rows
+        .iter()
+        .flatten()
+        .map(|&rgbe| {
+            let [r, g, b] = rgbe_to_f32(rgbe).map(|value| value / exposure);
+            [r, g, b, 1.0]
+        })
+        .collect();
+    Ok((width, height, pixels))
+}
+
+/// Shared exponent colors. Black has a zero exponent.
+fn rgbe_to_f32([r, g, b, e]: [u8; 4]) -> [f32; 3] {
+    if e == 0 {
+        return [0.0; 3];
+    }
+    let scale = 2f32.powi(i32::from(e) - (128 + 8));
+    [r, g, b].map(|value| f32::from(value) * scale)
+}
+
+fn read_scanline(data: &mut &[u8], width: usize) -> error::Result<Vec<[u8; 4]>> {
+    let truncated = || error::Custom("Radiance pixels are truncated".into());
+    // Run length encoded scanlines start with 2, 2 and the width.
+    let encoded = (8..0x8000).contains(&width)
+        && data.len() >= 4
+        && data[..2] == [2, 2]
+        && data[2] & 0x80 == 0
+        && usize::from(u16::from_be_bytes([data[2], data[3]])) == width;
+
+    let mut row = vec![[0; 4]; width];
+    if encoded {
+        *data = &data[4..];
+        for channel in 0..4 {
+            let mut x = 0;
+            while x < width {
+                let (&count, rest) = data.split_first().ok_or_else(truncated)?;
+                *data = rest;
+                if count > 128 {
+                    let count = usize::from(count - 128);
+                    let (&value, rest) = data.split_first().ok_or_else(truncated)?;
+                    *data = rest;
+                    let run = row.get_mut(x..x + count).ok_or_else(truncated)?;
+                    run.iter_mut().for_each(|pixel| pixel[channel] = value);
+                    x += count;
+                } else {
+                    let count = usize::from(count);
+                    if count == 0 || data.len() < count {
+                        return Err(truncated());
+                    }
+                    let run = row.get_mut(x..x + count).ok_or_else(truncated)?;
+                    for (pixel, &value) in run.iter_mut().zip(&data[..count]) {
+                        pixel[channel] = value;
+                    }
+                    *data = &data[count..];
+                    x += count;
+                }
+            }
+        }
+    } else {
+        // Flat pixels, where 1, 1, 1 repeats the previous pixel.
+        let mut x = 0;
+        let mut shift = 0;
+        while x < width {
+            let pixel: [u8; 4] = data.get(..4).ok_or_else(truncated)?.try_into().unwrap();
+            *data = &data[4..];
+            if pixel[..3] == [1, 1, 1] && x > 0 {
+                let count = usize::from(pixel[3]) << shift;
+                let previous = row[x - 1];
+                let run = row.get_mut(x..x + count).ok_or_else(truncated)?;
+                run.fill(previous);
+                x += count;
+                shift += 8;
+            } else {
+                row[x] = pixel;
+                x += 1;
+                shift = 0;
+            }
+        }
+    }
+    Ok(row)
+}
+
+/// Header lines, without their newlines.
+struct Lines<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Iterator for Lines<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let rest = self.bytes.get(self.offset..)?;
+        let end = rest.iter().position(|&byte| byte == b'\n')?;
+        self.offset += end + 1;
+        std::str::from_utf8(&rest[..end]).ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn header(resolution: &str) -> Vec<u8> {
+        format!(
+            "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\nEXPOSURE=2.0\n\n{}\n",
+            resolution
+        )
+        .into_bytes()
+    }
+
+    #[test]
+    fn flat_pixels() {
+        let mut bytes = header("+Y 2 +X 2");
+        // Bottom row first, the second pixel repeating the first.
+        bytes.extend([128, 64, 0, 129, 1, 1, 1, 1]);
+        bytes.extend([0, 0, 0, 0, 128, 128, 128, 136]);
+        let (width, height, pixels) = parse(&bytes).unwrap();
+
+        assert_eq!((width, height), (2, 2));
+        assert_eq!(pixels[0], [0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(pixels[1], [64.0, 64.0, 64.0, 1.0]);
+        assert_eq!(pixels[2], [0.5, 0.25, 0.0, 1.0]);
+        assert_eq!(pixels[3], pixels[2]);
+    }
+
+    #[test]
+    fn run_length_encoded() {
+        let mut bytes = header("-Y 1 +X 10");
+        bytes.extend([2, 2, 0, 10]);
+        // Red and exponent are single runs, green is literal and blue is two runs.
+        bytes.extend([138, 128]);
+        bytes.push(10);
+        bytes.extend(0..10);
+        bytes.extend([128 + 5, 0, 128 + 5, 64]);
+        bytes.extend([138, 130]);
+        let (_, _, pixels) = parse(&bytes).unwrap();
+
+        assert_eq!(pixels.len(), 10);
+        assert_eq!(pixels[0], [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(pixels[9], [1.0, 9.0 / 128.0, 0.5, 1.0]);
+
+        bytes.pop();
+        assert!(parse(&bytes).is_err());
+    }
+
+    #[test]
+    fn rejects_other_formats() {
+        assert!(parse(b"P6\n1 1\n255\n").is_err());
+        let bytes = b"#?RADIANCE\nFORMAT=32-bit_rle_xyze\n\n-Y 1 +X 1\n\0\0\0\0";
+        assert!(parse(bytes).is_err());
+    }
+}
@@ -0,0 +1,272 @@
+//! Decompresses zlib streams, as used by ZIP compressed OpenEXR files.
+
+use crate::error;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// The order code length code lengths are stored in.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Inflates a zlib stream. The checksum is not verified.
+pub fn decompress(data: &[u8], size_hint: usize) -> error::Result<Vec<u8>> {
+    let header = data.get(..2).ok_or("zlib stream is truncated")?;
+    if header[0] & 0x0f != 8 || u16::from_be_bytes([header[0], header[1]]) % 31 != 0 {
+        return Err("Not a zlib stream".into());
+    }
+    if header[1] & 0x20 != 0 {
+        return Err("zlib preset dictionaries are not supported".into());
+    }
+
+    let mut bits = Bits {
+        data: &data[2..],
+        position: 0,
+    };
+    let mut out = Vec::with_capacity(size_hint);
+    loop {
+        let last = bits.read(1)? == 1;
+        match bits.read(2)? {
+            0 => stored(&mut bits, &mut out)?,
+            1 => {
+                let (literals, distances) = fixed_codes();
+                codes(&mut bits, &mut out, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(&mut bits)?;
+                codes(&mut bits, &mut out, &literals, &distances)?;
+            }
+            _ => return Err("Invalid deflate block type".into()),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+/// Reads bits least significant first.
+struct Bits<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl Bits<'_> {
+    fn read(&mut self, count: u32) -> error::Result<u32> {
+        let mut value = 0;
+        for i in 0..count {
+            let byte = self
+                .data
+                .get(self.position / 8)
+                .ok_or("Deflate stream is truncated")?;
+            value |= u32::from(byte >> (self.position % 8) & 1) << i;
+            self.position += 1;
+        }
+        Ok(value)
+    }
+
+    fn align(&mut self) {
+        self.position = self.position.next_multiple_of(8);
+    }
+}
+
+/// A canonical Huffman code, as symbol counts per length and symbols by code.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0; 16];
+        for &length in lengths {
+            counts[usize::from(length)] += 1;
+        }
+        counts[0] = 0;
+
+        let mut offsets = [0; 16];
+        for length in 1..15 {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0; usize::from(offsets[15] + counts[15])];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                let offset = &mut offsets[usize::from(length)];
+                symbols[usize::from(*offset)] = symbol as u16;
+                *offset += 1;
+            }
+        }
+        Self { counts, symbols }
+    }
+
+    fn decode(&self, bits: &mut Bits) -> error::Result<u16> {
+        // Codes of each length follow on from the shorter ones, so the first code of
+        // each length and the index of its symbol can be tracked as bits arrive.
+        let (mut code, mut first, mut index) = (0, 0, 0);
+        for &count in &self.counts[1..] {
+            code |= bits.read(1)? as i32;
+            let count = i32::from(count);
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("Invalid Huffman code".into())
+    }
+}
+
+fn stored(bits: &mut Bits, out: &mut Vec<u8>) -> error::Result<()> {
+    bits.align();
+    let start = bits.position / 8;
+    let header = bits
+        .data
+        .get(start..start + 4)
+        .ok_or("Deflate stream is truncated")?;
+    let length = usize::from(u16::from_le_bytes([header[0], header[1]]));
+    if u16::from_le_bytes([header[2], header[3]]) != !(length as u16) {
+        return Err("Stored deflate block has a bad length".into());
+    }
+    let block = bits
+        .data
+        .get(start + 4..start + 4 + length)
+        .ok_or("Deflate stream is truncated")?;
+    out.extend_from_slice(block);
+    bits.position = (start + 4 + length) * 8;
+    Ok(())
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+fn dynamic_codes(bits: &mut Bits) -> error::Result<(Huffman, Huffman)> {
+    let literal_count = bits.read(5)? as usize + 257;
+    let distance_count = bits.read(5)? as usize + 1;
+    let code_count = bits.read(4)? as usize + 4;
+
+    let mut code_lengths = [0; 19];
+    for &symbol in &CODE_LENGTH_ORDER[..code_count] {
+        code_lengths[symbol] = bits.read(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths);
+
+    let mut lengths = vec![0; literal_count + distance_count];
+    let mut i = 0;
+    while i < lengths.len() {
+        let (value, repeat) = match code_lengths.decode(bits)? {
+            length @ 0..=15 => (length as u8, 1),
+            16 => {
+                let previous = *i
+                    .checked_sub(1)
+                    .and_then(|previous| lengths.get(previous))
+                    .ok_or("Deflate length repeat has nothing to repeat")?;
+                (previous, 3 + bits.read(2)?)
+            }
+            17 => (0, 3 + bits.read(3)?),
+            _ => (0, 11 + bits.read(7)?),
+        };
+        let run = lengths
+            .get_mut(i..i + repeat as usize)
+            .ok_or("Deflate code lengths overflow")?;
+        run.fill(value);
+        i += repeat as usize;
+    }
+    if lengths[256] == 0 {
+        return Err("Deflate block has no end code".into());
+    }
+
+    let (literals, distances) = lengths.split_at(literal_count);
+    Ok((Huffman::new(literals), Huffman::new(distances)))
+}
+
+fn codes(
+    bits: &mut Bits,
+    out: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> error::Result<()> {
+    loop {
+        let symbol = usize::from(literals.decode(bits)?);
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let symbol = symbol - 257;
+                if symbol >= LENGTH_BASE.len() {
+                    return Err("Invalid deflate length".into());
+                }
+                let length = usize::from(LENGTH_BASE[symbol])
+                    + bits.read(u32::from(LENGTH_EXTRA[symbol]))? as usize;
+
+                let symbol = usize::from(distances.decode(bits)?);
+                if symbol >= DISTANCE_BASE.len() {
+                    return Err("Invalid deflate distance".into());
+                }
+                let distance = usize::from(DISTANCE_BASE[symbol])
+                    + bits.read(u32::from(DISTANCE_EXTRA[symbol]))? as usize;
+                let start = out
+                    .len()
+                    .checked_sub(distance)
+                    .ok_or("Deflate distance is too far back")?;
+                // Copies may overlap what they are writing.
+                for i in start..start + length {
+                    out.push(out[i]);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn stored_block() {
+        let data = [120, 1, 1, 3, 0, 252, 255, 1, 2, 3, 0, 0, 0, 0];
+        assert_eq!(decompress(&data, 0).unwrap(), [1, 2, 3]);
+        assert!(decompress(&data[..8], 0).is_err());
+    }
+
+    #[test]
+    fn fixed_codes_with_back_references() {
+        let data = [
+            120, 218, 75, 76, 74, 78, 68, 69, 10, 25, 169, 57, 57, 249, 200, 36, 0, 249, 243, 13,
+            129,
+        ];
+        let text = decompress(&data, 0).unwrap();
+        assert_eq!(text, b"abcabcabcabcabcabc hello hello hello");
+    }
+
+    #[test]
+    fn dynamic_codes() {
+        let data = [
+            120, 218, 213, 202, 161, 17, 0, 48, 16, 2, 176, 89, 17, 168, 87, 28, 2, 133, 98, 241,
+            206, 209, 232, 224, 6, 157, 73, 159, 176, 3, 227, 202, 43, 18, 116, 86, 29, 226, 131,
+            243, 0, 118, 34, 87, 205,
+        ];
+        let expected: Vec<u8> = (0..200u32)
+            .map(|i| ((i * i * 7 + 3 * i) % 36 + 97) as u8)
+            .collect();
+        assert_eq!(decompress(&data, 200).unwrap(), expected);
+    }
+}
@@ -0,0 +1,229 @@
+//! KTX 2.0 files without supercompression.
+
+use super::data::check_dimensions;
+use super::{ColorSpace, MipLevel, TextureData, TextureFormat};
+
+use crate::error;
+
+const IDENTIFIER: [u8; 12] = [
+    0xab, 0x4b, 0x54, 0x58, 0x20, 0x32, 0x30, 0xbb, 0x0d, 0x0a, 0x1a, 0x0a,
+];
+const HEADER_SIZE: usize = 80;
+const LEVEL_INDEX_ENTRY_SIZE: usize = 24;
+
+/// Parses a KTX2 file. The Vulkan format decides the color space.
+pub fn parse(bytes: &[u8]) -> error::Result<TextureData> {
+    if bytes.get(..12) != Some(&IDENTIFIER) {
+        return Err("Not a KTX2 file".into());
+    }
+    let header = bytes.get(..HEADER_SIZE).ok_or("KTX2 header is truncated")?;
+
+    let (format, color_space) = vk_format(read_u32(header, 12))?;
+    let width = read_u32(header, 20);
+    let height = read_u32(header, 24);
+    let depth = read_u32(header, 28);
+    let layer_count = read_u32(header, 32).max(1) as usize;
+    let face_count = read_u32(header, 36) as usize;
+    let level_count = read_u32(header, 40).max(1);
+    let supercompression = read_u32(header, 44);
+
+    if supercompression != 0 {
+        return Err("Supercompressed KTX2 files are not supported".into());
+    }
+    if height == 0 || depth > 0 {
+        return Err("Only 2D KTX2 textures are supported".into());
+    }
+    if face_count != 1 && face_count != 6 {
+        return Err("KTX2 face count must be 1 or 6".into());
+    }
+
+    let surfaces = layer_count
+        .checked_mul(face_count)
+        .ok_or("KTX2 has too many layers")?;
+    check_dimensions(width, height, level_count, surfaces)?;
+
+    let mut layers = vec![Vec::with_capacity(level_count as usize); surfaces];
+    for level in 0..level_count {
+        let entry = HEADER_SIZE + level as usize * LEVEL_INDEX_ENTRY_SIZE;
+        let entry = bytes
+            .get(entry..entry + LEVEL_INDEX_ENTRY_SIZE)
+            .ok_or("KTX2 level index is truncated")?;
+        let offset = read_u64(entry, 0) as usize;
+        let length = read_u64(entry, 8) as usize;
+        let level_bytes = offset
+            .checked_add(length)
+            .and_then(|end| bytes.get(offset..end))
+            .ok_or("KTX2 level data is truncated")?;
+
+        let (width, height) = (mip_size(width, level), mip_size(height, level));
+        let size = format.surface_size(width, height);
+        if size
+            .checked_mul(surfaces)
+            .is_none_or(|needed| level_bytes.len() < needed)
+        {
+            return Err(error::Custom(format!("KTX2 level {} is truncated", level)));
+        }
+        // Images in a level are ordered by layer, then face.
+        for (mips, data) in layers.iter_mut().zip(level_bytes.chunks_exact(size)) {
+            mips.push(MipLevel {
+                width,
+                height,
+                data: data.to_vec(),
+            });
+        }
+    }
+
+    let data = TextureData {
+        format,
+        color_space,
+        cube: face_count == 6,
+        layers,
+    };
+    data.validate()?;
+    Ok(data)
+}
+
+fn vk_format(format: u32) -> error::Result<(TextureFormat, ColorSpace)> {
+    use ColorSpace::*;
+    Ok(match format {
+        37 => (TextureFormat::Rgba8, Linear),
+        43 => (TextureFormat::Rgba8, Srgb),
+        44 => (TextureFormat::Bgra8, Linear),
+        50 => (TextureFormat::Bgra8, Srgb),
+        97 => (TextureFormat::Rgba16Float, Linear),
+        131 | 133 => (TextureFormat::Bc1, Linear),
+        132 | 134 => (TextureFormat::Bc1, Srgb),
+        135 => (TextureFormat::Bc2, Linear),
+        136 => (TextureFormat::Bc2, Srgb),
+        137 => (TextureFormat::Bc3, Linear),
+        138 => (TextureFormat::Bc3, Srgb),
+        139 => (TextureFormat::Bc4, Linear),
+        141 => (TextureFormat::Bc5, Linear),
+        143 => (TextureFormat::Bc6hUfloat, Linear),
+        144 => (TextureFormat::Bc6hSfloat, Linear),
+        145 => (TextureFormat::Bc7, Linear),
+        146 => (TextureFormat::Bc7, Srgb),
+        0 => return Err("KTX2 files without a Vulkan format are not supported".into()),
+        _ => {
+            return Err(error::Custom(format!(
+                "Unsupported Vulkan format {}",
+                format
+            )))
+        }
+    })
+}
+
+fn mip_size(size: u32, level: u32) -> u32 {
+    size.checked_shr(level).unwrap_or(0).max(1)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Builds a file from whole levels, largest first.
+    fn build(
+        vk_format: u32,
+        size: [u32; 2],
+        layers: u32,
+        faces: u32,
+        levels: &[Vec<u8>],
+    ) -> Vec<u8> {
+        let mut bytes = IDENTIFIER.to_vec();
+        for value in [
+            vk_format,
+            1,
+            size[0],
+            size[1],
+            0,
+            layers,
+            faces,
+            levels.len() as u32,
+            0,
+        ] {
+            bytes.extend(value.to_le_bytes());
+        }
+        bytes.resize(HEADER_SIZE, 0);
+
+        let mut offset = HEADER_SIZE + levels.len() * LEVEL_INDEX_ENTRY_SIZE;
+        for level in levels {
+            let length = level.len() as u64;
+            for value in [offset as u64, length, length] {
+                bytes.extend(value.to_le_bytes());
+            }
+            offset += level.len();
+        }
+        for level in levels {
+            bytes.extend(level);
+        }
+        bytes
+    }
+
+    #[test]
+    fn rgba8_mip_chain() {
+        let levels = [vec![1; 4 * 2 * 4], vec![2; 2 * 4], vec![3; 4]];
+        let data = parse(&build(43, [4, 2], 0, 1, &levels)).unwrap();
+
+        assert_eq!(data.format, TextureFormat::Rgba8);
+        assert_eq!(data.color_space, ColorSpace::Srgb);
+        assert!(!data.cube);
+        let sizes: Vec<_> = data.layers[0].iter().map(|m| (m.width, m.height)).collect();
+        assert_eq!(sizes, [(4, 2), (2, 1), (1, 1)]);
+        assert_eq!(data.layers[0][2].data, [3; 4]);
+    }
+
+    #[test]
+    fn bc7_cube_array() {
+        // Two cubes of one 4x4 block each, every face tagged with its index.
+        let level: Vec<u8> = (0..12).flat_map(|face| [face; 16]).collect();
+        let data = parse(&build(145, [4, 4], 2, 6, &[level])).unwrap();
+
+        assert_eq!(data.format, TextureFormat::Bc7);
+        assert!(data.cube);
+        assert_eq!(data.layers.len(), 12);
+        assert_eq!(data.layers[7][0].data, [7; 16]);
+    }
+
+    #[test]
+    fn rejects_unsupported() {
+        let levels = [vec![0; 16]];
+        assert!(parse(&build(0, [4, 4], 0, 1, &levels)).is_err());
+        assert!(parse(&build(145, [4, 4], 0, 1, &[vec![0; 8]])).is_err());
+
+        let mut bytes = build(145, [4, 4], 0, 1, &levels);
+        bytes[44] = 1;
+        assert!(parse(&bytes).is_err());
+    }
+
+    #[test]
+    fn rejects_garbage_headers() {
+        let levels = [vec![0; 4 * 4 * 4]];
+        let bytes = build(37, [4, 4], 0, 1, &levels);
+        assert!(parse(&bytes[..HEADER_SIZE - 1]).is_err());
+        assert!(parse(&bytes[..HEADER_SIZE + 4]).is_err());
+
+        let put = |offset: usize, value: u32| {
+            let mut bytes = bytes.clone();
+            bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+            bytes
+        };
+        // Level count, layer count, width
+        assert!(parse(&put(40, u32::MAX)).is_err());
+        assert!(parse(&put(40, 4)).is_err());
+        assert!(parse(&put(32, u32::MAX)).is_err());
+        assert!(parse(&put(20, u32::MAX)).is_err());
+
+        // Level offset and length that overflow
+        let mut bytes = bytes.clone();
+        bytes[HEADER_SIZE..HEADER_SIZE + 16].copy_from_slice(&[0xff; 16]);
+        assert!(parse(&bytes).is_err());
+    }
+}
@@ -0,0 +1,470 @@
+//! CPU side texture processing: power of two resizing and mip chain generation.
+//!
+//! Filtering happens on linear values, so sRGB colors are decoded first and
+//! normal maps are filtered as vectors and renormalized.
+
+use super::{f32_to_half, ColorSpace, MipLevel, TextureData, TextureFormat};
+
+use std::f32::consts::PI;
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureKind {
+    Color,
+    /// Tangent space normals packed as `n * 0.5 + 0.5`.
+    NormalMap,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MipFilter {
+    /// Averages each 2x2 block.
+    Box,
+    /// Kaiser windowed sinc. Sharper mips than `Box`, at a higher cost.
+    Kaiser,
+}
+
+#[derive(Clone, Debug)]
+pub struct ProcessOptions {
+    pub kind: TextureKind,
+    pub color_space: ColorSpace,
+    pub generate_mips: bool,
+    pub mip_filter: MipFilter,
+    /// Resize each side to the nearest power of two.
+    pub resize_pow2: bool,
+}
+
+impl ProcessOptions {
+    /// Files ending in `_n` or `_normal` are treated as linear normal maps,
+    /// anything else as sRGB color.
+    pub fn for_path(path: impl AsRef<Path>) -> Self {
+        let stem = path
+            .as_ref()
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+        if stem.ends_with("_n") || stem.ends_with("_normal") {
+            Self::normal_map()
+        } else {
+            Self::default()
+        }
+    }
+
+    pub fn normal_map() -> Self {
+        Self {
+            kind: TextureKind::NormalMap,
+            color_space: ColorSpace::Linear,
+            mip_filter: MipFilter::Box,
+            ..Self::default()
+        }
+    }
+}
+
+impl Default for ProcessOptions {
+    fn default() -> Self {
+        Self {
+            kind: TextureKind::Color,
+            color_space: ColorSpace::Srgb,
+            generate_mips: true,
+            mip_filter: MipFilter::Kaiser,
+            resize_pow2: false,
+        }
+    }
+}
+
+/// Turns tightly packed RGBA8 pixels into a texture with a full mip chain.
+pub fn process(width: u32, height: u32, rgba: &[u8], options: &ProcessOptions) -> TextureData {
+    assert_eq!(rgba.len(), (width * height * 4) as usize);
+    let image = Image::decode(width, height, rgba, options);
+    let mips = mip_chain(image, options, |image| image.encode(options));
+    TextureData::rgba8(options.color_space, mips)
+}
+
+/// Turns linear RGBA pixels into a half float texture with a full mip chain.
+/// The color space and kind in `options` are ignored.
+pub fn process_hdr(
+    width: u32,
+    height: u32,
+    pixels: Vec<[f32; 4]>,
+    options: &ProcessOptions,
+) -> TextureData {
+    assert_eq!(pixels.len(), (width * height) as usize);
+    let options = ProcessOptions {
+        kind: TextureKind::Color,
+        color_space: ColorSpace::Linear,
+        ..options.clone()
+    };
+    let image = Image {
+        width,
+        height,
+        pixels,
+    };
+    let mips = mip_chain(image, &options, Image::encode_half);
+    TextureData {
+        format: TextureFormat::Rgba16Float,
+        ..TextureData::rgba8(ColorSpace::Linear, mips)
+    }
+}
+
+fn mip_chain(
+    mut image: Image,
+    options: &ProcessOptions,
+    encode: impl Fn(&Image) -> MipLevel,
+) -> Vec<MipLevel> {
+    let (width, height) = (image.width, image.height);
+    if options.resize_pow2 {
+        let (new_width, new_height) = (nearest_pow2(width), nearest_pow2(height));
+        if (new_width, new_height) != (width, height) {
+            image = image.resample(new_width, new_height, MipFilter::Kaiser, options.kind);
+        }
+    }
+
+    let mut mips = vec![encode(&image)];
+    if options.generate_mips {
+        while image.width > 1 || image.height > 1 {
+            let (width, height) = ((image.width / 2).max(1), (image.height / 2).max(1));
+            image = image.resample(width, height, options.mip_filter, options.kind);
+            mips.push(encode(&image));
+        }
+    }
+    mips
+}
+
+/// The power of two closest to `n`, rounding up on ties.
+pub fn nearest_pow2(n: u32) -> u32 {
+    let up = n.max(1).next_power_of_two();
+    let down = (up / 2).max(1);
+    if n - down < up - n {
+        down
+    } else {
+        up
+    }
+}
+
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Linear floating point pixels. Normal maps hold unpacked vectors.
+struct Image {
+    width: u32,
+    height: u32,
+    pixels: Vec<[f32; 4]>,
+}
+
+impl Image {
+    fn decode(width: u32, height: u32, rgba: &[u8], options: &ProcessOptions) -> Self {
+        let mut table = [0.0; 256];
+        for (i, value) in table.iter_mut().enumerate() {
+            let unorm = i as f32 / 255.0;
+            *value = match (options.kind, options.color_space) {
+                (TextureKind::NormalMap, _) => unorm * 2.0 - 1.0,
+                (TextureKind::Color, ColorSpace::Srgb) => srgb_to_linear(unorm),
+                (TextureKind::Color, ColorSpace::Linear) => unorm,
+            };
+        }
+
+        let pixels = rgba
+            .chunks_exact(4)
+            .map(|p| {
+                let [r, g, b, a] = [p[0], p[1], p[2], p[3]].map(usize::from);
+                [table[r], table[g], table[b], a as f32 / 255.0]
+            })
+            .collect();
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    fn encode(&self, options: &ProcessOptions) -> MipLevel {
+        let to_byte = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+        let data = self
+            .pixels
+            .iter()
+            .flat_map(|&[r, g, b, a]| {
+                let [r, g, b] = match (options.kind, options.color_space) {
+                    (TextureKind::NormalMap, _) => [r, g, b].map(|v| v * 0.5 + 0.5),
+                    (TextureKind::Color, ColorSpace::Srgb) => [r, g, b].map(linear_to_srgb),
+                    (TextureKind::Color, ColorSpace::Linear) => [r, g, b],
+                };
+                [to_byte(r), to_byte(g), to_byte(b), to_byte(a)]
+            })
+            .collect();
+        MipLevel {
+            width: self.width,
+            height: self.height,
+            data,
+        }
+    }
+
+    /// Ringing from sharp filters is clipped, since light cannot be negative.
+    fn encode_half(&self) -> MipLevel {
+        let data = self
+            .pixels
+            .iter()
+            .flat_map(|&[r, g, b, a]| [r.max(0.0), g.max(0.0), b.max(0.0), a.clamp(0.0, 1.0)])
+            .flat_map(|value| f32_to_half(value).to_le_bytes())
+            .collect();
+        MipLevel {
+            width: self.width,
+            height: self.height,
+            data,
+        }
+    }
+
+    /// Separable resample. Edges wrap, matching the default sampler.
+    fn resample(&self, width: u32, height: u32, filter: MipFilter, kind: TextureKind) -> Self {
+        let horizontal = axis_weights(self.width, width, filter);
+        let vertical = axis_weights(self.height, height, filter);
+
+        let mut rows = vec![[0.0; 4]; (width * self.height) as usize];
+        for y in 0..self.height as usize {
+            let src = &self.pixels[y * self.width as usize..][..self.width as usize];
+            let dst = &mut rows[y * width as usize..][..width as usize];
+            for (pixel, taps) in dst.iter_mut().zip(&horizontal) {
+                *pixel = weighted_sum(taps.iter().map(|&(i, w)| (src[i], w)));
+            }
+        }
+
+        let mut pixels = vec![[0.0; 4]; (width * height) as usize];
+        for (y, taps) in vertical.iter().enumerate() {
+            for x in 0..width as usize {
+                let column = taps.iter().map(|&(i, w)| (rows[i * width as usize + x], w));
+                pixels[y * width as usize + x] = weighted_sum(column);
+            }
+        }
+
+        if kind == TextureKind::NormalMap {
+            for pixel in &mut pixels {
+                let length = (pixel[0] * pixel[0] + pixel[1] * pixel[1] + pixel[2] * pixel[2])
+                    .sqrt()
+                    .max(f32::EPSILON);
+                for value in &mut pixel[..3] {
+                    *value /= length;
+                }
+            }
+        }
+
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+}
+
+fn weighted_sum(taps: impl Iterator<Item = ([f32; 4], f32)>) -> [f32; 4] {
+    let mut sum = [0.0; 4];
+    for (pixel, weight) in taps {
+        for (sum, value) in sum.iter_mut().zip(pixel) {
+            *sum += value * weight;
+        }
+    }
+    sum
+}
+
+/// For each destination pixel, the source pixels it reads and their normalized weights.
+fn axis_weights(src_len: u32, dst_len: u32, filter: MipFilter) -> Vec<Vec<(usize, f32)>> {
+    let scale = src_len as f32 / dst_len as f32;
+    // Stretch the kernel when shrinking, so it covers every source pixel.
+    let stretch = scale.max(1.0);
+    let support = filter.radius() * stretch;
+
+    (0..dst_len)
+        .map(|dst| {
+            let center = (dst as f32 + 0.5) * scale - 0.5;
+            let first = (center - support).floor() as i64;
+            let last = (center + support).ceil() as i64;
+
+            let mut taps: Vec<_> = (first..=last)
+                .map(|src| {
+                    let weight = filter.eval((src as f32 - center) / stretch);
+                    (src.rem_euclid(i64::from(src_len)) as usize, weight)
+                })
+                .filter(|&(_, weight)| weight != 0.0)
+                .collect();
+            let total: f32 = taps.iter().map(|&(_, weight)| weight).sum();
+            for (_, weight) in &mut taps {
+                *weight /= total;
+            }
+            taps
+        })
+        .collect()
+}
+
+impl MipFilter {
+    const KAISER_RADIUS: f32 = 3.0;
+    const KAISER_ALPHA: f32 = 4.0;
+
+    fn radius(self) -> f32 {
+        match self {
+            Self::Box => 0.5,
+            Self::Kaiser => Self::KAISER_RADIUS,
+        }
+    }
+
+    fn eval(self, t: f32) -> f32 {
+        match self {
+            Self::Box => {
+                if t.abs() <= 0.5 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Self::Kaiser => {
+                let x = t / Self::KAISER_RADIUS;
+                if x.abs() >= 1.0 {
+                    return 0.0;
+                }
+                let window = bessel_i0(Self::KAISER_ALPHA * (1.0 - x * x).sqrt())
+                    / bessel_i0(Self::KAISER_ALPHA);
+                sinc(t) * window
+            }
+        }
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-6 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// Modified Bessel function of the first kind, by its power series.
+fn bessel_i0(x: f32) -> f32 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    for k in 1..20 {
+        term *= (x / (2.0 * k as f32)).powi(2);
+        sum += term;
+    }
+    sum
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::graphics::resource::texture::half_to_f32;
+
+    fn uniform(width: u32, height: u32, pixel: [u8; 4]) -> Vec<u8> {
+        pixel.repeat((width * height) as usize)
+    }
+
+    #[test]
+    fn mip_chain_sizes() {
+        let data = process(5, 3, &uniform(5, 3, [9; 4]), &ProcessOptions::default());
+        let sizes: Vec<_> = data.layers[0].iter().map(|m| (m.width, m.height)).collect();
+        assert_eq!(sizes, [(5, 3), (2, 1), (1, 1)]);
+        for mip in &data.layers[0] {
+            assert_eq!(mip.data.len(), (mip.width * mip.height * 4) as usize);
+        }
+    }
+
+    #[test]
+    fn constant_color_survives() {
+        for mip_filter in [MipFilter::Box, MipFilter::Kaiser] {
+            let options = ProcessOptions {
+                mip_filter,
+                ..ProcessOptions::default()
+            };
+            let data = process(8, 8, &uniform(8, 8, [200, 100, 50, 255]), &options);
+            for mip in &data.layers[0] {
+                for pixel in mip.data.chunks_exact(4) {
+                    assert_eq!(pixel, [200, 100, 50, 255]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn box_filter_is_gamma_correct() {
+        let rgba = [[0, 0, 0, 255], [255, 255, 255, 255]].repeat(2).concat();
+        let options = ProcessOptions {
+            mip_filter: MipFilter::Box,
+            ..ProcessOptions::default()
+        };
+        let data = process(2, 2, &rgba, &options);
+        // Half of the light, not half of the sRGB value.
+        assert_eq!(data.layers[0][1].data, [188, 188, 188, 255]);
+
+        let linear = ProcessOptions {
+            color_space: ColorSpace::Linear,
+            ..options
+        };
+        assert_eq!(
+            process(2, 2, &rgba, &linear).layers[0][1].data,
+            [128, 128, 128, 255]
+        );
+    }
+
+    #[test]
+    fn normal_mips_stay_unit_length() {
+        // Alternating normals tilted left and right average to straight up.
+        let left = [38, 128, 218, 255];
+        let right = [218, 128, 218, 255];
+        let rgba = [left, right].repeat(8).concat();
+        let options = ProcessOptions::for_path("brick_n.jpg");
+        assert_eq!(options.kind, TextureKind::NormalMap);
+
+        let data = process(4, 4, &rgba, &options);
+        let pixel = &data.layers[0][1].data[..4];
+        assert_eq!(pixel, [128, 128, 255, 255]);
+    }
+
+    #[test]
+    fn pow2_resize() {
+        assert_eq!(nearest_pow2(1), 1);
+        assert_eq!(nearest_pow2(600), 512);
+        assert_eq!(nearest_pow2(1000), 1024);
+        assert_eq!(nearest_pow2(768), 1024);
+        assert_eq!(nearest_pow2(256), 256);
+
+        let options = ProcessOptions {
+            resize_pow2: true,
+            generate_mips: false,
+            ..ProcessOptions::default()
+        };
+        let data = process(6, 3, &uniform(6, 3, [40; 4]), &options);
+        assert_eq!((data.width(), data.height()), (8, 4));
+        assert!(data.layers[0][0].data.iter().all(|&v| v == 40));
+    }
+
+    #[test]
+    fn hdr_mips_keep_bright_values() {
+        let pixels = [[8.0, 0.0, 0.5, 1.0], [0.0, 0.0, 0.5, 1.0]].repeat(2);
+        let options = ProcessOptions {
+            mip_filter: MipFilter::Box,
+            ..ProcessOptions::default()
+        };
+        let data = process_hdr(2, 2, pixels, &options);
+        assert_eq!(data.format, TextureFormat::Rgba16Float);
+        assert_eq!(data.color_space, ColorSpace::Linear);
+
+        let texel = |level: usize| -> Vec<f32> {
+            data.layers[0][level].data[..8]
+                .chunks_exact(2)
+                .map(|half| half_to_f32(u16::from_le_bytes([half[0], half[1]])))
+                .collect()
+        };
+        assert_eq!(texel(0), [8.0, 0.0, 0.5, 1.0]);
+        // Averaged without any sRGB decoding or clipping.
+        assert_eq!(texel(1), [4.0, 0.0, 0.5, 1.0]);
+    }
+}
@@ -0,0 +1,138 @@
+//! Notices when files change on disk, so resources can be reloaded.
+//!
+//! Directories holding watched files get change notifications, which wake the
+//! watcher up as soon as something is written. Modification times are also
+//! polled on an interval, for directories notifications could not be set up on.
+//! Files are watched by virtual path, so files in packs are seen to change
+//! when their pack is replaced.
+
+use crate::vfs;
+
+use std::collections::HashMap;
+use std::os::windows::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use log::warn;
+use winapi::shared::minwindef::FALSE;
+use winapi::um::{fileapi, handleapi, synchapi, winbase, winnt};
+
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct FileWatcher {
+    /// Watched files and when they were last modified, if they could be read.
+    files: HashMap<PathBuf, Option<SystemTime>>,
+    notifications: HashMap<PathBuf, Option<ChangeNotification>>,
+    poll_interval: Duration,
+    last_poll: Instant,
+}
+
+impl FileWatcher {
+    pub fn new(poll_interval: Duration) -> Self {
+        Self {
+            files: HashMap::new(),
+            notifications: HashMap::new(),
+            poll_interval,
+            last_poll: Instant::now(),
+        }
+    }
+
+    pub fn watch(&mut self, path: impl AsRef<Path>) {
+        let path = path.as_ref();
+        if self.files.contains_key(path) {
+            return;
+        }
+        self.files.insert(path.to_owned(), vfs::modified(path));
+
+        let real_path = vfs::real_path(path);
+        if let Some(dir) = real_path.as_deref().and_then(Path::parent) {
+            self.notifications.entry(dir.to_owned()).or_insert_with(|| {
+                let notification = ChangeNotification::new(dir);
+                if notification.is_none() {
+                    warn!("Polling {} for changes", dir.display());
+                }
+                notification
+            });
+        }
+    }
+
+    /// Files modified since the last call. Nothing is checked until a directory
+    /// is notified of a change or the poll interval passes.
+    pub fn changed(&mut self) -> Vec<PathBuf> {
+        // Every notification is checked, so each one is rearmed.
+        let notified = self
+            .notifications
+            .values()
+            .flatten()
+            .filter(|notification| notification.signaled())
+            .count()
+            > 0;
+        if !notified && self.last_poll.elapsed() < self.poll_interval {
+            return Vec::new();
+        }
+        self.last_poll = Instant::now();
+
+        let mut changed = Vec::new();
+        for (path, last_modified) in &mut self.files {
+            let modified = vfs::modified(path);
+            if modified != *last_modified {
+                *last_modified = modified;
+                // Deleted files come back later; editors often save by replacing.
+                if modified.is_some() {
+                    changed.push(path.clone());
+                }
+            }
+        }
+        changed
+    }
+}
+
+impl Default for FileWatcher {
+    fn default() -> Self {
+        Self::new(DEFAULT_POLL_INTERVAL)
+    }
+}
+
+/// Signaled when a file in a directory is written, renamed or created.
+struct ChangeNotification(winnt::HANDLE);
+
+impl ChangeNotification {
+    fn new(dir: &Path) -> Option<Self> {
+        let wide: Vec<u16> = dir.as_os_str().encode_wide().chain(Some(0)).collect();
+        let handle = unsafe {
+            fileapi::FindFirstChangeNotificationW(
+                wide.as_ptr(),
+                FALSE,
+                winnt::FILE_NOTIFY_CHANGE_LAST_WRITE | winnt::FILE_NOTIFY_CHANGE_FILE_NAME,
+            )
+        };
+        if handle == handleapi::INVALID_HANDLE_VALUE {
+            None
+        } else {
+            Some(Self(handle))
+        }
+    }
+
+    fn signaled(&self) -> bool {
+        unsafe {
+            if synchapi::WaitForSingleObject(self.0, 0) == winbase::WAIT_OBJECT_0 {
+                fileapi::FindNextChangeNotification(self.0);
+                true
+            } else {
+                false
+            }
+        }
+    }
+}
+
+//TODO FIXME Verify
+unsafe impl Send for ChangeNotification {}
+unsafe impl Sync for ChangeNotification {}
+
+impl Drop for ChangeNotification {
+    fn drop(&mut self) {
+        unsafe {
+            fileapi::FindCloseChangeNotification(self.0);
+        }
+    }
+}
@@ -1,7 +1,8 @@
 #[macro_use]
 mod generate;
 
-use crate::math::{Vector2d, Vector3d, Vector4d};
+use crate::error;
+use crate::math::{Matrix4x4, Vector2d, Vector3d, Vector4d};
 
 /// Re-export used in proc macro
 pub use winapi::um::d3d11::D3D11_INPUT_ELEMENT_DESC;
@@ -10,6 +11,7 @@ use winapi::shared::dxgiformat;
 
 use std::collections::HashMap;
 use std::ffi::{CStr, CString};
+use std::fmt::Write;
 
 use winapi::um::d3d11;
 
@@ -57,6 +59,104 @@ vertex_generate!(
     b"TEXCOORD\0",
     dxgiformat::DXGI_FORMAT_R32G32_FLOAT
 );
+vertex_generate!(
+    LightIndices,
+    [i32; 4],
+    b"LIGHTS\0",
+    dxgiformat::DXGI_FORMAT_R32G32B32A32_SINT
+);
+
+/// A world matrix, as four `WORLD` rows. Read in HLSL as `row_major float4x4 world: WORLD`.
+#[repr(C)]
+#[derive(Clone, Debug, Default)]
+pub struct World(Matrix4x4);
+
+impl Vertex for World {
+    fn desc(offset: usize) -> Box<dyn Iterator<Item = d3d11::D3D11_INPUT_ELEMENT_DESC>> {
+        let semantic_name = c"WORLD";
+        let row_size = std::mem::size_of::<[f32; 4]>();
+
+        Box::new((0..4).map(move |row| d3d11::D3D11_INPUT_ELEMENT_DESC {
+            SemanticName: semantic_name.as_ptr(),
+            SemanticIndex: 0,
+            Format: dxgiformat::DXGI_FORMAT_R32G32B32A32_FLOAT,
+            InputSlot: 0,
+            AlignedByteOffset: (offset + row * row_size) as u32,
+            InputSlotClass: d3d11::D3D11_INPUT_PER_VERTEX_DATA,
+            InstanceDataStepRate: 0,
+        }))
+    }
+}
+
+impl<T: Into<Matrix4x4>> std::convert::From<T> for World {
+    fn from(matrix: T) -> Self {
+        World(matrix.into())
+    }
+}
+
+impl std::ops::Deref for World {
+    type Target = Matrix4x4;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl std::ops::DerefMut for World {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+/// Makes descriptions advance once every `step_rate` instances instead of once per vertex.
+/// Used by `#[vertex(instance)]`.
+pub fn per_instance(
+    desc: impl Iterator<Item = d3d11::D3D11_INPUT_ELEMENT_DESC> + 'static,
+    step_rate: u32,
+) -> Box<dyn Iterator<Item = d3d11::D3D11_INPUT_ELEMENT_DESC>> {
+    Box::new(desc.map(move |mut desc| {
+        desc.InputSlotClass = d3d11::D3D11_INPUT_PER_INSTANCE_DATA;
+        desc.InstanceDataStepRate = step_rate;
+        desc
+    }))
+}
+
+/// A vertex shader taking every element of `layout` and doing nothing, to check layouts
+/// against with `CreateInputLayout`.
+pub fn input_signature(layout: &[d3d11::D3D11_INPUT_ELEMENT_DESC]) -> error::Result<String> {
+    let mut hlsl = String::from("struct VS_INPUT\n{\n");
+    for (idx, desc) in layout.iter().enumerate() {
+        let ty = match desc.Format {
+            dxgiformat::DXGI_FORMAT_R32G32B32A32_FLOAT => "float4",
+            dxgiformat::DXGI_FORMAT_R32G32B32_FLOAT => "float3",
+            dxgiformat::DXGI_FORMAT_R32G32_FLOAT => "float2",
+            dxgiformat::DXGI_FORMAT_R32_FLOAT => "float",
+            dxgiformat::DXGI_FORMAT_R32G32B32A32_UINT => "uint4",
+            dxgiformat::DXGI_FORMAT_R32G32B32_UINT => "uint3",
+            dxgiformat::DXGI_FORMAT_R32G32_UINT => "uint2",
+            dxgiformat::DXGI_FORMAT_R32_UINT => "uint",
+            dxgiformat::DXGI_FORMAT_R32G32B32A32_SINT => "int4",
+            dxgiformat::DXGI_FORMAT_R32G32B32_SINT => "int3",
+            dxgiformat::DXGI_FORMAT_R32G32_SINT => "int2",
+            dxgiformat::DXGI_FORMAT_R32_SINT => "int",
+            format => {
+                return Err(error::Custom(format!(
+                    "No HLSL type for vertex format {}",
+                    format
+                )))
+            }
+        };
+        let name = unsafe { CStr::from_ptr(desc.SemanticName) }.to_string_lossy();
+        writeln!(
+            hlsl,
+            "    {} element{}: {}{};",
+            ty, idx, name, desc.SemanticIndex
+        )
+        .unwrap();
+    }
+    hlsl.push_str("};\n\nvoid vsmain( VS_INPUT input ) {}\n");
+    Ok(hlsl)
+}
 
 /// `SemanticIndex` must be unique per `SemanticName`.
 /// Import this trait and call `semantic_index_fix` before collecting descriptions into an array.
@@ -97,3 +197,83 @@ impl<I: Iterator<Item = d3d11::D3D11_INPUT_ELEMENT_DESC>> Iterator for SemanticI
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::graphics::render::Instance;
+
+    fn semantics(layout: &[d3d11::D3D11_INPUT_ELEMENT_DESC]) -> Vec<(String, u32, u32)> {
+        layout
+            .iter()
+            .map(|desc| {
+                let name = unsafe { CStr::from_ptr(desc.SemanticName) };
+                (
+                    name.to_string_lossy().into_owned(),
+                    desc.SemanticIndex,
+                    desc.AlignedByteOffset,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn instance_layout() {
+        let layout: Vec<_> = Instance::desc(0).semantic_index_fix().collect();
+        assert_eq!(
+            semantics(&layout),
+            [
+                ("WORLD".into(), 0, 0),
+                ("WORLD".into(), 1, 16),
+                ("WORLD".into(), 2, 32),
+                ("WORLD".into(), 3, 48),
+                ("COLOR".into(), 0, 64),
+                ("LIGHTS".into(), 0, 76),
+            ]
+        );
+        assert!(layout.iter().all(|desc| {
+            desc.InputSlotClass == d3d11::D3D11_INPUT_PER_INSTANCE_DATA
+                && desc.InstanceDataStepRate == 1
+        }));
+    }
+
+    #[test]
+    fn per_instance_step_rate() {
+        let desc: Vec<_> = per_instance(TexCoord::desc(8), 3).collect();
+        assert_eq!(desc.len(), 1);
+        assert_eq!(desc[0].AlignedByteOffset, 8);
+        assert_eq!(desc[0].InputSlotClass, d3d11::D3D11_INPUT_PER_INSTANCE_DATA);
+        assert_eq!(desc[0].InstanceDataStepRate, 3);
+
+        let desc: Vec<_> = TexCoord::desc(0).collect();
+        assert_eq!(desc[0].InputSlotClass, d3d11::D3D11_INPUT_PER_VERTEX_DATA);
+        assert_eq!(desc[0].InstanceDataStepRate, 0);
+    }
+
+    #[test]
+    fn input_signature_of_layout() {
+        let layout: Vec<_> = Position::desc(0)
+            .chain(TexCoord::desc(16))
+            .chain(Instance::desc(0))
+            .semantic_index_fix()
+            .collect();
+        let hlsl = input_signature(&layout).unwrap();
+        assert_eq!(
+            hlsl,
+            "struct VS_INPUT\n{\n\
+             \x20   float4 element0: POSITION0;\n\
+             \x20   float2 element1: TEXCOORD0;\n\
+             \x20   float4 element2: WORLD0;\n\
+             \x20   float4 element3: WORLD1;\n\
+             \x20   float4 element4: WORLD2;\n\
+             \x20   float4 element5: WORLD3;\n\
+             \x20   float3 element6: COLOR0;\n\
+             \x20   int4 element7: LIGHTS0;\n\
+             };\n\nvoid vsmain( VS_INPUT input ) {}\n"
+        );
+
+        let mut unsupported = layout[0];
+        unsupported.Format = dxgiformat::DXGI_FORMAT_R8G8B8A8_UNORM;
+        assert!(input_signature(&[unsupported]).is_err());
+    }
+}
@@ -4,6 +4,8 @@
     clippy::uninlined_format_args
 )]
 
+#[macro_use]
+extern crate constant_buffer_derive;
 #[macro_use]
 extern crate lazy_static;
 #[macro_use]
@@ -21,4 +23,5 @@ pub mod math;
 pub mod physics;
 pub mod time;
 pub mod util;
+pub mod vfs;
 pub mod window;
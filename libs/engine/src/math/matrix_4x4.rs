@@ -185,6 +185,17 @@ impl Matrix4x4 {
     pub fn get_translation(&self) -> Vector3d {
         Vector3d::new(self.0[3][0], self.0[3][1], self.0[3][2])
     }
+
+    /// Transforms a point, including translation.
+    pub fn transform_point(&self, point: impl Into<Vector3d>) -> Vector3d {
+        self.transform_direction(point) + self.get_translation()
+    }
+
+    /// Transforms a direction, ignoring translation.
+    pub fn transform_direction(&self, direction: impl Into<Vector3d>) -> Vector3d {
+        let Vector([x, y, z]) = direction.into();
+        self.get_direction_x() * x + self.get_direction_y() * y + self.get_direction_z() * z
+    }
 }
 
 impl convert::From<[[f32; 4]; 4]> for Matrix4x4 {
@@ -248,4 +259,20 @@ mod test {
             Matrix4x4::identity().inverse().unwrap(),
         );
     }
+
+    #[test]
+    fn transform_point() {
+        use super::*;
+
+        let mut matrix = Matrix4x4::scaling(2.0);
+        matrix *= Matrix4x4::rotation_y(0.5);
+        matrix *= Matrix4x4::translation([1.0, 2.0, 3.0]);
+        let point = Vector3d::new(0.5, -1.0, 4.0);
+
+        let expected = (matrix.clone().transpose() * point.to_4d(1.0)).to_3d_unchecked();
+        let actual = matrix.transform_point(point);
+        for i in 0..3 {
+            assert!((expected.0[i] - actual.0[i]).abs() < 1e-5);
+        }
+    }
 }
@@ -0,0 +1,232 @@
+use crate::math::{Matrix4x4, Vector3d};
+use crate::physics::collision3::GjkCollider;
+use crate::prelude::*;
+
+/// Axis aligned bounding box, ordered `(min, max)` like `GjkCollider::bounding_box`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Aabb {
+    pub min: Vector3d,
+    pub max: Vector3d,
+}
+
+impl Aabb {
+    pub fn new(min: impl Into<Vector3d>, max: impl Into<Vector3d>) -> Self {
+        Self {
+            min: min.into(),
+            max: max.into(),
+        }
+    }
+
+    /// Smallest box containing every point, or `None` if there are none.
+    pub fn from_points(points: impl IntoIterator<Item = Vector3d>) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        Some(points.fold(Self::new(first, first), |aabb, point| {
+            aabb.union(&Self::new(point, point))
+        }))
+    }
+
+    pub fn center(&self) -> Vector3d {
+        (self.min + self.max) / 2.0
+    }
+
+    /// Half the size along each axis.
+    pub fn extents(&self) -> Vector3d {
+        (self.max - self.min) / 2.0
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        let mut aabb = *self;
+        for i in 0..3 {
+            aabb.min.0[i] = aabb.min.0[i].min(other.min.0[i]);
+            aabb.max.0[i] = aabb.max.0[i].max(other.max.0[i]);
+        }
+        aabb
+    }
+
+    pub fn contains(&self, point: Vector3d) -> bool {
+        (0..3).all(|i| self.min.0[i] <= point.0[i] && point.0[i] <= self.max.0[i])
+    }
+
+    pub fn intersects(&self, other: &Self) -> bool {
+        (0..3).all(|i| self.min.0[i] <= other.max.0[i] && other.min.0[i] <= self.max.0[i])
+    }
+
+    /// Box containing this one after it has been transformed by `matrix`.
+    pub fn transform(&self, matrix: &Matrix4x4) -> Self {
+        let center = matrix.transform_point(self.center());
+        let extents = self.extents();
+        let axes = [
+            matrix.get_direction_x(),
+            matrix.get_direction_y(),
+            matrix.get_direction_z(),
+        ];
+
+        let mut half = Vector3d::ORIGIN;
+        for (axis, extent) in axes.iter().zip(extents.0) {
+            for i in 0..3 {
+                half.0[i] += axis.0[i].abs() * extent;
+            }
+        }
+        Self::new(center - half, center + half)
+    }
+}
+
+impl From<(Vector3d, Vector3d)> for Aabb {
+    fn from((min, max): (Vector3d, Vector3d)) -> Self {
+        Self::new(min, max)
+    }
+}
+
+impl From<Aabb> for (Vector3d, Vector3d) {
+    fn from(aabb: Aabb) -> Self {
+        (aabb.min, aabb.max)
+    }
+}
+
+impl GjkCollider for Aabb {
+    fn support(&self, angle: Vector3d) -> Vector3d {
+        let mut point = self.min;
+        for i in 0..3 {
+            if angle.0[i] > 0.0 {
+                point.0[i] = self.max.0[i];
+            }
+        }
+        point
+    }
+
+    fn bounding_box(&self) -> (Vector3d, Vector3d) {
+        (*self).into()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vector3d,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    pub fn new(center: impl Into<Vector3d>, radius: f32) -> Self {
+        Self {
+            center: center.into(),
+            radius,
+        }
+    }
+
+    /// Sphere around `center` reaching the furthest point.
+    pub fn around(center: Vector3d, points: impl IntoIterator<Item = Vector3d>) -> Self {
+        let radius = points
+            .into_iter()
+            .map(|point| (point - center).magnitude())
+            .partial_max()
+            .unwrap_or(0.0);
+        Self::new(center, radius)
+    }
+
+    /// Sphere containing this one after it has been transformed by `matrix`.
+    pub fn transform(&self, matrix: &Matrix4x4) -> Self {
+        let scale = [
+            matrix.get_direction_x(),
+            matrix.get_direction_y(),
+            matrix.get_direction_z(),
+        ]
+        .map(Vector3d::magnitude)
+        .into_iter()
+        .fold(0.0, f32::max);
+        Self::new(matrix.transform_point(self.center), self.radius * scale)
+    }
+}
+
+impl GjkCollider for BoundingSphere {
+    fn support(&self, angle: Vector3d) -> Vector3d {
+        angle.normalize() * self.radius + self.center
+    }
+
+    fn bounding_box(&self) -> (Vector3d, Vector3d) {
+        let r = Vector3d::new(self.radius, self.radius, self.radius);
+        (self.center - r, self.center + r)
+    }
+}
+
+/// Box and sphere bounds of the same geometry.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Bounds {
+    pub aabb: Aabb,
+    pub sphere: BoundingSphere,
+}
+
+impl Bounds {
+    /// The sphere is centered on the box. Returns `None` if there are no points.
+    pub fn from_points<I>(points: I) -> Option<Self>
+    where
+        I: IntoIterator<Item = Vector3d>,
+        I::IntoIter: Clone,
+    {
+        let points = points.into_iter();
+        let aabb = Aabb::from_points(points.clone())?;
+        let sphere = BoundingSphere::around(aabb.center(), points);
+        Some(Self { aabb, sphere })
+    }
+
+    pub fn transform(&self, matrix: &Matrix4x4) -> Self {
+        Self {
+            aabb: self.aabb.transform(matrix),
+            sphere: self.sphere.transform(matrix),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn cube() -> Vec<Vector3d> {
+        (0..8)
+            .map(|i| {
+                let bit = |b: usize| if i & (1 << b) == 0 { -1.0 } else { 1.0 };
+                Vector3d::new(bit(0), bit(1) * 2.0, bit(2) * 3.0)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn from_points() {
+        let bounds = Bounds::from_points(cube()).unwrap();
+        assert_eq!(bounds.aabb, Aabb::new([-1.0, -2.0, -3.0], [1.0, 2.0, 3.0]));
+        assert_eq!(bounds.sphere.center, Vector3d::ORIGIN);
+        assert!((bounds.sphere.radius - 14.0f32.sqrt()).abs() < 1e-6);
+        assert!(Bounds::from_points(Vec::new()).is_none());
+    }
+
+    #[test]
+    fn transform_contains_points() {
+        let bounds = Bounds::from_points(cube()).unwrap();
+        let mut matrix = Matrix4x4::scaling(1.5);
+        matrix *= Matrix4x4::rotation_vec([0.3, 0.7, -0.2]);
+        matrix *= Matrix4x4::translation([5.0, -2.0, 1.0]);
+        let world = bounds.transform(&matrix);
+
+        for point in cube() {
+            let point = matrix.transform_point(point);
+            let grown = Aabb::new(
+                world.aabb.min - Vector3d::new(1e-4, 1e-4, 1e-4),
+                world.aabb.max + Vector3d::new(1e-4, 1e-4, 1e-4),
+            );
+            assert!(grown.contains(point));
+            assert!((point - world.sphere.center).magnitude() <= world.sphere.radius + 1e-4);
+        }
+    }
+
+    #[test]
+    fn matches_gjk_bounding_box() {
+        let aabb = Aabb::new([-1.0, 0.0, 2.0], [3.0, 4.0, 5.0]);
+        let (min, max) = aabb.bounding_box();
+        let support_min = aabb.support(Vector3d::new(-1.0, -1.0, -1.0));
+        let support_max = aabb.support(Vector3d::new(1.0, 1.0, 1.0));
+        assert_eq!((support_min, support_max), (min, max));
+
+        let sphere = BoundingSphere::new([1.0, 1.0, 1.0], 2.0);
+        assert!(Aabb::from(sphere.bounding_box()).intersects(&aabb));
+    }
+}
@@ -0,0 +1,145 @@
+use crate::math::{Matrix4x4, Vector3d};
+use crate::physics::{Aabb, BoundingSphere, Bounds};
+
+/// Points with a non-negative distance are on the inside.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Plane {
+    pub normal: Vector3d,
+    pub distance: f32,
+}
+
+impl Plane {
+    /// From the coefficients of `ax + by + cz + d`, normalized so distances are in world units.
+    fn from_coefficients([a, b, c, d]: [f32; 4]) -> Self {
+        let normal = Vector3d::new(a, b, c);
+        let magnitude = normal.magnitude();
+        if magnitude > 0.0 {
+            Self {
+                normal: normal / magnitude,
+                distance: d / magnitude,
+            }
+        } else {
+            Self {
+                normal,
+                distance: d,
+            }
+        }
+    }
+
+    /// Signed distance of `point` from the plane.
+    pub fn distance_to(&self, point: Vector3d) -> f32 {
+        self.normal.dot(point) + self.distance
+    }
+}
+
+/// The volume a camera sees, bounded by six planes facing inward.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Frustum {
+    /// Left, right, bottom, top, near and far.
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes from a view matrix multiplied by a projection matrix,
+    /// clipping to `0 <= z <= w` like Direct3D.
+    pub fn from_view_proj(view_proj: &Matrix4x4) -> Self {
+        let column = |i: usize| view_proj.column(i).0;
+        let [x, y, z, w] = [column(0), column(1), column(2), column(3)];
+        let add = |a: [f32; 4], b: [f32; 4]| [a[0] + b[0], a[1] + b[1], a[2] + b[2], a[3] + b[3]];
+        let sub = |a: [f32; 4], b: [f32; 4]| [a[0] - b[0], a[1] - b[1], a[2] - b[2], a[3] - b[3]];
+
+        Self {
+            planes: [add(w, x), sub(w, x), add(w, y), sub(w, y), z, sub(w, z)]
+                .map(Plane::from_coefficients),
+        }
+    }
+
+    pub fn contains_point(&self, point: Vector3d) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.distance_to(point) >= 0.0)
+    }
+
+    /// Conservative: spheres near a corner outside the frustum may still pass.
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.distance_to(sphere.center) >= -sphere.radius)
+    }
+
+    /// Conservative: boxes near a corner outside the frustum may still pass.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the normal
+            let mut corner = aabb.min;
+            for i in 0..3 {
+                if plane.normal.0[i] >= 0.0 {
+                    corner.0[i] = aabb.max.0[i];
+                }
+            }
+            plane.distance_to(corner) >= 0.0
+        })
+    }
+
+    /// Tests the sphere first, as it is cheaper, then the box.
+    pub fn intersects(&self, bounds: &Bounds) -> bool {
+        self.intersects_sphere(&bounds.sphere) && self.intersects_aabb(&bounds.aabb)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Looking down +z from the origin, seeing 1 to 100 units away.
+    fn frustum() -> Frustum {
+        let proj = Matrix4x4::perspective(std::f32::consts::FRAC_PI_2, 1.0, 1.0, 100.0);
+        Frustum::from_view_proj(&proj)
+    }
+
+    #[test]
+    fn points() {
+        let frustum = frustum();
+        assert!(frustum.contains_point(Vector3d::new(0.0, 0.0, 10.0)));
+        assert!(frustum.contains_point(Vector3d::new(9.0, -9.0, 10.0)));
+        assert!(!frustum.contains_point(Vector3d::new(11.0, 0.0, 10.0)));
+        assert!(!frustum.contains_point(Vector3d::new(0.0, 0.0, -10.0)));
+        assert!(!frustum.contains_point(Vector3d::new(0.0, 0.0, 0.5)));
+        assert!(!frustum.contains_point(Vector3d::new(0.0, 0.0, 101.0)));
+
+        let near = frustum.planes[4];
+        assert!((near.distance_to(Vector3d::new(0.0, 0.0, 3.0)) - 2.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn spheres_and_boxes() {
+        let frustum = frustum();
+        let inside =
+            Bounds::from_points([[-1.0, -1.0, 9.0].into(), [1.0, 1.0, 11.0].into()]).unwrap();
+        let straddling =
+            Bounds::from_points([[9.0, 0.0, 9.0].into(), [12.0, 1.0, 11.0].into()]).unwrap();
+        let behind =
+            Bounds::from_points([[-1.0, -1.0, -11.0].into(), [1.0, 1.0, -9.0].into()]).unwrap();
+        assert!(frustum.intersects(&inside));
+        assert!(frustum.intersects(&straddling));
+        assert!(!frustum.intersects(&behind));
+
+        // Around the camera, reaching into view
+        assert!(frustum.intersects_sphere(&BoundingSphere::new([0.0, 0.0, 0.0], 2.0)));
+        assert!(!frustum.intersects_sphere(&BoundingSphere::new([0.0, 0.0, 0.0], 0.5)));
+        assert!(!frustum.intersects_aabb(&Aabb::new([20.0, 0.0, 9.0], [30.0, 1.0, 11.0])));
+    }
+
+    #[test]
+    fn follows_the_view() {
+        // Camera at x = 50, turned to look down -x
+        let mut camera = Matrix4x4::rotation_y(-std::f32::consts::FRAC_PI_2);
+        camera.set_translation([50.0, 0.0, 0.0]);
+        let proj = Matrix4x4::perspective(std::f32::consts::FRAC_PI_2, 1.0, 1.0, 100.0);
+        let frustum = Frustum::from_view_proj(&(camera.inverse().unwrap() * proj));
+
+        assert!(frustum.contains_point(Vector3d::new(40.0, 0.0, 0.0)));
+        assert!(!frustum.contains_point(Vector3d::new(60.0, 0.0, 0.0)));
+        assert!(!frustum.contains_point(Vector3d::new(40.0, 0.0, 20.0)));
+    }
+}
@@ -1,8 +1,12 @@
+pub mod bounds;
 pub mod collision;
 pub mod collision2;
 pub mod collision3;
+pub mod frustum;
 pub mod position;
 pub mod simplex;
 pub mod simplex2;
 
+pub use bounds::{Aabb, BoundingSphere, Bounds};
+pub use frustum::{Frustum, Plane};
 pub use position::Position;
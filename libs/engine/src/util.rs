@@ -1,5 +1,7 @@
+mod hash;
 mod partial_max_min;
 
+pub use hash::fnv1a;
 pub use partial_max_min::PartialMaxMin;
 
 use crate::error::{self, HResultToResult};
@@ -0,0 +1,359 @@
+//! Virtual file system. Assets are named by portable virtual paths, like
+//! `assets/Meshes/sphere.obj`, and read from whichever mount has them.
+//!
+//! Mounts are directories, pack archives or files held in memory. Later mounts
+//! shadow earlier ones, so loose files can override a pack while iterating.
+
+mod pack;
+
+pub use pack::{PackMount, PackWriter, PACK_EXTENSION};
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::SystemTime;
+use std::{env, fs, io};
+
+use log::{info, warn};
+
+lazy_static! {
+    pub static ref VFS: RwLock<Vfs> = RwLock::new(Vfs::with_default_mounts());
+}
+
+/// Somewhere files can be read from, by paths relative to its mount point.
+pub trait Mount: Send + Sync {
+    fn contains(&self, path: &str) -> bool;
+
+    fn read(&self, path: &str) -> io::Result<Vec<u8>>;
+
+    fn modified(&self, _path: &str) -> Option<SystemTime> {
+        None
+    }
+
+    /// Where the file is on disk, if it is a file of its own.
+    fn real_path(&self, _path: &str) -> Option<PathBuf> {
+        None
+    }
+}
+
+/// A directory on disk.
+pub struct DirMount {
+    root: PathBuf,
+}
+
+impl DirMount {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+impl Mount for DirMount {
+    fn contains(&self, path: &str) -> bool {
+        self.root.join(path).is_file()
+    }
+
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        fs::read(self.root.join(path))
+    }
+
+    fn modified(&self, path: &str) -> Option<SystemTime> {
+        fs::metadata(self.root.join(path))
+            .and_then(|meta| meta.modified())
+            .ok()
+    }
+
+    fn real_path(&self, path: &str) -> Option<PathBuf> {
+        Some(self.root.join(path))
+    }
+}
+
+/// Files held in memory, for generated assets and tests.
+#[derive(Default)]
+pub struct MemoryMount {
+    files: HashMap<String, Vec<u8>>,
+}
+
+impl MemoryMount {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a file, replacing any file already at `path`.
+    pub fn insert(&mut self, path: impl AsRef<Path>, data: impl Into<Vec<u8>>) -> io::Result<()> {
+        self.files.insert(normalize(path)?, data.into());
+        Ok(())
+    }
+}
+
+impl Mount for MemoryMount {
+    fn contains(&self, path: &str) -> bool {
+        self.files.contains_key(path)
+    }
+
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        self.files.get(path).cloned().ok_or_else(|| not_found(path))
+    }
+}
+
+struct MountPoint {
+    /// Virtual directory the mount appears at, empty for the root.
+    point: String,
+    mount: Box<dyn Mount>,
+}
+
+pub struct Vfs {
+    mounts: Vec<MountPoint>,
+    root: PathBuf,
+}
+
+impl Vfs {
+    /// A file system with nothing mounted. `root` is where generated files are written.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            mounts: Vec::new(),
+            root: root.into(),
+        }
+    }
+
+    /// Mounts the asset root found by `find_root`, with any packs in it underneath.
+    pub fn with_default_mounts() -> Self {
+        let root = find_root().unwrap_or_else(|| PathBuf::from("."));
+        info!("Mounting assets from {}", root.display());
+        let mut vfs = Self::new(&root);
+
+        let mut packs: Vec<_> = fs::read_dir(&root)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == PACK_EXTENSION))
+            .collect();
+        packs.sort();
+        for pack in packs {
+            match PackMount::open(&pack) {
+                Ok(mount) => vfs.mount("", mount),
+                Err(e) => warn!("Could not mount {}: {}", pack.display(), e),
+            }
+        }
+
+        vfs.mount("", DirMount::new(root));
+        vfs
+    }
+
+    /// Where caches and other generated files go.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Mounts at the virtual directory `point`, or the root if it is empty.
+    /// The mount shadows everything mounted before it.
+    pub fn mount(&mut self, point: &str, mount: impl Mount + 'static) {
+        let point = normalize(point).unwrap_or_default();
+        self.mounts.push(MountPoint {
+            point,
+            mount: Box::new(mount),
+        });
+    }
+
+    pub fn read(&self, path: impl AsRef<Path>) -> io::Result<Vec<u8>> {
+        let path = path.as_ref();
+        if path.is_absolute() {
+            return fs::read(path);
+        }
+        let path = normalize(path)?;
+        let (mount, relative) = self.find(&path).ok_or_else(|| not_found(&path))?;
+        mount.read(relative)
+    }
+
+    pub fn read_to_string(&self, path: impl AsRef<Path>) -> io::Result<String> {
+        String::from_utf8(self.read(path)?)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "File is not UTF-8"))
+    }
+
+    pub fn exists(&self, path: impl AsRef<Path>) -> bool {
+        let path = path.as_ref();
+        if path.is_absolute() {
+            return path.is_file();
+        }
+        normalize(path).is_ok_and(|path| self.find(&path).is_some())
+    }
+
+    pub fn modified(&self, path: impl AsRef<Path>) -> Option<SystemTime> {
+        let path = path.as_ref();
+        if path.is_absolute() {
+            return fs::metadata(path).and_then(|meta| meta.modified()).ok();
+        }
+        let path = normalize(path).ok()?;
+        let (mount, relative) = self.find(&path)?;
+        mount.modified(relative)
+    }
+
+    /// Where a file is on disk, if it isn't inside a pack or in memory.
+    pub fn real_path(&self, path: impl AsRef<Path>) -> Option<PathBuf> {
+        let path = path.as_ref();
+        if path.is_absolute() {
+            return Some(path.to_owned());
+        }
+        let path = normalize(path).ok()?;
+        let (mount, relative) = self.find(&path)?;
+        mount.real_path(relative)
+    }
+
+    /// The newest mount with the file, and the path relative to it.
+    fn find<'a>(&self, path: &'a str) -> Option<(&dyn Mount, &'a str)> {
+        self.mounts.iter().rev().find_map(|mount_point| {
+            let relative = if mount_point.point.is_empty() {
+                path
+            } else {
+                path.strip_prefix(mount_point.point.as_str())?
+                    .strip_prefix('/')?
+            };
+            mount_point
+                .mount
+                .contains(relative)
+                .then_some((&*mount_point.mount, relative))
+        })
+    }
+}
+
+/// Turns a path into a virtual path: forward slashes, no `.` or `..`, and no
+/// leading slash. Backslashes are accepted as separators too.
+/// Absolute paths name real files and are left as they are.
+pub fn normalize(path: impl AsRef<Path>) -> io::Result<String> {
+    let path = path.as_ref();
+    let text = path.to_string_lossy();
+    if path.is_absolute() {
+        return Ok(text.into_owned());
+    }
+
+    let mut parts: Vec<&str> = Vec::new();
+    for part in text.split(['/', '\\']) {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop().ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("{} is outside the virtual file system", text),
+                    )
+                })?;
+            }
+            part => parts.push(part),
+        }
+    }
+    Ok(parts.join("/"))
+}
+
+/// The first directory with an `assets` directory in it, searching up from the
+/// working directory, then from the executable.
+pub fn find_root() -> Option<PathBuf> {
+    let exe_dir = env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_owned));
+    [env::current_dir().ok(), exe_dir]
+        .into_iter()
+        .flatten()
+        .find_map(|start| {
+            start
+                .ancestors()
+                .find(|dir| dir.join("assets").is_dir())
+                .map(Path::to_owned)
+        })
+}
+
+fn not_found(path: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("{} is not in any mount", path),
+    )
+}
+
+pub fn read(path: impl AsRef<Path>) -> io::Result<Vec<u8>> {
+    VFS.read().unwrap().read(path)
+}
+
+pub fn read_to_string(path: impl AsRef<Path>) -> io::Result<String> {
+    VFS.read().unwrap().read_to_string(path)
+}
+
+pub fn exists(path: impl AsRef<Path>) -> bool {
+    VFS.read().unwrap().exists(path)
+}
+
+pub fn modified(path: impl AsRef<Path>) -> Option<SystemTime> {
+    VFS.read().unwrap().modified(path)
+}
+
+pub fn real_path(path: impl AsRef<Path>) -> Option<PathBuf> {
+    VFS.read().unwrap().real_path(path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn memory(files: &[(&str, &str)]) -> MemoryMount {
+        let mut mount = MemoryMount::new();
+        for (path, data) in files {
+            mount.insert(path, *data).unwrap();
+        }
+        mount
+    }
+
+    #[test]
+    fn normalizes_paths() {
+        assert_eq!(
+            normalize("assets\\Meshes\\sphere.obj").unwrap(),
+            "assets/Meshes/sphere.obj"
+        );
+        assert_eq!(
+            normalize("./shaders//skybox/../tone_map/pixel_shader.hlsl").unwrap(),
+            "shaders/tone_map/pixel_shader.hlsl"
+        );
+        assert_eq!(normalize("").unwrap(), "");
+        assert!(normalize("assets/../../secrets.txt").is_err());
+    }
+
+    #[test]
+    fn later_mounts_shadow_earlier_ones() {
+        let mut vfs = Vfs::new(".");
+        vfs.mount("", memory(&[("a.txt", "packed"), ("b.txt", "packed")]));
+        vfs.mount("", memory(&[("a.txt", "loose")]));
+
+        assert_eq!(vfs.read_to_string("a.txt").unwrap(), "loose");
+        assert_eq!(vfs.read_to_string("./b.txt").unwrap(), "packed");
+        let missing = vfs.read("c.txt").unwrap_err();
+        assert_eq!(missing.kind(), io::ErrorKind::NotFound);
+        assert!(!vfs.exists("c.txt"));
+    }
+
+    #[test]
+    fn mount_points() {
+        let mut vfs = Vfs::new(".");
+        vfs.mount("shaders/", memory(&[("skybox/pixel_shader.hlsl", "hlsl")]));
+
+        assert!(vfs.exists("shaders\\skybox\\pixel_shader.hlsl"));
+        assert!(!vfs.exists("skybox/pixel_shader.hlsl"));
+        assert!(!vfs.exists("shadersskybox/pixel_shader.hlsl"));
+        assert_eq!(vfs.real_path("shaders/skybox/pixel_shader.hlsl"), None);
+    }
+
+    #[test]
+    fn directories() {
+        let dir = env::temp_dir().join(format!("vfs_directories_{}", std::process::id()));
+        fs::create_dir_all(dir.join("assets")).unwrap();
+        fs::write(dir.join("assets/file.txt"), "on disk").unwrap();
+
+        let mut vfs = Vfs::new(&dir);
+        vfs.mount("", DirMount::new(&dir));
+        assert_eq!(vfs.read_to_string("assets/file.txt").unwrap(), "on disk");
+        assert!(vfs.modified("assets/file.txt").is_some());
+        assert_eq!(
+            vfs.real_path("assets/file.txt"),
+            Some(dir.join("assets/file.txt"))
+        );
+        assert!(!vfs.exists("assets"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,285 @@
+//! Pack archives, many files in one that are read without unpacking.
+//!
+//! Layout, all little endian: magic, version, entry count, then each entry's
+//! path, offset and size, then the contents of every file.
+
+use super::{normalize, not_found, Mount};
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+const MAGIC: [u8; 4] = *b"TEPK";
+const VERSION: u32 = 1;
+pub const PACK_EXTENSION: &str = "pack";
+
+struct Entry {
+    offset: u64,
+    size: u64,
+}
+
+/// A pack archive on disk. Only its index is kept in memory.
+pub struct PackMount {
+    path: PathBuf,
+    entries: HashMap<String, Entry>,
+    modified: Option<SystemTime>,
+}
+
+impl PackMount {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let entries = read_index(&mut BufReader::new(file), len)?;
+        Ok(Self {
+            path: path.to_owned(),
+            entries,
+            modified: fs::metadata(path).and_then(|meta| meta.modified()).ok(),
+        })
+    }
+
+    /// Virtual paths of every file in the pack, sorted.
+    pub fn files(&self) -> Vec<&str> {
+        let mut files: Vec<_> = self.entries.keys().map(String::as_str).collect();
+        files.sort_unstable();
+        files
+    }
+}
+
+impl Mount for PackMount {
+    fn contains(&self, path: &str) -> bool {
+        self.entries.contains_key(path)
+    }
+
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        let entry = self.entries.get(path).ok_or_else(|| not_found(path))?;
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(entry.offset))?;
+        // The pack may have shrunk since it was opened, so read only what is there.
+        let mut data = Vec::new();
+        file.take(entry.size).read_to_end(&mut data)?;
+        if data.len() as u64 != entry.size {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("Pack entry {} is truncated", path),
+            ));
+        }
+        Ok(data)
+    }
+
+    /// Files in a pack change when the pack does.
+    fn modified(&self, path: &str) -> Option<SystemTime> {
+        self.modified.filter(|_| self.contains(path))
+    }
+}
+
+/// Reads the index of a pack `len` bytes long. Every length is checked against
+/// what is left of the pack before anything is allocated for it.
+fn read_index(reader: &mut impl Read, len: u64) -> io::Result<HashMap<String, Entry>> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(invalid("Not a pack archive"));
+    }
+    if read_u32(reader)? != VERSION {
+        return Err(invalid("Unsupported pack version"));
+    }
+
+    let count = read_u32(reader)?;
+    let mut index_end = 12u64;
+    let mut entries = HashMap::new();
+    for _ in 0..count {
+        let path_len = u64::from(read_u32(reader)?);
+        index_end += 4 + path_len + 16;
+        if index_end > len {
+            return Err(invalid("Pack index runs past the end of the file"));
+        }
+        let mut path = vec![0; path_len as usize];
+        reader.read_exact(&mut path)?;
+        let path = String::from_utf8(path).map_err(|_| invalid("Pack path is not UTF-8"))?;
+        let offset = read_u64(reader)?;
+        let size = read_u64(reader)?;
+        entries.insert(path, Entry { offset, size });
+    }
+    for entry in entries.values() {
+        let in_bounds = entry
+            .offset
+            .checked_add(entry.size)
+            .is_some_and(|end| entry.offset >= index_end && end <= len);
+        if !in_bounds {
+            return Err(invalid("Pack entry runs past the end of the file"));
+        }
+    }
+    Ok(entries)
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// Collects files, then writes them out as a pack archive.
+#[derive(Default)]
+pub struct PackWriter {
+    files: BTreeMap<String, Vec<u8>>,
+}
+
+impl PackWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a file at a virtual path, replacing any file already there.
+    pub fn add(&mut self, path: impl AsRef<Path>, data: Vec<u8>) -> io::Result<()> {
+        self.files.insert(normalize(path)?, data);
+        Ok(())
+    }
+
+    /// Adds every file under `dir`, at the same place under the virtual directory `prefix`.
+    /// Returns how many were added.
+    pub fn add_dir(&mut self, dir: impl AsRef<Path>, prefix: &str) -> io::Result<usize> {
+        let mut added = 0;
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let path = format!("{}/{}", prefix, name.to_string_lossy());
+            if entry.file_type()?.is_dir() {
+                added += self.add_dir(entry.path(), &path)?;
+            } else {
+                self.add(path, fs::read(entry.path())?)?;
+                added += 1;
+            }
+        }
+        Ok(added)
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&(self.files.len() as u32).to_le_bytes())?;
+
+        // Contents start right after the index.
+        let mut offset = 12
+            + self
+                .files
+                .keys()
+                .map(|path| 4 + path.len() as u64 + 16)
+                .sum::<u64>();
+        for (path, data) in &self.files {
+            writer.write_all(&(path.len() as u32).to_le_bytes())?;
+            writer.write_all(path.as_bytes())?;
+            writer.write_all(&offset.to_le_bytes())?;
+            writer.write_all(&(data.len() as u64).to_le_bytes())?;
+            offset += data.len() as u64;
+        }
+        for data in self.files.values() {
+            writer.write_all(data)?;
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::env;
+
+    #[test]
+    fn round_trip() {
+        let mut writer = PackWriter::new();
+        writer
+            .add(
+                "shaders\\skybox\\pixel_shader.hlsl",
+                b"float4 psmain".to_vec(),
+            )
+            .unwrap();
+        writer.add("assets/empty.txt", Vec::new()).unwrap();
+        writer.add("assets/data.bin", (0..=255).collect()).unwrap();
+        assert_eq!(writer.len(), 3);
+
+        let path = env::temp_dir().join(format!("round_trip_{}.pack", std::process::id()));
+        writer.save(&path).unwrap();
+        let pack = PackMount::open(&path).unwrap();
+
+        assert_eq!(
+            pack.files(),
+            [
+                "assets/data.bin",
+                "assets/empty.txt",
+                "shaders/skybox/pixel_shader.hlsl"
+            ]
+        );
+        assert_eq!(
+            pack.read("shaders/skybox/pixel_shader.hlsl").unwrap(),
+            b"float4 psmain"
+        );
+        assert_eq!(pack.read("assets/data.bin").unwrap().len(), 256);
+        assert!(pack.read("assets/empty.txt").unwrap().is_empty());
+        assert!(pack.read("assets/missing.txt").is_err());
+        assert!(pack.modified("assets/data.bin").is_some());
+        assert!(pack.modified("assets/missing.txt").is_none());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let mut bytes = Vec::new();
+        PackWriter::new().write(&mut bytes).unwrap();
+        assert!(read_index(&mut &bytes[..], 12).unwrap().is_empty());
+
+        bytes[0] = b'X';
+        assert!(read_index(&mut &bytes[..], 12).is_err());
+        assert!(read_index(&mut &bytes[..6], 6).is_err());
+    }
+
+    #[test]
+    fn rejects_corrupt_lengths() {
+        let mut writer = PackWriter::new();
+        writer.add("a.txt", b"abc".to_vec()).unwrap();
+        let mut bytes = Vec::new();
+        writer.write(&mut bytes).unwrap();
+        let len = bytes.len() as u64;
+        assert_eq!(read_index(&mut &bytes[..], len).unwrap().len(), 1);
+
+        let corrupt = |at: usize, value: &[u8]| {
+            let mut bytes = bytes.clone();
+            bytes[at..at + value.len()].copy_from_slice(value);
+            read_index(&mut &bytes[..], len)
+        };
+        // Path length, offset and size of the only entry.
+        assert!(corrupt(12, &u32::MAX.to_le_bytes()).is_err());
+        assert!(corrupt(21, &u64::MAX.to_le_bytes()).is_err());
+        assert!(corrupt(21, &0u64.to_le_bytes()).is_err());
+        assert!(corrupt(29, &u64::MAX.to_le_bytes()).is_err());
+        assert!(corrupt(29, &4u64.to_le_bytes()).is_err());
+        // More entries than the file has room for.
+        assert!(corrupt(8, &u32::MAX.to_le_bytes()).is_err());
+    }
+}
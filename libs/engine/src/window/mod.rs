@@ -3,6 +3,7 @@ mod hwnd;
 pub use hwnd::Hwnd;
 
 use crate::error::Result;
+use crate::graphics::GRAPHICS;
 use crate::input::INPUT;
 use crate::util::os_vec;
 
@@ -139,6 +140,7 @@ impl<A: Application> Window<A> {
                 if self.moving.swap(false, Ordering::Relaxed) {
                     app.on_move();
                 }
+                GRAPHICS.lock().unwrap().update_resources();
                 app.on_update();
             }
 
@@ -3,8 +3,9 @@ use engine::graphics::material;
 pub struct DirectionalLight;
 
 impl material::Template for DirectionalLight {
-    const PIXEL_SHADER_PATH: &'static str = "shaders\\directional_light\\pixel_shader.hlsl";
-    const VERTEX_SHADER_PATH: &'static str = "shaders\\directional_light\\vertex_shader.hlsl";
+    const PIXEL_SHADER_PATH: &'static str = "shaders/directional_light/pixel_shader.hlsl";
+    const VERTEX_SHADER_PATH: &'static str = "shaders/directional_light/vertex_shader.hlsl";
+    const KEYWORDS: &'static [&'static str] = &["SPECULAR", material::INSTANCED];
 
     type Environment = super::Environment;
 }
@@ -1,7 +1,11 @@
+#[macro_use]
+extern crate constant_buffer_derive;
+
 pub mod dir_light_bump_map;
 pub mod directional_light;
 pub mod point_light;
 pub mod skybox;
+pub mod tone_map;
 
 use engine::math::{Matrix4x4, Vector4d};
 
@@ -9,17 +13,15 @@ pub use dir_light_bump_map::DirLightBumpMap;
 pub use directional_light::DirectionalLight;
 pub use point_light::PointLight;
 pub use skybox::Skybox;
+pub use tone_map::{ToneMap, ToneMapOperator, ToneMapSettings};
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, ConstantBuffer)]
 #[repr(C, align(16))]
 pub struct Environment {
     pub view: Matrix4x4,
     pub proj: Matrix4x4,
 
-    pub light_dir: Vector4d,
     pub camera_pos: Vector4d,
-    pub light_pos: Vector4d,
 
-    pub light_rad: f32,
     pub time: f32,
 }
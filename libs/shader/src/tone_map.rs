@@ -0,0 +1,55 @@
+use engine::graphics::material;
+use engine::graphics::resource::shader::HlslType;
+
+/// Maps an HDR render target onto the screen. Draw with `Render::draw_fullscreen`.
+pub struct ToneMap;
+
+impl material::Template for ToneMap {
+    const PIXEL_SHADER_PATH: &'static str = "shaders/tone_map/pixel_shader.hlsl";
+    const VERTEX_SHADER_PATH: &'static str = "shaders/tone_map/vertex_shader.hlsl";
+
+    type Environment = ToneMapSettings;
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(u32)]
+pub enum ToneMapOperator {
+    /// `c / (1 + c)`, never fully white.
+    #[default]
+    Reinhard = 0,
+    /// Filmic curve with more contrast and a white point.
+    Aces = 1,
+    /// Clips everything above one.
+    Clamp = 2,
+}
+
+impl HlslType for ToneMapOperator {
+    const SIZE: usize = 4;
+}
+
+impl ToneMapOperator {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Reinhard => Self::Aces,
+            Self::Aces => Self::Clamp,
+            Self::Clamp => Self::Reinhard,
+        }
+    }
+}
+
+#[derive(Debug, ConstantBuffer)]
+#[repr(C, align(16))]
+pub struct ToneMapSettings {
+    /// Scene color is multiplied by this before mapping.
+    pub exposure: f32,
+    pub operator: ToneMapOperator,
+}
+
+impl Default for ToneMapSettings {
+    fn default() -> Self {
+        Self {
+            exposure: 1.0,
+            operator: ToneMapOperator::default(),
+        }
+    }
+}
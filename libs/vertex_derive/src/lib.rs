@@ -4,12 +4,26 @@ use proc_macro2::TokenStream;
 use quote::{quote, quote_spanned};
 use syn::spanned::Spanned;
 use syn::{
-    parse_macro_input, parse_quote, Data, DeriveInput, Field, Fields, GenericParam, Generics,
+    parse_macro_input, parse_quote, Attribute, Data, DeriveInput, Error, Field, Fields,
+    GenericParam, Generics, Lit, Meta, NestedMeta,
 };
 
-#[proc_macro_derive(Vertex)]
+/// Describes each field in turn, packed in declaration order.
+///
+/// `#[vertex(instance)]` makes the whole struct per-instance data, advancing once per
+/// instance instead of once per vertex, or once every `n` instances with
+/// `#[vertex(instance, step_rate = n)]`.
+#[proc_macro_derive(Vertex, attributes(vertex))]
 pub fn derive_vertex(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
+    proc_macro::TokenStream::from(expand(input))
+}
+
+fn expand(input: DeriveInput) -> TokenStream {
+    let step_rate = match instance_step_rate(&input.attrs) {
+        Ok(step_rate) => step_rate,
+        Err(e) => return e.into_compile_error(),
+    };
 
     let name = input.ident;
 
@@ -17,16 +31,56 @@ pub fn derive_vertex(input: proc_macro::TokenStream) -> proc_macro::TokenStream
     let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
 
     let chain = desc_chain(&input.data);
+    let body = match step_rate {
+        Some(step_rate) => quote! {
+            let desc = { #chain };
+            engine::graphics::vertex::per_instance(desc, #step_rate)
+        },
+        None => chain,
+    };
 
-    let expanded = quote! {
+    quote! {
         impl #impl_generics engine::graphics::vertex::Vertex for #name #ty_generics #where_clause {
             fn desc(offset: usize) -> Box<dyn Iterator<Item = engine::graphics::vertex::D3D11_INPUT_ELEMENT_DESC>> {
-                #chain
+                #body
             }
         }
-    };
+    }
+}
+
+/// `Some` with the step rate if the struct is per-instance data.
+fn instance_step_rate(attributes: &[Attribute]) -> syn::Result<Option<u32>> {
+    for attribute in attributes {
+        if !attribute.path.is_ident("vertex") {
+            continue;
+        }
+        let meta = attribute.parse_meta()?;
+        let expected = || Error::new_spanned(&meta, "Expected #[vertex(instance, step_rate = n)]");
+        let list = match &meta {
+            Meta::List(list) => list,
+            _ => return Err(expected()),
+        };
 
-    proc_macro::TokenStream::from(expanded)
+        let mut instance = false;
+        let mut step_rate = 1;
+        for nested in &list.nested {
+            match nested {
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("instance") => instance = true,
+                NestedMeta::Meta(Meta::NameValue(pair)) if pair.path.is_ident("step_rate") => {
+                    step_rate = match &pair.lit {
+                        Lit::Int(rate) => rate.base10_parse()?,
+                        _ => return Err(expected()),
+                    };
+                }
+                _ => return Err(expected()),
+            }
+        }
+        if !instance {
+            return Err(expected());
+        }
+        return Ok(Some(step_rate));
+    }
+    Ok(None)
 }
 
 fn add_trait_bounds(mut generics: Generics) -> Generics {
@@ -70,3 +124,125 @@ fn desc_chain_inner<'a>(iter: impl Iterator<Item = &'a Field>) -> TokenStream {
         Box::new(iter)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn step_rate(input: DeriveInput) -> syn::Result<Option<u32>> {
+        instance_step_rate(&input.attrs)
+    }
+
+    #[test]
+    fn parses_instance_attribute() {
+        assert_eq!(
+            step_rate(parse_quote!(
+                struct V;
+            ))
+            .unwrap(),
+            None
+        );
+        assert_eq!(
+            step_rate(parse_quote!(
+                #[derive(Clone)]
+                struct V;
+            ))
+            .unwrap(),
+            None
+        );
+        assert_eq!(
+            step_rate(parse_quote!(
+                #[vertex(instance)]
+                struct V;
+            ))
+            .unwrap(),
+            Some(1)
+        );
+        assert_eq!(
+            step_rate(parse_quote!(
+                #[vertex(instance, step_rate = 4)]
+                struct V;
+            ))
+            .unwrap(),
+            Some(4)
+        );
+        assert_eq!(
+            step_rate(parse_quote!(
+                #[vertex(step_rate = 2, instance)]
+                struct V;
+            ))
+            .unwrap(),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn rejects_bad_attributes() {
+        let inputs: [DeriveInput; 6] = [
+            parse_quote!(
+                #[vertex]
+                struct V;
+            ),
+            parse_quote!(
+                #[vertex = "instance"]
+                struct V;
+            ),
+            parse_quote!(
+                #[vertex(step_rate = 4)]
+                struct V;
+            ),
+            parse_quote!(
+                #[vertex(instance, step_rate = "4")]
+                struct V;
+            ),
+            parse_quote!(
+                #[vertex(instance, step_rate = 1.5)]
+                struct V;
+            ),
+            parse_quote!(
+                #[vertex(instance, per_vertex)]
+                struct V;
+            ),
+        ];
+        for input in inputs {
+            let error = step_rate(input).unwrap_err().to_string();
+            assert!(
+                error.contains("#[vertex(instance, step_rate = n)]"),
+                "{}",
+                error
+            );
+        }
+
+        let expanded = expand(parse_quote!(
+            #[vertex(instances)]
+            struct V;
+        ))
+        .to_string();
+        assert!(expanded.starts_with("compile_error"), "{}", expanded);
+    }
+
+    #[test]
+    fn expands_per_instance() {
+        let expanded = expand(parse_quote! {
+            #[vertex(instance, step_rate = 3)]
+            struct V {
+                a: A,
+                b: B,
+            }
+        })
+        .to_string();
+        assert!(
+            expanded.contains("per_instance (desc , 3u32)"),
+            "{}",
+            expanded
+        );
+        assert!(expanded.contains("A :: desc (offset)"), "{}", expanded);
+        assert!(expanded.contains("B :: desc (offset)"), "{}", expanded);
+
+        let expanded = expand(parse_quote!(
+            struct V(A);
+        ))
+        .to_string();
+        assert!(!expanded.contains("per_instance"), "{}", expanded);
+    }
+}
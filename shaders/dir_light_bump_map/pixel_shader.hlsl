@@ -10,30 +10,13 @@ struct PS_INPUT
     float2 tex_coord: TEXCOORD0;
     float3 cam_dir: CAMDIR;
     row_major float3x3 tbn: TBN;
+    float3 world_pos: TEXCOORD1;
+    nointerpolation int4 lights: LIGHTS;
 };
 
-cbuffer constant: register(b0)
-{
-    row_major float4x4 m_view;
-    row_major float4x4 m_proj;
-
-    float4 m_light_dir;
-    float4 m_camera_pos;
-    float4 m_light_pos;
-    
-    float m_light_rad;
-    float time;
-};
-
-cbuffer constant1: register(b1)
-{
-    row_major float4x4 m_world;
-};
-
-cbuffer constant1: register(b2)
-{
-    float3 color;
-};
+#include "environment.hlsl"
+#include "entity.hlsl"
+#include "lights.hlsl"
 
 float4 psmain( PS_INPUT input ) : SV_Target
 {   
@@ -43,34 +26,46 @@ float4 psmain( PS_INPUT input ) : SV_Target
     normal.xyz = normal.xyz * 2.0 - 1.0;
     normal.xyz = mul(normal.xyz, input.tbn);
 
-    float dot_n1 = dot(m_light_dir.xyz, input.tbn[2]);
-
     //Ambient
     float3 ka = 8.5;
     float3 ia = float3(0.09, 0.082, 0.082);
     ia *= (color.rgb);
     float3 ambient_light = ka * ia;
 
-    //Diffuse
-    float3 kd = 0.7;
-    float amount_diffuse_light = dot(m_light_dir.xyz, normal.xyz);
-    float3 id = float3(1.0, 1.0, 1.0);
-    id *= (color.rgb);
+    float3 light = ambient_light;
 
-    float3 diffuse_light = kd * id * amount_diffuse_light;
+    [unroll]
+    for (int i = 0; i < MAX_OBJECT_LIGHTS; ++i)
+    {
+        int idx = input.lights[i];
+        if (!is_light(idx))
+        {
+            continue;
+        }
+        float3 light_dir;
+        float3 radiance = light_radiance(idx, input.world_pos, light_dir);
+        float dot_n1 = dot(light_dir, input.tbn[2]);
 
-    //Specular
-    float ks = 1.0;
-    float3 is = float3(1.0, 1.0, 1.0);
-    float3 reflected_light = reflect(m_light_dir.xyz, normal.xyz);
-    float shininess = 30.0;
-    float3 amount_specular_light = 0.0;
-    if (dot_n1 > 0) {
-        amount_specular_light = pow(max(0.0, dot(reflected_light, input.cam_dir)), shininess);
-    }
-    float3 specular_light = ks * amount_specular_light * is;
+        //Diffuse
+        float3 kd = 0.7;
+        float amount_diffuse_light = max(0.0, dot(light_dir, normal.xyz));
+        float3 id = color.rgb * radiance;
 
-    float3 light = ambient_light + diffuse_light + specular_light;
+        float3 diffuse_light = kd * id * amount_diffuse_light;
+
+        //Specular
+        float ks = 1.0;
+        float3 is = radiance;
+        float3 reflected_light = reflect(light_dir, normal.xyz);
+        float shininess = 30.0;
+        float3 amount_specular_light = 0.0;
+        if (dot_n1 > 0) {
+            amount_specular_light = pow(max(0.0, dot(reflected_light, input.cam_dir)), shininess);
+        }
+        float3 specular_light = ks * amount_specular_light * is;
+
+        light += diffuse_light + specular_light;
+    }
 
     return float4(light, 1.0);
 }
\ No newline at end of file
@@ -7,30 +7,13 @@ struct PS_INPUT
     float2 tex_coord: TEXCOORD0;
     float3 normal: NORMAL0;
     float3 cam_dir: CAMDIR;
+    float3 world_pos: TEXCOORD1;
+    nointerpolation int4 lights: LIGHTS;
 };
 
-cbuffer constant: register(b0)
-{
-    row_major float4x4 m_view;
-    row_major float4x4 m_proj;
-
-    float4 m_light_dir;
-    float4 m_camera_pos;
-    float4 m_light_pos;
-    
-    float m_light_rad;
-    float time;
-};
-
-cbuffer constant1: register(b1)
-{
-    row_major float4x4 m_world;
-};
-
-cbuffer constant1: register(b2)
-{
-    float3 color;
-};
+#include "environment.hlsl"
+#include "entity.hlsl"
+#include "lights.hlsl"
 
 float4 psmain( PS_INPUT input ) : SV_Target
 {   
@@ -42,23 +25,40 @@ float4 psmain( PS_INPUT input ) : SV_Target
     ia *= (color.rgb);
     float3 ambient_light = ka * ia;
 
-    //Diffuse
-    float3 kd = 0.7;
-    float amount_diffuse_light = dot(m_light_dir.xyz, input.normal);
-    float3 id = float3(1.0, 1.0, 1.0);
-    id *= (color.rgb);
+    float3 light = ambient_light;
+
+    [unroll]
+    for (int i = 0; i < MAX_OBJECT_LIGHTS; ++i)
+    {
+        int idx = input.lights[i];
+        if (!is_light(idx))
+        {
+            continue;
+        }
+        float3 light_dir;
+        float3 radiance = light_radiance(idx, input.world_pos, light_dir);
+
+        //Diffuse
+        float3 kd = 0.7;
+        float amount_diffuse_light = max(0.0, dot(light_dir, input.normal));
+        float3 id = color.rgb * radiance;
 
-    float3 diffuse_light = kd * id * amount_diffuse_light;
+        float3 diffuse_light = kd * id * amount_diffuse_light;
 
-    //Specular
-    float ks = 0.0;
-    float3 is = float3(1.0, 1.0, 1.0);
-    float3 reflected_light = reflect(m_light_dir.xyz, input.normal);
-    float shininess = 30.0;
-    float3 amount_specular_light = pow(max(0.0, dot(reflected_light, input.cam_dir)), shininess);
-    float3 specular_light = ks * amount_specular_light * is;
+        //Specular
+#ifdef SPECULAR
+        float ks = 1.0;
+#else
+        float ks = 0.0;
+#endif
+        float3 is = radiance;
+        float3 reflected_light = reflect(light_dir, input.normal);
+        float shininess = 30.0;
+        float3 amount_specular_light = pow(max(0.0, dot(reflected_light, input.cam_dir)), shininess);
+        float3 specular_light = ks * amount_specular_light * is;
 
-    float3 light = ambient_light + diffuse_light + specular_light;
+        light += diffuse_light + specular_light;
+    }
 
     return float4(light, 1.0);
 }
\ No newline at end of file
@@ -22,7 +22,7 @@ struct PS_INPUT
     float3 cam_dir: CAMDIR;
 };
 
-cbuffer constant: register(b0)
+cbuffer environment: register(b0)
 {
     row_major float4x4 m_view;
     row_major float4x4 m_proj;
@@ -31,15 +31,7 @@ cbuffer constant: register(b0)
     float cloud_offset;
 };
 
-cbuffer constant1: register(b1)
-{
-    row_major float4x4 m_world;
-};
-
-cbuffer constant1: register(b2)
-{
-    float3 color;
-};
+#include "entity.hlsl"
 
 float4 psmain( PS_INPUT input ) : SV_Target
 {   
@@ -15,7 +15,7 @@ struct VS_OUTPUT
     float3 cam_dir: CAMDIR;
 };
 
-cbuffer constant: register(b0)
+cbuffer environment: register(b0)
 {
     row_major float4x4 m_view;
     row_major float4x4 m_proj;
@@ -24,15 +24,7 @@ cbuffer constant: register(b0)
     float cloud_offset;
 };
 
-cbuffer constant1: register(b1)
-{
-    row_major float4x4 m_world;
-};
-
-cbuffer constant1: register(b2)
-{
-    float3 color;
-};
+#include "entity.hlsl"
 
 VS_OUTPUT vsmain( VS_INPUT input )
 {   
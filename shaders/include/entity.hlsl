@@ -0,0 +1,28 @@
+// Set for each entity drawn, or read for each instance from the second vertex
+// stream when compiled with INSTANCED. Vertex shaders add INSTANCE_INPUT to their
+// input, place vertices with WORLD_MATRIX(input) and pass OBJECT_LIGHTS(input) on to
+// the pixel shader.
+#ifdef INSTANCED
+#define INSTANCE_INPUT \
+    row_major float4x4 world: WORLD; \
+    float3 color: COLOR; \
+    int4 object_lights: LIGHTS;
+#define WORLD_MATRIX(input) (input.world)
+#define OBJECT_LIGHTS(input) (input.object_lights)
+#else
+#define INSTANCE_INPUT
+#define WORLD_MATRIX(input) m_world
+#define OBJECT_LIGHTS(input) m_object_lights
+
+cbuffer transform: register(b1)
+{
+    row_major float4x4 m_world;
+};
+
+cbuffer mesh_info: register(b2)
+{
+    // Indices into the lights cbuffer
+    int4 m_object_lights;
+    float3 color;
+};
+#endif
@@ -0,0 +1,10 @@
+// Matches the Environment struct shared by the lighting templates
+cbuffer environment: register(b0)
+{
+    row_major float4x4 m_view;
+    row_major float4x4 m_proj;
+
+    float4 m_camera_pos;
+
+    float time;
+};
@@ -0,0 +1,43 @@
+// Every light of the frame, set from a LightList. Matches the Lights struct.
+#define MAX_LIGHTS 16
+// Lights each object is lit by, as indices into the lights below
+#define MAX_OBJECT_LIGHTS 4
+
+cbuffer lights: register(b4)
+{
+    // xyz position, w 0 for directional lights
+    float4 m_light_positions[MAX_LIGHTS];
+    // xyz direction the light shines in, w range
+    float4 m_light_directions[MAX_LIGHTS];
+    // rgb color times intensity
+    float4 m_light_colors[MAX_LIGHTS];
+    // x cosine of the outer angle of spot lights, y of the inner angle
+    float4 m_light_cones[MAX_LIGHTS];
+    uint m_light_count;
+};
+
+// Whether `idx`, one of an object's lights, is a light. Unused ones are -1.
+bool is_light(int idx)
+{
+    return idx >= 0 && idx < (int)m_light_count;
+}
+
+// The light `idx` sheds on `world_pos`, and the direction to it
+float3 light_radiance(int idx, float3 world_pos, out float3 light_dir)
+{
+    float3 color = m_light_colors[idx].rgb;
+    if (m_light_positions[idx].w == 0.0)
+    {
+        light_dir = -m_light_directions[idx].xyz;
+        return color;
+    }
+
+    float3 to_light = m_light_positions[idx].xyz - world_pos;
+    float light_len = length(to_light);
+    light_dir = to_light / max(light_len, 0.0001);
+
+    // Fades smoothly to nothing at the range
+    float fade = saturate(1.0 - pow(light_len / m_light_directions[idx].w, 2));
+    float cone = smoothstep(m_light_cones[idx].x, m_light_cones[idx].y, dot(-light_dir, m_light_directions[idx].xyz));
+    return color * fade * fade * cone;
+}
@@ -10,28 +10,20 @@ struct PS_INPUT
     float2 tex_coord: TEXCOORD0;
     float3 normal: NORMAL0;
     float3 world_pos: TEXCOORD1;
+    nointerpolation int4 lights: LIGHTS;
 };
 
-cbuffer constant: register(b0)
-{
-    row_major float4x4 m_view;
-    row_major float4x4 m_proj;
-    float4 m_light_dir;
-    float4 m_camera_pos;
-    float4 m_light_pos;
-    float m_light_rad;
-    float time;
-};
-
-cbuffer constant1: register(b1)
-{
-    row_major float4x4 m_world;
-};
+#include "environment.hlsl"
+#include "entity.hlsl"
+#include "lights.hlsl"
 
-cbuffer constant1: register(b2)
+// Set by transparent materials, such as windows
+#ifdef TRANSPARENT
+cbuffer material: register(b3)
 {
-    float3 color;
+    float opacity;
 };
+#endif
 
 float4 psmain( PS_INPUT input ) : SV_Target
 {      
@@ -40,39 +32,46 @@ float4 psmain( PS_INPUT input ) : SV_Target
     //float3 tex = float3(1.0, 1.0, 1.0);
     //float3 tex = color;
 
-    float3 light_dir = normalize(m_light_pos.xyz - input.world_pos.xyz);
-    float light_len = length(m_light_pos.xyz - input.world_pos.xyz);
-    float fade = max(0, light_len - m_light_rad);
-
-    float const_func = 1.0;
-    float linear_func = 2.0;
-    float quad_func = 2.0;
-
-    float attenuation = const_func + linear_func * fade + quad_func * fade * fade;
-
     //Ambient
     float3 ka = 1.5;
     float3 ia = float3(0.09, 0.082, 0.082);
     ia *= tex;
     float3 ambient_light = ka * ia;
 
-    //Diffuse
-    float3 kd = 0.7;
-    float amount_diffuse_light = max(0.0, dot(light_dir, input.normal));
-    float3 id = float3(1.0, 1.0, 1.0);
-    id *= tex;
-    float3 diffuse_light = (kd * amount_diffuse_light * id) / attenuation;
-
-    //Specular
-    float ks = 1.0;
+    float3 light = ambient_light;
     float3 cam_dir = normalize(input.world_pos.xyz - m_camera_pos.xyz); 
-    float3 is = float3(1.0, 1.0, 1.0);
-    float3 reflected_light = reflect(light_dir, input.normal);
-    float shininess = 30.0;
-    float3 amount_specular_light = pow(max(0.0, dot(reflected_light, cam_dir)), shininess);
-    float3 specular_light = (ks * amount_specular_light * is) / attenuation;
-
-    float3 light = ambient_light + diffuse_light + specular_light;
 
+    [unroll]
+    for (int i = 0; i < MAX_OBJECT_LIGHTS; ++i)
+    {
+        int idx = input.lights[i];
+        if (!is_light(idx))
+        {
+            continue;
+        }
+        float3 light_dir;
+        float3 radiance = light_radiance(idx, input.world_pos, light_dir);
+
+        //Diffuse
+        float3 kd = 0.7;
+        float amount_diffuse_light = max(0.0, dot(light_dir, input.normal));
+        float3 id = tex * radiance;
+        float3 diffuse_light = kd * amount_diffuse_light * id;
+
+        //Specular
+        float ks = 1.0;
+        float3 is = radiance;
+        float3 reflected_light = reflect(light_dir, input.normal);
+        float shininess = 30.0;
+        float3 amount_specular_light = pow(max(0.0, dot(reflected_light, cam_dir)), shininess);
+        float3 specular_light = ks * amount_specular_light * is;
+
+        light += diffuse_light + specular_light;
+    }
+
+#ifdef TRANSPARENT
+    return float4(light, opacity);
+#else
     return float4(light, 1.0);
+#endif
 }
\ No newline at end of file
@@ -1,3 +1,6 @@
+#include "environment.hlsl"
+#include "entity.hlsl"
+
 struct VS_INPUT
 {
     float4 pos: POSITION0;
@@ -5,6 +8,7 @@ struct VS_INPUT
     float3 tangent: TANGENT0;
     float3 binormal: BINORMAL0;
     float3 normal: NORMAL0;
+    INSTANCE_INPUT
 };
 
 struct VS_OUTPUT
@@ -13,29 +17,7 @@ struct VS_OUTPUT
     float2 tex_coord: TEXCOORD0;
     float3 normal: NORMAL0;
     float3 world_pos: TEXCOORD1;
-};
-
-cbuffer constant: register(b0)
-{
-    row_major float4x4 m_view;
-    row_major float4x4 m_proj;
-
-    float4 m_light_dir;
-    float4 m_camera_pos;
-    float4 m_light_pos;
-    
-    float m_light_rad;
-    float time;
-};
-
-cbuffer constant1: register(b1)
-{
-    row_major float4x4 m_world;
-};
-
-cbuffer constant1: register(b2)
-{
-    float3 color;
+    nointerpolation int4 lights: LIGHTS;
 };
 
 VS_OUTPUT vsmain( VS_INPUT input )
@@ -44,7 +26,7 @@ VS_OUTPUT vsmain( VS_INPUT input )
 //    output.pos = lerp(input.pos, input.pos1, (1.0f+cos(m_time/1000.0f))/2.0);
 
 // World space
-    output.pos = mul(input.pos, m_world);
+    output.pos = mul(input.pos, WORLD_MATRIX(input));
 //    output.cam_dir = normalize(output.pos.xyz - m_camera_pos.xyz); 
     output.world_pos = output.pos.xyz;
 // View space
@@ -55,5 +37,7 @@ VS_OUTPUT vsmain( VS_INPUT input )
     output.tex_coord = input.tex_coord;
     output.normal = input.normal;
 
+    output.lights = OBJECT_LIGHTS(input);
+
     return output;
 }
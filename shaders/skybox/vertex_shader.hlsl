@@ -10,49 +10,22 @@ struct VS_INPUT
 struct VS_OUTPUT
 {
     float4 pos: SV_POSITION;
-    float2 tex_coord: TEXCOORD0;
-    float3 normal: NORMAL0;
-    float3 cam_dir: CAMDIR;
-};
-
-cbuffer constant: register(b0)
-{
-    row_major float4x4 m_view;
-    row_major float4x4 m_proj;
-
-    float4 m_light_dir;
-    float4 m_camera_pos;
-    float4 m_light_pos;
-    
-    float m_light_rad;
-    float time;
+    float3 direction: DIRECTION;
 };
 
-cbuffer constant1: register(b1)
-{
-    row_major float4x4 m_world;
-};
-
-cbuffer constant1: register(b2)
-{
-    float3 color;
-};
+#include "environment.hlsl"
 
 VS_OUTPUT vsmain( VS_INPUT input )
 {   
     VS_OUTPUT output = (VS_OUTPUT)0;
-//    output.pos = lerp(input.pos, input.pos1, (1.0f+cos(m_time/1000.0f))/2.0);
-
-// World space
-    output.pos = mul(input.pos, m_world);
-    output.cam_dir = normalize(output.pos.xyz - m_camera_pos.xyz); 
-// View space
-    output.pos = mul(output.pos, m_view);
-// Projection space
-    output.pos = mul(output.pos, m_proj);
 
-    output.tex_coord = input.tex_coord;
-    output.normal = input.normal;
+// Any closed mesh around the origin works; only the direction to each vertex is used.
+    output.direction = input.pos.xyz;
+// Rotate with the camera, but never move
+    output.pos = mul(float4(input.pos.xyz, 0.0), m_view);
+    output.pos = mul(float4(output.pos.xyz, 1.0), m_proj);
+// Depth of w / w = 1, on the far plane
+    output.pos = output.pos.xyww;
 
     return output;
 }
@@ -0,0 +1,45 @@
+Texture2D Scene: register(t0);
+sampler SceneSampler: register(s0);
+
+struct PS_INPUT
+{
+    float4 pos: SV_POSITION;
+    float2 tex_coord: TEXCOORD0;
+};
+
+cbuffer tone_map_settings: register(b0)
+{
+    float exposure;
+    uint tone_operator;
+};
+
+float3 reinhard(float3 color)
+{
+    return color / (1.0 + color);
+}
+
+// Krzysztof Narkowicz's fit of the ACES filmic curve
+float3 aces(float3 color)
+{
+    float a = 2.51;
+    float b = 0.03;
+    float c = 2.43;
+    float d = 0.59;
+    float e = 0.14;
+    return saturate((color * (a * color + b)) / (color * (c * color + d) + e));
+}
+
+float4 psmain( PS_INPUT input ) : SV_Target
+{
+    float3 color = Scene.Sample(SceneSampler, input.tex_coord).rgb * exposure;
+
+// Lighting is done in gamma space, so the result is not encoded again
+    if (tone_operator == 0)
+        color = reinhard(color);
+    else if (tone_operator == 1)
+        color = aces(color);
+    else
+        color = saturate(color);
+
+    return float4(color, 1.0);
+}
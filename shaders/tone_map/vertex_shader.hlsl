@@ -0,0 +1,17 @@
+struct VS_OUTPUT
+{
+    float4 pos: SV_POSITION;
+    float2 tex_coord: TEXCOORD0;
+};
+
+VS_OUTPUT vsmain( uint id: SV_VertexID )
+{
+    VS_OUTPUT output = (VS_OUTPUT)0;
+
+// One triangle covering the screen, with texture coordinates 0 to 1 inside it
+    output.tex_coord = float2((id << 1) & 2, id & 2);
+// On the far plane. Render::draw_fullscreen turns off depth testing for it
+    output.pos = float4(output.tex_coord * float2(2.0, -2.0) + float2(-1.0, 1.0), 1.0, 1.0);
+
+    return output;
+}